@@ -124,7 +124,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     registry.register(calculator_tool::registration())?;
 
     // For multiple tools, you could use the register_tools! macro:
-    // register_tools!(registry, calculator_tool, weather_tool)?;
+    // register_tools!(registry, calculator_tool, weather_tool);
 
     // Get all registered tool declarations
     let tool_declarations = registry.get_declarations();
@@ -149,7 +149,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         while let Some(event) = stream.next().await {
             match event? {
-                AgentEvent::IterationStarted { iteration } => {
+                AgentEvent::IterationStarted { iteration, .. } => {
                     println!("[Iteration {}]", iteration);
                 }
                 AgentEvent::LlmEvent(StreamEvent::ContentDelta {
@@ -168,7 +168,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 AgentEvent::ToolExecutionFailed { name, error, .. } => {
                     println!("[Tool {} failed: {}]", name, error);
                 }
-                AgentEvent::Completed => {
+                AgentEvent::Completed { .. } => {
                     println!("\n[Agent completed]\n");
                 }
                 _ => {}
@@ -184,7 +184,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         while let Some(event) = stream.next().await {
             match event? {
-                AgentEvent::IterationStarted { iteration } => {
+                AgentEvent::IterationStarted { iteration, .. } => {
                     println!("[Iteration {}]", iteration);
                 }
                 AgentEvent::LlmEvent(StreamEvent::ContentDelta {
@@ -200,7 +200,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 AgentEvent::ToolExecutionCompleted { name, result, .. } => {
                     println!("[Tool {} completed: {}]", name, result);
                 }
-                AgentEvent::Completed => {
+                AgentEvent::Completed { .. } => {
                     println!("\n[Agent completed]\n");
                 }
                 _ => {}
@@ -227,6 +227,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     println!("   [{}] Tool {}: (id: {})", j, status, tool_use_id);
                     println!("       {}", content);
                 }
+                rust2::llm::ContentBlock::Image { media_type, .. } => {
+                    println!("   [{}] Image: {}", j, media_type);
+                }
+                rust2::llm::ContentBlock::RedactedThinking { .. } => {
+                    println!("   [{}] Redacted thinking", j);
+                }
             }
         }
         println!();
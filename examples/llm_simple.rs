@@ -1,17 +1,17 @@
-/// Simple LLM Example
-///
-/// This example demonstrates the basic usage of the LLM abstraction layer:
-/// - Creating a provider using create_provider
-/// - Sending a simple prompt
-/// - Streaming and printing the response
-///
-/// To run this example:
-/// 1. Set up GCP Application Default Credentials:
-///    gcloud auth application-default login
-/// 2. Create a .env file in the project root with:
-///    GCP_PROJECT_ID=your-project-id
-///    GCP_LOCATION=us-central1
-/// 3. Run: cargo run --example llm_simple
+//! Simple LLM Example
+//!
+//! This example demonstrates the basic usage of the LLM abstraction layer:
+//! - Creating a provider using create_provider
+//! - Sending a simple prompt
+//! - Streaming and printing the response
+//!
+//! To run this example:
+//! 1. Set up GCP Application Default Credentials:
+//!    gcloud auth application-default login
+//! 2. Create a .env file in the project root with:
+//!    GCP_PROJECT_ID=your-project-id
+//!    GCP_LOCATION=us-central1
+//! 3. Run: cargo run --example llm_simple
 
 use futures::StreamExt;
 use rust2::llm::{
@@ -44,12 +44,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Create the LLM provider
     println!("Creating provider...");
-    let provider = create_provider(
-        Model::Claude(ClaudeModel::Sonnet45),
-        project_id,
-        location,
-    )
-    .await?;
+    let provider =
+        create_provider(Model::Claude(ClaudeModel::Sonnet45), project_id, location).await?;
     println!("✓ Provider created successfully\n");
 
     // Create a simple request
@@ -58,8 +54,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "Write a haiku about Rust programming language.",
         )],
         tools: None,
+        tool_choice: None,
         config: GenerationConfig::new(1024).with_temperature(0.7),
         system: Some("You are a helpful assistant that writes creative poetry.".to_string()),
+        locale: None,
     };
 
     println!("Sending request to LLM...");
@@ -87,11 +85,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
             }
-            StreamEvent::ContentDelta { delta, .. } => {
-                if let rust2::llm::core::types::ContentDelta::TextDelta { text } = delta {
-                    print!("{}", text);
-                    full_text.push_str(&text);
-                }
+            StreamEvent::ContentDelta {
+                delta: rust2::llm::core::types::ContentDelta::TextDelta { text },
+                ..
+            } => {
+                print!("{}", text);
+                full_text.push_str(&text);
             }
             StreamEvent::ContentBlockEnd { index } => {
                 println!("\n[Content block {} ended]", index);
@@ -2,7 +2,9 @@
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, punctuated::Punctuated, token::Comma, Expr, ExprLit, ItemFn, Lit, Meta, Type};
+use syn::{parse_macro_input, punctuated::Punctuated, token::Comma, Attribute, DeriveInput, Expr, ExprLit, ItemFn, Lit, Meta, Type};
+
+mod tool_input;
 
 /// Attribute macro to automatically generate tool declarations from functions
 ///
@@ -13,6 +15,13 @@ use syn::{parse_macro_input, punctuated::Punctuated, token::Comma, Expr, ExprLit
 /// async fn calculator(args: CalculatorArgs) -> Result<CalculatorResult, String> {
 ///     // Implementation
 /// }
+///
+/// // Or describe the tool with its doc comment
+/// /// Perform basic arithmetic operations
+/// #[tool]
+/// async fn calculator(args: CalculatorArgs) -> Result<CalculatorResult, String> {
+///     // Implementation
+/// }
 /// ```
 ///
 /// This will generate a module `calculator_tool` containing:
@@ -20,6 +29,7 @@ use syn::{parse_macro_input, punctuated::Punctuated, token::Comma, Expr, ExprLit
 /// - `declaration()`: Function returning the ToolDeclaration
 /// - `execute`: Re-export of the original function
 /// - `registration()`: Function returning a complete ToolRegistration for one-step registration
+///   (`registration_with(ctx)` for tools that take a context, see below)
 ///
 /// # Usage
 ///
@@ -34,11 +44,35 @@ use syn::{parse_macro_input, punctuated::Punctuated, token::Comma, Expr, ExprLit
 /// )?;
 /// ```
 ///
+/// # Shared state
+///
+/// A tool may take a context value before its arguments. Instead of
+/// `registration()`, its module then has `registration_with(ctx)`, and every
+/// call receives a clone of `ctx`:
+///
+/// ```ignore
+/// #[tool(description = "Look up a user by id")]
+/// async fn lookup(ctx: Arc<AppState>, args: LookupArgs) -> Result<User, String> {
+///     ctx.users.get(&args.id).await
+/// }
+///
+/// registry.register(lookup_tool::registration_with(state.clone()))?;
+/// ```
+///
 /// # Attributes
 ///
-/// - `description`: (required) Description of what the tool does
+/// - `description`: (optional) Description of what the tool does; defaults to
+///   the function's doc comment, one line per doc line. One of the two is required.
 /// - `name`: (optional) Override the tool name (defaults to function name)
 ///
+/// # Input schema
+///
+/// The args type must implement `schemars::JsonSchema` (derive it, or use
+/// `#[derive(ToolInput)]`); `declaration()` uses it as the tool's
+/// `input_schema`, so the model sees the real field names, types and doc
+/// comments. A unit struct declares an empty object, and the `{}` the model
+/// sends for it is accepted.
+///
 #[proc_macro_attribute]
 pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
     // Parse the attribute arguments
@@ -68,13 +102,13 @@ pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
         }
     }
 
-    // Description is required
-    let description = match description {
+    // The attribute wins over the doc comment; one of them is required
+    let description = match description.or_else(|| doc_comment(&input_fn.attrs)) {
         Some(d) => d,
         None => {
             return syn::Error::new_spanned(
                 &input_fn.sig,
-                "tool attribute requires a 'description' parameter"
+                "tool requires a 'description' parameter or a doc comment"
             )
             .to_compile_error()
             .into();
@@ -85,10 +119,16 @@ pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
     let fn_name = &input_fn.sig.ident;
     let tool_name = tool_name.unwrap_or_else(|| fn_name.to_string());
 
-    // Extract the argument type from the first parameter
-    let arg_type = match input_fn.sig.inputs.first() {
-        Some(syn::FnArg::Typed(pat_type)) => &pat_type.ty,
-        _ => {
+    // `fn tool(args)` or `fn tool(ctx, args)`; the args type is always last
+    let param_type = |arg: &syn::FnArg| match arg {
+        syn::FnArg::Typed(pat_type) => Some(pat_type.ty.clone()),
+        syn::FnArg::Receiver(_) => None,
+    };
+    let params: Vec<_> = input_fn.sig.inputs.iter().map(param_type).collect();
+    let (ctx_type, arg_type) = match params.as_slice() {
+        [Some(args)] => (None, args.clone()),
+        [Some(ctx), Some(args)] => (Some(ctx.clone()), args.clone()),
+        [] => {
             return syn::Error::new_spanned(
                 &input_fn.sig,
                 "tool function must have at least one parameter"
@@ -96,6 +136,14 @@ pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
             .to_compile_error()
             .into();
         }
+        _ => {
+            return syn::Error::new_spanned(
+                &input_fn.sig.inputs,
+                "tool function must take `args` or `(ctx, args)`"
+            )
+            .to_compile_error()
+            .into();
+        }
     };
 
     // Generate the module name: calculator -> calculator_tool
@@ -105,7 +153,7 @@ pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
     );
 
     // Strip any reference or path from the type to get the base type
-    let base_type = strip_type_modifiers(arg_type);
+    let base_type = strip_type_modifiers(&arg_type);
 
     // Make the function public so it can be re-exported
     let mut pub_input_fn = input_fn.clone();
@@ -114,7 +162,14 @@ pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
     // Check if the function is async or sync
     let is_async = input_fn.sig.asyncness.is_some();
 
-    // Generate the wrapper logic for the registration() function
+    // Stateful tools get the context captured by the wrapper
+    let call = if ctx_type.is_some() {
+        quote! { execute(ctx.clone(), args) }
+    } else {
+        quote! { execute(args) }
+    };
+
+    // Generate the wrapper logic for the registration function
     let wrapper_logic = if is_async {
         // Async function wrapper
         quote! {
@@ -122,7 +177,7 @@ pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
                 use futures::future::BoxFuture;
 
                 // Deserialize arguments
-                let args = match serde_json::from_value::<#base_type>(args_json) {
+                let args = match rust2::llm::tools::__private::deserialize_args::<#base_type>(args_json) {
                     Ok(args) => args,
                     Err(e) => {
                         let err_msg = format!("Failed to deserialize arguments: {}", e);
@@ -131,7 +186,7 @@ pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
                 };
 
                 // Call the async function
-                let future = execute(args);
+                let future = #call;
 
                 // Box the future and handle serialization
                 Box::pin(async move {
@@ -152,7 +207,7 @@ pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
                 use futures::future::BoxFuture;
 
                 // Deserialize arguments
-                let args = match serde_json::from_value::<#base_type>(args_json) {
+                let args = match rust2::llm::tools::__private::deserialize_args::<#base_type>(args_json) {
                     Ok(args) => args,
                     Err(e) => {
                         let err_msg = format!("Failed to deserialize arguments: {}", e);
@@ -161,7 +216,7 @@ pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
                 };
 
                 // Call the sync function
-                let result = execute(args);
+                let result = #call;
 
                 // Box the result as a future
                 Box::pin(async move {
@@ -177,6 +232,42 @@ pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
         }
     };
 
+    let registration = match &ctx_type {
+        None => quote! {
+            /// Get a complete ToolRegistration for one-step registration
+            ///
+            /// This is the simplest way to register a tool:
+            /// ```ignore
+            /// registry.register(calculator_tool::registration())?;
+            /// ```
+            pub fn registration() -> rust2::llm::tools::ToolRegistration {
+                #wrapper_logic
+
+                rust2::llm::tools::ToolRegistration {
+                    name: NAME,
+                    function: Box::new(wrapper),
+                    declaration: declaration(),
+                }
+            }
+        },
+        Some(ctx_type) => quote! {
+            /// Get a complete ToolRegistration whose calls all receive `ctx`
+            ///
+            /// ```ignore
+            /// registry.register(lookup_tool::registration_with(state.clone()))?;
+            /// ```
+            pub fn registration_with(ctx: #ctx_type) -> rust2::llm::tools::ToolRegistration {
+                #wrapper_logic
+
+                rust2::llm::tools::ToolRegistration {
+                    name: NAME,
+                    function: Box::new(wrapper),
+                    declaration: declaration(),
+                }
+            }
+        },
+    };
+
     // Generate the output - creates a module with all tool metadata
     let output = quote! {
         // Original function (made pub for re-export)
@@ -201,27 +292,85 @@ pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
             /// The executable function for this tool (re-exported from parent)
             pub use super::#fn_name as execute;
 
-            /// Get a complete ToolRegistration for one-step registration
-            ///
-            /// This is the simplest way to register a tool:
-            /// ```ignore
-            /// registry.register(calculator_tool::registration())?;
-            /// ```
-            pub fn registration() -> rust2::llm::tools::ToolRegistration {
-                #wrapper_logic
-
-                rust2::llm::tools::ToolRegistration {
-                    name: NAME,
-                    function: Box::new(wrapper),
-                    declaration: declaration(),
-                }
-            }
+            #registration
         }
     };
 
     TokenStream::from(output)
 }
 
+/// Derive the JSON Schema of a tool's argument struct
+///
+/// Implements `schemars::JsonSchema` without a direct `schemars` dependency,
+/// so the struct can be passed to `create_tool_declaration` or taken by a
+/// `#[tool]` function. The schema is an object with one property per field;
+/// fields other than `Option<T>` or `#[serde(default)]` ones are `required`.
+///
+/// # Example
+///
+/// ```ignore
+/// use rust2_tool_macros::ToolInput;
+/// use serde::{Deserialize, Serialize};
+///
+/// /// Look up a user
+/// #[derive(Serialize, Deserialize, ToolInput)]
+/// struct LookupArgs {
+///     #[tool_param(description = "User id, e.g. u-123")]
+///     id: String,
+///     /// Include deleted users
+///     include_deleted: Option<bool>,
+/// }
+/// ```
+///
+/// # Attributes
+///
+/// - `#[tool_param(description = "...")]` on a field: the property's
+///   description; defaults to the field's doc comment
+/// - `#[serde(rename = "...")]` on a field is used as the property name.
+///   `#[serde(rename_all)]` isn't supported.
+/// - Fields with `#[serde(skip)]` or `#[serde(skip_deserializing)]` are left
+///   out of the schema.
+/// - `#[serde(default)]` on a field or the struct makes fields optional.
+///
+/// Every other field type must implement `Serialize`, `DeserializeOwned` and
+/// `JsonSchema`; otherwise the derive fails to compile.
+#[proc_macro_derive(ToolInput, attributes(tool_param))]
+pub fn derive_tool_input(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    tool_input::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// The item's doc comment, one line per `#[doc]` attribute with leading
+/// whitespace trimmed, or `None` if there is no non-blank doc text
+fn doc_comment(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) => match &nv.value {
+                Expr::Lit(ExprLit { lit: Lit::Str(lit), .. }) => Some(lit.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .flat_map(|doc| {
+            // `split` rather than `lines`, so an empty `///` line is kept
+            doc.split('\n')
+                .map(|line| line.trim_start().to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let doc = lines.join("\n").trim().to_string();
+    if doc.is_empty() {
+        None
+    } else {
+        Some(doc)
+    }
+}
+
 /// Strip reference and other modifiers from a type to get the base type
 fn strip_type_modifiers(ty: &Type) -> &Type {
     match ty {
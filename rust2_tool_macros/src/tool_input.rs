@@ -0,0 +1,228 @@
+//! `#[derive(ToolInput)]`: JSON Schema for tool argument structs
+
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Expr, ExprLit, Fields, Lit, Meta, Type};
+
+use crate::doc_comment;
+
+/// One property of the generated schema
+struct Property<'a> {
+    name: String,
+    ty: &'a Type,
+    description: Option<String>,
+    required: bool,
+}
+
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "ToolInput cannot be derived for generic types",
+        ));
+    }
+    if serde_value(&input.attrs, "rename_all")?.is_some() {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "ToolInput doesn't support #[serde(rename_all)]; rename fields with #[serde(rename)]",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(not_a_struct(&input)),
+        },
+        _ => return Err(not_a_struct(&input)),
+    };
+
+    // With a container `#[serde(default)]`, every field may be left out
+    let container_default = serde_flag(&input.attrs, "default")?;
+
+    let mut properties = Vec::new();
+    for field in fields {
+        // Never read from the arguments, so not part of the schema
+        if serde_flag(&field.attrs, "skip")? || serde_flag(&field.attrs, "skip_deserializing")? {
+            continue;
+        }
+        let ident = field.ident.as_ref().expect("named field");
+        let name = match serde_value(&field.attrs, "rename")? {
+            Some(rename) => rename,
+            None => ident.to_string().trim_start_matches("r#").to_string(),
+        };
+        // The attribute wins over the doc comment
+        let description = match tool_param_description(&field.attrs)? {
+            Some(description) => Some(description),
+            None => doc_comment(&field.attrs),
+        };
+        properties.push(Property {
+            name,
+            ty: &field.ty,
+            description,
+            required: !is_option(&field.ty)
+                && !container_default
+                && !serde_flag(&field.attrs, "default")?,
+        });
+    }
+
+    let type_name = &input.ident;
+    let schema_name = type_name.to_string();
+    let root_description = match doc_comment(&input.attrs) {
+        Some(description) => quote! {
+            schema.metadata().description = Some(#description.to_string());
+        },
+        None => quote! {},
+    };
+
+    // Spanned so an unsupported field type is reported at the field
+    let serde_checks = properties.iter().map(|property| {
+        let ty = property.ty;
+        quote_spanned! {ty.span()=> assert_serde::<#ty>(); }
+    });
+
+    let insert_properties = properties.iter().map(|property| {
+        let Property {
+            name,
+            ty,
+            description,
+            required,
+        } = property;
+        let describe = match description {
+            Some(description) => quote! {
+                if let schemars::schema::Schema::Object(object) = &mut property {
+                    object.metadata().description = Some(#description.to_string());
+                }
+            },
+            None => quote! {},
+        };
+        let require = if *required {
+            quote! { validation.required.insert(#name.to_string()); }
+        } else {
+            quote! {}
+        };
+        quote! {
+            #[allow(unused_mut)]
+            let mut property = generator.subschema_for::<#ty>();
+            #describe
+            validation.properties.insert(#name.to_string(), property);
+            #require
+        }
+    });
+
+    Ok(quote! {
+        const _: () = {
+            use rust2::llm::tools::__private::{schemars, serde};
+
+            // Tool arguments are deserialized from, and logged as, JSON
+            #[allow(dead_code)]
+            fn assert_serde<T: serde::Serialize + serde::de::DeserializeOwned>() {}
+            #[allow(dead_code)]
+            fn assert_fields() {
+                #(#serde_checks)*
+            }
+
+            impl schemars::JsonSchema for #type_name {
+                fn schema_name() -> String {
+                    #schema_name.to_string()
+                }
+
+                fn json_schema(
+                    generator: &mut schemars::gen::SchemaGenerator,
+                ) -> schemars::schema::Schema {
+                    let mut validation = schemars::schema::ObjectValidation::default();
+                    #(#insert_properties)*
+
+                    #[allow(unused_mut)]
+                    let mut schema = schemars::schema::SchemaObject {
+                        instance_type: Some(schemars::schema::InstanceType::Object.into()),
+                        object: Some(Box::new(validation)),
+                        ..Default::default()
+                    };
+                    #root_description
+                    schema.into()
+                }
+            }
+        };
+    })
+}
+
+fn not_a_struct(input: &DeriveInput) -> syn::Error {
+    syn::Error::new_spanned(
+        &input.ident,
+        "ToolInput can only be derived for structs with named fields",
+    )
+}
+
+/// `T` is spelled `Option<...>`, so the property isn't required
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
+
+/// `description` from `#[tool_param(description = "...")]`
+fn tool_param_description(attrs: &[syn::Attribute]) -> syn::Result<Option<String>> {
+    let mut description = None;
+    for attr in attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("tool_param"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("description") {
+                let value: syn::LitStr = meta.value()?.parse()?;
+                description = Some(value.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `description = \"...\"`"))
+            }
+        })?;
+    }
+    Ok(description)
+}
+
+/// String value of `key` in `#[serde(key = "...")]`, ignoring other serde options
+fn serde_value(attrs: &[syn::Attribute], key: &str) -> syn::Result<Option<String>> {
+    let mut found = None;
+    for meta in serde_options(attrs)? {
+        if let Meta::NameValue(nv) = meta {
+            if nv.path.is_ident(key) {
+                if let Expr::Lit(ExprLit {
+                    lit: Lit::Str(lit), ..
+                }) = &nv.value
+                {
+                    found = Some(lit.value());
+                }
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Whether `#[serde(key)]` or `#[serde(key = "...")]` is present
+fn serde_flag(attrs: &[syn::Attribute], key: &str) -> syn::Result<bool> {
+    Ok(serde_options(attrs)?.iter().any(|meta| match meta {
+        Meta::Path(path) => path.is_ident(key),
+        Meta::NameValue(nv) => nv.path.is_ident(key),
+        Meta::List(_) => false,
+    }))
+}
+
+/// Options of every `#[serde(...)]` attribute in `attrs`
+fn serde_options(attrs: &[syn::Attribute]) -> syn::Result<Vec<Meta>> {
+    let mut options = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        let Meta::List(list) = &attr.meta else {
+            continue;
+        };
+        let nested = list.parse_args_with(
+            syn::punctuated::Punctuated::<Meta, syn::Token![,]>::parse_terminated,
+        )?;
+        options.extend(nested);
+    }
+    Ok(options)
+}
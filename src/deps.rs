@@ -0,0 +1,149 @@
+// Services the HTTP routes run against
+
+use crate::llm::moderation::{Moderator, NoopModerator};
+use crate::llm::tools::RegistrySnapshot;
+use crate::llm::{FunctionRegistry, GenerationConfig, LlmProvider};
+use crate::message_db::MessageDbClient;
+use crate::usage::UsageLedger;
+use std::sync::Arc;
+
+/// Dependencies injected into every handler by [`configure_routes`]
+///
+/// Everything is optional except the moderator, so tests can build routes
+/// with only the parts they exercise:
+///
+/// - Without a `provider`, `POST /threads/{id}` streams a scripted mock run.
+/// - Without a `message_db`, runs start from an empty history and aren't
+///   saved, and `GET /threads/{id}` returns a sample thread.
+/// - `GET /tools` reports `tools`, or else the `functions` registry.
+///
+/// # Example
+///
+/// ```no_run
+/// use rust2::deps::AppDeps;
+/// use rust2::llm::{create_provider, ClaudeModel, FunctionRegistry, Model};
+/// use rust2::routes::configure_routes;
+/// use std::sync::Arc;
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let provider = create_provider(
+///     Model::Claude(ClaudeModel::Sonnet45),
+///     "my-project".to_string(),
+///     "us-central1".to_string(),
+/// ).await?;
+///
+/// let deps = AppDeps::new()
+///     .with_provider(Arc::from(provider))
+///     .with_functions(Arc::new(FunctionRegistry::new()))
+///     .with_system_prompt("You are a helpful assistant.");
+/// warp::serve(configure_routes(deps)).run(([127, 0, 0, 1], 3030)).await;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`configure_routes`]: crate::routes::configure_routes
+#[derive(Clone)]
+pub struct AppDeps {
+    /// Screens user input before a run starts (default: `NoopModerator`)
+    pub moderator: Arc<dyn Moderator>,
+
+    /// Per-principal usage tracking; requires an `X-Api-Key` header when set
+    pub usage: Option<UsageLedger>,
+
+    /// Registry snapshot reported by `GET /tools`
+    pub tools: Option<RegistrySnapshot>,
+
+    /// Model that answers messages
+    pub provider: Option<Arc<dyn LlmProvider>>,
+
+    /// Tools offered to the model
+    pub functions: Option<Arc<FunctionRegistry>>,
+
+    /// Where thread history is loaded from and saved to
+    pub message_db: Option<MessageDbClient>,
+
+    /// Generation parameters for each run (default: `GenerationConfig::default()`)
+    pub generation_config: GenerationConfig,
+
+    /// System prompt for each run
+    pub system_prompt: Option<String>,
+}
+
+impl AppDeps {
+    /// Dependencies for the mock server: no model, tools, storage or usage tracking
+    pub fn new() -> Self {
+        Self {
+            moderator: Arc::new(NoopModerator),
+            usage: None,
+            tools: None,
+            provider: None,
+            functions: None,
+            message_db: None,
+            generation_config: GenerationConfig::default(),
+            system_prompt: None,
+        }
+    }
+
+    /// Set the input moderator (builder pattern)
+    pub fn with_moderator(mut self, moderator: Arc<dyn Moderator>) -> Self {
+        self.moderator = moderator;
+        self
+    }
+
+    /// Track usage per API key in `ledger` (builder pattern)
+    pub fn with_usage(mut self, ledger: UsageLedger) -> Self {
+        self.usage = Some(ledger);
+        self
+    }
+
+    /// Report `snapshot` from `GET /tools` (builder pattern)
+    pub fn with_tool_snapshot(mut self, snapshot: RegistrySnapshot) -> Self {
+        self.tools = Some(snapshot);
+        self
+    }
+
+    /// Answer messages with `provider` (builder pattern)
+    pub fn with_provider(mut self, provider: Arc<dyn LlmProvider>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Offer the tools in `functions` to the model (builder pattern)
+    pub fn with_functions(mut self, functions: Arc<FunctionRegistry>) -> Self {
+        self.functions = Some(functions);
+        self
+    }
+
+    /// Load and save thread history with `client` (builder pattern)
+    pub fn with_message_db(mut self, client: MessageDbClient) -> Self {
+        self.message_db = Some(client);
+        self
+    }
+
+    /// Set the generation parameters (builder pattern)
+    pub fn with_generation_config(mut self, config: GenerationConfig) -> Self {
+        self.generation_config = config;
+        self
+    }
+
+    /// Set the system prompt (builder pattern)
+    pub fn with_system_prompt(mut self, system: impl Into<String>) -> Self {
+        self.system_prompt = Some(system.into());
+        self
+    }
+
+    /// Snapshot for `GET /tools`: the explicit one, or the registry's
+    pub(crate) fn tool_snapshot(&self) -> Option<RegistrySnapshot> {
+        self.tools.clone().or_else(|| {
+            self.functions
+                .as_ref()
+                .map(|functions| functions.snapshot())
+        })
+    }
+}
+
+impl Default for AppDeps {
+    fn default() -> Self {
+        Self::new()
+    }
+}
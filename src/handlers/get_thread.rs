@@ -1,18 +1,52 @@
 // GET /threads/{threadId} handler
 
+use crate::deps::AppDeps;
+use crate::handlers::error_response;
+use crate::message_db::StreamReadOptions;
 use crate::models::{Message, MessageContent, MessageType, ThreadResponse};
+use crate::thread_events::{render_thread, thread_stream_name};
 use chrono::Utc;
-use std::convert::Infallible;
 use uuid::Uuid;
 use warp::http::StatusCode;
+use warp::Reply;
 
-pub async fn get_thread_handler(thread_id: Uuid) -> Result<impl warp::Reply, Infallible> {
+pub async fn get_thread_handler(
+    thread_id: Uuid,
+    deps: AppDeps,
+) -> Result<warp::reply::Response, warp::Rejection> {
     println!("GET /threads/{}", thread_id);
 
+    let messages = match &deps.message_db {
+        Some(client) => {
+            let options = StreamReadOptions::new(thread_stream_name(thread_id));
+            match client.get_stream_messages(options).await {
+                Ok(events) => render_thread(&events),
+                Err(e) => {
+                    tracing::error!(%thread_id, error = %e, "failed to read thread");
+                    return Ok(error_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Failed to read thread",
+                    ));
+                }
+            }
+        }
+        None => sample_messages(),
+    };
+
+    let response = ThreadResponse {
+        thread_id,
+        messages,
+    };
+
+    Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::OK).into_response())
+}
+
+/// Hardcoded thread returned when no Message DB is configured
+fn sample_messages() -> Vec<Message> {
     // Create hardcoded messages with various types
     let now = Utc::now();
 
-    let messages = vec![
+    vec![
         Message {
             id: "msg_1".to_string(),
             message_type: MessageType::User,
@@ -62,15 +96,5 @@ pub async fn get_thread_handler(thread_id: Uuid) -> Result<impl warp::Reply, Inf
                 text: "The weather in San Francisco is currently 62°F with partly cloudy skies and 70% humidity.".to_string(),
             },
         },
-    ];
-
-    let response = ThreadResponse {
-        thread_id,
-        messages,
-    };
-
-    Ok(warp::reply::with_status(
-        warp::reply::json(&response),
-        StatusCode::OK,
-    ))
+    ]
 }
@@ -0,0 +1,38 @@
+// GET /usage handler
+
+use crate::handlers::error_response;
+use crate::usage::{key_id, UsageLedger, API_KEY_HEADER};
+use warp::http::StatusCode;
+use warp::Reply;
+
+pub async fn get_usage_handler(
+    api_key: Option<String>,
+    ledger: Option<UsageLedger>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let Some(ledger) = ledger else {
+        return Ok(error_response(
+            StatusCode::NOT_FOUND,
+            "Usage tracking is not enabled",
+        ));
+    };
+    let Some(api_key) = api_key else {
+        return Ok(error_response(
+            StatusCode::UNAUTHORIZED,
+            &format!("Missing {} header", API_KEY_HEADER),
+        ));
+    };
+
+    let key_id = key_id(&api_key);
+    println!("GET /usage for {}", key_id);
+
+    match ledger.summary(&key_id).await {
+        Ok(summary) => Ok(warp::reply::json(&summary).into_response()),
+        Err(e) => {
+            tracing::error!(%key_id, error = %e, "failed to read usage");
+            Ok(error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read usage",
+            ))
+        }
+    }
+}
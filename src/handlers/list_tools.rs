@@ -0,0 +1,36 @@
+// GET /tools handler
+
+use crate::handlers::error_response;
+use crate::llm::tools::RegistrySnapshot;
+use serde::Deserialize;
+use warp::http::StatusCode;
+use warp::Reply;
+
+#[derive(Debug, Deserialize)]
+pub struct ToolsQuery {
+    pub format: Option<String>,
+}
+
+pub async fn list_tools_handler(
+    query: ToolsQuery,
+    snapshot: Option<RegistrySnapshot>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let Some(snapshot) = snapshot else {
+        return Ok(error_response(
+            StatusCode::NOT_FOUND,
+            "No tool registry is configured",
+        ));
+    };
+
+    match query.format.as_deref() {
+        None | Some("names") => Ok(warp::reply::json(&serde_json::json!({
+            "tools": snapshot.names()
+        }))
+        .into_response()),
+        Some("snapshot") => Ok(warp::reply::json(&snapshot).into_response()),
+        Some(other) => Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            &format!("Unknown format '{}', expected 'names' or 'snapshot'", other),
+        )),
+    }
+}
@@ -1,7 +1,25 @@
 // Handlers module
 
 pub mod get_thread;
+pub mod get_usage;
+pub mod list_tools;
 pub mod send_message;
+pub mod stream_input;
 
 pub use get_thread::get_thread_handler;
+pub use get_usage::get_usage_handler;
+pub use list_tools::list_tools_handler;
 pub use send_message::send_message_handler;
+pub use stream_input::stream_input_handler;
+
+use warp::http::StatusCode;
+use warp::Reply;
+
+/// JSON error body of the form `{"error": message}`
+pub(crate) fn error_response(status: StatusCode, message: &str) -> warp::reply::Response {
+    warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "error": message })),
+        status,
+    )
+    .into_response()
+}
@@ -1,32 +1,347 @@
 // POST /threads/{threadId} handler
 
+use crate::deps::AppDeps;
+use crate::handlers::error_response;
+use crate::llm::moderation::ModerationDecision;
+use crate::llm::{
+    Agent, AgentEvent, ContentDelta, FunctionRegistry, LlmProvider, Message as LlmMessage,
+    StreamEvent, ToolExecutor, UsageMetadata,
+};
+use crate::message_db::{MessageDbClient, StreamReadOptions};
 use crate::models::SendMessageRequest;
 use crate::sse::{
-    create_agent_text_event, create_done_event, create_tool_call_event, create_tool_response_event,
+    create_agent_text_discarded_event, create_agent_text_event, create_agent_text_redacted_event,
+    create_done_event,
+    create_error_event, create_tool_call_event, create_tool_response_event, create_waiting_event,
 };
+use crate::thread_events::{append_message, decode, thread_stream_name, MESSAGE_APPENDED};
+use crate::usage::{key_id, UsageLedger, UsageRecorded, API_KEY_HEADER};
+use crate::webhooks::{RunStatus, WebhookDispatcher, WebhookPayload, WebhookRegistration};
+use chrono::Utc;
 use futures_util::stream::StreamExt;
 use std::convert::Infallible;
 use std::time::Duration;
+use tokio::sync::mpsc;
 use tokio::time::interval;
-use tokio_stream::wrappers::IntervalStream;
+use tokio_stream::wrappers::{IntervalStream, ReceiverStream};
 use uuid::Uuid;
+use warp::http::StatusCode;
 use warp::sse::Event;
+use warp::Reply;
+
+/// Model name recorded for runs of the mock event stream
+const MOCK_MODEL: &str = "mock";
 
 pub async fn send_message_handler(
     thread_id: Uuid,
-    request: SendMessageRequest,
-) -> Result<impl warp::Reply, warp::Rejection> {
+    mut request: SendMessageRequest,
+    api_key: Option<String>,
+    deps: AppDeps,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    // Enforce the principal's monthly budget before doing any work
+    let usage = match deps.usage.clone() {
+        Some(ledger) => {
+            let Some(api_key) = api_key else {
+                return Ok(error_response(
+                    StatusCode::UNAUTHORIZED,
+                    &format!("Missing {} header", API_KEY_HEADER),
+                ));
+            };
+            let key_id = key_id(&api_key);
+
+            let summary = match ledger.summary(&key_id).await {
+                Ok(summary) => summary,
+                Err(e) => {
+                    tracing::error!(%key_id, error = %e, "failed to read usage");
+                    return Ok(error_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Failed to read usage",
+                    ));
+                }
+            };
+            if summary.is_over_budget() {
+                println!("POST /threads/{}: {} is over budget", thread_id, key_id);
+                let retry_after = summary.seconds_until_reset(Utc::now());
+                let body = serde_json::json!({
+                    "error": "Monthly token budget exceeded",
+                    "usage": summary,
+                });
+                return Ok(warp::reply::with_header(
+                    warp::reply::with_status(
+                        warp::reply::json(&body),
+                        StatusCode::TOO_MANY_REQUESTS,
+                    ),
+                    "Retry-After",
+                    retry_after.to_string(),
+                )
+                .into_response());
+            }
+
+            Some((ledger, key_id))
+        }
+        None => None,
+    };
+
+    // Screen the input before any run starts
+    match deps.moderator.check_input(&request.text).await {
+        ModerationDecision::Allow => {}
+        ModerationDecision::Redact(replacement) => request.text = replacement,
+        ModerationDecision::Block(reason) => {
+            println!("POST /threads/{}: blocked by moderation: {}", thread_id, reason);
+            return Ok(error_response(StatusCode::UNPROCESSABLE_ENTITY, &reason));
+        }
+    }
+
     println!("POST /threads/{}: {}", thread_id, request.text);
 
-    // Create SSE event stream
-    let event_stream = create_event_stream();
+    let Some(provider) = deps.provider.clone() else {
+        // No model configured: stream the scripted mock run
+        let event_stream = create_event_stream(
+            thread_id,
+            &request.text,
+            request.webhook(),
+            usage,
+            deps.message_db.clone(),
+        );
+        return Ok(warp::sse::reply(warp::sse::keep_alive().stream(event_stream)).into_response());
+    };
+
+    let history = match &deps.message_db {
+        Some(client) => match load_history(client, thread_id).await {
+            Ok(history) => history,
+            Err(e) => {
+                tracing::error!(%thread_id, error = %e, "failed to load thread");
+                return Ok(error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to load thread",
+                ));
+            }
+        },
+        None => Vec::new(),
+    };
+
+    let (tools, executor): (_, Box<dyn ToolExecutor>) = match &deps.functions {
+        Some(functions) => (functions.get_declarations(), Box::new(functions.clone())),
+        None => (Vec::new(), Box::new(FunctionRegistry::new())),
+    };
+    let model = provider.name().to_string();
+    let agent = Agent::new(
+        Box::new(provider),
+        executor,
+        tools,
+        deps.generation_config.clone(),
+        deps.system_prompt.clone(),
+    )
+    .with_moderator(deps.moderator.clone())
+    .with_history(history);
+    let agent = match agent {
+        Ok(agent) => agent,
+        Err(e) => {
+            tracing::error!(%thread_id, error = %e, "thread has an invalid history");
+            return Ok(error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Thread history is invalid",
+            ));
+        }
+    };
 
-    Ok(warp::sse::reply(
-        warp::sse::keep_alive().stream(event_stream),
-    ))
+    // The run gets its own task, so it finishes and is saved even if the
+    // client disconnects, and so the SSE stream is Sync
+    let (tx, rx) = mpsc::channel(64);
+    let run = AgentRun {
+        thread_id,
+        input: request.text.clone(),
+        model,
+        message_db: deps.message_db.clone(),
+        webhook: request.webhook(),
+        usage,
+    };
+    tokio::spawn(run.drive(agent, tx));
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(ReceiverStream::new(rx))).into_response())
+}
+
+/// Conversation messages stored on the thread stream, oldest first
+async fn load_history(
+    client: &MessageDbClient,
+    thread_id: Uuid,
+) -> Result<Vec<LlmMessage>, String> {
+    let options = StreamReadOptions::new(thread_stream_name(thread_id));
+    let events = client
+        .get_stream_messages(options)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    events
+        .iter()
+        .filter(|event| event.message_type == MESSAGE_APPENDED)
+        .map(|event| decode(event).map_err(|e| e.reason))
+        .collect()
 }
 
-fn create_event_stream() -> impl futures_util::Stream<Item = Result<Event, Infallible>> {
+/// A message being answered by an agent
+struct AgentRun {
+    thread_id: Uuid,
+    input: String,
+    model: String,
+    message_db: Option<MessageDbClient>,
+    webhook: Option<WebhookRegistration>,
+    usage: Option<(UsageLedger, String)>,
+}
+
+impl AgentRun {
+    /// Run `agent`, sending its events to `tx` as SSE events
+    ///
+    /// Once the run ends, new messages are appended to the thread, usage is
+    /// recorded and the webhook is notified, and then `done` is sent.
+    async fn drive(self, mut agent: Agent, tx: mpsc::Sender<Result<Event, Infallible>>) {
+        let run_id = Uuid::new_v4();
+        let history_len = agent.messages().len();
+        let mut message_id = Uuid::new_v4().to_string();
+        let mut final_text = String::new();
+        let mut total_usage: Option<UsageMetadata> = None;
+        let mut failure: Option<String> = None;
+
+        match agent.run(&self.input).await {
+            Ok(mut events) => {
+                while let Some(event) = events.next().await {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(e) => {
+                            failure = Some(e.to_string());
+                            break;
+                        }
+                    };
+                    let sse = match event {
+                        AgentEvent::IterationStarted { .. } => {
+                            message_id = Uuid::new_v4().to_string();
+                            final_text.clear();
+                            None
+                        }
+                        AgentEvent::LlmEvent(StreamEvent::MessageStart { message }) => {
+                            message_id = message.id;
+                            None
+                        }
+                        AgentEvent::LlmEvent(StreamEvent::ContentDelta {
+                            delta: ContentDelta::TextDelta { text },
+                            ..
+                        }) => {
+                            final_text.push_str(&text);
+                            Some(create_agent_text_event(message_id.clone(), text))
+                        }
+                        AgentEvent::ToolExecutionStarted {
+                            tool_use_id,
+                            name,
+                            input,
+                        } => Some(create_tool_call_event(tool_use_id, name, input)),
+                        AgentEvent::ToolExecutionCompleted {
+                            tool_use_id, result, ..
+                        } => Some(create_tool_response_event(
+                            Uuid::new_v4().to_string(),
+                            tool_use_id,
+                            serde_json::from_str(&result)
+                                .unwrap_or(serde_json::Value::String(result)),
+                        )),
+                        AgentEvent::ToolExecutionFailed {
+                            tool_use_id, error, ..
+                        } => Some(create_tool_response_event(
+                            Uuid::new_v4().to_string(),
+                            tool_use_id,
+                            serde_json::json!({ "error": error }),
+                        )),
+                        AgentEvent::Waiting { elapsed_ms } => Some(create_waiting_event(elapsed_ms)),
+                        AgentEvent::OutputBudgetExhausted { .. } => Some(
+                            create_agent_text_discarded_event(message_id.clone(), "output_budget_exhausted"),
+                        ),
+                        AgentEvent::LocaleRetryRequested { .. } => Some(
+                            create_agent_text_discarded_event(message_id.clone(), "locale_retry"),
+                        ),
+                        AgentEvent::JsonRepairRequested { .. } => Some(
+                            create_agent_text_discarded_event(message_id.clone(), "json_repair"),
+                        ),
+                        AgentEvent::OutputRedacted { text } => {
+                            final_text = text.clone();
+                            Some(create_agent_text_redacted_event(message_id.clone(), text))
+                        }
+                        AgentEvent::Completed {
+                            total_usage: usage, ..
+                        } => {
+                            total_usage = Some(usage);
+                            None
+                        }
+                        _ => None,
+                    };
+                    // A closed channel means the client left; the run still finishes
+                    if let Some(sse) = sse {
+                        let _ = tx.send(sse).await;
+                    }
+                }
+            }
+            Err(e) => failure = Some(e.to_string()),
+        }
+
+        if let Some(client) = &self.message_db {
+            for message in &agent.messages()[history_len..] {
+                if let Err(e) = append_message(client, self.thread_id, message).await {
+                    tracing::error!(thread_id = %self.thread_id, error = %e, "failed to save message");
+                    break;
+                }
+            }
+        }
+
+        if let (Some((ledger, key_id)), Some(usage)) = (self.usage, total_usage) {
+            let recorded = UsageRecorded::new(run_id, &self.model, &usage);
+            if let Err(e) = ledger.record(&key_id, &recorded).await {
+                tracing::warn!(%run_id, error = %e, "failed to record usage");
+            }
+        }
+
+        if let Some(registration) = self.webhook {
+            let payload = WebhookPayload {
+                run_id,
+                thread_id: self.thread_id,
+                status: if failure.is_some() {
+                    RunStatus::Failed
+                } else {
+                    RunStatus::Completed
+                },
+                final_text: failure.is_none().then_some(final_text),
+                usage: total_usage,
+            };
+            let dispatcher = webhook_dispatcher(self.message_db);
+            tokio::spawn(async move {
+                dispatcher.deliver(&registration, &payload).await;
+            });
+        }
+
+        if let Some(error) = failure {
+            tracing::warn!(%run_id, thread_id = %self.thread_id, %error, "run failed");
+            let _ = tx.send(create_error_event(error)).await;
+        }
+        let _ = tx.send(create_done_event()).await;
+    }
+}
+
+/// Dispatcher for run webhooks, recording attempts on the thread when Message DB is configured
+fn webhook_dispatcher(message_db: Option<MessageDbClient>) -> WebhookDispatcher {
+    match message_db {
+        Some(client) => WebhookDispatcher::new().with_audit_log(client),
+        None => WebhookDispatcher::new(),
+    }
+}
+
+/// Rough token count (~4 characters per token) for the mock run
+fn estimate_tokens(text: &str) -> u32 {
+    (text.chars().count() as u32).div_ceil(4)
+}
+
+fn create_event_stream(
+    thread_id: Uuid,
+    input: &str,
+    mut webhook: Option<WebhookRegistration>,
+    mut usage: Option<(UsageLedger, String)>,
+    mut message_db: Option<MessageDbClient>,
+) -> impl futures_util::Stream<Item = Result<Event, Infallible>> {
     // Create an interval that ticks every 500ms
     let interval = interval(Duration::from_millis(500));
     let stream = IntervalStream::new(interval);
@@ -50,39 +365,375 @@ fn create_event_stream() -> impl futures_util::Stream<Item = Result<Event, Infal
         EventType::Done,
     ];
 
+    // Final text is the last agent message, which the webhook reports
+    let final_text: String = events
+        .iter()
+        .filter_map(|event| match event {
+            EventType::AgentText(id, text) if id == "msg-2" => Some(text.as_str()),
+            _ => None,
+        })
+        .collect();
+    let input_tokens = estimate_tokens(input);
+
     // Use enumerate to track which event we're on
     stream
         .take(events.len())
         .enumerate()
-        .map(move |(i, _tick)| {
-            let event = &events[i];
-            match event {
-                EventType::AgentText(id, text) => create_agent_text_event(id.clone(), text.clone()),
-                EventType::ToolCall => create_tool_call_event(
-                    "tool-call-456".to_string(),
-                    "weather_lookup".to_string(),
-                    serde_json::json!({
-                        "location": "San Francisco",
-                        "units": "fahrenheit"
-                    }),
-                ),
-                EventType::ToolResponse => create_tool_response_event(
-                    "response-789".to_string(),
-                    "tool-call-456".to_string(),
-                    serde_json::json!({
-                        "temperature": 72,
-                        "condition": "sunny",
-                        "humidity": 65
-                    }),
-                ),
-                EventType::Done => create_done_event(),
+        .then(move |(i, _tick)| {
+            let event = events[i].clone();
+            let done = matches!(event, EventType::Done);
+            let webhook = if done { webhook.take() } else { None };
+            let usage = if done { usage.take() } else { None };
+            let message_db = if done { message_db.take() } else { None };
+            let final_text = final_text.clone();
+
+            async move {
+                match event {
+                    EventType::AgentText(id, text) => create_agent_text_event(id, text),
+                    EventType::ToolCall => create_tool_call_event(
+                        "tool-call-456".to_string(),
+                        "weather_lookup".to_string(),
+                        serde_json::json!({
+                            "location": "San Francisco",
+                            "units": "fahrenheit"
+                        }),
+                    ),
+                    EventType::ToolResponse => create_tool_response_event(
+                        "response-789".to_string(),
+                        "tool-call-456".to_string(),
+                        serde_json::json!({
+                            "temperature": 72,
+                            "condition": "sunny",
+                            "humidity": 65
+                        }),
+                    ),
+                    EventType::Done => {
+                        let run_id = Uuid::new_v4();
+
+                        // Recorded before `done` is sent, so a client that saw the
+                        // end of the stream also sees the run in GET /usage. The
+                        // write runs on its own task because SSE streams must be Sync.
+                        if let Some((ledger, key_id)) = usage {
+                            let metadata =
+                                UsageMetadata::new(input_tokens, estimate_tokens(&final_text));
+                            let recorded = UsageRecorded::new(run_id, MOCK_MODEL, &metadata);
+                            let write = tokio::spawn(async move {
+                                ledger.record(&key_id, &recorded).await
+                            });
+                            if let Ok(Err(e)) = write.await {
+                                tracing::warn!(%run_id, error = %e, "failed to record usage");
+                            }
+                        }
+
+                        if let Some(registration) = webhook {
+                            let payload = WebhookPayload {
+                                run_id,
+                                thread_id,
+                                status: RunStatus::Completed,
+                                final_text: Some(final_text),
+                                usage: None,
+                            };
+                            let dispatcher = webhook_dispatcher(message_db);
+                            tokio::spawn(async move {
+                                dispatcher.deliver(&registration, &payload).await;
+                            });
+                        }
+                        create_done_event()
+                    }
+                }
             }
         })
 }
 
+#[derive(Clone)]
 enum EventType {
     AgentText(String, String), // (id, text)
     ToolCall,
     ToolResponse,
     Done,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::core::types::{ContentBlockStart, PartialToolUse};
+    use crate::llm::moderation::{KeywordModerator, Moderator, NoopModerator};
+    use crate::llm::{FinishReason, GenerateRequest, GenerationConfig, LlmError, ToolDeclaration};
+    use crate::routes::configure_routes;
+    use async_trait::async_trait;
+    use futures_util::Stream;
+    use std::net::SocketAddr;
+    use std::pin::Pin;
+    use std::sync::Arc;
+
+    async fn spawn_server(moderator: Arc<dyn Moderator>) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let routes = configure_routes(AppDeps::new().with_moderator(moderator));
+        tokio::spawn(warp::serve(routes).incoming(listener).run());
+        addr
+    }
+
+    async fn post_message(addr: SocketAddr, text: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "http://{}/api/v1/threads/550e8400-e29b-41d4-a716-446655440000",
+                addr
+            ))
+            .json(&serde_json::json!({ "text": text }))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_blocked_input_returns_422() {
+        let addr = spawn_server(Arc::new(KeywordModerator::new(["badword"]))).await;
+
+        let response = post_message(addr, "say a BADWORD").await;
+
+        assert_eq!(response.status().as_u16(), 422);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "Message contains blocked term 'badword'");
+    }
+
+    #[tokio::test]
+    async fn test_allowed_input_starts_stream() {
+        let addr = spawn_server(Arc::new(NoopModerator)).await;
+
+        let response = post_message(addr, "Hello").await;
+
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+    }
+
+    #[tokio::test]
+    async fn test_usage_endpoint_disabled_without_ledger() {
+        let addr = spawn_server(Arc::new(NoopModerator)).await;
+
+        let response = reqwest::get(format!("http://{}/api/v1/usage", addr))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), 404);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "Usage tracking is not enabled");
+    }
+
+    /// Provider that replays one scripted response per call
+    struct ScriptedProvider {
+        responses: std::sync::Mutex<Vec<Vec<StreamEvent>>>,
+    }
+
+    impl ScriptedProvider {
+        fn new(mut responses: Vec<Vec<StreamEvent>>) -> Self {
+            responses.reverse();
+            Self {
+                responses: std::sync::Mutex::new(responses),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LlmProvider for ScriptedProvider {
+        async fn stream_generate(
+            &self,
+            _request: GenerateRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>
+        {
+            let events = self
+                .responses
+                .lock()
+                .unwrap()
+                .pop()
+                .ok_or_else(|| LlmError::StreamError("No more responses".to_string()))?;
+            Ok(Box::pin(futures_util::stream::iter(events.into_iter().map(Ok))))
+        }
+    }
+
+    fn text_response(text: &str) -> Vec<StreamEvent> {
+        vec![
+            StreamEvent::ContentDelta {
+                index: 0,
+                delta: ContentDelta::TextDelta {
+                    text: text.to_string(),
+                },
+            },
+            StreamEvent::MessageEnd {
+                finish_reason: FinishReason::EndTurn,
+                usage: UsageMetadata::new(10, 5),
+            },
+        ]
+    }
+
+    fn tool_use_response(id: &str, name: &str, input: serde_json::Value) -> Vec<StreamEvent> {
+        vec![
+            StreamEvent::ContentBlockStart {
+                index: 0,
+                block: ContentBlockStart::ToolUse {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                },
+            },
+            StreamEvent::ContentDelta {
+                index: 0,
+                delta: ContentDelta::ToolUseDelta {
+                    partial: PartialToolUse {
+                        id: None,
+                        name: None,
+                        partial_json: input.to_string(),
+                    },
+                },
+            },
+            StreamEvent::ContentBlockEnd { index: 0 },
+            StreamEvent::MessageEnd {
+                finish_reason: FinishReason::ToolUse,
+                usage: UsageMetadata::new(10, 5),
+            },
+        ]
+    }
+
+    async fn spawn_server_with(deps: AppDeps) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(warp::serve(configure_routes(deps)).incoming(listener).run());
+        addr
+    }
+
+    /// `(event, data)` pairs from an SSE body
+    fn sse_events(body: &str) -> Vec<(String, serde_json::Value)> {
+        body.split("\n\n")
+            .filter_map(|frame| {
+                let mut event = None;
+                let mut data = None;
+                for line in frame.lines() {
+                    if let Some(name) = line.strip_prefix("event:") {
+                        event = Some(name.trim().to_string());
+                    } else if let Some(payload) = line.strip_prefix("data:") {
+                        data = serde_json::from_str(payload.trim()).ok();
+                    }
+                }
+                Some((event?, data?))
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_provider_text_is_streamed_as_agent_text() {
+        let provider = ScriptedProvider::new(vec![text_response("Hi there")]);
+        let addr = spawn_server_with(AppDeps::new().with_provider(Arc::new(provider))).await;
+
+        let response = post_message(addr, "Hello").await;
+        assert_eq!(response.status().as_u16(), 200);
+        let events = sse_events(&response.text().await.unwrap());
+
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["agent_text", "done"]);
+        assert_eq!(events[0].1["chunk"], "Hi there");
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_are_streamed_with_their_responses() {
+        let mut functions = FunctionRegistry::new();
+        functions
+            .register_sync_tool(
+                |args: serde_json::Value| {
+                    Ok(serde_json::json!({ "sum": args["a"].as_i64().unwrap() + args["b"].as_i64().unwrap() }))
+                },
+                ToolDeclaration {
+                    name: "add".to_string(),
+                    description: "Add two numbers".to_string(),
+                    input_schema: serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            "a": {"type": "integer"},
+                            "b": {"type": "integer"}
+                        }
+                    }),
+                },
+            )
+            .unwrap();
+        let provider = ScriptedProvider::new(vec![
+            tool_use_response("tool-1", "add", serde_json::json!({ "a": 2, "b": 3 })),
+            text_response("2 + 3 = 5"),
+        ]);
+        let deps = AppDeps::new()
+            .with_provider(Arc::new(provider))
+            .with_functions(Arc::new(functions));
+        let addr = spawn_server_with(deps).await;
+
+        let response = post_message(addr, "What is 2 + 3?").await;
+        let events = sse_events(&response.text().await.unwrap());
+
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["tool_call", "tool_response", "agent_text", "done"]);
+        assert_eq!(events[0].1["tool_name"], "add");
+        assert_eq!(events[1].1["tool_call_id"], "tool-1");
+        assert_eq!(events[1].1["result"]["sum"], 5);
+        assert_eq!(events[2].1["chunk"], "2 + 3 = 5");
+    }
+
+    #[tokio::test]
+    async fn test_repaired_answer_discards_the_invalid_text() {
+        let provider = ScriptedProvider::new(vec![
+            text_response("Sunny"),
+            text_response(r#"{"forecast": "sunny"}"#),
+        ]);
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"forecast": {"type": "string"}},
+            "required": ["forecast"]
+        });
+        let deps = AppDeps::new()
+            .with_provider(Arc::new(provider))
+            .with_generation_config(GenerationConfig::new(1024).with_response_schema(schema));
+        let addr = spawn_server_with(deps).await;
+
+        let response = post_message(addr, "Weather?").await;
+        let events = sse_events(&response.text().await.unwrap());
+
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["agent_text", "agent_text_discarded", "agent_text", "done"]
+        );
+        assert_eq!(events[1].1["id"], events[0].1["id"]);
+        assert_eq!(events[1].1["reason"], "json_repair");
+    }
+
+    #[tokio::test]
+    async fn test_redacted_output_replaces_the_streamed_text() {
+        let provider = ScriptedProvider::new(vec![text_response("The secret is 42")]);
+        let deps = AppDeps::new()
+            .with_provider(Arc::new(provider))
+            .with_moderator(Arc::new(KeywordModerator::new(["secret"])));
+        let addr = spawn_server_with(deps).await;
+
+        let response = post_message(addr, "Tell me something").await;
+        let events = sse_events(&response.text().await.unwrap());
+
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["agent_text", "agent_text_redacted", "done"]);
+        assert_eq!(events[1].1["id"], events[0].1["id"]);
+        assert_eq!(events[1].1["text"], "The [redacted] is 42");
+    }
+
+    #[tokio::test]
+    async fn test_provider_failure_sends_error_before_done() {
+        let provider = ScriptedProvider::new(vec![]);
+        let addr = spawn_server_with(AppDeps::new().with_provider(Arc::new(provider))).await;
+
+        let response = post_message(addr, "Hello").await;
+        let events = sse_events(&response.text().await.unwrap());
+
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["error", "done"]);
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(super::estimate_tokens(""), 0);
+        assert_eq!(super::estimate_tokens("Hello"), 2);
+        assert_eq!(super::estimate_tokens("abcd"), 1);
+    }
+}
@@ -0,0 +1,355 @@
+// POST /threads/{threadId}/messages/stream-input handler
+
+use crate::deps::AppDeps;
+use crate::handlers::error_response;
+use crate::handlers::send_message_handler;
+use crate::models::SendMessageRequest;
+use bytes::Buf;
+use futures_util::{pin_mut, Stream, StreamExt};
+use std::io::Write;
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::Reply;
+
+/// Largest message accepted by the stream-input route, after decoding (1 MiB)
+pub const MAX_STREAM_INPUT_BYTES: usize = 1024 * 1024;
+
+/// Why a streamed message body was rejected
+#[derive(Debug, PartialEq)]
+enum InputError {
+    /// The decoded body isn't valid UTF-8 at this byte offset
+    InvalidUtf8 { offset: usize },
+    /// The decoded body is larger than the limit
+    TooLarge { limit: usize },
+    /// The gzip stream is corrupt or truncated
+    Gzip(String),
+}
+
+impl InputError {
+    fn into_response(self) -> warp::reply::Response {
+        match self {
+            InputError::InvalidUtf8 { offset } => {
+                let body = serde_json::json!({
+                    "error": format!("Message is not valid UTF-8 at byte {}", offset),
+                    "offset": offset,
+                });
+                warp::reply::with_status(warp::reply::json(&body), StatusCode::BAD_REQUEST)
+                    .into_response()
+            }
+            InputError::TooLarge { limit } => error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                &format!("Message is larger than {} bytes", limit),
+            ),
+            InputError::Gzip(e) => error_response(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid gzip body: {}", e),
+            ),
+        }
+    }
+}
+
+/// Builds a message from UTF-8 chunks, validating each chunk as it arrives
+///
+/// A multi-byte character split across chunks is held back until the rest
+/// of it arrives.
+struct Utf8Assembler {
+    text: String,
+    /// Start of an incomplete character at the end of the last chunk
+    pending: Vec<u8>,
+    limit: usize,
+}
+
+impl Utf8Assembler {
+    fn new(limit: usize) -> Self {
+        Self {
+            text: String::new(),
+            pending: Vec::new(),
+            limit,
+        }
+    }
+
+    /// Bytes accepted so far, including any held-back partial character
+    fn len(&self) -> usize {
+        self.text.len() + self.pending.len()
+    }
+
+    fn push(&mut self, chunk: &[u8]) -> Result<(), InputError> {
+        if self.len() + chunk.len() > self.limit {
+            return Err(InputError::TooLarge { limit: self.limit });
+        }
+
+        let mut bytes = std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(chunk);
+
+        match std::str::from_utf8(&bytes) {
+            Ok(text) => self.text.push_str(text),
+            Err(e) => {
+                let valid = e.valid_up_to();
+                if e.error_len().is_some() {
+                    return Err(InputError::InvalidUtf8 {
+                        offset: self.text.len() + valid,
+                    });
+                }
+                // Only the end is incomplete; wait for the next chunk
+                let text = std::str::from_utf8(&bytes[..valid]).expect("validated prefix");
+                self.text.push_str(text);
+                self.pending = bytes[valid..].to_vec();
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<String, InputError> {
+        if self.pending.is_empty() {
+            Ok(self.text)
+        } else {
+            Err(InputError::InvalidUtf8 {
+                offset: self.text.len(),
+            })
+        }
+    }
+}
+
+/// Writer that feeds decompressed bytes to a `Utf8Assembler`
+///
+/// Keeps the first validation error, since `GzDecoder` only reports I/O errors.
+struct AssemblerWriter {
+    assembler: Utf8Assembler,
+    error: Option<InputError>,
+}
+
+impl Write for AssemblerWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Err(e) = self.assembler.push(buf) {
+            self.error = Some(e);
+            return Err(std::io::Error::other("invalid message body"));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The body's decoding, chosen from its `Content-Encoding`
+enum Decoder {
+    Identity(Utf8Assembler),
+    Gzip(Box<flate2::write::GzDecoder<AssemblerWriter>>),
+}
+
+impl Decoder {
+    fn push(&mut self, chunk: &[u8]) -> Result<(), InputError> {
+        match self {
+            Decoder::Identity(assembler) => assembler.push(chunk),
+            Decoder::Gzip(decoder) => match decoder.write_all(chunk) {
+                Ok(()) => Ok(()),
+                Err(e) => Err(decoder
+                    .get_mut()
+                    .error
+                    .take()
+                    .unwrap_or(InputError::Gzip(e.to_string()))),
+            },
+        }
+    }
+
+    fn finish(self) -> Result<String, InputError> {
+        match self {
+            Decoder::Identity(assembler) => assembler.finish(),
+            Decoder::Gzip(decoder) => match decoder.finish() {
+                Ok(writer) => writer.assembler.finish(),
+                Err(e) => Err(InputError::Gzip(e.to_string())),
+            },
+        }
+    }
+}
+
+/// Accept a long message as a streamed `text/plain` body
+///
+/// The body may be gzip-compressed (`Content-Encoding: gzip`). Once it has
+/// been read in full, the request is handled exactly like `POST /threads/{id}`
+/// and the response is the same SSE stream.
+pub async fn stream_input_handler<S, B>(
+    thread_id: Uuid,
+    content_type: Option<String>,
+    content_encoding: Option<String>,
+    body: S,
+    api_key: Option<String>,
+    deps: AppDeps,
+) -> Result<warp::reply::Response, warp::Rejection>
+where
+    S: Stream<Item = Result<B, warp::Error>>,
+    B: Buf,
+{
+    let is_text = content_type
+        .as_deref()
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("text/plain"));
+    if !is_text {
+        return Ok(error_response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Expected a text/plain body",
+        ));
+    }
+
+    let assembler = Utf8Assembler::new(MAX_STREAM_INPUT_BYTES);
+    let mut decoder = match content_encoding.as_deref().map(str::trim) {
+        None | Some("identity") => Decoder::Identity(assembler),
+        Some(encoding) if encoding.eq_ignore_ascii_case("gzip") => {
+            Decoder::Gzip(Box::new(flate2::write::GzDecoder::new(AssemblerWriter {
+                assembler,
+                error: None,
+            })))
+        }
+        Some(other) => {
+            return Ok(error_response(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                &format!("Unsupported Content-Encoding '{}'", other),
+            ))
+        }
+    };
+
+    pin_mut!(body);
+    while let Some(chunk) = body.next().await {
+        let mut chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                tracing::warn!(%thread_id, error = %e, "failed to read streamed request body");
+                return Ok(error_response(
+                    StatusCode::BAD_REQUEST,
+                    "Failed to read request body",
+                ));
+            }
+        };
+        while chunk.has_remaining() {
+            let bytes = chunk.chunk();
+            let len = bytes.len();
+            if let Err(e) = decoder.push(bytes) {
+                return Ok(e.into_response());
+            }
+            chunk.advance(len);
+        }
+    }
+
+    let text = match decoder.finish() {
+        Ok(text) => text,
+        Err(e) => return Ok(e.into_response()),
+    };
+
+    let request = SendMessageRequest {
+        text,
+        callback_url: None,
+        callback_secret: None,
+    };
+    send_message_handler(thread_id, request, api_key, deps).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::moderation::{KeywordModerator, Moderator, NoopModerator};
+    use crate::routes::configure_routes;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    async fn spawn_server(moderator: Arc<dyn Moderator>) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let routes = configure_routes(AppDeps::new().with_moderator(moderator));
+        tokio::spawn(warp::serve(routes).incoming(listener).run());
+        addr
+    }
+
+    /// Send `chunks` as a chunked body
+    async fn post_chunks(
+        addr: SocketAddr,
+        chunks: Vec<Vec<u8>>,
+        encoding: Option<&str>,
+    ) -> reqwest::Response {
+        let body = futures_util::stream::iter(chunks.into_iter().map(Ok::<_, std::io::Error>));
+        let mut request = reqwest::Client::new()
+            .post(format!(
+                "http://{}/api/v1/threads/550e8400-e29b-41d4-a716-446655440000/messages/stream-input",
+                addr
+            ))
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(reqwest::Body::wrap_stream(body));
+        if let Some(encoding) = encoding {
+            request = request.header("content-encoding", encoding);
+        }
+        request.send().await.unwrap()
+    }
+
+    fn gzip(text: &str) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_gzip_body_is_decoded_and_screened() {
+        let addr = spawn_server(Arc::new(KeywordModerator::new(["badword"]))).await;
+
+        // The blocked term is at the very end of a long paste
+        let log = format!("{}say a BADWORD", "INFO request ok\n".repeat(10_000));
+        let compressed = gzip(&log);
+        let chunks = compressed.chunks(1000).map(<[u8]>::to_vec).collect();
+
+        let response = post_chunks(addr, chunks, Some("gzip")).await;
+
+        assert_eq!(response.status().as_u16(), 422);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "Message contains blocked term 'badword'");
+    }
+
+    #[tokio::test]
+    async fn test_chunked_body_starts_stream() {
+        let addr = spawn_server(Arc::new(NoopModerator)).await;
+
+        // "é" is split across chunks
+        let chunks = vec![b"caf\xc3".to_vec(), b"\xa9 logs".to_vec()];
+        let response = post_chunks(addr, chunks, None).await;
+
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_utf8_returns_400_with_offset() {
+        let addr = spawn_server(Arc::new(NoopModerator)).await;
+
+        let chunks = vec![b"hello ".to_vec(), b"wor\xffld".to_vec()];
+        let response = post_chunks(addr, chunks, None).await;
+
+        assert_eq!(response.status().as_u16(), 400);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["offset"], 9);
+        assert_eq!(body["error"], "Message is not valid UTF-8 at byte 9");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_non_text_bodies() {
+        let addr = spawn_server(Arc::new(NoopModerator)).await;
+
+        let response = post_chunks(addr, vec![b"hi".to_vec()], Some("br")).await;
+        assert_eq!(response.status().as_u16(), 415);
+    }
+
+    #[test]
+    fn test_assembler_limits_and_truncation() {
+        let mut assembler = Utf8Assembler::new(8);
+        assembler.push("ab".as_bytes()).unwrap();
+        assert_eq!(
+            assembler.push(b"cdefghi"),
+            Err(InputError::TooLarge { limit: 8 })
+        );
+
+        // Ends partway through a character
+        let mut assembler = Utf8Assembler::new(8);
+        assembler.push(b"ab\xe2\x82").unwrap();
+        assert_eq!(
+            assembler.finish(),
+            Err(InputError::InvalidUtf8 { offset: 2 })
+        );
+    }
+}
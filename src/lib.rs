@@ -1,8 +1,12 @@
 // HTTP Server modules
+pub mod deps;
 pub mod handlers;
 pub mod models;
 pub mod routes;
 pub mod sse;
+pub mod thread_events;
+pub mod usage;
+pub mod webhooks;
 
 // Message DB client library
 pub mod message_db;
@@ -0,0 +1,159 @@
+//! Estimating how many prompt tokens each tool's results cost
+//!
+//! Providers only report usage per LLM call, so a tool's share is estimated
+//! from how the prompt grows between iterations:
+//!
+//! 1. The results of iteration `n`'s tool calls first appear in the prompt
+//!    of iteration `n + 1`. That prompt's growth over iteration `n`'s prompt,
+//!    minus iteration `n`'s output tokens (the assistant turn that is now part
+//!    of the history), is taken as the size of those results in tokens.
+//! 2. Those tokens are split between the tools by their share of the result
+//!    characters.
+//! 3. Every later LLM call resends the whole history, so each tool is charged
+//!    its estimated size again for every call its results are part of.
+//!
+//! Prompt size counts cached tokens too, so prompt caching doesn't move the
+//! attribution. Only results added during the run are attributed; tool
+//! results already in the history passed to `with_history` are not. The
+//! totals are estimates, but the same usage numbers always give the same
+//! attribution.
+
+use crate::llm::core::types::UsageMetadata;
+use std::collections::HashMap;
+
+/// Running per-tool attribution for one agent run
+#[derive(Debug, Default)]
+pub(crate) struct ToolTokenAttribution {
+    /// Result characters per tool added since the last LLM call
+    pending: Vec<(String, usize)>,
+    /// Estimated tokens per tool already in the history
+    carried: HashMap<String, f64>,
+    /// Tokens charged to each tool so far
+    totals: HashMap<String, f64>,
+    /// The previous LLM call's usage
+    previous: Option<UsageMetadata>,
+}
+
+impl ToolTokenAttribution {
+    /// Record a tool result (or error) added to the history
+    pub(crate) fn record_result(&mut self, name: &str, content: &str) {
+        self.pending
+            .push((name.to_string(), content.chars().count()));
+    }
+
+    /// Record the usage of an LLM call, charging the tools whose results it carried
+    pub(crate) fn record_usage(&mut self, usage: &UsageMetadata) {
+        let pending = std::mem::take(&mut self.pending);
+        let pending_chars: usize = pending.iter().map(|(_, chars)| chars).sum();
+
+        if let (Some(previous), true) = (self.previous, pending_chars > 0) {
+            let growth = prompt_tokens(usage) as f64
+                - prompt_tokens(&previous) as f64
+                - previous.output_tokens as f64;
+            let results_tokens = growth.max(0.0);
+            for (name, chars) in pending {
+                let share = results_tokens * chars as f64 / pending_chars as f64;
+                *self.carried.entry(name).or_default() += share;
+            }
+        }
+
+        for (name, tokens) in &self.carried {
+            *self.totals.entry(name.clone()).or_default() += tokens;
+        }
+        self.previous = Some(*usage);
+    }
+
+    /// Tokens attributed to each tool, rounded to whole tokens
+    pub(crate) fn totals(&self) -> HashMap<String, u32> {
+        self.totals
+            .iter()
+            .map(|(name, tokens)| (name.clone(), tokens.round() as u32))
+            .collect()
+    }
+}
+
+/// Everything the provider read for a call, cached or not
+fn prompt_tokens(usage: &UsageMetadata) -> u32 {
+    usage.input_tokens + usage.cache_creation_input_tokens + usage.cache_read_input_tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_results_are_split_by_characters() {
+        let mut attribution = ToolTokenAttribution::default();
+        attribution.record_usage(&UsageMetadata::new(100, 20));
+
+        // 300 characters of results, then the prompt grows by 20 + 120 tokens
+        attribution.record_result("search", &"x".repeat(200));
+        attribution.record_result("weather", &"x".repeat(100));
+        attribution.record_usage(&UsageMetadata::new(240, 10));
+
+        let totals = attribution.totals();
+        assert_eq!(totals["search"], 80);
+        assert_eq!(totals["weather"], 40);
+    }
+
+    #[test]
+    fn test_results_are_charged_on_every_later_call() {
+        let mut attribution = ToolTokenAttribution::default();
+        attribution.record_usage(&UsageMetadata::new(100, 20));
+
+        attribution.record_result("search", "result");
+        attribution.record_usage(&UsageMetadata::new(170, 30));
+
+        // Second call adds 25 tokens of weather results on top of the search ones
+        attribution.record_result("weather", "result");
+        attribution.record_usage(&UsageMetadata::new(225, 5));
+
+        let totals = attribution.totals();
+        assert_eq!(totals["search"], 100);
+        assert_eq!(totals["weather"], 25);
+    }
+
+    #[test]
+    fn test_calls_without_new_results_still_charge_carried_results() {
+        let mut attribution = ToolTokenAttribution::default();
+        attribution.record_usage(&UsageMetadata::new(100, 20));
+        attribution.record_result("search", "result");
+        attribution.record_usage(&UsageMetadata::new(150, 40));
+
+        // e.g. a JSON repair turn: no tool results, but the history is resent
+        attribution.record_usage(&UsageMetadata::new(200, 10));
+
+        assert_eq!(attribution.totals()["search"], 60);
+    }
+
+    #[test]
+    fn test_cached_prompt_tokens_count_toward_growth() {
+        let mut attribution = ToolTokenAttribution::default();
+        attribution.record_usage(&UsageMetadata::new(100, 20));
+        attribution.record_result("search", "result");
+
+        let mut cached = UsageMetadata::new(40, 10);
+        cached.cache_read_input_tokens = 100;
+        attribution.record_usage(&cached);
+
+        assert_eq!(attribution.totals()["search"], 20);
+    }
+
+    #[test]
+    fn test_shrinking_prompt_attributes_nothing() {
+        let mut attribution = ToolTokenAttribution::default();
+        attribution.record_usage(&UsageMetadata::new(100, 20));
+        attribution.record_result("search", "result");
+        attribution.record_usage(&UsageMetadata::new(90, 10));
+
+        assert_eq!(attribution.totals()["search"], 0);
+    }
+
+    #[test]
+    fn test_no_tools_no_attribution() {
+        let mut attribution = ToolTokenAttribution::default();
+        attribution.record_usage(&UsageMetadata::new(100, 20));
+
+        assert!(attribution.totals().is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+//! Citation keys linking the final answer back to tool results
+
+/// Instructions appended to the system prompt when citations are enabled
+pub(crate) const CITATION_INSTRUCTIONS: &str = "Each tool result starts with a citation key such as [T1]. \
+When your answer uses information from a tool result, cite it by writing its key in square brackets \
+right after the statement, e.g. \"It is 18°C in Paris [T1].\" Only cite keys you were given.";
+
+/// A tool result referenced by the final answer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Citation {
+    /// Key as written in the answer, without brackets (e.g. `T1`)
+    pub key: String,
+    /// ID of the tool call that produced the cited result
+    pub tool_use_id: String,
+    /// Name of the tool that was called
+    pub name: String,
+}
+
+/// Key for the `n`th tool result of a conversation (1-based)
+pub(crate) fn citation_key(n: usize) -> String {
+    format!("T{}", n)
+}
+
+/// Tool result content with its citation key preamble
+pub(crate) fn cited_content(key: &str, result: &str) -> String {
+    format!("[{}]\n{}", key, result)
+}
+
+/// Citation keys referenced in `text`, in order of first appearance
+///
+/// Matches `[T<digits>]`. Markers inside fenced code blocks or inline code
+/// spans are ignored, since those are usually literal output rather than
+/// citations.
+pub(crate) fn extract_citation_keys(text: &str) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut in_fence = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+
+        for key in keys_in_line(line) {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+
+    keys
+}
+
+/// Citation markers in a single line, skipping inline code spans
+fn keys_in_line(line: &str) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut in_code = false;
+    let mut rest = line;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '`' {
+            in_code = !in_code;
+            rest = &rest[1..];
+            continue;
+        }
+
+        if !in_code && c == '[' {
+            if let Some(key) = marker_at(rest) {
+                rest = &rest[key.len() + 2..];
+                keys.push(key);
+                continue;
+            }
+        }
+
+        rest = &rest[c.len_utf8()..];
+    }
+
+    keys
+}
+
+/// The key of a `[T<digits>]` marker at the start of `text`, if there is one
+fn marker_at(text: &str) -> Option<String> {
+    let inner = text.strip_prefix("[T")?;
+    let digits = inner.len() - inner.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 || !inner[digits..].starts_with(']') {
+        return None;
+    }
+    Some(format!("T{}", &inner[..digits]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extracts_keys_in_order_without_duplicates() {
+        let text = "Paris is 18°C [T2] and London 12°C [T1]. Both are mild [T2][T3].";
+        assert_eq!(extract_citation_keys(text), vec!["T2", "T1", "T3"]);
+    }
+
+    #[test]
+    fn test_ignores_keys_in_code() {
+        let text = "See [T1].\n\n```\nlet x = results[T2];\n```\n\nAlso `[T3]` but [T4]\n~~~\n[T5]\n~~~";
+        assert_eq!(extract_citation_keys(text), vec!["T1", "T4"]);
+    }
+
+    #[test]
+    fn test_ignores_malformed_markers() {
+        let text = "[T] [t1] [T1a] [ T2] [T3 ] T4] [Ω] [T５]";
+        assert!(extract_citation_keys(text).is_empty());
+    }
+
+    #[test]
+    fn test_cited_content() {
+        assert_eq!(cited_content(&citation_key(3), "{\"ok\":true}"), "[T3]\n{\"ok\":true}");
+    }
+}
@@ -0,0 +1,391 @@
+//! Redacted export of an agent's state for bug reports
+//!
+//! A [`DebugBundle`] holds everything needed to replay a conversation
+//! locally: the history, system prompt, tool schemas, generation config and
+//! the summary of the last run. Text that may hold secrets goes through a
+//! [`Redactor`] before it leaves the agent.
+
+use super::{Agent, AgentError};
+use crate::llm::core::{
+    config::GenerationConfig,
+    provider::LlmProvider,
+    types::{ContentBlock, ImageData, Message, ToolDeclaration},
+};
+use crate::llm::tools::executor::ToolExecutor;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Removes secrets from text before it is exported
+///
+/// Any `Fn(&str) -> String` is a redactor.
+///
+/// # Example
+///
+/// ```
+/// use rust2::llm::agent::Redactor;
+///
+/// let redactor = |text: &str| text.replace("sk-live-123", "[REDACTED]");
+/// assert_eq!(redactor.redact("key=sk-live-123"), "key=[REDACTED]");
+/// ```
+pub trait Redactor: Send + Sync {
+    /// Redacted copy of `text`
+    fn redact(&self, text: &str) -> String;
+
+    /// Redacted copy of a JSON value
+    ///
+    /// Defaults to redacting every string in the value; object keys are kept.
+    fn redact_value(&self, value: &Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.redact(text)),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|item| self.redact_value(item)).collect())
+            }
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(key, value)| (key.clone(), self.redact_value(value)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+impl<F> Redactor for F
+where
+    F: Fn(&str) -> String + Send + Sync,
+{
+    fn redact(&self, text: &str) -> String {
+        self(text)
+    }
+}
+
+/// A tool as recorded in a bundle; descriptions are left out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolSchema {
+    /// Function name
+    pub name: String,
+    /// JSON Schema for parameters
+    pub input_schema: Value,
+}
+
+/// Redacted snapshot of an agent, serializable to a single JSON file
+///
+/// See [`Agent::export_debug_bundle`] and [`Agent::import_debug_bundle`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DebugBundle {
+    /// Version of this crate that wrote the bundle
+    pub crate_version: String,
+    /// Name of the primary provider (the model, for the built-in clients)
+    pub provider: String,
+    /// Name of the fallback provider, if one was set
+    pub fallback_provider: Option<String>,
+    /// System prompt, redacted
+    pub system: Option<String>,
+    /// System prompt template, redacted; see [`Agent::with_system_template`]
+    #[serde(default)]
+    pub system_template: Option<String>,
+    /// Values for the template's placeholders, redacted
+    #[serde(default)]
+    pub template_vars: HashMap<String, String>,
+    /// Conversation history, redacted
+    pub messages: Vec<Message>,
+    /// Tools offered to the model
+    pub tools: Vec<ToolSchema>,
+    /// Generation parameters
+    pub config: GenerationConfig,
+    /// Compact summary of the last run, redacted
+    pub last_run: Option<Value>,
+}
+
+impl Agent {
+    /// Export the conversation and settings for a bug report
+    ///
+    /// Message text, tool inputs and results, image URLs, the system prompt,
+    /// its template and variables, and the last run summary are passed
+    /// through `redactor`. Inline image
+    /// data is dropped, leaving an empty string.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let bundle = agent.export_debug_bundle(&|text: &str| scrub_secrets(text));
+    /// std::fs::write("bundle.json", serde_json::to_string_pretty(&bundle)?)?;
+    /// ```
+    pub fn export_debug_bundle(&self, redactor: &dyn Redactor) -> DebugBundle {
+        DebugBundle {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            provider: self.provider.name().to_string(),
+            fallback_provider: self
+                .fallback_provider
+                .as_ref()
+                .map(|provider| provider.name().to_string()),
+            system: self.system.as_deref().map(|system| redactor.redact(system)),
+            system_template: self
+                .system_template
+                .as_deref()
+                .map(|template| redactor.redact(template)),
+            template_vars: self
+                .template_vars
+                .iter()
+                .map(|(name, value)| (name.clone(), redactor.redact(value)))
+                .collect(),
+            messages: self
+                .messages
+                .iter()
+                .map(|message| redact_message(message, redactor))
+                .collect(),
+            tools: self
+                .tool_declarations
+                .iter()
+                .map(|tool| ToolSchema {
+                    name: tool.name.clone(),
+                    input_schema: tool.input_schema.clone(),
+                })
+                .collect(),
+            config: self.config.clone(),
+            last_run: self
+                .last_run
+                .as_ref()
+                .map(|summary| redactor.redact_value(&summary.to_compact_json())),
+        }
+    }
+
+    /// Rebuild an agent from a bundle, for reproducing a report locally
+    ///
+    /// History, system prompt and template, tools and config come from the bundle;
+    /// `provider` and `tool_executor` are usually mocks replaying the
+    /// reported responses. Tool descriptions aren't exported, so the rebuilt
+    /// declarations have empty descriptions.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AgentError::InvalidTranscript` if the bundle's history has
+    /// unmatched tool uses or results.
+    pub fn import_debug_bundle(
+        bundle: DebugBundle,
+        provider: Box<dyn LlmProvider>,
+        tool_executor: Box<dyn ToolExecutor>,
+    ) -> Result<Self, AgentError> {
+        let tools = bundle
+            .tools
+            .into_iter()
+            .map(|tool| ToolDeclaration {
+                name: tool.name,
+                description: String::new(),
+                input_schema: tool.input_schema,
+            })
+            .collect();
+
+        let mut agent = Agent::new(provider, tool_executor, tools, bundle.config, bundle.system);
+        agent.system_template = bundle.system_template;
+        agent.template_vars = bundle.template_vars;
+        agent.with_history(bundle.messages)
+    }
+}
+
+fn redact_message(message: &Message, redactor: &dyn Redactor) -> Message {
+    let content = message
+        .content
+        .iter()
+        .map(|block| match block {
+            ContentBlock::Text { text } => ContentBlock::Text {
+                text: redactor.redact(text),
+            },
+            ContentBlock::ToolUse { id, name, input } => ContentBlock::ToolUse {
+                id: id.clone(),
+                name: name.clone(),
+                input: redactor.redact_value(input),
+            },
+            ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+                is_error,
+            } => ContentBlock::ToolResult {
+                tool_use_id: tool_use_id.clone(),
+                content: redactor.redact(content),
+                is_error: *is_error,
+            },
+            ContentBlock::Image { media_type, data } => ContentBlock::Image {
+                media_type: media_type.clone(),
+                data: match data {
+                    ImageData::Base64(_) => ImageData::Base64(String::new()),
+                    ImageData::Url(url) => ImageData::Url(redactor.redact(url)),
+                },
+            },
+            // Encrypted by the provider, so there is nothing readable to redact
+            ContentBlock::RedactedThinking { data } => ContentBlock::RedactedThinking {
+                data: data.clone(),
+            },
+        })
+        .collect();
+
+    Message {
+        role: message.role,
+        content,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::core::error::LlmError;
+    use crate::llm::core::types::{GenerateRequest, MessageRole, StreamEvent};
+    use async_trait::async_trait;
+    use futures::stream::Stream;
+    use serde_json::json;
+    use std::pin::Pin;
+
+    struct NamedProvider(&'static str);
+
+    #[async_trait]
+    impl LlmProvider for NamedProvider {
+        async fn stream_generate(
+            &self,
+            _request: GenerateRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>
+        {
+            Err(LlmError::StreamError("replay has no responses".to_string()))
+        }
+
+        fn name(&self) -> &str {
+            self.0
+        }
+    }
+
+    struct NoTools;
+
+    #[async_trait]
+    impl ToolExecutor for NoTools {
+        async fn execute(
+            &self,
+            _tool_use_id: String,
+            _name: String,
+            _arguments: Value,
+        ) -> Result<String, String> {
+            Err("no tools".to_string())
+        }
+    }
+
+    fn redact_key(text: &str) -> String {
+        text.replace("sk-secret", "[REDACTED]")
+    }
+
+    fn agent() -> Agent {
+        let tools = vec![ToolDeclaration {
+            name: "lookup".to_string(),
+            description: "Look up an account".to_string(),
+            input_schema: json!({"type": "object", "properties": {"key": {"type": "string"}}}),
+        }];
+        let history = vec![
+            Message::user("My key is sk-secret, why is my account locked?"),
+            Message {
+                role: MessageRole::Assistant,
+                content: vec![ContentBlock::ToolUse {
+                    id: "toolu_1".to_string(),
+                    name: "lookup".to_string(),
+                    input: json!({"key": "sk-secret", "verbose": true}),
+                }],
+            },
+            Message::tool_result("toolu_1", "account sk-secret is locked"),
+            Message::assistant("It was locked after three failed logins."),
+        ];
+
+        Agent::new(
+            Box::new(NamedProvider("claude-sonnet-4-5")),
+            Box::new(NoTools),
+            tools,
+            GenerationConfig::new(1024).with_temperature(0.2),
+            Some("Support agent. Internal token sk-secret.".to_string()),
+        )
+        .with_history(history)
+        .unwrap()
+    }
+
+    #[test]
+    fn test_export_redacts_text_and_tool_inputs() {
+        let bundle = agent().export_debug_bundle(&redact_key);
+
+        let json = serde_json::to_string(&bundle).unwrap();
+        assert!(!json.contains("sk-secret"), "{}", json);
+
+        assert_eq!(bundle.provider, "claude-sonnet-4-5");
+        assert_eq!(bundle.crate_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            bundle.system.as_deref(),
+            Some("Support agent. Internal token [REDACTED].")
+        );
+        assert_eq!(
+            bundle.messages[1].content[0],
+            ContentBlock::ToolUse {
+                id: "toolu_1".to_string(),
+                name: "lookup".to_string(),
+                input: json!({"key": "[REDACTED]", "verbose": true}),
+            }
+        );
+        assert_eq!(
+            bundle.tools,
+            vec![ToolSchema {
+                name: "lookup".to_string(),
+                input_schema: json!({"type": "object", "properties": {"key": {"type": "string"}}}),
+            }]
+        );
+        assert!(!json.contains("Look up an account"));
+    }
+
+    #[test]
+    fn test_bundle_round_trips_into_agent() {
+        let bundle = agent().export_debug_bundle(&redact_key);
+        let json = serde_json::to_string(&bundle).unwrap();
+        let restored: DebugBundle = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, bundle);
+
+        let replay = Agent::import_debug_bundle(
+            restored,
+            Box::new(NamedProvider("replay")),
+            Box::new(NoTools),
+        )
+        .unwrap();
+
+        assert_eq!(replay.messages(), bundle.messages.as_slice());
+        assert_eq!(replay.config, bundle.config);
+        assert_eq!(replay.system, bundle.system);
+        assert_eq!(replay.system_template, bundle.system_template);
+        assert_eq!(replay.tool_declarations.len(), 1);
+        assert_eq!(replay.tool_declarations[0].name, "lookup");
+    }
+
+    #[test]
+    fn test_bundle_keeps_system_template() {
+        let mut agent = Agent::new(
+            Box::new(NamedProvider("claude-sonnet-4-5")),
+            Box::new(NoTools),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_system_template("Helping {{user}} with key {{key}}.".to_string());
+        agent.set_template_vars(HashMap::from([
+            ("user".to_string(), "Ada".to_string()),
+            ("key".to_string(), "sk-secret".to_string()),
+        ]));
+
+        let bundle = agent.export_debug_bundle(&redact_key);
+        assert_eq!(bundle.system, None);
+        assert_eq!(
+            bundle.system_template.as_deref(),
+            Some("Helping {{user}} with key {{key}}.")
+        );
+        assert_eq!(bundle.template_vars["key"], "[REDACTED]");
+
+        let replay = Agent::import_debug_bundle(
+            bundle.clone(),
+            Box::new(NamedProvider("replay")),
+            Box::new(NoTools),
+        )
+        .unwrap();
+        assert_eq!(replay.system_template, bundle.system_template);
+        assert_eq!(replay.template_vars, bundle.template_vars);
+    }
+}
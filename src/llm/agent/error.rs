@@ -1,4 +1,7 @@
-use crate::llm::core::error::LlmError;
+use crate::llm::core::{
+    error::LlmError,
+    types::{MessageRole, TranscriptError},
+};
 
 /// Errors that can occur during agent execution
 #[derive(Debug, thiserror::Error)]
@@ -11,11 +14,43 @@ pub enum AgentError {
     #[error("Failed to parse tool input: {0}")]
     ToolInputParse(#[from] serde_json::Error),
 
+    /// Conversation history has unbalanced tool uses/results
+    #[error("Invalid transcript: {0}")]
+    InvalidTranscript(#[from] TranscriptError),
+
+    /// A run was started with a message the model can't reply to
+    #[error("A run must start with a user or tool message, not {0:?}")]
+    InvalidRunMessage(MessageRole),
+
+    /// A fork was requested past the end of the conversation history
+    #[error("Cannot fork at message {index}: history has {len} message(s)")]
+    ForkOutOfRange { index: usize, len: usize },
+
+    /// The system prompt template uses a variable that isn't set
+    #[error("System prompt template variable not set: {0}")]
+    TemplateVar(String),
+
     /// LLM stream ended unexpectedly
     #[error("Stream ended unexpectedly")]
     UnexpectedStreamEnd,
 
+    /// Final response still didn't match the response schema after all repair attempts
+    #[error("Response failed schema validation after {attempts} repair attempt(s): {}", errors.join("; "))]
+    JsonValidation {
+        attempts: usize,
+        errors: Vec<String>,
+        output: String,
+    },
+
+    /// The moderator blocked the final response
+    #[error("Response blocked by moderation: {0}")]
+    OutputBlocked(String),
+
     /// Maximum iterations reached without completion
     #[error("Maximum iterations reached ({0})")]
     MaxIterationsReached(usize),
+
+    /// Tokens used by the run's LLM calls went over the configured budget
+    #[error("Token budget exceeded: used {used} of {budget} tokens")]
+    TokenBudgetExceeded { used: u32, budget: u32 },
 }
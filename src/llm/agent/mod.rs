@@ -7,24 +7,47 @@
 //! - Loops until getting a text-only response
 //! - Returns a stream of events throughout the entire loop
 
+mod attribution;
+mod citations;
+mod debug_bundle;
 mod error;
+mod summary;
+mod template;
 
+pub use citations::Citation;
+pub use debug_bundle::{DebugBundle, Redactor, ToolSchema};
 pub use error::AgentError;
+pub use summary::{AgentRunResult, AgentRunSummary, SummaryLimits, ToolCallSummary};
 
+use attribution::ToolTokenAttribution;
+use citations::{citation_key, cited_content, extract_citation_keys, CITATION_INSTRUCTIONS};
+use template::render_template;
 use crate::llm::core::{
     config::GenerationConfig,
+    error::LlmError,
     provider::LlmProvider,
+    schema::validate_json,
     types::{
-        ContentBlock, ContentBlockStart, ContentDelta, GenerateRequest, Message, MessageRole,
-        StreamEvent, ToolDeclaration,
+        ContentBlock, ContentBlockStart, ContentDelta, FinishReason, GenerateRequest, Message,
+        MessageMetadata, MessageRole, StreamEvent, ToolDeclaration, UsageMetadata,
     },
 };
+use crate::llm::core::locale::{matches_locale, strong_locale_instruction};
+use crate::llm::moderation::{ModerationDecision, Moderator};
 use crate::llm::tools::executor::ToolExecutor;
 use async_stream::stream;
-use futures::stream::Stream;
+use futures::stream::{FuturesUnordered, Stream};
 use futures::StreamExt;
 use pin_utils::pin_mut;
+use std::collections::HashMap;
+use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::Interval;
+use tokio_util::sync::CancellationToken;
+use tracing::field::Empty;
+use tracing::Instrument;
 
 /// Events emitted by the agent during execution
 #[derive(Debug, Clone)]
@@ -54,10 +77,248 @@ pub enum AgentEvent {
     },
 
     /// Agent is starting a new iteration (calling LLM again after tool execution)
-    IterationStarted { iteration: usize },
+    ///
+    /// `max_tokens` is the output budget sent with this iteration's request;
+    /// see [`Agent::with_max_output_tokens_per_iteration`].
+    IterationStarted { iteration: usize, max_tokens: u32 },
+
+    /// The response ran into the per-iteration output cap without calling a
+    /// tool, so it was dropped and will be requested again with the full
+    /// `GenerationConfig::max_tokens` budget
+    ///
+    /// Text streamed during `iteration` should be discarded by consumers.
+    OutputBudgetExhausted { iteration: usize, max_tokens: u32 },
+
+    /// The final answer didn't look written in the agent's locale, so it was
+    /// dropped and will be requested again with a firmer instruction
+    ///
+    /// Only emitted with [`Agent::with_locale_retry`]. Text streamed during
+    /// `iteration` should be discarded by consumers.
+    LocaleRetryRequested { iteration: usize, locale: String },
+
+    /// Older messages were left out of this iteration's request to keep it
+    /// within the context budget; see [`Agent::with_context_budget`]
+    ///
+    /// `dropped` holds their indices in the history, which itself is kept.
+    ContextTrimmed {
+        iteration: usize,
+        dropped: Vec<usize>,
+        input_tokens: u32,
+    },
+
+    /// Heartbeat while waiting for the first event of an iteration
+    ///
+    /// Only emitted when a heartbeat interval is configured. These are purely
+    /// informational and never added to conversation history.
+    Waiting { elapsed_ms: u64 },
+
+    /// Snapshot of the assistant message built so far in this iteration
+    ///
+    /// Contains the accumulated text plus every tool use whose input has been
+    /// fully received, in the same block order as the final message. Only
+    /// emitted when partial messages are enabled; see
+    /// [`Agent::with_partial_message_deltas`] and
+    /// [`Agent::with_partial_message_interval`].
+    PartialMessage(Message),
+
+    /// An assistant message was appended to the conversation history
+    ///
+    /// Carries the message exactly as stored, so for the final answer it
+    /// reflects any [`Agent::with_output_transform`] applied after streaming.
+    AssistantMessageComplete(Message),
+
+    /// The moderator redacted the final answer; `text` is what was stored
+    ///
+    /// The streamed deltas carried the original text, so consumers should
+    /// replace what they displayed with `text`.
+    OutputRedacted { text: String },
+
+    /// The final response didn't match `GenerationConfig::response_schema`,
+    /// so the model is being asked to correct it
+    JsonRepairRequested { attempt: usize, errors: Vec<String> },
+
+    /// The primary provider failed before producing any output, so the
+    /// request is being retried against the fallback provider
+    ProviderFallback {
+        from: String,
+        to: String,
+        reason: String,
+    },
+
+    /// Token usage after an LLM response finished
+    ///
+    /// `iteration_usage` is the response's own usage and `cumulative_usage`
+    /// the total across every LLM call of this run so far, including
+    /// responses that were retried or repaired. A response that fails, is
+    /// cancelled or ends without a final usage report counts with the last
+    /// usage its provider reported mid-stream, if any.
+    UsageUpdated {
+        iteration_usage: UsageMetadata,
+        cumulative_usage: UsageMetadata,
+    },
 
     /// Agent loop completed (final response with no tool calls)
-    Completed,
+    ///
+    /// `total_usage` is the run's token usage across all LLM calls,
+    /// `iterations` how many LLM iterations the run took, and
+    /// `tool_token_attribution` estimates how many of those tokens each
+    /// tool's results cost, by tool name. The estimate is derived from how
+    /// the prompt grows between iterations and counts a result again for
+    /// every later call that resends it.
+    ///
+    /// When citations are enabled (see [`Agent::with_citations`]),
+    /// `citations` lists the tool results the final answer cites, in order
+    /// of first mention, and `unresolved_citations` holds any cited keys that
+    /// don't match a tool result. Both are empty otherwise.
+    Completed {
+        total_usage: UsageMetadata,
+        iterations: usize,
+        tool_token_attribution: HashMap<String, u32>,
+        citations: Vec<Citation>,
+        unresolved_citations: Vec<String>,
+    },
+
+    /// A side-effecting tool call from the failed run was compensated
+    ///
+    /// Emitted just before the run's terminal error, newest call first,
+    /// for every successful call whose tool registered a compensation.
+    /// `ok` is false if the compensation itself failed.
+    CompensationExecuted { name: String, ok: bool },
+
+    /// The run was cancelled through its `CancellationToken`; no more events follow
+    ///
+    /// History is left consistent: a partially streamed response is not
+    /// stored, and every tool call in history has a result (tools that were
+    /// cut short or never started get an error result).
+    Cancelled,
+}
+
+/// Error recorded for tool calls interrupted or skipped by cancellation
+const TOOL_CANCELLED_ERROR: &str = "Tool execution was cancelled";
+
+/// Outcome of waiting on a future with an optional heartbeat
+enum Waited<T> {
+    Ready(T),
+    Heartbeat,
+    Cancelled,
+}
+
+/// Await `fut`, returning early with `Waited::Heartbeat` if the heartbeat ticks
+/// first or `Waited::Cancelled` once `cancel` fires
+async fn wait_or_heartbeat<F>(
+    fut: F,
+    heartbeat: Option<&mut Interval>,
+    cancel: &CancellationToken,
+) -> Waited<F::Output>
+where
+    F: Future + Unpin,
+{
+    match heartbeat {
+        None => tokio::select! {
+            biased;
+            _ = cancel.cancelled() => Waited::Cancelled,
+            output = fut => Waited::Ready(output),
+        },
+        Some(heartbeat) => tokio::select! {
+            biased;
+            _ = cancel.cancelled() => Waited::Cancelled,
+            output = fut => Waited::Ready(output),
+            _ = heartbeat.tick() => Waited::Heartbeat,
+        },
+    }
+}
+
+/// Build an assistant message from redacted thinking, accumulated text and completed tool uses
+///
+/// Claude expects thinking blocks before the rest of the turn.
+fn assistant_message(thinking: &[ContentBlock], text: &str, tool_uses: &[ContentBlock]) -> Message {
+    let mut content = thinking.to_vec();
+    if !text.is_empty() {
+        content.push(ContentBlock::Text {
+            text: text.to_string(),
+        });
+    }
+    content.extend(tool_uses.iter().cloned());
+
+    Message {
+        role: MessageRole::Assistant,
+        content,
+    }
+}
+
+/// Parse the final text as JSON, tolerating a surrounding Markdown code fence
+fn parse_json_output(text: &str) -> Result<serde_json::Value, serde_json::Error> {
+    let trimmed = text.trim();
+    let unfenced = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(trimmed);
+    serde_json::from_str(unfenced.trim())
+}
+
+/// Follow-up prompt asking the model to fix output that failed validation
+fn json_repair_prompt(output: &str, errors: &[String]) -> String {
+    format!(
+        "Your previous response did not match the required JSON schema.\n\n\
+         Validation errors:\n- {}\n\n\
+         Previous response:\n{}\n\n\
+         Reply with only the corrected JSON.",
+        errors.join("\n- "),
+        output
+    )
+}
+
+/// Rewrites the final answer's text; see [`Agent::with_output_transform`]
+pub type OutputTransform = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Text delta injected into the stream by the output prefix/suffix
+fn injected_text(index: usize, text: &str) -> StreamEvent {
+    StreamEvent::ContentDelta {
+        index,
+        delta: ContentDelta::TextDelta {
+            text: text.to_string(),
+        },
+    }
+}
+
+/// `ToolExecutionCompleted` or `ToolExecutionFailed` for a finished tool call
+fn tool_outcome_event(id: &str, name: &str, outcome: &Result<String, String>) -> AgentEvent {
+    match outcome {
+        Ok(result) => AgentEvent::ToolExecutionCompleted {
+            tool_use_id: id.to_string(),
+            name: name.to_string(),
+            result: result.clone(),
+        },
+        Err(error) => AgentEvent::ToolExecutionFailed {
+            tool_use_id: id.to_string(),
+            name: name.to_string(),
+            error: error.clone(),
+        },
+    }
+}
+
+/// `system` with the firmer instruction for retrying an answer given in the wrong language
+fn strong_locale_system_prompt(system: Option<String>, locale: &str) -> String {
+    match system {
+        Some(system) => format!("{}\n\n{}", system, strong_locale_instruction(locale)),
+        None => strong_locale_instruction(locale),
+    }
+}
+
+/// Whether `messages` holds the result of tool call `tool_use_id`
+fn has_tool_result(messages: &[Message], tool_use_id: &str) -> bool {
+    messages.iter().flat_map(|message| &message.content).any(|block| {
+        matches!(block, ContentBlock::ToolResult { tool_use_id: id, .. } if id == tool_use_id)
+    })
+}
+
+/// A successful call to a side-effecting tool during the current run
+struct SideEffect {
+    tool_use_id: String,
+    name: String,
+    input: serde_json::Value,
+    result: String,
 }
 
 /// Helper struct for accumulating partial tool use data
@@ -69,11 +330,14 @@ struct PartialToolUseAccumulator {
 
 /// Simple agent that manages conversation history and tool execution
 pub struct Agent {
-    /// LLM provider (Claude or Gemini)
-    provider: Box<dyn LlmProvider>,
+    /// LLM provider (Claude or Gemini), shared with forks
+    provider: Arc<dyn LlmProvider>,
 
-    /// Tool executor for handling function calls
-    tool_executor: Box<dyn ToolExecutor>,
+    /// Provider used when the primary fails before streaming anything (optional)
+    fallback_provider: Option<Arc<dyn LlmProvider>>,
+
+    /// Tool executor for handling function calls, shared with forks
+    tool_executor: Arc<dyn ToolExecutor>,
 
     /// Tool declarations available to the LLM
     tool_declarations: Vec<ToolDeclaration>,
@@ -87,8 +351,71 @@ pub struct Agent {
     /// System prompt (optional)
     system: Option<String>,
 
+    /// Template rendered into `system` at the start of each iteration (optional)
+    system_template: Option<String>,
+
+    /// Values for the `{{var}}` placeholders in `system_template`
+    template_vars: HashMap<String, String>,
+
     /// Maximum number of agent loop iterations (default: 10)
     max_iterations: usize,
+
+    /// Maximum total tokens across a run's LLM calls (default: unlimited)
+    token_budget: Option<u32>,
+
+    /// Input tokens each request's history is trimmed to fit (default: off)
+    context_budget: Option<u32>,
+
+    /// Interval for `Waiting` heartbeats before the first token (default: off)
+    heartbeat_interval: Option<Duration>,
+
+    /// Follow-up requests allowed to fix schema-invalid JSON output (default: 1)
+    max_json_repairs: usize,
+
+    /// Emit a `PartialMessage` after this many content deltas (default: off)
+    partial_message_deltas: Option<usize>,
+
+    /// Emit a `PartialMessage` once this much time has passed since the last one (default: off)
+    partial_message_interval: Option<Duration>,
+
+    /// Rewrites the final answer before it is stored (optional)
+    output_transform: Option<OutputTransform>,
+
+    /// Text streamed before the first text of each response (optional)
+    output_prefix: Option<String>,
+
+    /// Text streamed after the final answer (optional)
+    output_suffix: Option<String>,
+
+    /// Checks the final answer before it is stored (optional)
+    moderator: Option<Arc<dyn Moderator>>,
+
+    /// Run the tool calls of one response concurrently (default: off)
+    parallel_tool_execution: bool,
+
+    /// Longest a single tool call may run before it fails (default: unlimited)
+    tool_timeout: Option<Duration>,
+
+    /// Output token cap for iterations that may still call tools (default: off)
+    max_output_tokens_per_iteration: Option<u32>,
+
+    /// Tag tool results with citation keys and resolve them in the answer (default: off)
+    citations_enabled: bool,
+
+    /// Citation keys assigned to tool results so far in this conversation
+    cited_results: Vec<Citation>,
+
+    /// Locale every answer must be written in, as a BCP-47 tag (optional)
+    locale: Option<String>,
+
+    /// Retry once when the answer isn't in the locale's script (default: off)
+    locale_retry: bool,
+
+    /// Side-effecting tool calls made by the current run, oldest first
+    side_effects: Vec<SideEffect>,
+
+    /// Summary of the last run whose stream was read to the end
+    last_run: Option<AgentRunSummary>,
 }
 
 impl Agent {
@@ -101,22 +428,279 @@ impl Agent {
         system: Option<String>,
     ) -> Self {
         Self {
-            provider,
-            tool_executor,
+            provider: Arc::from(provider),
+            fallback_provider: None,
+            tool_executor: Arc::from(tool_executor),
             tool_declarations,
             messages: Vec::new(),
             config,
             system,
+            system_template: None,
+            template_vars: HashMap::new(),
             max_iterations: 10,
+            token_budget: None,
+            context_budget: None,
+            heartbeat_interval: None,
+            max_json_repairs: 1,
+            partial_message_deltas: None,
+            partial_message_interval: None,
+            output_transform: None,
+            output_prefix: None,
+            output_suffix: None,
+            moderator: None,
+            parallel_tool_execution: false,
+            tool_timeout: None,
+            max_output_tokens_per_iteration: None,
+            citations_enabled: false,
+            cited_results: Vec::new(),
+            locale: None,
+            locale_retry: false,
+            side_effects: Vec::new(),
+            last_run: None,
         }
     }
 
+    /// Build the system prompt from `template` before every LLM call
+    ///
+    /// `{{name}}` placeholders are replaced with the values set through
+    /// [`Agent::set_template_vars`], so per-request details such as the
+    /// user's name or today's date can change between runs without
+    /// recreating the agent or touching its history. Whitespace inside the
+    /// braces is ignored and `\{{` produces a literal `{{`. The rendered
+    /// prompt replaces the one given to [`Agent::new`].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut agent = Agent::new(provider, executor, tools, config, None)
+    ///     .with_system_template("You are helping {{user}}. Today is {{date}}.".to_string());
+    ///
+    /// agent.set_template_vars(HashMap::from([
+    ///     ("user".to_string(), "Ada".to_string()),
+    ///     ("date".to_string(), "2026-10-17".to_string()),
+    /// ]));
+    /// let result = agent.run_to_completion("What's on my calendar?").await?;
+    /// ```
+    ///
+    /// A run whose template uses a variable that isn't set fails with
+    /// `AgentError::TemplateVar` before its message is added to history.
+    pub fn with_system_template(mut self, template: String) -> Self {
+        self.system_template = Some(template);
+        self
+    }
+
+    /// Replace the values for the system prompt template's placeholders
+    ///
+    /// Takes effect from the next LLM call; see [`Agent::with_system_template`].
+    pub fn set_template_vars(&mut self, vars: HashMap<String, String>) {
+        self.template_vars = vars;
+    }
+
     /// Set the maximum number of iterations (default: 10)
     pub fn with_max_iterations(mut self, max: usize) -> Self {
         self.max_iterations = max;
         self
     }
 
+    /// Stop a run once its LLM calls have used more than `max_total_tokens`
+    ///
+    /// Each `MessageEnd` adds its `total_tokens` to a running total for the
+    /// current `run`. When the total goes over the budget, the stream ends
+    /// with `AgentError::TokenBudgetExceeded` and the response that crossed
+    /// it is not added to history.
+    pub fn with_token_budget(mut self, max_total_tokens: u32) -> Self {
+        self.token_budget = Some(max_total_tokens);
+        self
+    }
+
+    /// Keep each request's input within `max_input_tokens`
+    ///
+    /// Before every LLM call the request is trimmed with
+    /// [`GenerateRequest::trim_to_fit`], dropping the oldest exchanges and
+    /// emitting `AgentEvent::ContextTrimmed` when anything was dropped. Only
+    /// the request is trimmed; the agent's history keeps every message. The
+    /// guard does nothing when the provider can't count tokens.
+    pub fn with_context_budget(mut self, max_input_tokens: u32) -> Self {
+        self.context_budget = Some(max_input_tokens);
+        self
+    }
+
+    /// Retry against `fallback` when the primary provider fails
+    ///
+    /// Applies per LLM call: if the primary cannot establish a stream, or its
+    /// stream errors before yielding any event, the same request is sent to
+    /// the fallback and `AgentEvent::ProviderFallback` is emitted. Errors after
+    /// the primary has started streaming are returned as usual, since part of
+    /// its response has already been forwarded. The next iteration tries the
+    /// primary again.
+    pub fn with_fallback_provider(mut self, fallback: Box<dyn LlmProvider>) -> Self {
+        self.fallback_provider = Some(Arc::from(fallback));
+        self
+    }
+
+    /// Emit `Waiting` heartbeats at this interval until the first LLM event arrives
+    ///
+    /// Useful for showing a typing indicator while a large prompt is processed.
+    pub fn with_heartbeat_interval(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = Some(interval);
+        self
+    }
+
+    /// Set how many repair requests are sent for schema-invalid output (default: 1)
+    ///
+    /// Only applies when `GenerationConfig::response_schema` is set. When the
+    /// final text isn't valid JSON for the schema, the validation errors and
+    /// the original output are sent back as a user message and the model is
+    /// asked to correct it. Repairs count towards `max_iterations`. Once the
+    /// limit is reached the run fails with `AgentError::JsonValidation`.
+    pub fn with_max_json_repairs(mut self, max: usize) -> Self {
+        self.max_json_repairs = max;
+        self
+    }
+
+    /// Emit a `PartialMessage` snapshot after every `deltas` content deltas
+    ///
+    /// Lets callers persist a coherent partial response for resume-on-disconnect.
+    /// Can be combined with [`with_partial_message_interval`](Self::with_partial_message_interval);
+    /// a snapshot is emitted when either threshold is reached.
+    pub fn with_partial_message_deltas(mut self, deltas: usize) -> Self {
+        self.partial_message_deltas = Some(deltas);
+        self
+    }
+
+    /// Emit a `PartialMessage` snapshot on the first delta after `interval` has elapsed
+    pub fn with_partial_message_interval(mut self, interval: Duration) -> Self {
+        self.partial_message_interval = Some(interval);
+        self
+    }
+
+    /// Rewrite the final answer's text before it is added to history
+    ///
+    /// Runs once the final (tool-free) response has finished streaming and,
+    /// when a `response_schema` is set, after it has passed validation. The
+    /// stored message and `AssistantMessageComplete` carry the transformed
+    /// text, but the `LlmEvent` deltas already forwarded do not, so streaming
+    /// consumers should replace their text with the completed message. Text
+    /// that accompanies tool calls and the tool-use inputs are never passed
+    /// through the transform.
+    pub fn with_output_transform<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.output_transform = Some(Arc::new(transform));
+        self
+    }
+
+    /// Stream `prefix` ahead of the model's text
+    ///
+    /// Delta-safe counterpart to [`with_output_transform`](Self::with_output_transform):
+    /// the prefix is emitted as an extra text delta, so streamed content and
+    /// stored history always agree. Whether a response will call tools isn't
+    /// known when its text starts, so the prefix precedes the text of every
+    /// response that has any, including tool-calling turns. Ignored when a
+    /// `response_schema` is set.
+    pub fn with_output_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.output_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Stream `suffix` after the final answer's text
+    ///
+    /// Emitted as an extra text delta just before the final response's
+    /// `MessageEnd`, so it is never appended to tool-calling turns. Ignored
+    /// when a `response_schema` is set.
+    pub fn with_output_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.output_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Run the final answer through `moderator.check_output` before it is stored
+    ///
+    /// Applied after [`with_output_transform`](Self::with_output_transform).
+    /// A `Redact` decision replaces the stored text and emits
+    /// `AgentEvent::OutputRedacted`; a `Block` decision ends the run with
+    /// `AgentError::OutputBlocked` without storing the answer. Input checks
+    /// are left to the caller, since the agent only sees accepted messages.
+    pub fn with_moderator(mut self, moderator: Arc<dyn Moderator>) -> Self {
+        self.moderator = Some(moderator);
+        self
+    }
+
+    /// Choose whether a response's tool calls run concurrently (default: false)
+    ///
+    /// When several tools are called in one response, all
+    /// `ToolExecutionStarted` events are emitted up front and each
+    /// `ToolExecutionCompleted`/`ToolExecutionFailed` follows as that tool
+    /// finishes. Results are added to history in call order either way.
+    /// Leave this off for tools whose side effects must happen in order.
+    pub fn with_parallel_tools(mut self, parallel: bool) -> Self {
+        self.parallel_tool_execution = parallel;
+        self
+    }
+
+    /// Choose whether a response's tool calls run concurrently
+    #[deprecated(note = "use `with_parallel_tools`")]
+    pub fn with_parallel_tool_execution(self, parallel: bool) -> Self {
+        self.with_parallel_tools(parallel)
+    }
+
+    /// Fail any tool call that runs longer than `timeout`
+    ///
+    /// A timed-out call is reported as `ToolExecutionFailed` and its error
+    /// goes into history like any other tool error, so the model can retry
+    /// or work around it. The loop carries on.
+    pub fn with_tool_timeout(mut self, timeout: Duration) -> Self {
+        self.tool_timeout = Some(timeout);
+        self
+    }
+
+    /// Cap the output of intermediate iterations at `max_tokens`
+    ///
+    /// Stops the model from writing long explanations between tool calls.
+    /// Each request is sent with the smaller of `max_tokens` and
+    /// `GenerationConfig::max_tokens`. If a capped response stops at the
+    /// limit without calling a tool, it was most likely the final answer
+    /// being cut short: it is dropped (`AgentEvent::OutputBudgetExhausted`)
+    /// and the next iteration gets the full `GenerationConfig::max_tokens`.
+    pub fn with_max_output_tokens_per_iteration(mut self, max_tokens: u32) -> Self {
+        self.max_output_tokens_per_iteration = Some(max_tokens);
+        self
+    }
+
+    /// Track which tool results the final answer cites
+    ///
+    /// Each successful tool result is stored with a `[T1]`, `[T2]`, ...
+    /// preamble, numbered across the whole conversation, and the system
+    /// prompt asks the model to cite those keys. The final answer is then
+    /// scanned for markers (ignoring code blocks and inline code) and
+    /// `AgentEvent::Completed` reports what they refer to.
+    pub fn with_citations(mut self, enabled: bool) -> Self {
+        self.citations_enabled = enabled;
+        self
+    }
+
+    /// Answer in `locale` (a BCP-47 tag such as `de-DE`) whatever language
+    /// the user writes in (builder pattern)
+    ///
+    /// Sent with each request as `GenerateRequest::locale`, which providers
+    /// turn into an instruction after the system prompt.
+    pub fn with_locale(mut self, locale: impl Into<String>) -> Self {
+        self.locale = Some(locale.into());
+        self
+    }
+
+    /// Retry a final answer given in the wrong language once (builder pattern)
+    ///
+    /// Only applies to locales written in a non-Latin script, which can be
+    /// told apart cheaply (see [`matches_locale`]). A final answer mostly in
+    /// another script is dropped, `AgentEvent::LocaleRetryRequested` is
+    /// emitted and the request is repeated with a firmer instruction. The
+    /// second answer is kept whatever its language.
+    pub fn with_locale_retry(mut self, enabled: bool) -> Self {
+        self.locale_retry = enabled;
+        self
+    }
+
     /// Process a new user message through the agent loop
     ///
     /// This is the main entry point. It:
@@ -136,15 +720,132 @@ impl Agent {
         user_message: impl Into<String>,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<AgentEvent, AgentError>> + Send + '_>>, AgentError>
     {
-        // Add user message to history
-        self.messages.push(Message::user(user_message));
+        self.run_cancellable(user_message, CancellationToken::new()).await
+    }
+
+    /// Like [`Agent::run`], but stops early once `cancel` is cancelled
+    ///
+    /// Cancellation is honoured before each iteration, while waiting on the
+    /// LLM, and before or during tool execution. The stream then yields
+    /// `AgentEvent::Cancelled` and ends. A response still streaming is
+    /// discarded. Tool calls that were already requested get an error result
+    /// in history, so the conversation can be resumed with another `run`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let cancel = CancellationToken::new();
+    /// let mut stream = agent.run_cancellable("Plan my trip", cancel.clone()).await?;
+    /// // Elsewhere, e.g. when the user clicks "Stop":
+    /// cancel.cancel();
+    /// ```
+    pub async fn run_cancellable(
+        &mut self,
+        user_message: impl Into<String>,
+        cancel: CancellationToken,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<AgentEvent, AgentError>> + Send + '_>>, AgentError>
+    {
+        self.run_message_cancellable(Message::user(user_message), cancel)
+            .await
+    }
+
+    /// Like [`Agent::run`], but starts the turn with a pre-built message
+    ///
+    /// Use this for multi-block user messages, or to hand back a tool result
+    /// computed outside the agent. The message must have the `User` or `Tool`
+    /// role.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // The previous run was cancelled before `lookup` finished; supply its result
+    /// let mut stream = agent.run_message(Message::tool_result("toolu_01", result)).await?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `AgentError::InvalidRunMessage` for other roles, an
+    /// `InvalidRequest` error if the config fails
+    /// [`GenerationConfig::validate`], or `AgentError::TemplateVar` for a
+    /// missing template variable. History is left unchanged.
+    pub async fn run_message(
+        &mut self,
+        message: Message,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<AgentEvent, AgentError>> + Send + '_>>, AgentError>
+    {
+        self.run_message_cancellable(message, CancellationToken::new())
+            .await
+    }
+
+    /// [`Agent::run_message`] with a cancellation token, see [`Agent::run_cancellable`]
+    pub async fn run_message_cancellable(
+        &mut self,
+        message: Message,
+        cancel: CancellationToken,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<AgentEvent, AgentError>> + Send + '_>>, AgentError>
+    {
+        if !matches!(message.role, MessageRole::User | MessageRole::Tool) {
+            return Err(AgentError::InvalidRunMessage(message.role));
+        }
+
+        // A bad config or template would fail the first iteration
+        self.config.validate()?;
+        self.system_prompt()?;
+
+        // Add the message to history
+        self.messages.push(message);
 
         // Create the event stream
-        let stream = self.create_agent_stream();
+        let stream = self.create_agent_stream(cancel);
 
         Ok(Box::pin(stream))
     }
 
+    /// Run the agent on `user_message` and wait for the final answer
+    ///
+    /// Drains the stream returned by [`Agent::run`], so intermediate events
+    /// are discarded and only the outcome is kept. Convenient for scripts and
+    /// tests that don't display progress.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let result = agent.run_to_completion("What is 6 * 7?").await?;
+    /// println!("{} ({} tool calls)", result.final_text, result.tool_calls.len());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error the run yields, or
+    /// `AgentError::UnexpectedStreamEnd` if it ends without completing.
+    pub async fn run_to_completion(
+        &mut self,
+        user_message: impl Into<String>,
+    ) -> Result<AgentRunResult, AgentError> {
+        {
+            let mut stream = self.run(user_message).await?;
+            while let Some(event) = stream.next().await {
+                event?;
+            }
+        }
+
+        // The stream stored the run's summary once it ended
+        self.last_run
+            .as_ref()
+            .and_then(AgentRunSummary::result)
+            .ok_or(AgentError::UnexpectedStreamEnd)
+    }
+
+    /// Append a message to the history without running the agent
+    ///
+    /// Useful for seeding a few-shot prefix or restoring a saved
+    /// conversation before the first `run`. Messages are not checked here;
+    /// the next run rejects a transcript with unmatched tool uses or results
+    /// (`AgentError::InvalidTranscript`).
+    pub fn push_message(&mut self, message: Message) {
+        self.messages.push(message);
+    }
+
     /// Get the full conversation history
     pub fn messages(&self) -> &[Message] {
         &self.messages
@@ -153,69 +854,604 @@ impl Agent {
     /// Clear conversation history (start fresh)
     pub fn clear_history(&mut self) {
         self.messages.clear();
+        self.cited_results.clear();
     }
 
-    /// Create the agent event stream
-    fn create_agent_stream(
-        &mut self,
-    ) -> impl Stream<Item = Result<AgentEvent, AgentError>> + '_ {
-        stream! {
-            let mut iteration = 0;
+    /// Start from a saved conversation (builder pattern)
+    ///
+    /// See [`Agent::import_history`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `AgentError::InvalidTranscript` if `messages` has unmatched
+    /// tool uses or results.
+    pub fn with_history(mut self, messages: Vec<Message>) -> Result<Self, AgentError> {
+        self.import_history(messages)?;
+        Ok(self)
+    }
 
-            loop {
-                iteration += 1;
+    /// Copy of the conversation history, for saving it
+    ///
+    /// `Message` implements `Serialize` and `Deserialize`, so the history can
+    /// be stored as JSON and handed back to [`Agent::import_history`] later,
+    /// e.g. after a restart.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let saved = serde_json::to_string(&agent.export_history())?;
+    ///
+    /// // In a new process
+    /// let messages: Vec<Message> = serde_json::from_str(&saved)?;
+    /// agent.import_history(messages)?;
+    /// ```
+    pub fn export_history(&self) -> Vec<Message> {
+        self.messages.clone()
+    }
 
-                // Check max iterations before starting
-                if iteration > self.max_iterations {
-                    yield Err(AgentError::MaxIterationsReached(iteration - 1));
-                    return;
-                }
+    /// Replace the conversation history with `messages`
+    ///
+    /// Citations collected in earlier runs are dropped, as with
+    /// [`Agent::clear_history`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `AgentError::InvalidTranscript` if a tool use has no result
+    /// (for example, a history ending in an assistant tool call) or a result
+    /// has no tool use. History is left unchanged.
+    pub fn import_history(&mut self, messages: Vec<Message>) -> Result<(), AgentError> {
+        Message::validate_transcript(&messages)?;
+        self.messages = messages;
+        self.cited_results.clear();
+        Ok(())
+    }
 
-                // Emit iteration started
-                yield Ok(AgentEvent::IterationStarted { iteration });
+    /// New agent continuing this conversation from its first `message_index` messages
+    ///
+    /// The fork shares this agent's providers and tool executor and copies
+    /// its settings, so the two conversations can then evolve independently,
+    /// e.g. to explore a "what if" branch without disturbing the original.
+    /// Citations for tool results kept in the fork carry over; the last run
+    /// summary does not.
+    ///
+    /// The agent doesn't persist anything itself. To save the fork as a new
+    /// thread that records where it branched off, pass its `messages()` to
+    /// [`thread_events::append_fork`](crate::thread_events::append_fork).
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // Branch just before the second user message and ask something else
+    /// let mut branch = agent.fork_at(2)?;
+    /// let result = branch.run_to_completion("What about Lyon instead?").await?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * `AgentError::ForkOutOfRange` - If `message_index` is past the end of the history
+    /// * `AgentError::InvalidTranscript` - If the cut would leave a tool use
+    ///   without its result
+    pub fn fork_at(&self, message_index: usize) -> Result<Agent, AgentError> {
+        if message_index > self.messages.len() {
+            return Err(AgentError::ForkOutOfRange {
+                index: message_index,
+                len: self.messages.len(),
+            });
+        }
 
-                // Create LLM request
-                let request = GenerateRequest {
-                    messages: self.messages.clone(),
-                    tools: Some(self.tool_declarations.clone()),
-                    config: self.config.clone(),
-                    system: self.system.clone(),
-                };
+        let messages = self.messages[..message_index].to_vec();
+        Message::validate_transcript(&messages)?;
 
-                // Call LLM and get stream
-                let llm_stream = match self.provider.stream_generate(request).await {
-                    Ok(s) => s,
-                    Err(e) => {
-                        yield Err(AgentError::Llm(e));
-                        return;
-                    }
-                };
+        // Keys are assigned in history order, so the kept citations are a prefix
+        let cited_results = self
+            .cited_results
+            .iter()
+            .filter(|citation| has_tool_result(&messages, &citation.tool_use_id))
+            .cloned()
+            .collect();
 
-                // Process LLM stream, forwarding events and accumulating data
-                let mut text_content = String::new();
-                let mut tool_uses = Vec::new();
-                let mut current_tool_use: Option<PartialToolUseAccumulator> = None;
+        Ok(Agent {
+            provider: Arc::clone(&self.provider),
+            fallback_provider: self.fallback_provider.clone(),
+            tool_executor: Arc::clone(&self.tool_executor),
+            tool_declarations: self.tool_declarations.clone(),
+            messages,
+            config: self.config.clone(),
+            system: self.system.clone(),
+            system_template: self.system_template.clone(),
+            template_vars: self.template_vars.clone(),
+            max_iterations: self.max_iterations,
+            token_budget: self.token_budget,
+            context_budget: self.context_budget,
+            heartbeat_interval: self.heartbeat_interval,
+            max_json_repairs: self.max_json_repairs,
+            partial_message_deltas: self.partial_message_deltas,
+            partial_message_interval: self.partial_message_interval,
+            output_transform: self.output_transform.clone(),
+            output_prefix: self.output_prefix.clone(),
+            output_suffix: self.output_suffix.clone(),
+            moderator: self.moderator.clone(),
+            parallel_tool_execution: self.parallel_tool_execution,
+            tool_timeout: self.tool_timeout,
+            max_output_tokens_per_iteration: self.max_output_tokens_per_iteration,
+            citations_enabled: self.citations_enabled,
+            cited_results,
+            locale: self.locale.clone(),
+            locale_retry: self.locale_retry,
+            side_effects: Vec::new(),
+            last_run: None,
+        })
+    }
 
-                pin_mut!(llm_stream);
+    /// Summary of the most recent run
+    ///
+    /// Available once the run's stream has been read to the end; `None`
+    /// before the first run and while a run is in progress.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut stream = agent.run("What's the weather in Paris?").await?;
+    /// while let Some(event) = stream.next().await { /* ... */ }
+    /// drop(stream);
+    ///
+    /// let summary = agent.last_run_summary().unwrap().clone();
+    /// let json = summary.with_limits(SummaryLimits::default()).to_compact_json();
+    /// ```
+    pub fn last_run_summary(&self) -> Option<&AgentRunSummary> {
+        self.last_run.as_ref()
+    }
+
+    /// Token usage of the most recent run, across all its LLM calls
+    ///
+    /// Also set for runs that failed or were cancelled, so their cost can
+    /// still be reported. `None` before the first run finishes.
+    pub fn last_run_usage(&self) -> Option<UsageMetadata> {
+        self.last_run.as_ref().map(|summary| summary.usage)
+    }
+
+    /// System prompt sent with each request, including citation instructions
+    ///
+    /// Renders the system template with the current variables, if one is set.
+    fn system_prompt(&self) -> Result<Option<String>, AgentError> {
+        let system = match &self.system_template {
+            Some(template) => Some(render_template(template, &self.template_vars)?),
+            None => self.system.clone(),
+        };
+
+        Ok(match (system, self.citations_enabled) {
+            (Some(system), true) => Some(format!("{}\n\n{}", system, CITATION_INSTRUCTIONS)),
+            (None, true) => Some(CITATION_INSTRUCTIONS.to_string()),
+            (system, false) => system,
+        })
+    }
+
+    /// Split the keys cited in `text` into known citations and unknown keys
+    fn resolve_citations(&self, text: &str) -> (Vec<Citation>, Vec<String>) {
+        let mut citations = Vec::new();
+        let mut unresolved = Vec::new();
+
+        for key in extract_citation_keys(text) {
+            match self.cited_results.iter().find(|c| c.key == key) {
+                Some(citation) => citations.push(citation.clone()),
+                None => unresolved.push(key),
+            }
+        }
+
+        (citations, unresolved)
+    }
+
+    /// Run one tool call inside its own `tool_call` span
+    async fn execute_tool(
+        &self,
+        id: &str,
+        name: &str,
+        input: &serde_json::Value,
+        parent: &tracing::Span,
+    ) -> Result<String, String> {
+        let tool_span = tracing::info_span!(
+            parent: parent,
+            "tool_call",
+            tool_name = %name,
+            tool_use_id = %id,
+            is_error = Empty,
+            duration_ms = Empty,
+        );
+        let tool_start = Instant::now();
+
+        let execution = self
+            .tool_executor
+            .execute(id.to_string(), name.to_string(), input.clone())
+            .instrument(tool_span.clone());
+
+        let outcome = match self.tool_timeout {
+            Some(limit) => tokio::time::timeout(limit, execution)
+                .await
+                .unwrap_or_else(|_| Err(format!("tool '{}' timed out after {:?}", name, limit))),
+            None => execution.await,
+        };
+
+        tool_span.record("is_error", outcome.is_err());
+        tool_span.record("duration_ms", tool_start.elapsed().as_millis() as u64);
+        outcome
+    }
+
+    /// Undo this run's side effects, newest first
+    ///
+    /// Returns a `CompensationExecuted` event per compensated call.
+    /// Compensation failures are logged; they never replace the run's error.
+    async fn compensate_side_effects(&mut self) -> Vec<AgentEvent> {
+        let mut events = Vec::new();
+
+        for effect in std::mem::take(&mut self.side_effects).into_iter().rev() {
+            let outcome = self
+                .tool_executor
+                .compensate(effect.name.clone(), effect.input, effect.result)
+                .await;
+
+            match outcome {
+                None => tracing::warn!(
+                    tool_name = %effect.name,
+                    tool_use_id = %effect.tool_use_id,
+                    "run failed after a side-effecting tool with no compensation"
+                ),
+                Some(Ok(())) => events.push(AgentEvent::CompensationExecuted {
+                    name: effect.name,
+                    ok: true,
+                }),
+                Some(Err(error)) => {
+                    tracing::error!(
+                        tool_name = %effect.name,
+                        tool_use_id = %effect.tool_use_id,
+                        %error,
+                        "compensation failed"
+                    );
+                    events.push(AgentEvent::CompensationExecuted {
+                        name: effect.name,
+                        ok: false,
+                    });
+                }
+            }
+        }
+
+        events
+    }
+
+    /// Create the agent event stream
+    ///
+    /// Runs the agent loop and, if it ends in an error, compensates the
+    /// run's side-effecting tool calls before passing the error on. The run
+    /// summary is stored once the loop has finished.
+    fn create_agent_stream(
+        &mut self,
+        cancel: CancellationToken,
+    ) -> impl Stream<Item = Result<AgentEvent, AgentError>> + '_ {
+        stream! {
+            self.side_effects.clear();
+            self.last_run = None;
+            let mut summary = AgentRunSummary::new();
+            let mut completed = false;
+
+            let failure = {
+                let events = self.agent_loop(cancel);
+                pin_mut!(events);
+
+                let mut failure = None;
+                while let Some(event) = events.next().await {
+                    match event {
+                        Ok(event) => {
+                            summary.record(&event);
+                            completed |= matches!(event, AgentEvent::Completed { .. });
+                            yield Ok(event);
+                        }
+                        Err(e) => {
+                            summary.record_error(&e);
+                            failure = Some(e);
+                            break;
+                        }
+                    }
+                }
+                failure
+            };
+
+            summary.finish(completed);
+            self.last_run = Some(summary);
+
+            if let Some(error) = failure {
+                for event in self.compensate_side_effects().await {
+                    yield Ok(event);
+                }
+                yield Err(error);
+            }
+        }
+    }
+
+    /// The agent loop: call the LLM, run tools, repeat until a final answer
+    fn agent_loop(
+        &mut self,
+        cancel: CancellationToken,
+    ) -> impl Stream<Item = Result<AgentEvent, AgentError>> + '_ {
+        stream! {
+            let mut iteration = 0;
+            let mut json_repairs = 0;
+            let mut full_budget = false;
+            let mut locale_retried = false;
+            let mut strong_locale = false;
+            let mut total_usage = UsageMetadata::new(0, 0);
+            let mut attribution = ToolTokenAttribution::default();
+            let run_span = tracing::info_span!(
+                "agent_run",
+                max_iterations = self.max_iterations,
+                iterations = Empty,
+                json_repairs = Empty,
+            );
+
+            loop {
+                if cancel.is_cancelled() {
+                    yield Ok(AgentEvent::Cancelled);
+                    return;
+                }
+
+                iteration += 1;
+                run_span.record("iterations", iteration);
+
+                // Check max iterations before starting
+                if iteration > self.max_iterations {
+                    yield Err(AgentError::MaxIterationsReached(iteration - 1));
+                    return;
+                }
+
+                // Intermediate iterations are capped; a retried final answer isn't
+                let max_tokens = match self.max_output_tokens_per_iteration {
+                    Some(cap) if !full_budget => cap.min(self.config.max_tokens),
+                    _ => self.config.max_tokens,
+                };
+                full_budget = false;
+
+                // Emit iteration started
+                yield Ok(AgentEvent::IterationStarted { iteration, max_tokens });
+
+                // Refuse to send an unbalanced transcript to the provider
+                if let Err(e) = Message::validate_transcript(&self.messages) {
+                    yield Err(AgentError::InvalidTranscript(e));
+                    return;
+                }
+
+                // Pick up template variables changed since the last iteration
+                let system = match self.system_prompt() {
+                    Ok(system) => system,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                };
+
+                // The firmer instruction replaces the one providers add for `locale`
+                let (system, locale) = match (&self.locale, strong_locale) {
+                    (Some(locale), true) => (Some(strong_locale_system_prompt(system, locale)), None),
+                    _ => (system, self.locale.clone()),
+                };
+
+                // Create LLM request
+                let mut request = GenerateRequest {
+                    messages: self.messages.clone(),
+                    tools: Some(self.tool_declarations.clone()),
+                    tool_choice: None,
+                    config: GenerationConfig {
+                        max_tokens,
+                        ..self.config.clone()
+                    },
+                    system,
+                    locale,
+                };
+
+                if let Some(budget) = self.context_budget {
+                    match request.trim_to_fit(self.provider.as_ref(), budget).await {
+                        Ok(report) if !report.dropped.is_empty() => {
+                            yield Ok(AgentEvent::ContextTrimmed {
+                                iteration,
+                                dropped: report.dropped,
+                                input_tokens: report.input_tokens,
+                            });
+                        }
+                        Ok(_) | Err(LlmError::NotSupported) => {}
+                        Err(e) => {
+                            yield Err(AgentError::Llm(e));
+                            return;
+                        }
+                    }
+                }
+
+                let iteration_span = tracing::info_span!(
+                    parent: &run_span,
+                    "agent_iteration",
+                    iteration,
+                    input_tokens = Empty,
+                    output_tokens = Empty,
+                    duration_ms = Empty,
+                );
+                let iteration_start = Instant::now();
+
+                // Heartbeats run from here until the first LLM event arrives
+                let wait_start = tokio::time::Instant::now();
+                let mut heartbeat = self
+                    .heartbeat_interval
+                    .map(|period| tokio::time::interval_at(wait_start + period, period));
+
+                // Call LLM and get stream
+                let generate = self
+                    .provider
+                    .stream_generate_abortable(request.clone())
+                    .instrument(iteration_span.clone());
+                pin_mut!(generate);
+
+                let generate_result = loop {
+                    match wait_or_heartbeat(generate.as_mut(), heartbeat.as_mut(), &cancel).await {
+                        Waited::Ready(result) => break result,
+                        Waited::Heartbeat => {
+                            let elapsed_ms = wait_start.elapsed().as_millis() as u64;
+                            yield Ok(AgentEvent::Waiting { elapsed_ms });
+                        }
+                        Waited::Cancelled => {
+                            yield Ok(AgentEvent::Cancelled);
+                            return;
+                        }
+                    }
+                };
+
+                // Only one fallback attempt per LLM call
+                let mut fell_back = false;
+
+                let (mut llm_stream, mut abort) = match (generate_result, &self.fallback_provider) {
+                    (Ok(s), _) => s,
+                    (Err(e), Some(fallback)) => {
+                        fell_back = true;
+                        yield Ok(AgentEvent::ProviderFallback {
+                            from: self.provider.name().to_string(),
+                            to: fallback.name().to_string(),
+                            reason: e.to_string(),
+                        });
+
+                        match fallback.stream_generate_abortable(request.clone()).instrument(iteration_span.clone()).await {
+                            Ok(s) => s,
+                            Err(e) => {
+                                yield Err(AgentError::Llm(e));
+                                return;
+                            }
+                        }
+                    }
+                    (Err(e), None) => {
+                        yield Err(AgentError::Llm(e));
+                        return;
+                    }
+                };
+
+                // Process LLM stream, forwarding events and accumulating data
+                let mut text_content = String::new();
+                let mut tool_uses = Vec::new();
+                let mut redacted_thinking = Vec::new();
+                let mut current_tool_use: Option<PartialToolUseAccumulator> = None;
+                let partial_messages_enabled =
+                    self.partial_message_deltas.is_some() || self.partial_message_interval.is_some();
+                let mut deltas_since_snapshot = 0;
+                let mut last_snapshot = tokio::time::Instant::now();
+                let mut forwarded_events = false;
+                let mut finish_reason = None;
+                // Running usage of this response, until its MessageEnd reports the total
+                let mut response_usage: Option<UsageMetadata> = None;
+
+                // Affixes would break structured output, so they only apply to free text
+                let affixes_enabled = self.config.response_schema.is_none();
+                let mut prefix_pending = affixes_enabled && self.output_prefix.is_some();
+                let mut next_block_index = 0;
+
+                loop {
+                    let event_result = match wait_or_heartbeat(llm_stream.next(), heartbeat.as_mut(), &cancel).await {
+                        Waited::Ready(Some(event_result)) => event_result,
+                        Waited::Ready(None) => break,
+                        Waited::Heartbeat => {
+                            let elapsed_ms = wait_start.elapsed().as_millis() as u64;
+                            yield Ok(AgentEvent::Waiting { elapsed_ms });
+                            continue;
+                        }
+                        // The partial response is dropped; history still ends where this iteration began.
+                        // Abort before yielding, since the caller may stop polling after `Cancelled`
+                        Waited::Cancelled => {
+                            abort.abort();
+                            if let Some(usage) = response_usage.take() {
+                                total_usage.add(&usage);
+                                yield Ok(AgentEvent::UsageUpdated { iteration_usage: usage, cumulative_usage: total_usage });
+                            }
+                            yield Ok(AgentEvent::Cancelled);
+                            return;
+                        }
+                    };
+
+                    // First event arrived - stop heartbeats for this iteration
+                    heartbeat = None;
 
-                while let Some(event_result) = llm_stream.next().await {
                     let event = match event_result {
                         Ok(e) => e,
                         Err(e) => {
+                            // Nothing has reached the caller yet, so the fallback can start cleanly
+                            if let (false, false, Some(fallback)) = (forwarded_events, fell_back, &self.fallback_provider) {
+                                fell_back = true;
+                                yield Ok(AgentEvent::ProviderFallback {
+                                    from: self.provider.name().to_string(),
+                                    to: fallback.name().to_string(),
+                                    reason: e.to_string(),
+                                });
+
+                                match fallback.stream_generate_abortable(request.clone()).instrument(iteration_span.clone()).await {
+                                    Ok((s, handle)) => {
+                                        llm_stream = s;
+                                        abort = handle;
+                                        continue;
+                                    }
+                                    Err(e) => {
+                                        yield Err(AgentError::Llm(e));
+                                        return;
+                                    }
+                                }
+                            }
+
+                            if let Some(usage) = response_usage.take() {
+                                total_usage.add(&usage);
+                                yield Ok(AgentEvent::UsageUpdated { iteration_usage: usage, cumulative_usage: total_usage });
+                            }
                             yield Err(AgentError::Llm(e));
                             return;
                         }
                     };
 
+                    match &event {
+                        StreamEvent::ContentBlockStart { index, .. }
+                        | StreamEvent::ContentDelta { index, .. }
+                        | StreamEvent::ContentBlockEnd { index } => {
+                            next_block_index = next_block_index.max(index + 1);
+                        }
+                        _ => {}
+                    }
+
+                    // A bare text delta with no block start gets the prefix ahead of it
+                    if let (true, StreamEvent::ContentDelta { index, delta: ContentDelta::TextDelta { .. } }) = (prefix_pending, &event) {
+                        if let Some(prefix) = &self.output_prefix {
+                            prefix_pending = false;
+                            text_content.push_str(prefix);
+                            yield Ok(AgentEvent::LlmEvent(injected_text(*index, prefix)));
+                        }
+                    }
+
+                    // The suffix goes in its own text block once we know no tools were called
+                    if let (true, true, StreamEvent::MessageEnd { .. }) = (affixes_enabled, tool_uses.is_empty() && current_tool_use.is_none(), &event) {
+                        if let Some(suffix) = &self.output_suffix {
+                            let index = next_block_index;
+                            text_content.push_str(suffix);
+                            yield Ok(AgentEvent::LlmEvent(StreamEvent::ContentBlockStart {
+                                index,
+                                block: ContentBlockStart::Text { text: String::new() },
+                            }));
+                            yield Ok(AgentEvent::LlmEvent(injected_text(index, suffix)));
+                            yield Ok(AgentEvent::LlmEvent(StreamEvent::ContentBlockEnd { index }));
+                        }
+                    }
+
                     // Forward the LLM event to caller
+                    forwarded_events = true;
                     yield Ok(AgentEvent::LlmEvent(event.clone()));
 
                     // Also accumulate data for tool detection
                     match &event {
-                        StreamEvent::ContentBlockStart { block, .. } => {
+                        StreamEvent::ContentBlockStart { index, block } => {
                             match block {
                                 ContentBlockStart::Text { text } => {
                                     text_content.push_str(text);
+
+                                    if let (true, Some(prefix)) = (prefix_pending, &self.output_prefix) {
+                                        prefix_pending = false;
+                                        text_content.push_str(prefix);
+                                        yield Ok(AgentEvent::LlmEvent(injected_text(*index, prefix)));
+                                    }
                                 }
                                 ContentBlockStart::ToolUse { id, name } => {
                                     current_tool_use = Some(PartialToolUseAccumulator {
@@ -224,6 +1460,12 @@ impl Agent {
                                         input: String::new(),
                                     });
                                 }
+                                // Thinking is streamed to the caller but kept out of history
+                                ContentBlockStart::Thinking { .. } => {}
+                                // Redacted thinking must go back to Claude with the turn
+                                ContentBlockStart::RedactedThinking { data } => {
+                                    redacted_thinking.push(ContentBlock::RedactedThinking { data: data.clone() });
+                                }
                             }
                         }
                         StreamEvent::ContentDelta { delta, .. } => {
@@ -236,6 +1478,7 @@ impl Agent {
                                         tool_use.input.push_str(&partial.partial_json);
                                     }
                                 }
+                                ContentDelta::ThinkingDelta { .. } => {}
                             }
                         }
                         StreamEvent::ContentBlockEnd { .. } => {
@@ -256,79 +1499,268 @@ impl Agent {
                                 }
                             }
                         }
-                        StreamEvent::MessageEnd { .. } => break,
+                        StreamEvent::MessageStart { message: MessageMetadata { usage: Some(usage), .. } }
+                        | StreamEvent::MessageDelta { usage: Some(usage) } => {
+                            response_usage = Some(*usage);
+                        }
+                        StreamEvent::MessageEnd { usage, finish_reason: reason } => {
+                            response_usage = None;
+                            finish_reason = Some(reason.clone());
+                            iteration_span.record("input_tokens", usage.input_tokens);
+                            iteration_span.record("output_tokens", usage.output_tokens);
+
+                            total_usage.add(usage);
+                            attribution.record_usage(usage);
+                            yield Ok(AgentEvent::UsageUpdated {
+                                iteration_usage: *usage,
+                                cumulative_usage: total_usage,
+                            });
+
+                            if let Some(budget) = self.token_budget.filter(|budget| total_usage.total_tokens > *budget) {
+                                yield Err(AgentError::TokenBudgetExceeded { used: total_usage.total_tokens, budget });
+                                return;
+                            }
+                            break;
+                        }
                         _ => {}
                     }
+
+                    if partial_messages_enabled && matches!(event, StreamEvent::ContentDelta { .. }) {
+                        deltas_since_snapshot += 1;
+                        let due = self.partial_message_deltas.is_some_and(|n| deltas_since_snapshot >= n)
+                            || self.partial_message_interval.is_some_and(|d| last_snapshot.elapsed() >= d);
+
+                        if due {
+                            deltas_since_snapshot = 0;
+                            last_snapshot = tokio::time::Instant::now();
+                            yield Ok(AgentEvent::PartialMessage(assistant_message(&redacted_thinking, &text_content, &tool_uses)));
+                        }
+                    }
+                }
+
+                iteration_span.record("duration_ms", iteration_start.elapsed().as_millis() as u64);
+
+                // The stream ended without reporting its final usage
+                if let Some(usage) = response_usage.take() {
+                    total_usage.add(&usage);
+                    yield Ok(AgentEvent::UsageUpdated { iteration_usage: usage, cumulative_usage: total_usage });
+                }
+
+                // An answer cut off by the per-iteration cap is retried with the full budget
+                if tool_uses.is_empty()
+                    && max_tokens < self.config.max_tokens
+                    && finish_reason == Some(FinishReason::MaxTokens)
+                {
+                    yield Ok(AgentEvent::OutputBudgetExhausted { iteration, max_tokens });
+                    full_budget = true;
+                    continue;
+                }
+
+                // An answer in the wrong script is asked for again, once
+                if let Some(locale) = self.locale.clone().filter(|_| self.locale_retry && !locale_retried) {
+                    if tool_uses.is_empty() && matches_locale(&text_content, &locale) == Some(false) {
+                        yield Ok(AgentEvent::LocaleRetryRequested { iteration, locale });
+                        locale_retried = true;
+                        strong_locale = true;
+                        continue;
+                    }
                 }
 
                 // Check if we need to execute tools
                 if tool_uses.is_empty() {
-                    // Build final assistant message with text only
-                    let mut assistant_content = Vec::new();
-                    if !text_content.is_empty() {
-                        assistant_content.push(ContentBlock::Text { text: text_content });
+                    // Structured output: validate and ask for a correction if needed
+                    if let Some(schema) = self.config.response_schema.clone() {
+                        let errors = match parse_json_output(&text_content) {
+                            Ok(value) => validate_json(&value, &schema),
+                            Err(e) => vec![format!("$: invalid JSON: {}", e)],
+                        };
+
+                        if !errors.is_empty() {
+                            // The rejected output stays in history, untransformed, for the repair turn
+                            let message = assistant_message(&redacted_thinking, &text_content, &[]);
+                            self.messages.push(message.clone());
+                            yield Ok(AgentEvent::AssistantMessageComplete(message));
+
+                            if json_repairs >= self.max_json_repairs {
+                                yield Err(AgentError::JsonValidation {
+                                    attempts: json_repairs,
+                                    errors,
+                                    output: text_content,
+                                });
+                                return;
+                            }
+
+                            json_repairs += 1;
+                            run_span.record("json_repairs", json_repairs);
+                            yield Ok(AgentEvent::JsonRepairRequested {
+                                attempt: json_repairs,
+                                errors: errors.clone(),
+                            });
+
+                            self.messages.push(Message::user(json_repair_prompt(&text_content, &errors)));
+                            continue;
+                        }
                     }
 
-                    // Add to conversation history
-                    self.messages.push(Message {
-                        role: MessageRole::Assistant,
-                        content: assistant_content,
-                    });
+                    // Build final assistant message with text only and add to history
+                    let mut final_text = match &self.output_transform {
+                        Some(transform) => transform(&text_content),
+                        None => text_content,
+                    };
+
+                    if let Some(moderator) = self.moderator.clone() {
+                        match moderator.check_output(&final_text).await {
+                            ModerationDecision::Allow => {}
+                            ModerationDecision::Redact(replacement) => {
+                                final_text = replacement;
+                                yield Ok(AgentEvent::OutputRedacted { text: final_text.clone() });
+                            }
+                            ModerationDecision::Block(reason) => {
+                                yield Err(AgentError::OutputBlocked(reason));
+                                return;
+                            }
+                        }
+                    }
+                    let message = assistant_message(&redacted_thinking, &final_text, &[]);
+                    self.messages.push(message.clone());
+                    yield Ok(AgentEvent::AssistantMessageComplete(message));
 
                     // No tools - we're done!
-                    yield Ok(AgentEvent::Completed);
+                    let (citations, unresolved_citations) = if self.citations_enabled {
+                        self.resolve_citations(&final_text)
+                    } else {
+                        (Vec::new(), Vec::new())
+                    };
+                    yield Ok(AgentEvent::Completed {
+                        total_usage,
+                        iterations: iteration,
+                        tool_token_attribution: attribution.totals(),
+                        citations,
+                        unresolved_citations,
+                    });
                     return;
                 }
 
-                // Build assistant message with tool uses
-                let mut assistant_content = Vec::new();
-                if !text_content.is_empty() {
-                    assistant_content.push(ContentBlock::Text { text: text_content });
-                }
-                assistant_content.extend(tool_uses.clone());
-
-                // Add to conversation history
-                self.messages.push(Message {
-                    role: MessageRole::Assistant,
-                    content: assistant_content,
-                });
+                // Build assistant message with tool uses and add to history
+                let message = assistant_message(&redacted_thinking, &text_content, &tool_uses);
+                self.messages.push(message.clone());
+                yield Ok(AgentEvent::AssistantMessageComplete(message));
 
                 // Execute tools and add results to history
-                for block in &tool_uses {
-                    if let ContentBlock::ToolUse { id, name, input } = block {
+                let calls: Vec<(&String, &String, &serde_json::Value)> = tool_uses
+                    .iter()
+                    .filter_map(|block| match block {
+                        ContentBlock::ToolUse { id, name, input } => Some((id, name, input)),
+                        _ => None,
+                    })
+                    .collect();
+                let mut outcomes: Vec<Option<Result<String, String>>> = vec![None; calls.len()];
+                let mut started = vec![false; calls.len()];
+
+                if self.parallel_tool_execution && calls.len() > 1 && !cancel.is_cancelled() {
+                    for (id, name, input) in &calls {
+                        yield Ok(AgentEvent::ToolExecutionStarted {
+                            tool_use_id: (*id).clone(),
+                            name: (*name).clone(),
+                            input: (*input).clone(),
+                        });
+                    }
+                    started.fill(true);
+
+                    // Completion events follow completion order
+                    let mut pending: FuturesUnordered<_> = calls
+                        .iter()
+                        .enumerate()
+                        .map(|(i, (id, name, input))| {
+                            let execution = self.execute_tool(id, name, input, &iteration_span);
+                            async move { (i, execution.await) }
+                        })
+                        .collect();
+
+                    loop {
+                        let next = tokio::select! {
+                            biased;
+                            _ = cancel.cancelled() => break,
+                            next = pending.next() => next,
+                        };
+                        let Some((i, outcome)) = next else { break };
+                        let (id, name, _) = calls[i];
+                        yield Ok(tool_outcome_event(id, name, &outcome));
+                        outcomes[i] = Some(outcome);
+                    }
+                } else {
+                    for (i, (id, name, input)) in calls.iter().enumerate() {
+                        if cancel.is_cancelled() {
+                            break;
+                        }
+
                         // Emit tool execution started
                         yield Ok(AgentEvent::ToolExecutionStarted {
-                            tool_use_id: id.clone(),
-                            name: name.clone(),
-                            input: input.clone(),
+                            tool_use_id: (*id).clone(),
+                            name: (*name).clone(),
+                            input: (*input).clone(),
                         });
+                        started[i] = true;
+
+                        let outcome = tokio::select! {
+                            biased;
+                            _ = cancel.cancelled() => break,
+                            outcome = self.execute_tool(id, name, input, &iteration_span) => outcome,
+                        };
+                        yield Ok(tool_outcome_event(id, name, &outcome));
+                        outcomes[i] = Some(outcome);
+                    }
+                }
+
+                // Calls interrupted or skipped by cancellation still need a result in history
+                for (i, (id, name, _)) in calls.iter().enumerate() {
+                    if outcomes[i].is_none() {
+                        let outcome = Err(TOOL_CANCELLED_ERROR.to_string());
+                        if started[i] {
+                            yield Ok(tool_outcome_event(id, name, &outcome));
+                        }
+                        outcomes[i] = Some(outcome);
+                    }
+                }
 
-                        // Execute the tool
-                        match self.tool_executor.execute(
-                            id.clone(),
-                            name.clone(),
-                            input.clone(),
-                        ).await {
-                            Ok(result) => {
-                                yield Ok(AgentEvent::ToolExecutionCompleted {
-                                    tool_use_id: id.clone(),
-                                    name: name.clone(),
+                // Results go into history in call order, however they finished
+                for ((id, name, input), outcome) in calls.iter().zip(outcomes) {
+                    match outcome.expect("every tool call has an outcome") {
+                        Ok(result) => {
+                            if self.tool_executor.has_side_effects(name) {
+                                tracing::info!(
+                                    parent: &iteration_span,
+                                    tool_name = %name,
+                                    tool_use_id = %id,
+                                    "side-effecting tool executed"
+                                );
+                                self.side_effects.push(SideEffect {
+                                    tool_use_id: (*id).clone(),
+                                    name: (*name).clone(),
+                                    input: (*input).clone(),
                                     result: result.clone(),
                                 });
-
-                                // Add tool result to history
-                                self.messages.push(Message::tool_result(id.clone(), result));
                             }
-                            Err(error) => {
-                                yield Ok(AgentEvent::ToolExecutionFailed {
-                                    tool_use_id: id.clone(),
-                                    name: name.clone(),
-                                    error: error.clone(),
-                                });
 
-                                // Add tool error to history
-                                self.messages.push(Message::tool_error(id.clone(), error));
-                            }
+                            // Tag the result with its citation key when citations are on
+                            let content = if self.citations_enabled {
+                                let key = citation_key(self.cited_results.len() + 1);
+                                let content = cited_content(&key, &result);
+                                self.cited_results.push(Citation {
+                                    key,
+                                    tool_use_id: (*id).clone(),
+                                    name: (*name).clone(),
+                                });
+                                content
+                            } else {
+                                result
+                            };
+                            attribution.record_result(name, &content);
+                            self.messages.push(Message::tool_result((*id).clone(), content));
+                        }
+                        Err(error) => {
+                            attribution.record_result(name, &error);
+                            self.messages.push(Message::tool_error((*id).clone(), error));
                         }
                     }
                 }
@@ -434,4 +1866,2753 @@ mod tests {
         agent.clear_history();
         assert_eq!(agent.messages().len(), 0);
     }
+
+    #[tokio::test]
+    async fn test_run_rejects_unbalanced_transcript() {
+        let call_count = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let provider = Box::new(MockProvider {
+            responses: vec![],
+            call_count: call_count.clone(),
+        });
+        let executor = Box::new(MockExecutor);
+        let config = GenerationConfig::new(1024);
+
+        let mut agent = Agent::new(provider, executor, vec![], config, None);
+        agent.messages.push(Message {
+            role: MessageRole::Assistant,
+            content: vec![ContentBlock::ToolUse {
+                id: "tool-1".to_string(),
+                name: "calculator".to_string(),
+                input: serde_json::json!({}),
+            }],
+        });
+
+        let mut stream = agent.run("hello").await.unwrap();
+        let mut saw_error = false;
+        while let Some(event) = stream.next().await {
+            if let Err(AgentError::InvalidTranscript(_)) = event {
+                saw_error = true;
+            }
+        }
+        drop(stream);
+
+        assert!(saw_error);
+        assert_eq!(*call_count.lock().unwrap(), 0);
+    }
+
+    /// Span name plus every (field, value) recorded on it
+    type RecordedSpan = (String, Vec<(String, String)>);
+
+    /// Minimal subscriber that records span names and their fields
+    #[derive(Clone, Default)]
+    struct SpanRecorder {
+        spans: std::sync::Arc<std::sync::Mutex<Vec<RecordedSpan>>>,
+    }
+
+    struct FieldVisitor<'a>(&'a mut Vec<(String, String)>);
+
+    impl tracing::field::Visit for FieldVisitor<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    impl tracing::Subscriber for SpanRecorder {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut fields = Vec::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((attrs.metadata().name().to_string(), fields));
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            let entry = &mut spans[span.into_u64() as usize - 1];
+            values.record(&mut FieldVisitor(&mut entry.1));
+        }
+
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+        fn event(&self, _event: &tracing::Event<'_>) {}
+        fn enter(&self, _span: &tracing::span::Id) {}
+        fn exit(&self, _span: &tracing::span::Id) {}
+    }
+
+    impl SpanRecorder {
+        fn field(&self, span_name: &str, field: &str) -> Vec<String> {
+            self.spans
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(name, _)| name == span_name)
+                .filter_map(|(_, fields)| {
+                    fields.iter().rev().find(|(f, _)| f == field).map(|(_, v)| v.clone())
+                })
+                .collect()
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_run_emits_tracing_spans() {
+        use crate::llm::core::types::{FinishReason, PartialToolUse, UsageMetadata};
+
+        let recorder = SpanRecorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+
+        let provider = Box::new(MockProvider {
+            responses: vec![
+                vec![
+                    StreamEvent::ContentBlockStart {
+                        index: 0,
+                        block: ContentBlockStart::ToolUse {
+                            id: "tool-1".to_string(),
+                            name: "calculator".to_string(),
+                        },
+                    },
+                    StreamEvent::ContentDelta {
+                        index: 0,
+                        delta: ContentDelta::ToolUseDelta {
+                            partial: PartialToolUse {
+                                id: None,
+                                name: None,
+                                partial_json: "{}".to_string(),
+                            },
+                        },
+                    },
+                    StreamEvent::ContentBlockEnd { index: 0 },
+                    StreamEvent::MessageEnd {
+                        finish_reason: FinishReason::ToolUse,
+                        usage: UsageMetadata::new(10, 5),
+                    },
+                ],
+                vec![
+                    StreamEvent::ContentBlockStart {
+                        index: 0,
+                        block: ContentBlockStart::Text {
+                            text: "42".to_string(),
+                        },
+                    },
+                    StreamEvent::MessageEnd {
+                        finish_reason: FinishReason::EndTurn,
+                        usage: UsageMetadata::new(20, 3),
+                    },
+                ],
+            ],
+            call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+        });
+        let mut agent = Agent::new(
+            provider,
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+
+        let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+        while let Some(event) = stream.next().await {
+            event.unwrap();
+        }
+        drop(stream);
+
+        assert_eq!(recorder.field("agent_run", "iterations"), vec!["2"]);
+        assert_eq!(recorder.field("agent_iteration", "iteration"), vec!["1", "2"]);
+        assert_eq!(recorder.field("agent_iteration", "input_tokens"), vec!["10", "20"]);
+        assert_eq!(recorder.field("agent_iteration", "output_tokens"), vec!["5", "3"]);
+        assert_eq!(recorder.field("agent_iteration", "duration_ms").len(), 2);
+        assert_eq!(recorder.field("tool_call", "tool_name"), vec!["calculator"]);
+        assert_eq!(recorder.field("tool_call", "is_error"), vec!["false"]);
+    }
+
+    // Provider whose stream holds back its first event for `delay`
+    struct DelayedProvider {
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl LlmProvider for DelayedProvider {
+        async fn stream_generate(
+            &self,
+            _request: GenerateRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>
+        {
+            use crate::llm::core::types::{FinishReason, UsageMetadata};
+
+            let delay = self.delay;
+            Ok(Box::pin(stream! {
+                tokio::time::sleep(delay).await;
+                yield Ok(StreamEvent::ContentBlockStart {
+                    index: 0,
+                    block: ContentBlockStart::Text { text: "Hi".to_string() },
+                });
+                tokio::time::sleep(delay).await;
+                yield Ok(StreamEvent::MessageEnd {
+                    finish_reason: FinishReason::EndTurn,
+                    usage: UsageMetadata::new(1, 1),
+                });
+            }))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_waiting_heartbeats_until_first_event() {
+        let provider = Box::new(DelayedProvider {
+            delay: Duration::from_millis(350),
+        });
+        let mut agent = Agent::new(
+            provider,
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_heartbeat_interval(Duration::from_millis(100));
+
+        let mut stream = agent.run("hello").await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await {
+            events.push(event.unwrap());
+        }
+        drop(stream);
+
+        let waiting: Vec<u64> = events
+            .iter()
+            .filter_map(|e| match e {
+                AgentEvent::Waiting { elapsed_ms } => Some(*elapsed_ms),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(waiting, vec![100, 200, 300]);
+
+        // Heartbeats stop once the first LLM event arrives
+        let first_llm = events
+            .iter()
+            .position(|e| matches!(e, AgentEvent::LlmEvent(_)))
+            .unwrap();
+        assert!(events[first_llm..]
+            .iter()
+            .all(|e| !matches!(e, AgentEvent::Waiting { .. })));
+
+        // Heartbeats are never persisted
+        assert_eq!(agent.messages().len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_no_heartbeats_by_default() {
+        let provider = Box::new(DelayedProvider {
+            delay: Duration::from_millis(350),
+        });
+        let mut agent = Agent::new(
+            provider,
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+
+        let mut stream = agent.run("hello").await.unwrap();
+        let mut waiting = 0;
+        while let Some(event) = stream.next().await {
+            if let AgentEvent::Waiting { .. } = event.unwrap() {
+                waiting += 1;
+            }
+        }
+
+        assert_eq!(waiting, 0);
+    }
+
+    fn text_delta(text: &str) -> StreamEvent {
+        StreamEvent::ContentDelta {
+            index: 0,
+            delta: ContentDelta::TextDelta {
+                text: text.to_string(),
+            },
+        }
+    }
+
+    fn message_text(message: &Message) -> String {
+        message
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_partial_messages_grow_and_match_final() {
+        use crate::llm::core::types::{FinishReason, UsageMetadata};
+
+        let provider = Box::new(MockProvider {
+            responses: vec![vec![
+                StreamEvent::ContentBlockStart {
+                    index: 0,
+                    block: ContentBlockStart::Text {
+                        text: String::new(),
+                    },
+                },
+                text_delta("The "),
+                text_delta("answer "),
+                text_delta("is "),
+                text_delta("42."),
+                StreamEvent::ContentBlockEnd { index: 0 },
+                StreamEvent::MessageEnd {
+                    finish_reason: FinishReason::EndTurn,
+                    usage: UsageMetadata::new(10, 4),
+                },
+            ]],
+            call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+        });
+        let mut agent = Agent::new(
+            provider,
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_partial_message_deltas(2);
+
+        let mut stream = agent.run("hello").await.unwrap();
+        let mut snapshots = Vec::new();
+        while let Some(event) = stream.next().await {
+            if let AgentEvent::PartialMessage(message) = event.unwrap() {
+                snapshots.push(message);
+            }
+        }
+        drop(stream);
+
+        let texts: Vec<String> = snapshots.iter().map(message_text).collect();
+        assert_eq!(texts, vec!["The answer ", "The answer is 42."]);
+        assert!(texts.windows(2).all(|w| w[1].starts_with(&w[0])));
+        assert!(snapshots.iter().all(|m| m.role == MessageRole::Assistant));
+
+        let final_message = agent.messages().last().unwrap();
+        assert_eq!(snapshots.last().unwrap().content, final_message.content);
+    }
+
+    #[tokio::test]
+    async fn test_partial_messages_include_completed_tool_uses_only() {
+        use crate::llm::core::types::{FinishReason, PartialToolUse, UsageMetadata};
+
+        let tool_delta = |json: &str| StreamEvent::ContentDelta {
+            index: 1,
+            delta: ContentDelta::ToolUseDelta {
+                partial: PartialToolUse {
+                    id: None,
+                    name: None,
+                    partial_json: json.to_string(),
+                },
+            },
+        };
+
+        let provider = Box::new(MockProvider {
+            responses: vec![
+                vec![
+                    StreamEvent::ContentBlockStart {
+                        index: 0,
+                        block: ContentBlockStart::Text {
+                            text: String::new(),
+                        },
+                    },
+                    text_delta("Checking"),
+                    StreamEvent::ContentBlockEnd { index: 0 },
+                    StreamEvent::ContentBlockStart {
+                        index: 1,
+                        block: ContentBlockStart::ToolUse {
+                            id: "tool-1".to_string(),
+                            name: "calculator".to_string(),
+                        },
+                    },
+                    tool_delta("{\"a\":"),
+                    tool_delta("1}"),
+                    StreamEvent::ContentBlockEnd { index: 1 },
+                    StreamEvent::MessageEnd {
+                        finish_reason: FinishReason::ToolUse,
+                        usage: UsageMetadata::new(10, 4),
+                    },
+                ],
+                vec![
+                    StreamEvent::ContentBlockStart {
+                        index: 0,
+                        block: ContentBlockStart::Text {
+                            text: String::new(),
+                        },
+                    },
+                    text_delta("Done"),
+                    StreamEvent::MessageEnd {
+                        finish_reason: FinishReason::EndTurn,
+                        usage: UsageMetadata::new(20, 1),
+                    },
+                ],
+            ],
+            call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+        });
+        let mut agent = Agent::new(
+            provider,
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_partial_message_deltas(1);
+
+        let mut stream = agent.run("hello").await.unwrap();
+        let mut snapshots = Vec::new();
+        while let Some(event) = stream.next().await {
+            if let AgentEvent::PartialMessage(message) = event.unwrap() {
+                snapshots.push(message);
+            }
+        }
+        drop(stream);
+
+        // One snapshot per delta; the tool use is still streaming in all of
+        // the first iteration's snapshots, so none of them contain it
+        assert_eq!(snapshots.len(), 4);
+        for snapshot in &snapshots[..3] {
+            assert_eq!(message_text(snapshot), "Checking");
+            assert_eq!(snapshot.content.len(), 1);
+        }
+        assert_eq!(message_text(&snapshots[3]), "Done");
+        assert_eq!(&snapshots[3], agent.messages().last().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_no_partial_messages_by_default() {
+        use crate::llm::core::types::{FinishReason, UsageMetadata};
+
+        let provider = Box::new(MockProvider {
+            responses: vec![vec![
+                text_delta("Hi"),
+                StreamEvent::MessageEnd {
+                    finish_reason: FinishReason::EndTurn,
+                    usage: UsageMetadata::new(1, 1),
+                },
+            ]],
+            call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+        });
+        let mut agent = Agent::new(
+            provider,
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+
+        let mut stream = agent.run("hello").await.unwrap();
+        while let Some(event) = stream.next().await {
+            assert!(!matches!(event.unwrap(), AgentEvent::PartialMessage(_)));
+        }
+    }
+
+    /// How `FailingProvider` fails
+    #[derive(Clone, Copy)]
+    enum FailureMode {
+        /// `stream_generate` itself returns an error
+        Connect,
+        /// The stream's first item is an error
+        FirstEvent,
+        /// The stream yields a text delta, then errors
+        AfterContent,
+    }
+
+    struct FailingProvider {
+        mode: FailureMode,
+        call_count: std::sync::Arc<std::sync::Mutex<usize>>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for FailingProvider {
+        async fn stream_generate(
+            &self,
+            _request: GenerateRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>
+        {
+            *self.call_count.lock().unwrap() += 1;
+            let outage = || LlmError::HttpError {
+                status: 503,
+                body: "unavailable".to_string(),
+            };
+
+            match self.mode {
+                FailureMode::Connect => Err(outage()),
+                FailureMode::FirstEvent => Ok(Box::pin(futures::stream::iter(vec![Err(outage())]))),
+                FailureMode::AfterContent => Ok(Box::pin(futures::stream::iter(vec![
+                    Ok(text_delta("partial")),
+                    Err(outage()),
+                ]))),
+            }
+        }
+
+        fn name(&self) -> &str {
+            "primary"
+        }
+    }
+
+    /// Fallback mock that answers with a tool call, then text
+    fn fallback_provider(call_count: std::sync::Arc<std::sync::Mutex<usize>>) -> MockProvider {
+        use crate::llm::core::types::{FinishReason, PartialToolUse, UsageMetadata};
+
+        MockProvider {
+            responses: vec![
+                vec![
+                    StreamEvent::ContentBlockStart {
+                        index: 0,
+                        block: ContentBlockStart::ToolUse {
+                            id: "toolu_fallback".to_string(),
+                            name: "calculator".to_string(),
+                        },
+                    },
+                    StreamEvent::ContentDelta {
+                        index: 0,
+                        delta: ContentDelta::ToolUseDelta {
+                            partial: PartialToolUse {
+                                id: None,
+                                name: None,
+                                partial_json: "{}".to_string(),
+                            },
+                        },
+                    },
+                    StreamEvent::ContentBlockEnd { index: 0 },
+                    StreamEvent::MessageEnd {
+                        finish_reason: FinishReason::ToolUse,
+                        usage: UsageMetadata::new(10, 5),
+                    },
+                ],
+                vec![
+                    text_delta("42"),
+                    StreamEvent::MessageEnd {
+                        finish_reason: FinishReason::EndTurn,
+                        usage: UsageMetadata::new(20, 1),
+                    },
+                ],
+            ],
+            call_count,
+        }
+    }
+
+    async fn run_with_fallback(mode: FailureMode) -> (Vec<Result<AgentEvent, AgentError>>, Agent) {
+        let provider = Box::new(FailingProvider {
+            mode,
+            call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+        });
+        let fallback = Box::new(fallback_provider(std::sync::Arc::new(std::sync::Mutex::new(0))));
+        let mut agent = Agent::new(
+            provider,
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_fallback_provider(fallback);
+
+        let mut events = Vec::new();
+        let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+        while let Some(event) = stream.next().await {
+            events.push(event);
+        }
+        drop(stream);
+
+        (events, agent)
+    }
+
+    fn fallback_events(events: &[Result<AgentEvent, AgentError>]) -> Vec<(String, String, String)> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                Ok(AgentEvent::ProviderFallback { from, to, reason }) => {
+                    Some((from.clone(), to.clone(), reason.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_fallback_when_primary_cannot_connect() {
+        let (events, agent) = run_with_fallback(FailureMode::Connect).await;
+
+        // The primary is retried (and fails over) on each iteration
+        let fallbacks = fallback_events(&events);
+        assert_eq!(fallbacks.len(), 2);
+        let (from, to, reason) = &fallbacks[0];
+        assert_eq!(from, "primary");
+        assert!(to.contains("MockProvider"));
+        assert!(reason.contains("503"));
+
+        assert!(events.iter().all(|e| e.is_ok()));
+        assert!(matches!(events.last(), Some(Ok(AgentEvent::Completed { .. }))));
+
+        // The fallback's tool ids flow through history like any other provider's
+        let messages = agent.messages();
+        assert_eq!(messages.len(), 4);
+        assert!(Message::validate_transcript(messages).is_ok());
+        assert!(matches!(
+            &messages[1].content[0],
+            ContentBlock::ToolUse { id, .. } if id == "toolu_fallback"
+        ));
+        assert_eq!(message_text(&messages[3]), "42");
+    }
+
+    #[tokio::test]
+    async fn test_fallback_when_primary_stream_fails_first() {
+        let (events, agent) = run_with_fallback(FailureMode::FirstEvent).await;
+
+        assert_eq!(fallback_events(&events).len(), 2);
+        assert!(matches!(events.last(), Some(Ok(AgentEvent::Completed { .. }))));
+        assert_eq!(agent.messages().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_no_fallback_after_primary_streamed_content() {
+        let (events, _agent) = run_with_fallback(FailureMode::AfterContent).await;
+
+        assert!(fallback_events(&events).is_empty());
+        assert!(matches!(
+            events.last(),
+            Some(Err(AgentError::Llm(LlmError::HttpError { status: 503, .. })))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_primary_error_without_fallback() {
+        let mut agent = Agent::new(
+            Box::new(FailingProvider {
+                mode: FailureMode::Connect,
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+
+        let mut stream = agent.run("hello").await.unwrap();
+        let mut last = None;
+        while let Some(event) = stream.next().await {
+            last = Some(event);
+        }
+
+        assert!(matches!(last, Some(Err(AgentError::Llm(_)))));
+    }
+
+    fn text_response(text: &str) -> Vec<StreamEvent> {
+        use crate::llm::core::types::{FinishReason, UsageMetadata};
+
+        vec![
+            text_delta(text),
+            StreamEvent::MessageEnd {
+                finish_reason: FinishReason::EndTurn,
+                usage: UsageMetadata::new(10, 5),
+            },
+        ]
+    }
+
+    fn json_agent(
+        responses: Vec<Vec<StreamEvent>>,
+        call_count: std::sync::Arc<std::sync::Mutex<usize>>,
+    ) -> Agent {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "city": {"type": "string"},
+                "temperature": {"type": "number"}
+            },
+            "required": ["city", "temperature"]
+        });
+        Agent::new(
+            Box::new(MockProvider {
+                responses,
+                call_count,
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024).with_response_schema(schema),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_json_output_repaired_once() {
+        let call_count = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let mut agent = json_agent(
+            vec![
+                text_response(r#"{"city": "SF"}"#),
+                text_response("```json\n{\"city\": \"SF\", \"temperature\": 18}\n```"),
+            ],
+            call_count.clone(),
+        );
+
+        let mut stream = agent.run("Weather in SF as JSON").await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await {
+            events.push(event.unwrap());
+        }
+        drop(stream);
+
+        let repairs: Vec<(usize, Vec<String>)> = events
+            .iter()
+            .filter_map(|e| match e {
+                AgentEvent::JsonRepairRequested { attempt, errors } => Some((*attempt, errors.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            repairs,
+            vec![(1, vec!["$: missing required property 'temperature'".to_string()])]
+        );
+        assert!(matches!(events.last(), Some(AgentEvent::Completed { .. })));
+        assert_eq!(*call_count.lock().unwrap(), 2);
+
+        // The repair prompt carries the errors and the original output
+        let messages = agent.messages();
+        assert_eq!(messages.len(), 4);
+        let repair_prompt = message_text(&messages[2]);
+        assert_eq!(messages[2].role, MessageRole::User);
+        assert!(repair_prompt.contains("missing required property 'temperature'"));
+        assert!(repair_prompt.contains(r#"{"city": "SF"}"#));
+    }
+
+    #[tokio::test]
+    async fn test_json_output_fails_after_max_repairs() {
+        let call_count = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let mut agent = json_agent(
+            vec![
+                text_response("not json"),
+                text_response(r#"{"city": 7, "temperature": 18}"#),
+            ],
+            call_count.clone(),
+        );
+
+        let mut stream = agent.run("Weather in SF as JSON").await.unwrap();
+        let mut repairs = 0;
+        let mut error = None;
+        while let Some(event) = stream.next().await {
+            match event {
+                Ok(AgentEvent::JsonRepairRequested { errors, .. }) => {
+                    repairs += 1;
+                    assert!(errors[0].starts_with("$: invalid JSON"));
+                }
+                Ok(_) => {}
+                Err(e) => error = Some(e),
+            }
+        }
+        drop(stream);
+
+        assert_eq!(repairs, 1);
+        assert_eq!(*call_count.lock().unwrap(), 2);
+        match error {
+            Some(AgentError::JsonValidation {
+                attempts,
+                errors,
+                output,
+            }) => {
+                assert_eq!(attempts, 1);
+                assert_eq!(errors, vec!["$.city: expected string, got integer".to_string()]);
+                assert_eq!(output, r#"{"city": 7, "temperature": 18}"#);
+            }
+            other => panic!("Expected JsonValidation error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_valid_json_output_needs_no_repair() {
+        let call_count = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let mut agent = json_agent(
+            vec![text_response(r#"{"city": "SF", "temperature": 18.5}"#)],
+            call_count.clone(),
+        )
+        .with_max_json_repairs(0);
+
+        let mut stream = agent.run("Weather in SF as JSON").await.unwrap();
+        while let Some(event) = stream.next().await {
+            assert!(!matches!(event.unwrap(), AgentEvent::JsonRepairRequested { .. }));
+        }
+        drop(stream);
+
+        assert_eq!(*call_count.lock().unwrap(), 1);
+    }
+
+    /// A text block followed by one tool call, ending the turn with `ToolUse`
+    fn tool_call_response(text: &str, input_json: &str) -> Vec<StreamEvent> {
+        use crate::llm::core::types::{FinishReason, PartialToolUse, UsageMetadata};
+
+        vec![
+            StreamEvent::ContentBlockStart {
+                index: 0,
+                block: ContentBlockStart::Text {
+                    text: String::new(),
+                },
+            },
+            text_delta(text),
+            StreamEvent::ContentBlockEnd { index: 0 },
+            StreamEvent::ContentBlockStart {
+                index: 1,
+                block: ContentBlockStart::ToolUse {
+                    id: "tool-1".to_string(),
+                    name: "calculator".to_string(),
+                },
+            },
+            StreamEvent::ContentDelta {
+                index: 1,
+                delta: ContentDelta::ToolUseDelta {
+                    partial: PartialToolUse {
+                        id: None,
+                        name: None,
+                        partial_json: input_json.to_string(),
+                    },
+                },
+            },
+            StreamEvent::ContentBlockEnd { index: 1 },
+            StreamEvent::MessageEnd {
+                finish_reason: FinishReason::ToolUse,
+                usage: UsageMetadata::new(10, 4),
+            },
+        ]
+    }
+
+    fn tool_then_answer_agent(answer: &str) -> Agent {
+        Agent::new(
+            Box::new(MockProvider {
+                responses: vec![
+                    tool_call_response("**Checking**", r#"{"expr": "**2"}"#),
+                    text_response(answer),
+                ],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_output_transform_applies_to_final_answer_only() {
+        let mut agent = tool_then_answer_agent("The answer is **42**.")
+            .with_output_transform(|text| format!("{}\n\nNot financial advice.", text.replace("**", "")));
+
+        let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await {
+            events.push(event.unwrap());
+        }
+        drop(stream);
+
+        let completed: Vec<Message> = events
+            .iter()
+            .filter_map(|e| match e {
+                AgentEvent::AssistantMessageComplete(message) => Some(message.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(completed.len(), 2);
+        assert!(matches!(events.last(), Some(AgentEvent::Completed { .. })));
+
+        // Final answer is transformed in both the event and history
+        let messages = agent.messages();
+        let final_message = messages.last().unwrap();
+        assert_eq!(message_text(final_message), "The answer is 42.\n\nNot financial advice.");
+        assert_eq!(&completed[1], final_message);
+
+        // The tool-calling turn, including its tool input, is left alone
+        assert_eq!(&completed[0], &messages[1]);
+        assert_eq!(message_text(&messages[1]), "**Checking**");
+        assert!(messages[1].content.contains(&ContentBlock::ToolUse {
+            id: "tool-1".to_string(),
+            name: "calculator".to_string(),
+            input: serde_json::json!({"expr": "**2"}),
+        }));
+        assert!(events.iter().any(|e| matches!(
+            e,
+            AgentEvent::ToolExecutionStarted { input, .. } if input == &serde_json::json!({"expr": "**2"})
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_output_affixes_stream_consistently_with_history() {
+        let mut agent = tool_then_answer_agent("It is 42.")
+            .with_output_prefix("[bot] ")
+            .with_output_suffix(" Not financial advice.");
+
+        let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+        let mut streamed: Vec<String> = Vec::new();
+        let mut tool_json = String::new();
+        while let Some(event) = stream.next().await {
+            match event.unwrap() {
+                AgentEvent::IterationStarted { .. } => streamed.push(String::new()),
+                AgentEvent::LlmEvent(StreamEvent::ContentDelta { delta, .. }) => match delta {
+                    ContentDelta::TextDelta { text } => streamed.last_mut().unwrap().push_str(&text),
+                    ContentDelta::ToolUseDelta { partial } => tool_json.push_str(&partial.partial_json),
+                    ContentDelta::ThinkingDelta { .. } => {}
+                },
+                _ => {}
+            }
+        }
+        drop(stream);
+
+        // What SSE consumers saw matches what was stored, turn by turn
+        let messages = agent.messages();
+        assert_eq!(streamed, vec!["[bot] **Checking**", "[bot] It is 42. Not financial advice."]);
+        assert_eq!(message_text(&messages[1]), streamed[0]);
+        assert_eq!(message_text(messages.last().unwrap()), streamed[1]);
+
+        // Tool input deltas pass through unchanged
+        assert_eq!(tool_json, r#"{"expr": "**2"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_output_affixes_ignored_for_structured_output() {
+        let call_count = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let mut agent = json_agent(
+            vec![text_response(r#"{"city": "SF", "temperature": 18}"#)],
+            call_count.clone(),
+        )
+        .with_output_prefix("Answer: ")
+        .with_output_suffix(" (unverified)");
+
+        let mut stream = agent.run("Weather in SF as JSON").await.unwrap();
+        while let Some(event) = stream.next().await {
+            assert!(!matches!(event.unwrap(), AgentEvent::JsonRepairRequested { .. }));
+        }
+        drop(stream);
+
+        assert_eq!(*call_count.lock().unwrap(), 1);
+        assert_eq!(
+            message_text(agent.messages().last().unwrap()),
+            r#"{"city": "SF", "temperature": 18}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_moderator_redacts_final_answer() {
+        use crate::llm::moderation::KeywordModerator;
+
+        let mut agent = tool_then_answer_agent("Your password is hunter2.")
+            .with_moderator(Arc::new(KeywordModerator::new(["hunter2"])));
+
+        let mut stream = agent.run("What's my password?").await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await {
+            events.push(event.unwrap());
+        }
+        drop(stream);
+
+        let redacted: Vec<&String> = events
+            .iter()
+            .filter_map(|e| match e {
+                AgentEvent::OutputRedacted { text } => Some(text),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(redacted, vec!["Your password is [redacted]."]);
+        assert!(matches!(events.last(), Some(AgentEvent::Completed { .. })));
+        assert_eq!(
+            message_text(agent.messages().last().unwrap()),
+            "Your password is [redacted]."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_moderator_blocks_final_answer() {
+        struct BlockAll;
+
+        #[async_trait]
+        impl Moderator for BlockAll {
+            async fn check_input(&self, _text: &str) -> ModerationDecision {
+                ModerationDecision::Allow
+            }
+
+            async fn check_output(&self, _text: &str) -> ModerationDecision {
+                ModerationDecision::Block("unsafe".to_string())
+            }
+        }
+
+        let mut agent = tool_then_answer_agent("Something unsafe").with_moderator(Arc::new(BlockAll));
+
+        let mut stream = agent.run("hello").await.unwrap();
+        let mut last = None;
+        while let Some(event) = stream.next().await {
+            last = Some(event);
+        }
+        drop(stream);
+
+        assert!(matches!(last, Some(Err(AgentError::OutputBlocked(ref reason))) if reason == "unsafe"));
+        // The blocked answer never reaches history; the tool turn does
+        assert_eq!(agent.messages().len(), 3);
+        assert_eq!(agent.messages()[2].role, MessageRole::Tool);
+    }
+
+    #[tokio::test]
+    async fn test_moderator_passes_clean_output_through() {
+        use crate::llm::moderation::NoopModerator;
+
+        let mut agent = tool_then_answer_agent("All good.").with_moderator(Arc::new(NoopModerator));
+
+        let mut stream = agent.run("hello").await.unwrap();
+        while let Some(event) = stream.next().await {
+            assert!(!matches!(event.unwrap(), AgentEvent::OutputRedacted { .. }));
+        }
+        drop(stream);
+
+        assert_eq!(message_text(agent.messages().last().unwrap()), "All good.");
+    }
+
+    #[test]
+    fn test_parse_json_output_strips_fences() {
+        assert_eq!(parse_json_output(" {\"a\": 1} ").unwrap()["a"], 1);
+        assert_eq!(parse_json_output("```json\n{\"a\": 1}\n```").unwrap()["a"], 1);
+        assert_eq!(parse_json_output("```\n[1]\n```").unwrap()[0], 1);
+        assert!(parse_json_output("```json\n{").is_err());
+    }
+
+    /// One turn calling two tools: `weather` (tool-1) and `forecast` (tool-2)
+    fn two_tool_calls_response() -> Vec<StreamEvent> {
+        use crate::llm::core::types::{FinishReason, PartialToolUse, UsageMetadata};
+
+        let mut events = Vec::new();
+        for (index, (id, name)) in [("tool-1", "weather"), ("tool-2", "forecast")].into_iter().enumerate() {
+            events.push(StreamEvent::ContentBlockStart {
+                index,
+                block: ContentBlockStart::ToolUse {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                },
+            });
+            events.push(StreamEvent::ContentDelta {
+                index,
+                delta: ContentDelta::ToolUseDelta {
+                    partial: PartialToolUse {
+                        id: None,
+                        name: None,
+                        partial_json: r#"{"city": "Paris"}"#.to_string(),
+                    },
+                },
+            });
+            events.push(StreamEvent::ContentBlockEnd { index });
+        }
+        events.push(StreamEvent::MessageEnd {
+            finish_reason: FinishReason::ToolUse,
+            usage: UsageMetadata::new(10, 5),
+        });
+        events
+    }
+
+    fn citing_agent(answer: &str) -> Agent {
+        Agent::new(
+            Box::new(MockProvider {
+                responses: vec![two_tool_calls_response(), text_response(answer)],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            Some("You are a weather bot.".to_string()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_citations_resolved_from_final_answer() {
+        let mut agent = citing_agent(
+            "It is sunny [T2] and 18°C [T1], rising to 30°C [T7].\n\n```\nreadings[T1]\n```\nSee [T1].",
+        )
+        .with_citations(true);
+
+        let mut stream = agent.run("Weather in Paris?").await.unwrap();
+        let mut last = None;
+        while let Some(event) = stream.next().await {
+            last = Some(event.unwrap());
+        }
+        drop(stream);
+
+        let Some(AgentEvent::Completed { citations, unresolved_citations, .. }) = last else {
+            panic!("expected Completed, got {:?}", last);
+        };
+        assert_eq!(
+            citations,
+            vec![
+                Citation {
+                    key: "T2".to_string(),
+                    tool_use_id: "tool-2".to_string(),
+                    name: "forecast".to_string(),
+                },
+                Citation {
+                    key: "T1".to_string(),
+                    tool_use_id: "tool-1".to_string(),
+                    name: "weather".to_string(),
+                },
+            ]
+        );
+        assert_eq!(unresolved_citations, vec!["T7"]);
+
+        // Tool results carry their keys in history
+        let results: Vec<&str> = agent
+            .messages()
+            .iter()
+            .flat_map(|m| &m.content)
+            .filter_map(|block| match block {
+                ContentBlock::ToolResult { content, .. } => Some(content.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(results, vec!["[T1]\n{\"result\":42}", "[T2]\n{\"result\":42}"]);
+
+        let system = agent.system_prompt().unwrap().unwrap();
+        assert!(system.starts_with("You are a weather bot.\n\n"));
+        assert!(system.ends_with(CITATION_INSTRUCTIONS));
+    }
+
+    #[tokio::test]
+    async fn test_citations_off_by_default() {
+        let mut agent = citing_agent("It is sunny [T2].");
+
+        let mut stream = agent.run("Weather in Paris?").await.unwrap();
+        let mut last = None;
+        while let Some(event) = stream.next().await {
+            last = Some(event.unwrap());
+        }
+        drop(stream);
+
+        assert!(matches!(
+            last,
+            Some(AgentEvent::Completed { ref citations, ref unresolved_citations, .. })
+                if citations.is_empty() && unresolved_citations.is_empty()
+        ));
+        assert!(agent.messages().iter().flat_map(|m| &m.content).all(|block| match block {
+            ContentBlock::ToolResult { content, .. } => !content.starts_with("[T"),
+            _ => true,
+        }));
+        assert_eq!(
+            agent.system_prompt().unwrap().as_deref(),
+            Some("You are a weather bot.")
+        );
+    }
+
+    /// Executor where `weather` is slower than `forecast`, tracking peak concurrency
+    #[derive(Default, Clone)]
+    struct SlowExecutor {
+        in_flight: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        peak: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl ToolExecutor for SlowExecutor {
+        async fn execute(
+            &self,
+            _tool_use_id: String,
+            name: String,
+            _arguments: serde_json::Value,
+        ) -> Result<String, String> {
+            use std::sync::atomic::Ordering;
+
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            let delay = if name == "weather" { 50 } else { 10 };
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            if name == "forecast" {
+                Err("forecast unavailable".to_string())
+            } else {
+                Ok(format!("{} ok", name))
+            }
+        }
+    }
+
+    // Executor whose tools never finish in time
+    struct HangingExecutor;
+
+    #[async_trait]
+    impl ToolExecutor for HangingExecutor {
+        async fn execute(
+            &self,
+            _tool_use_id: String,
+            _name: String,
+            _arguments: serde_json::Value,
+        ) -> Result<String, String> {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok("too late".to_string())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tool_timeout_fails_the_call_and_continues() {
+        let call_count = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![
+                    tool_call_response("Let me calculate", r#"{"expr": "6 * 7"}"#),
+                    text_response("The calculator timed out."),
+                ],
+                call_count: call_count.clone(),
+            }),
+            Box::new(HangingExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_tool_timeout(Duration::from_millis(50));
+
+        let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+        let mut failure = None;
+        let mut completed = false;
+        while let Some(event) = stream.next().await {
+            match event.unwrap() {
+                AgentEvent::ToolExecutionFailed { name, error, .. } => failure = Some((name, error)),
+                AgentEvent::Completed { .. } => completed = true,
+                _ => {}
+            }
+        }
+        drop(stream);
+
+        let expected = "tool 'calculator' timed out after 50ms";
+        assert_eq!(
+            failure,
+            Some(("calculator".to_string(), expected.to_string()))
+        );
+        assert!(completed);
+        assert_eq!(*call_count.lock().unwrap(), 2);
+        assert!(agent
+            .messages()
+            .contains(&Message::tool_error("tool-1", expected)));
+    }
+
+    /// Run the two-tool script and return (tool events, tool result ids in history, peak concurrency)
+    async fn run_two_tools(parallel: bool) -> (Vec<String>, Vec<String>, usize) {
+        let executor = SlowExecutor::default();
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![two_tool_calls_response(), text_response("Done.")],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(executor.clone()),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_parallel_tools(parallel);
+
+        let mut stream = agent.run("Weather in Paris?").await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await {
+            match event.unwrap() {
+                AgentEvent::ToolExecutionStarted { name, .. } => events.push(format!("start {}", name)),
+                AgentEvent::ToolExecutionCompleted { name, .. } => events.push(format!("done {}", name)),
+                AgentEvent::ToolExecutionFailed { name, .. } => events.push(format!("fail {}", name)),
+                _ => {}
+            }
+        }
+        drop(stream);
+
+        let results = agent
+            .messages()
+            .iter()
+            .flat_map(|m| &m.content)
+            .filter_map(|block| match block {
+                ContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id.clone()),
+                _ => None,
+            })
+            .collect();
+        let peak = executor.peak.load(std::sync::atomic::Ordering::SeqCst);
+        (events, results, peak)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_parallel_tools_complete_out_of_order_but_keep_history_order() {
+        let (events, results, peak) = run_two_tools(true).await;
+
+        assert_eq!(peak, 2);
+        assert_eq!(
+            events,
+            vec!["start weather", "start forecast", "fail forecast", "done weather"]
+        );
+        assert_eq!(results, vec!["tool-1", "tool-2"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sequential_tools_when_parallel_disabled() {
+        let (events, results, peak) = run_two_tools(false).await;
+
+        assert_eq!(peak, 1);
+        assert_eq!(
+            events,
+            vec!["start weather", "done weather", "start forecast", "fail forecast"]
+        );
+        assert_eq!(results, vec!["tool-1", "tool-2"]);
+    }
+
+    /// Time the two-tool script, leaving the parallel setting at its default when `None`
+    async fn time_two_tools(parallel: Option<bool>) -> Duration {
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![two_tool_calls_response(), text_response("Done.")],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(SlowExecutor::default()),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+        if let Some(parallel) = parallel {
+            agent = agent.with_parallel_tools(parallel);
+        }
+
+        let started = tokio::time::Instant::now();
+        let mut stream = agent.run("Weather in Paris?").await.unwrap();
+        while let Some(event) = stream.next().await {
+            event.unwrap();
+        }
+        started.elapsed()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_parallel_tools_take_the_slowest_call_not_the_sum() {
+        // weather sleeps 50ms and forecast 10ms
+        let parallel = time_two_tools(Some(true)).await;
+        let sequential = time_two_tools(Some(false)).await;
+        let default = time_two_tools(None).await;
+
+        assert!(parallel >= Duration::from_millis(50), "{:?}", parallel);
+        assert!(parallel < Duration::from_millis(60), "{:?}", parallel);
+        assert!(sequential >= Duration::from_millis(60), "{:?}", sequential);
+        assert_eq!(default, sequential);
+    }
+
+    /// Provider that records the `max_tokens` of each request it receives
+    struct BudgetRecordingProvider {
+        responses: Vec<Vec<StreamEvent>>,
+        budgets: std::sync::Arc<std::sync::Mutex<Vec<u32>>>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for BudgetRecordingProvider {
+        async fn stream_generate(
+            &self,
+            request: GenerateRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>
+        {
+            let mut budgets = self.budgets.lock().unwrap();
+            budgets.push(request.config.max_tokens);
+
+            let events = self.responses[budgets.len() - 1].clone();
+            Ok(Box::pin(futures::stream::iter(events.into_iter().map(Ok))))
+        }
+    }
+
+    /// Run a capped agent, returning the budgets sent, the events and the agent
+    async fn run_with_budget_cap(
+        responses: Vec<Vec<StreamEvent>>,
+    ) -> (Vec<u32>, Vec<AgentEvent>, Agent) {
+        let budgets = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut agent = Agent::new(
+            Box::new(BudgetRecordingProvider {
+                responses,
+                budgets: budgets.clone(),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_max_output_tokens_per_iteration(200);
+
+        let mut events = Vec::new();
+        {
+            let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+            while let Some(event) = stream.next().await {
+                events.push(event.unwrap());
+            }
+        }
+
+        let budgets = budgets.lock().unwrap().clone();
+        (budgets, events, agent)
+    }
+
+    fn iteration_budgets(events: &[AgentEvent]) -> Vec<u32> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                AgentEvent::IterationStarted { max_tokens, .. } => Some(*max_tokens),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_iterations_use_the_capped_budget() {
+        let (budgets, events, _) = run_with_budget_cap(vec![
+            tool_call_response("Let me calculate", r#"{"expr": "6 * 7"}"#),
+            text_response("42"),
+        ])
+        .await;
+
+        assert_eq!(budgets, vec![200, 200]);
+        assert_eq!(iteration_budgets(&events), vec![200, 200]);
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, AgentEvent::OutputBudgetExhausted { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_truncated_answer_is_retried_with_full_budget() {
+        use crate::llm::core::types::UsageMetadata;
+
+        let truncated = vec![
+            text_delta("Well, to explain how multiplication works we first"),
+            StreamEvent::MessageEnd {
+                finish_reason: FinishReason::MaxTokens,
+                usage: UsageMetadata::new(10, 200),
+            },
+        ];
+        let (budgets, events, agent) = run_with_budget_cap(vec![
+            tool_call_response("Let me calculate", r#"{"expr": "6 * 7"}"#),
+            truncated,
+            text_response("6 * 7 is 42."),
+        ])
+        .await;
+
+        assert_eq!(budgets, vec![200, 200, 1024]);
+        assert_eq!(iteration_budgets(&events), vec![200, 200, 1024]);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            AgentEvent::OutputBudgetExhausted { iteration: 2, max_tokens: 200 }
+        )));
+        assert!(matches!(events.last(), Some(AgentEvent::Completed { .. })));
+
+        // The cut-off answer never reaches history
+        let history: Vec<String> = agent.messages().iter().map(message_text).collect();
+        assert!(!history.iter().any(|text| text.contains("Well, to explain")));
+        assert_eq!(history.last().unwrap(), "6 * 7 is 42.");
+    }
+
+    #[tokio::test]
+    async fn test_cap_never_exceeds_config_budget() {
+        let budgets = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut agent = Agent::new(
+            Box::new(BudgetRecordingProvider {
+                responses: vec![text_response("42")],
+                budgets: budgets.clone(),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(100),
+            None,
+        )
+        .with_max_output_tokens_per_iteration(200);
+
+        let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+        while let Some(event) = stream.next().await {
+            event.unwrap();
+        }
+        drop(stream);
+
+        assert_eq!(*budgets.lock().unwrap(), vec![100]);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_after_first_iteration_leaves_coherent_history() {
+        let call_count = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![
+                    tool_call_response("Let me calculate", r#"{"expr": "6 * 7"}"#),
+                    text_response("6 * 7 is 42."),
+                ],
+                call_count: call_count.clone(),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+        let cancel = CancellationToken::new();
+
+        let mut events = Vec::new();
+        {
+            let mut stream = agent
+                .run_cancellable("What is 6 * 7?", cancel.clone())
+                .await
+                .unwrap();
+            while let Some(event) = stream.next().await {
+                let event = event.unwrap();
+                if matches!(event, AgentEvent::ToolExecutionCompleted { .. }) {
+                    cancel.cancel();
+                }
+                events.push(event);
+            }
+        }
+
+        assert!(matches!(events.last(), Some(AgentEvent::Cancelled)));
+        assert_eq!(*call_count.lock().unwrap(), 1);
+
+        // user, assistant tool call, tool result
+        let roles: Vec<MessageRole> = agent.messages().iter().map(|m| m.role).collect();
+        assert_eq!(roles, vec![MessageRole::User, MessageRole::Assistant, MessageRole::Tool]);
+        assert!(Message::validate_transcript(agent.messages()).is_ok());
+
+        // The conversation can be resumed
+        let mut stream = agent.run("Go on").await.unwrap();
+        let mut completed = false;
+        while let Some(event) = stream.next().await {
+            completed |= matches!(event.unwrap(), AgentEvent::Completed { .. });
+        }
+        drop(stream);
+        assert!(completed);
+        assert_eq!(message_text(agent.messages().last().unwrap()), "6 * 7 is 42.");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_before_run_starts_calls_nothing() {
+        let call_count = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![text_response("Hi")],
+                call_count: call_count.clone(),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let mut stream = agent.run_cancellable("Hello", cancel).await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await {
+            events.push(event.unwrap());
+        }
+        drop(stream);
+
+        assert!(matches!(events.as_slice(), [AgentEvent::Cancelled]));
+        assert_eq!(*call_count.lock().unwrap(), 0);
+        assert_eq!(agent.messages().len(), 1);
+    }
+
+    /// Streams one text delta and then nothing, flagging when the stream is dropped
+    struct EndlessProvider {
+        dropped: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    struct DropFlag(std::sync::Arc<std::sync::atomic::AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[async_trait]
+    impl LlmProvider for EndlessProvider {
+        async fn stream_generate(
+            &self,
+            _request: GenerateRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>
+        {
+            let guard = DropFlag(self.dropped.clone());
+            let first = StreamEvent::ContentDelta {
+                index: 0,
+                delta: ContentDelta::TextDelta {
+                    text: "Thinking".to_string(),
+                },
+            };
+            let endless = futures::stream::pending().map(move |event| {
+                let _ = &guard;
+                event
+            });
+            Ok(Box::pin(futures::stream::iter(vec![Ok(first)]).chain(endless)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_aborts_the_llm_stream_before_reporting() {
+        let dropped = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let mut agent = Agent::new(
+            Box::new(EndlessProvider {
+                dropped: dropped.clone(),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+        let cancel = CancellationToken::new();
+
+        let mut stream = agent.run_cancellable("Hello", cancel.clone()).await.unwrap();
+        loop {
+            let event = stream.next().await.unwrap().unwrap();
+            if matches!(event, AgentEvent::LlmEvent(StreamEvent::ContentDelta { .. })) {
+                break;
+            }
+        }
+        assert!(!dropped.load(std::sync::atomic::Ordering::SeqCst));
+        cancel.cancel();
+
+        // The response stream is gone by the time the caller hears about the cancel
+        assert!(matches!(stream.next().await, Some(Ok(AgentEvent::Cancelled))));
+        assert!(dropped.load(std::sync::atomic::Ordering::SeqCst));
+        drop(stream);
+    }
+
+    /// Run the two-tool script, cancelling 5ms after the first tool starts
+    ///
+    /// Returns the tool events and the (id, content) of each tool result in history.
+    async fn run_two_tools_cancelled(parallel: bool) -> (Vec<String>, Vec<(String, String)>, Agent) {
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![two_tool_calls_response(), text_response("Done.")],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(SlowExecutor::default()),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_parallel_tools(parallel);
+        let cancel = CancellationToken::new();
+
+        let mut events = Vec::new();
+        {
+            let mut stream = agent
+                .run_cancellable("Weather in Paris?", cancel.clone())
+                .await
+                .unwrap();
+            while let Some(event) = stream.next().await {
+                match event.unwrap() {
+                    AgentEvent::ToolExecutionStarted { name, .. } => {
+                        if events.is_empty() {
+                            let cancel = cancel.clone();
+                            tokio::spawn(async move {
+                                tokio::time::sleep(Duration::from_millis(5)).await;
+                                cancel.cancel();
+                            });
+                        }
+                        events.push(format!("start {}", name));
+                    }
+                    AgentEvent::ToolExecutionCompleted { name, .. } => events.push(format!("done {}", name)),
+                    AgentEvent::ToolExecutionFailed { name, error, .. } => {
+                        events.push(format!("fail {}: {}", name, error))
+                    }
+                    AgentEvent::Cancelled => events.push("cancelled".to_string()),
+                    _ => {}
+                }
+            }
+        }
+
+        let results = agent
+            .messages()
+            .iter()
+            .flat_map(|m| &m.content)
+            .filter_map(|block| match block {
+                ContentBlock::ToolResult { tool_use_id, content, .. } => {
+                    Some((tool_use_id.clone(), content.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        (events, results, agent)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancel_mid_tool_records_errors_for_issued_calls() {
+        let cancelled = |id: &str| (id.to_string(), TOOL_CANCELLED_ERROR.to_string());
+
+        // Sequential: weather is interrupted, forecast never starts
+        let (events, results, agent) = run_two_tools_cancelled(false).await;
+        assert_eq!(
+            events,
+            vec![
+                "start weather",
+                "fail weather: Tool execution was cancelled",
+                "cancelled"
+            ]
+        );
+        assert_eq!(results, vec![cancelled("tool-1"), cancelled("tool-2")]);
+        assert!(Message::validate_transcript(agent.messages()).is_ok());
+
+        // Parallel: both were running when the token fired
+        let (events, results, agent) = run_two_tools_cancelled(true).await;
+        assert_eq!(
+            events,
+            vec![
+                "start weather",
+                "start forecast",
+                "fail weather: Tool execution was cancelled",
+                "fail forecast: Tool execution was cancelled",
+                "cancelled"
+            ]
+        );
+        assert_eq!(results, vec![cancelled("tool-1"), cancelled("tool-2")]);
+        assert!(Message::validate_transcript(agent.messages()).is_ok());
+    }
+
+    fn budgeted_agent(budget: u32, call_count: std::sync::Arc<std::sync::Mutex<usize>>) -> Agent {
+        // The tool call response uses 14 tokens, the answer 15
+        Agent::new(
+            Box::new(MockProvider {
+                responses: vec![
+                    tool_call_response("Let me calculate", r#"{"expr": "6 * 7"}"#),
+                    text_response("42"),
+                ],
+                call_count,
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_token_budget(budget)
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_stops_the_loop() {
+        let call_count = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let mut agent = budgeted_agent(20, call_count.clone());
+
+        let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+        let mut last = None;
+        while let Some(event) = stream.next().await {
+            last = Some(event);
+        }
+        drop(stream);
+
+        match last {
+            Some(Err(AgentError::TokenBudgetExceeded { used, budget })) => {
+                assert_eq!(used, 29);
+                assert_eq!(budget, 20);
+            }
+            other => panic!("expected TokenBudgetExceeded, got {:?}", other),
+        }
+        assert_eq!(*call_count.lock().unwrap(), 2);
+
+        // The response that crossed the budget isn't stored
+        assert_eq!(agent.messages().len(), 3);
+        assert!(Message::validate_transcript(agent.messages()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_allows_runs_within_it() {
+        let mut agent = budgeted_agent(29, std::sync::Arc::new(std::sync::Mutex::new(0)));
+
+        let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+        let mut completed = false;
+        while let Some(event) = stream.next().await {
+            completed |= matches!(event.unwrap(), AgentEvent::Completed { .. });
+        }
+        drop(stream);
+
+        assert!(completed);
+    }
+
+    #[tokio::test]
+    async fn test_redacted_thinking_is_kept_in_history() {
+        use crate::llm::core::types::{FinishReason, UsageMetadata};
+
+        let data = "EmwKAhgBEgy3va3pzix".to_string();
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![vec![
+                    StreamEvent::ContentBlockStart {
+                        index: 0,
+                        block: ContentBlockStart::RedactedThinking { data: data.clone() },
+                    },
+                    StreamEvent::ContentBlockEnd { index: 0 },
+                    StreamEvent::ContentBlockStart {
+                        index: 1,
+                        block: ContentBlockStart::Text {
+                            text: String::new(),
+                        },
+                    },
+                    StreamEvent::ContentDelta {
+                        index: 1,
+                        delta: ContentDelta::TextDelta {
+                            text: "The answer is 42.".to_string(),
+                        },
+                    },
+                    StreamEvent::ContentBlockEnd { index: 1 },
+                    StreamEvent::MessageEnd {
+                        finish_reason: FinishReason::EndTurn,
+                        usage: UsageMetadata::new(10, 20),
+                    },
+                ]],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(4096).with_thinking_budget(1024),
+            None,
+        );
+
+        let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+        while let Some(event) = stream.next().await {
+            event.unwrap();
+        }
+        drop(stream);
+
+        // Claude needs the encrypted block back, so unlike thinking it is stored
+        let answer = &agent.messages()[1];
+        assert_eq!(
+            answer.content,
+            vec![
+                ContentBlock::RedactedThinking { data },
+                ContentBlock::Text {
+                    text: "The answer is 42.".to_string()
+                }
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_thinking_is_streamed_but_not_stored() {
+        use crate::llm::core::types::{FinishReason, UsageMetadata};
+
+        let thinking = "6 * 7 is 42";
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![vec![
+                    StreamEvent::ContentBlockStart {
+                        index: 0,
+                        block: ContentBlockStart::Thinking {
+                            thinking: String::new(),
+                        },
+                    },
+                    StreamEvent::ContentDelta {
+                        index: 0,
+                        delta: ContentDelta::ThinkingDelta {
+                            thinking: thinking.to_string(),
+                        },
+                    },
+                    StreamEvent::ContentBlockEnd { index: 0 },
+                    StreamEvent::ContentBlockStart {
+                        index: 1,
+                        block: ContentBlockStart::Text {
+                            text: String::new(),
+                        },
+                    },
+                    StreamEvent::ContentDelta {
+                        index: 1,
+                        delta: ContentDelta::TextDelta {
+                            text: "The answer is 42.".to_string(),
+                        },
+                    },
+                    StreamEvent::ContentBlockEnd { index: 1 },
+                    StreamEvent::MessageEnd {
+                        finish_reason: FinishReason::EndTurn,
+                        usage: UsageMetadata::new(10, 20),
+                    },
+                ]],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(4096).with_thinking_budget(1024),
+            None,
+        );
+
+        let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+        let mut streamed_thinking = String::new();
+        while let Some(event) = stream.next().await {
+            if let AgentEvent::LlmEvent(StreamEvent::ContentDelta {
+                delta: ContentDelta::ThinkingDelta { thinking },
+                ..
+            }) = event.unwrap()
+            {
+                streamed_thinking.push_str(&thinking);
+            }
+        }
+        drop(stream);
+
+        assert_eq!(streamed_thinking, thinking);
+        let answer = &agent.messages()[1];
+        assert_eq!(
+            answer.content,
+            vec![ContentBlock::Text {
+                text: "The answer is 42.".to_string()
+            }]
+        );
+    }
+
+    // Executor whose tools all have side effects, recording compensations
+    struct CompensatingExecutor {
+        compensated: std::sync::Arc<std::sync::Mutex<Vec<(String, serde_json::Value)>>>,
+    }
+
+    #[async_trait]
+    impl ToolExecutor for CompensatingExecutor {
+        async fn execute(
+            &self,
+            _tool_use_id: String,
+            _name: String,
+            _arguments: serde_json::Value,
+        ) -> Result<String, String> {
+            Ok(serde_json::json!({"result": 42}).to_string())
+        }
+
+        fn has_side_effects(&self, _name: &str) -> bool {
+            true
+        }
+
+        async fn compensate(
+            &self,
+            name: String,
+            input: serde_json::Value,
+            _result: String,
+        ) -> Option<Result<(), String>> {
+            self.compensated.lock().unwrap().push((name, input));
+            Some(Ok(()))
+        }
+    }
+
+    fn compensating_agent(
+        responses: Vec<Vec<StreamEvent>>,
+        compensated: std::sync::Arc<std::sync::Mutex<Vec<(String, serde_json::Value)>>>,
+    ) -> Agent {
+        Agent::new(
+            Box::new(MockProvider {
+                responses,
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(CompensatingExecutor { compensated }),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_failed_run_compensates_side_effects() {
+        let compensated = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        // The second LLM call fails: the provider has no more responses
+        let mut agent = compensating_agent(
+            vec![tool_call_response("Charging", r#"{"amount": 10}"#)],
+            compensated.clone(),
+        );
+
+        let mut stream = agent.run("Charge the card").await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await {
+            events.push(event);
+        }
+        drop(stream);
+
+        let tail: Vec<_> = events.iter().rev().take(2).collect();
+        assert!(matches!(tail[0], Err(AgentError::Llm(_))));
+        assert!(matches!(
+            tail[1],
+            Ok(AgentEvent::CompensationExecuted { name, ok: true }) if name == "calculator"
+        ));
+        let compensations = events
+            .iter()
+            .filter(|e| matches!(e, Ok(AgentEvent::CompensationExecuted { .. })))
+            .count();
+        assert_eq!(compensations, 1);
+        assert_eq!(
+            *compensated.lock().unwrap(),
+            vec![("calculator".to_string(), serde_json::json!({"amount": 10}))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_successful_run_keeps_side_effects() {
+        let compensated = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut agent = compensating_agent(
+            vec![
+                tool_call_response("Charging", r#"{"amount": 10}"#),
+                text_response("Done."),
+            ],
+            compensated.clone(),
+        );
+
+        let mut stream = agent.run("Charge the card").await.unwrap();
+        while let Some(event) = stream.next().await {
+            assert!(!matches!(
+                event.unwrap(),
+                AgentEvent::CompensationExecuted { .. }
+            ));
+        }
+        drop(stream);
+
+        assert!(compensated.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_reported_per_iteration_and_in_total() {
+        use crate::llm::core::types::UsageMetadata;
+
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![
+                    tool_call_response("Let me calculate", r#"{"expr": "6 * 7"}"#),
+                    text_response("6 * 7 = 42"),
+                ],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+
+        let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+        let mut updates = Vec::new();
+        let mut total = None;
+        let mut iterations = None;
+        while let Some(event) = stream.next().await {
+            match event.unwrap() {
+                AgentEvent::UsageUpdated {
+                    iteration_usage,
+                    cumulative_usage,
+                } => updates.push((iteration_usage, cumulative_usage)),
+                AgentEvent::Completed {
+                    total_usage,
+                    iterations: n,
+                    ..
+                } => {
+                    total = Some(total_usage);
+                    iterations = Some(n);
+                }
+                _ => {}
+            }
+        }
+        drop(stream);
+
+        assert_eq!(
+            updates,
+            vec![
+                (UsageMetadata::new(10, 4), UsageMetadata::new(10, 4)),
+                (UsageMetadata::new(10, 5), UsageMetadata::new(20, 9)),
+            ]
+        );
+        assert_eq!(total, Some(UsageMetadata::new(20, 9)));
+        assert_eq!(iterations, Some(2));
+        assert_eq!(agent.last_run_usage(), Some(UsageMetadata::new(20, 9)));
+    }
+
+    /// Provider whose responses fail after reporting some usage
+    struct FailingMidStreamProvider;
+
+    #[async_trait]
+    impl LlmProvider for FailingMidStreamProvider {
+        async fn stream_generate(
+            &self,
+            _request: GenerateRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>
+        {
+            use crate::llm::core::types::UsageMetadata;
+
+            let events = vec![
+                Ok(StreamEvent::MessageStart {
+                    message: MessageMetadata {
+                        id: "msg_1".to_string(),
+                        role: MessageRole::Assistant,
+                        usage: Some(UsageMetadata::new(12, 1)),
+                    },
+                }),
+                Ok(text_delta("Partial answ")),
+                Ok(StreamEvent::MessageDelta {
+                    usage: Some(UsageMetadata::new(12, 3)),
+                }),
+                Err(LlmError::StreamError("connection reset".to_string())),
+            ];
+            Ok(Box::pin(futures::stream::iter(events)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_stream_counts_partial_usage() {
+        use crate::llm::core::types::UsageMetadata;
+
+        let mut agent = Agent::new(
+            Box::new(FailingMidStreamProvider),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+        assert_eq!(agent.last_run_usage(), None);
+
+        let mut stream = agent.run("What is 6 * 7?").await.unwrap();
+        let mut updates = Vec::new();
+        let mut failed = false;
+        while let Some(event) = stream.next().await {
+            match event {
+                Ok(AgentEvent::UsageUpdated {
+                    cumulative_usage, ..
+                }) => updates.push(cumulative_usage),
+                Ok(_) => {}
+                Err(_) => failed = true,
+            }
+        }
+        drop(stream);
+
+        assert!(failed);
+        assert_eq!(updates, vec![UsageMetadata::new(12, 3)]);
+        assert_eq!(agent.last_run_usage(), Some(UsageMetadata::new(12, 3)));
+    }
+
+    #[tokio::test]
+    async fn test_usage_of_responses_without_message_end_is_counted() {
+        use crate::llm::core::types::UsageMetadata;
+
+        // Tool-calling response cut off before MessageEnd, then a normal answer
+        let mut tool_call = tool_call_response("Let me calculate", r#"{"expr": "6 * 7"}"#);
+        tool_call.pop();
+        tool_call.push(StreamEvent::MessageDelta {
+            usage: Some(UsageMetadata::new(10, 2)),
+        });
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![tool_call, text_response("6 * 7 = 42")],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+
+        let result = agent.run_to_completion("What is 6 * 7?").await.unwrap();
+
+        assert_eq!(result.iterations, 2);
+        assert_eq!(result.usage, UsageMetadata::new(20, 7));
+        assert_eq!(agent.last_run_usage(), Some(UsageMetadata::new(20, 7)));
+    }
+
+    #[tokio::test]
+    async fn test_run_message_with_tool_result_continues_the_loop() {
+        let call_count = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![
+                    tool_call_response("Now the product", r#"{"expr": "6 * 7"}"#),
+                    text_response("The sum is 5 and the product is 42."),
+                ],
+                call_count: call_count.clone(),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+
+        // Few-shot prefix plus a tool call that was answered out-of-band
+        agent.push_message(Message::user("What is 1 + 1?"));
+        agent.push_message(Message::assistant("2"));
+        agent.push_message(Message::user("Add 2 and 3, then multiply 6 by 7"));
+        agent.push_message(assistant_message(
+            &[],
+            "",
+            &[ContentBlock::ToolUse {
+                id: "lookup-1".to_string(),
+                name: "calculator".to_string(),
+                input: serde_json::json!({"expr": "2 + 3"}),
+            }],
+        ));
+
+        let mut stream = agent
+            .run_message(Message::tool_result("lookup-1", "5"))
+            .await
+            .unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await {
+            events.push(event.unwrap());
+        }
+        drop(stream);
+
+        assert!(matches!(events.last(), Some(AgentEvent::Completed { .. })));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, AgentEvent::ToolExecutionCompleted { tool_use_id, .. } if tool_use_id == "tool-1")));
+        assert_eq!(*call_count.lock().unwrap(), 2);
+
+        let roles: Vec<MessageRole> = agent.messages().iter().map(|m| m.role).collect();
+        assert_eq!(
+            roles,
+            vec![
+                MessageRole::User,
+                MessageRole::Assistant,
+                MessageRole::User,
+                MessageRole::Assistant,
+                MessageRole::Tool,
+                MessageRole::Assistant,
+                MessageRole::Tool,
+                MessageRole::Assistant,
+            ]
+        );
+        assert!(Message::validate_transcript(agent.messages()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_run_message_rejects_assistant_messages() {
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![text_response("Hi")],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+
+        let result = agent.run_message(Message::assistant("Hello")).await;
+        assert!(matches!(
+            result.err(),
+            Some(AgentError::InvalidRunMessage(MessageRole::Assistant))
+        ));
+        assert!(agent.messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_history_round_trips_through_json() {
+        let mut agent = tool_then_answer_agent("Done");
+        let mut stream = agent.run("Add things").await.unwrap();
+        while stream.next().await.is_some() {}
+        drop(stream);
+
+        let saved = serde_json::to_string(&agent.export_history()).unwrap();
+        let messages: Vec<Message> = serde_json::from_str(&saved).unwrap();
+
+        let restored = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_history(messages)
+        .unwrap();
+
+        assert_eq!(restored.messages(), agent.messages());
+        assert_eq!(restored.messages().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_last_run_summary_lists_tool_calls_and_answer() {
+        let mut agent = tool_then_answer_agent("The answer is 42");
+        assert!(agent.last_run_summary().is_none());
+
+        let mut stream = agent.run("Add things").await.unwrap();
+        while stream.next().await.is_some() {}
+        drop(stream);
+
+        let summary = agent.last_run_summary().unwrap();
+        assert_eq!(summary.final_answer.as_deref(), Some("The answer is 42"));
+        assert_eq!(summary.iterations, 2);
+        assert_eq!(summary.tool_calls.len(), 1);
+        assert_eq!(summary.tool_calls[0].name, "calculator");
+        assert!(summary.errors.is_empty());
+
+        let json = summary.to_compact_json();
+        assert_eq!(json["tool_calls"][0]["output"], "{\"result\":42}");
+        assert_eq!(json["usage"]["total_tokens"], summary.usage.total_tokens);
+    }
+
+    #[tokio::test]
+    async fn test_last_run_summary_records_errors() {
+        let mut agent = tool_then_answer_agent("Done").with_max_iterations(1);
+
+        let mut stream = agent.run("Add things").await.unwrap();
+        while stream.next().await.is_some() {}
+        drop(stream);
+
+        let summary = agent.last_run_summary().unwrap();
+        assert_eq!(summary.final_answer, None);
+        assert_eq!(
+            summary.errors,
+            vec!["Maximum iterations reached (1)".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_to_completion_returns_answer_and_tool_calls() {
+        let mut agent = tool_then_answer_agent("The answer is 42");
+
+        let result = agent.run_to_completion("Add things").await.unwrap();
+
+        assert_eq!(result.final_text, "The answer is 42");
+        assert_eq!(result.iterations, 2);
+        assert_eq!(result.tool_calls.len(), 1);
+        assert_eq!(result.tool_calls[0].name, "calculator");
+        assert_eq!(result.tool_calls[0].input, serde_json::json!({"expr": "**2"}));
+        assert_eq!(result.tool_calls[0].output.as_deref(), Some("{\"result\":42}"));
+        assert!(!result.tool_calls[0].is_error);
+        assert!(result.usage.total_tokens > 0);
+
+        // The result is the stored summary, not a second tally of the events
+        let summary = agent.last_run_summary().unwrap();
+        assert_eq!(summary.final_answer.as_deref(), Some(result.final_text.as_str()));
+        assert_eq!(summary.tool_calls, result.tool_calls);
+        assert_eq!(summary.iterations, result.iterations);
+        assert_eq!(summary.usage, result.usage);
+    }
+
+    #[tokio::test]
+    async fn test_run_to_completion_returns_run_error() {
+        let mut agent = tool_then_answer_agent("Done").with_max_iterations(1);
+
+        let result = agent.run_to_completion("Add things").await;
+
+        assert!(matches!(result, Err(AgentError::MaxIterationsReached(1))));
+    }
+
+    #[test]
+    fn test_import_history_rejects_trailing_tool_use() {
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+        agent.push_message(Message::user("kept"));
+
+        let result = agent.import_history(vec![
+            Message::user("What is 2 + 2?"),
+            Message {
+                role: MessageRole::Assistant,
+                content: vec![ContentBlock::ToolUse {
+                    id: "tool-1".to_string(),
+                    name: "calculator".to_string(),
+                    input: serde_json::json!({}),
+                }],
+            },
+        ]);
+
+        assert!(matches!(
+            result,
+            Err(AgentError::InvalidTranscript(
+                crate::llm::core::types::TranscriptError::OrphanToolUse { .. }
+            ))
+        ));
+        assert_eq!(agent.messages(), &[Message::user("kept")]);
+    }
+
+    #[tokio::test]
+    async fn test_fork_evolves_independently_of_original() {
+        // Both agents share the provider, so its responses are served in call order
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![
+                    tool_call_response("**Checking**", r#"{"expr": "**2"}"#),
+                    text_response("The answer is 42"),
+                    text_response("Original follow-up"),
+                    text_response("Branch follow-up"),
+                ],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_max_iterations(3);
+        agent.run_to_completion("Add things").await.unwrap();
+        assert_eq!(agent.messages().len(), 4);
+
+        // Branch before the final answer was given
+        let mut branch = agent.fork_at(3).unwrap();
+        assert_eq!(branch.messages(), &agent.messages()[..3]);
+        assert_eq!(branch.max_iterations, 3);
+        assert!(branch.last_run_summary().is_none());
+
+        let original = agent.run_to_completion("And then?").await.unwrap();
+        assert_eq!(original.final_text, "Original follow-up");
+
+        let forked = branch.run_to_completion("What if?").await.unwrap();
+        assert_eq!(forked.final_text, "Branch follow-up");
+
+        assert_eq!(agent.messages().len(), 6);
+        assert_eq!(branch.messages().len(), 5);
+        assert_eq!(branch.messages()[3], Message::user("What if?"));
+        assert_eq!(agent.messages()[4], Message::user("And then?"));
+    }
+
+    #[tokio::test]
+    async fn test_fork_rejects_stranded_tool_use_and_out_of_range_index() {
+        let mut agent = tool_then_answer_agent("Done");
+        agent.run_to_completion("Add things").await.unwrap();
+
+        // Cutting after the tool call but before its result
+        assert!(matches!(
+            agent.fork_at(2),
+            Err(AgentError::InvalidTranscript(
+                crate::llm::core::types::TranscriptError::OrphanToolUse { .. }
+            ))
+        ));
+        assert!(matches!(
+            agent.fork_at(5),
+            Err(AgentError::ForkOutOfRange { index: 5, len: 4 })
+        ));
+
+        assert!(agent.fork_at(0).unwrap().messages().is_empty());
+        assert_eq!(agent.fork_at(4).unwrap().messages(), agent.messages());
+    }
+
+    /// One response calling each of `(id, name)` with `{}`, ending with `usage`
+    fn multi_tool_response(calls: &[(&str, &str)], usage: UsageMetadata) -> Vec<StreamEvent> {
+        use crate::llm::core::types::{FinishReason, PartialToolUse};
+
+        let mut events = Vec::new();
+        for (index, (id, name)) in calls.iter().enumerate() {
+            events.push(StreamEvent::ContentBlockStart {
+                index,
+                block: ContentBlockStart::ToolUse {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                },
+            });
+            events.push(StreamEvent::ContentDelta {
+                index,
+                delta: ContentDelta::ToolUseDelta {
+                    partial: PartialToolUse {
+                        id: None,
+                        name: None,
+                        partial_json: "{}".to_string(),
+                    },
+                },
+            });
+            events.push(StreamEvent::ContentBlockEnd { index });
+        }
+        events.push(StreamEvent::MessageEnd {
+            finish_reason: FinishReason::ToolUse,
+            usage,
+        });
+        events
+    }
+
+    #[tokio::test]
+    async fn test_completed_attributes_tokens_to_tools() {
+        use crate::llm::core::types::FinishReason;
+
+        let answer = vec![
+            text_delta("Done"),
+            StreamEvent::MessageEnd {
+                finish_reason: FinishReason::EndTurn,
+                usage: UsageMetadata::new(260, 5),
+            },
+        ];
+        let mut agent = Agent::new(
+            Box::new(MockProvider {
+                responses: vec![
+                    // Both results are the same size, so they split the growth evenly
+                    multi_tool_response(
+                        &[("tool-1", "search"), ("tool-2", "lookup")],
+                        UsageMetadata::new(100, 20),
+                    ),
+                    multi_tool_response(&[("tool-3", "search")], UsageMetadata::new(160, 10)),
+                    answer,
+                ],
+                call_count: std::sync::Arc::new(std::sync::Mutex::new(0)),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        );
+
+        let mut stream = agent.run("Find it").await.unwrap();
+        let mut attribution = None;
+        while let Some(event) = stream.next().await {
+            if let AgentEvent::Completed { tool_token_attribution, .. } = event.unwrap() {
+                attribution = Some(tool_token_attribution);
+            }
+        }
+        drop(stream);
+
+        // Call 2: 160 - 100 - 20 = 40 tokens of results, 20 each.
+        // Call 3: 260 - 160 - 10 = 90 more search tokens, plus the 40 carried.
+        let attribution = attribution.expect("run completed");
+        assert_eq!(attribution["search"], 20 + (20 + 90));
+        assert_eq!(attribution["lookup"], 20 + 20);
+        assert_eq!(
+            agent.last_run_summary().unwrap().tool_token_attribution,
+            attribution
+        );
+    }
+
+    struct RequestRecordingProvider {
+        responses: Vec<Vec<StreamEvent>>,
+        requests: std::sync::Arc<std::sync::Mutex<Vec<GenerateRequest>>>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for RequestRecordingProvider {
+        async fn stream_generate(
+            &self,
+            request: GenerateRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>
+        {
+            let mut requests = self.requests.lock().unwrap();
+            requests.push(request);
+
+            let events = self.responses[requests.len() - 1].clone();
+            Ok(Box::pin(futures::stream::iter(events.into_iter().map(Ok))))
+        }
+    }
+
+    /// Run an agent answering in `ja-JP`, returning the requests sent, the events and the agent
+    async fn run_with_locale(
+        responses: Vec<Vec<StreamEvent>>,
+        locale_retry: bool,
+    ) -> (Vec<GenerateRequest>, Vec<AgentEvent>, Agent) {
+        let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut agent = Agent::new(
+            Box::new(RequestRecordingProvider {
+                responses,
+                requests: requests.clone(),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            Some("You are a weather assistant.".to_string()),
+        )
+        .with_locale("ja-JP")
+        .with_locale_retry(locale_retry);
+
+        let mut events = Vec::new();
+        {
+            let mut stream = agent.run("What's the weather in Tokyo?").await.unwrap();
+            while let Some(event) = stream.next().await {
+                events.push(event.unwrap());
+            }
+        }
+
+        let requests = requests.lock().unwrap().clone();
+        (requests, events, agent)
+    }
+
+    #[tokio::test]
+    async fn test_locale_is_sent_with_each_request() {
+        let (requests, events, _) = run_with_locale(
+            vec![
+                tool_call_response("Let me check", r#"{"city": "Tokyo"}"#),
+                text_response("東京は晴れです。"),
+            ],
+            true,
+        )
+        .await;
+
+        assert_eq!(requests.len(), 2);
+        for request in &requests {
+            assert_eq!(request.locale.as_deref(), Some("ja-JP"));
+            assert_eq!(request.system.as_deref(), Some("You are a weather assistant."));
+        }
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, AgentEvent::LocaleRetryRequested { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_language_answer_is_retried_once() {
+        use crate::llm::claude::mapper::to_claude_request;
+        use crate::llm::core::locale::locale_instruction;
+        use crate::llm::gemini::mapper::to_gemini_request;
+
+        let (requests, events, agent) = run_with_locale(
+            vec![
+                text_response("It is sunny in Tokyo."),
+                text_response("Still sunny in Tokyo."),
+            ],
+            true,
+        )
+        .await;
+
+        assert_eq!(requests.len(), 2);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            AgentEvent::LocaleRetryRequested { iteration: 1, locale } if locale == "ja-JP"
+        )));
+        assert!(matches!(events.last(), Some(AgentEvent::Completed { .. })));
+
+        // Providers send only the firmer instruction with the retry
+        let claude = serde_json::to_string(&to_claude_request(requests[1].clone())).unwrap();
+        let gemini =
+            serde_json::to_string(&to_gemini_request(requests[1].clone()).unwrap()).unwrap();
+        for mapped in [claude, gemini] {
+            assert!(mapped.contains(&strong_locale_instruction("ja-JP")), "{}", mapped);
+            assert!(!mapped.contains(&locale_instruction("ja-JP")), "{}", mapped);
+        }
+
+        // The dropped answer never reaches history; the retry is kept as is
+        let history: Vec<String> = agent.messages().iter().map(message_text).collect();
+        assert!(!history.iter().any(|text| text.contains("It is sunny")));
+        assert_eq!(history.last().unwrap(), "Still sunny in Tokyo.");
+    }
+
+    #[tokio::test]
+    async fn test_wrong_language_answer_is_kept_without_retry() {
+        let (requests, events, agent) =
+            run_with_locale(vec![text_response("It is sunny in Tokyo.")], false).await;
+
+        assert_eq!(requests.len(), 1);
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, AgentEvent::LocaleRetryRequested { .. })));
+        let history: Vec<String> = agent.messages().iter().map(message_text).collect();
+        assert_eq!(history.last().unwrap(), "It is sunny in Tokyo.");
+    }
+
+    #[tokio::test]
+    async fn test_system_template_is_rendered_with_current_vars() {
+        let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut agent = Agent::new(
+            Box::new(RequestRecordingProvider {
+                responses: vec![text_response("Hi Ada"), text_response("Hi Grace")],
+                requests: requests.clone(),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_system_template("You are helping {{user}}.".to_string());
+
+        agent.set_template_vars(HashMap::from([("user".to_string(), "Ada".to_string())]));
+        agent.run_to_completion("Hello").await.unwrap();
+
+        agent.set_template_vars(HashMap::from([("user".to_string(), "Grace".to_string())]));
+        agent.run_to_completion("Hello again").await.unwrap();
+
+        let systems: Vec<_> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.system.clone())
+            .collect();
+        assert_eq!(
+            systems,
+            vec![
+                Some("You are helping Ada.".to_string()),
+                Some("You are helping Grace.".to_string()),
+            ]
+        );
+        assert_eq!(agent.messages().len(), 4);
+
+        // Runs render into the request only, so the template is what gets exported
+        let bundle = agent.export_debug_bundle(&|text: &str| text.to_string());
+        assert_eq!(bundle.system, None);
+        assert_eq!(
+            bundle.system_template.as_deref(),
+            Some("You are helping {{user}}.")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_config_fails_before_calling_the_model() {
+        let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut agent = Agent::new(
+            Box::new(RequestRecordingProvider {
+                responses: vec![text_response("Hi")],
+                requests: requests.clone(),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024).with_temperature(2.5),
+            None,
+        );
+
+        let result = agent.run_to_completion("Hello").await;
+
+        assert!(matches!(
+            result,
+            Err(AgentError::Llm(LlmError::InvalidRequest(ref msg))) if msg.contains("temperature")
+        ));
+        assert!(requests.lock().unwrap().is_empty());
+        assert!(agent.messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_system_template_missing_var_fails_before_calling_the_model() {
+        let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut agent = Agent::new(
+            Box::new(RequestRecordingProvider {
+                responses: vec![text_response("Hi")],
+                requests: requests.clone(),
+            }),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            None,
+        )
+        .with_system_template("Accounts: {{account_ids}}".to_string());
+
+        let result = agent.run_to_completion("Hello").await;
+
+        assert!(matches!(result, Err(AgentError::TemplateVar(name)) if name == "account_ids"));
+        assert!(requests.lock().unwrap().is_empty());
+        assert!(agent.messages().is_empty());
+
+        // Setting the variable lets the same agent run without an orphaned turn
+        agent.set_template_vars(HashMap::from([(
+            "account_ids".to_string(),
+            "a-1".to_string(),
+        )]));
+        agent.run_to_completion("Hello").await.unwrap();
+        assert_eq!(agent.messages().len(), 2);
+        assert_eq!(agent.system_template.as_deref(), Some("Accounts: {{account_ids}}"));
+    }
+
+    /// Records requests like `RequestRecordingProvider`, counting 100 tokens per message
+    struct PerMessageCountingProvider(RequestRecordingProvider);
+
+    #[async_trait]
+    impl LlmProvider for PerMessageCountingProvider {
+        async fn stream_generate(
+            &self,
+            request: GenerateRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>
+        {
+            self.0.stream_generate(request).await
+        }
+
+        async fn count_tokens(&self, request: &GenerateRequest) -> Result<u32, LlmError> {
+            Ok(100 * request.messages.len() as u32)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_context_budget_trims_the_request_but_not_history() {
+        let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut agent = Agent::new(
+            Box::new(PerMessageCountingProvider(RequestRecordingProvider {
+                responses: vec![text_response("Sunny."), text_response("Rainy.")],
+                requests: requests.clone(),
+            })),
+            Box::new(MockExecutor),
+            vec![],
+            GenerationConfig::new(1024),
+            Some("You are a weather assistant.".to_string()),
+        )
+        .with_context_budget(200);
+
+        let mut trimmed = Vec::new();
+        for question in ["Weather in Paris?", "And in London?"] {
+            let mut stream = agent.run(question).await.unwrap();
+            while let Some(event) = stream.next().await {
+                if let AgentEvent::ContextTrimmed { dropped, input_tokens, .. } = event.unwrap() {
+                    trimmed.push((dropped, input_tokens));
+                }
+            }
+        }
+
+        assert_eq!(trimmed, vec![(vec![0, 1], 100)]);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[1].messages, vec![Message::user("And in London?")]);
+        assert_eq!(
+            requests[1].system.as_deref(),
+            Some("You are a weather assistant.")
+        );
+        assert_eq!(agent.messages().len(), 4);
+    }
 }
@@ -0,0 +1,439 @@
+//! Compact, size-bounded summary of an agent run
+
+use super::{AgentError, AgentEvent};
+use crate::llm::core::types::{ContentBlock, Message, UsageMetadata};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Appended to text cut short by the summary limits
+const ELLIPSIS: &str = "…";
+
+/// Size limits applied by [`AgentRunSummary::to_compact_json`]
+///
+/// String limits are in bytes and never split a UTF-8 character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SummaryLimits {
+    /// Longest final answer (default: 2000)
+    pub max_answer_bytes: usize,
+    /// Longest tool input, as serialized JSON (default: 500)
+    pub max_input_bytes: usize,
+    /// Longest tool output or tool error (default: 500)
+    pub max_output_bytes: usize,
+    /// Longest run error (default: 500)
+    pub max_error_bytes: usize,
+    /// Most tool calls listed; later calls are only counted (default: 50)
+    pub max_tool_calls: usize,
+}
+
+impl Default for SummaryLimits {
+    fn default() -> Self {
+        Self {
+            max_answer_bytes: 2000,
+            max_input_bytes: 500,
+            max_output_bytes: 500,
+            max_error_bytes: 500,
+            max_tool_calls: 50,
+        }
+    }
+}
+
+/// One tool call made during a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCallSummary {
+    /// ID the model gave the call
+    pub tool_use_id: String,
+    /// Name of the tool that was called
+    pub name: String,
+    /// Arguments the model passed
+    pub input: Value,
+    /// Result or error text; `None` if the call never finished
+    pub output: Option<String>,
+    /// Whether the call failed
+    pub is_error: bool,
+}
+
+/// Outcome of [`Agent::run_to_completion`](super::Agent::run_to_completion)
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentRunResult {
+    /// Text of the final answer, as stored in history
+    pub final_text: String,
+    /// Tool calls in the order they were started
+    pub tool_calls: Vec<ToolCallSummary>,
+    /// LLM iterations started
+    pub iterations: usize,
+    /// Token usage across all LLM calls
+    pub usage: UsageMetadata,
+}
+
+/// What happened during an agent run, built from its events
+///
+/// See [`Agent::last_run_summary`](super::Agent::last_run_summary).
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentRunSummary {
+    /// Text of the final answer, if the run completed
+    pub final_answer: Option<String>,
+    /// Tool calls in the order they were started
+    pub tool_calls: Vec<ToolCallSummary>,
+    /// LLM iterations started
+    pub iterations: usize,
+    /// Token usage across all LLM calls
+    pub usage: UsageMetadata,
+    /// Estimated tokens spent on each tool's results, by tool name; see
+    /// [`AgentEvent::Completed`]. Empty unless the run completed.
+    pub tool_token_attribution: HashMap<String, u32>,
+    /// Errors that ended the run
+    pub errors: Vec<String>,
+    /// Whether the run was cancelled
+    pub cancelled: bool,
+    limits: SummaryLimits,
+}
+
+impl AgentRunSummary {
+    pub(crate) fn new() -> Self {
+        Self {
+            final_answer: None,
+            tool_calls: Vec::new(),
+            iterations: 0,
+            usage: UsageMetadata::new(0, 0),
+            tool_token_attribution: HashMap::new(),
+            errors: Vec::new(),
+            cancelled: false,
+            limits: SummaryLimits::default(),
+        }
+    }
+
+    /// Set the limits used by [`to_compact_json`](Self::to_compact_json) (builder pattern)
+    pub fn with_limits(mut self, limits: SummaryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Update the summary with an event from the run
+    pub(crate) fn record(&mut self, event: &AgentEvent) {
+        match event {
+            AgentEvent::IterationStarted { iteration, .. } => self.iterations = *iteration,
+            AgentEvent::ToolExecutionStarted {
+                tool_use_id,
+                name,
+                input,
+            } => self.tool_calls.push(ToolCallSummary {
+                tool_use_id: tool_use_id.clone(),
+                name: name.clone(),
+                input: input.clone(),
+                output: None,
+                is_error: false,
+            }),
+            AgentEvent::ToolExecutionCompleted {
+                tool_use_id,
+                result,
+                ..
+            } => self.finish_tool_call(tool_use_id, result, false),
+            AgentEvent::ToolExecutionFailed {
+                tool_use_id, error, ..
+            } => self.finish_tool_call(tool_use_id, error, true),
+            AgentEvent::AssistantMessageComplete(message) => {
+                self.final_answer = Some(message_text(message))
+            }
+            AgentEvent::OutputRedacted { text } => self.final_answer = Some(text.clone()),
+            AgentEvent::UsageUpdated {
+                cumulative_usage, ..
+            } => self.usage = *cumulative_usage,
+            AgentEvent::Completed {
+                total_usage,
+                tool_token_attribution,
+                ..
+            } => {
+                self.usage = *total_usage;
+                self.tool_token_attribution = tool_token_attribution.clone();
+            }
+            AgentEvent::Cancelled => self.cancelled = true,
+            _ => {}
+        }
+    }
+
+    /// Record the error that ended the run
+    pub(crate) fn record_error(&mut self, error: &AgentError) {
+        self.errors.push(error.to_string());
+    }
+
+    /// Drop the answer of a run that didn't complete
+    ///
+    /// Assistant messages are recorded as they arrive, so until the run
+    /// completes the last one may be a tool-calling turn rather than the answer.
+    pub(crate) fn finish(&mut self, completed: bool) {
+        if !completed {
+            self.final_answer = None;
+        }
+    }
+
+    /// The run's result, if it completed with an answer
+    pub(crate) fn result(&self) -> Option<AgentRunResult> {
+        Some(AgentRunResult {
+            final_text: self.final_answer.clone()?,
+            tool_calls: self.tool_calls.clone(),
+            iterations: self.iterations,
+            usage: self.usage,
+        })
+    }
+
+    /// Fill in the output of the call with `tool_use_id`
+    ///
+    /// Parallel calls can finish in any order, so results are matched by ID
+    /// rather than by tool name.
+    fn finish_tool_call(&mut self, tool_use_id: &str, output: &str, is_error: bool) {
+        if let Some(call) = self
+            .tool_calls
+            .iter_mut()
+            .find(|call| call.tool_use_id == tool_use_id && call.output.is_none())
+        {
+            call.output = Some(output.to_string());
+            call.is_error = is_error;
+        }
+    }
+
+    /// The summary as JSON, with every field cut to the configured limits
+    ///
+    /// The structure is the same for every run, so it can be fed to another
+    /// model or compared across runs:
+    ///
+    /// ```json
+    /// {
+    ///   "final_answer": "It is 18°C in Paris.",
+    ///   "iterations": 2,
+    ///   "usage": {"input_tokens": 120, "output_tokens": 30, "total_tokens": 150},
+    ///   "tool_token_attribution": {"weather": 12},
+    ///   "tool_calls": [
+    ///     {"name": "weather", "input": "{\"city\":\"Paris\"}", "output": "18°C", "is_error": false}
+    ///   ],
+    ///   "omitted_tool_calls": 0,
+    ///   "errors": [],
+    ///   "cancelled": false
+    /// }
+    /// ```
+    pub fn to_compact_json(&self) -> Value {
+        let limits = &self.limits;
+
+        let tool_calls: Vec<Value> = self
+            .tool_calls
+            .iter()
+            .take(limits.max_tool_calls)
+            .map(|call| {
+                json!({
+                    "name": call.name,
+                    "input": truncate(&call.input.to_string(), limits.max_input_bytes),
+                    "output": call
+                        .output
+                        .as_deref()
+                        .map(|output| truncate(output, limits.max_output_bytes)),
+                    "is_error": call.is_error,
+                })
+            })
+            .collect();
+
+        json!({
+            "final_answer": self
+                .final_answer
+                .as_deref()
+                .map(|answer| truncate(answer, limits.max_answer_bytes)),
+            "iterations": self.iterations,
+            "usage": self.usage,
+            // Sorted so the output is the same for every run
+            "tool_token_attribution": self
+                .tool_token_attribution
+                .iter()
+                .collect::<BTreeMap<_, _>>(),
+            "tool_calls": tool_calls,
+            "omitted_tool_calls": self.tool_calls.len().saturating_sub(limits.max_tool_calls),
+            "errors": self
+                .errors
+                .iter()
+                .map(|error| truncate(error, limits.max_error_bytes))
+                .collect::<Vec<_>>(),
+            "cancelled": self.cancelled,
+        })
+    }
+}
+
+/// Concatenated text blocks of `message`
+fn message_text(message: &Message) -> String {
+    message
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `text` cut to at most `max_bytes` bytes, ending in an ellipsis if shortened
+fn truncate(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+
+    let marker = if max_bytes >= ELLIPSIS.len() {
+        ELLIPSIS
+    } else {
+        ""
+    };
+    let mut cut = max_bytes - marker.len();
+    while !text.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    format!("{}{}", &text[..cut], marker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary() -> AgentRunSummary {
+        let mut summary = AgentRunSummary::new();
+        let events = [
+            AgentEvent::IterationStarted {
+                iteration: 1,
+                max_tokens: 1024,
+            },
+            AgentEvent::ToolExecutionStarted {
+                tool_use_id: "tool-1".to_string(),
+                name: "weather".to_string(),
+                input: json!({"city": "Zürich, Schweiz"}),
+            },
+            AgentEvent::ToolExecutionCompleted {
+                tool_use_id: "tool-1".to_string(),
+                name: "weather".to_string(),
+                result: "18°C und sonnig".to_string(),
+            },
+            AgentEvent::IterationStarted {
+                iteration: 2,
+                max_tokens: 1024,
+            },
+            AgentEvent::AssistantMessageComplete(Message::assistant("Es sind 18°C in Zürich.")),
+            AgentEvent::Completed {
+                total_usage: UsageMetadata::new(20, 9),
+                iterations: 2,
+                tool_token_attribution: HashMap::from([("weather".to_string(), 4)]),
+                citations: vec![],
+                unresolved_citations: vec![],
+            },
+        ];
+        for event in &events {
+            summary.record(event);
+        }
+        summary.finish(true);
+        summary
+    }
+
+    #[test]
+    fn test_compact_json_structure_is_deterministic() {
+        let json = summary().to_compact_json();
+
+        assert_eq!(
+            json,
+            json!({
+                "final_answer": "Es sind 18°C in Zürich.",
+                "iterations": 2,
+                "usage": {"input_tokens": 20, "output_tokens": 9, "total_tokens": 29},
+                "tool_token_attribution": {"weather": 4},
+                "tool_calls": [{
+                    "name": "weather",
+                    "input": "{\"city\":\"Zürich, Schweiz\"}",
+                    "output": "18°C und sonnig",
+                    "is_error": false,
+                }],
+                "omitted_tool_calls": 0,
+                "errors": [],
+                "cancelled": false,
+            })
+        );
+        assert_eq!(
+            serde_json::to_string(&json).unwrap(),
+            serde_json::to_string(&summary().to_compact_json()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_compact_json_respects_limits_and_char_boundaries() {
+        let mut summary = summary().with_limits(SummaryLimits {
+            max_answer_bytes: 14,
+            max_input_bytes: 11,
+            max_output_bytes: 4,
+            max_error_bytes: 2,
+            max_tool_calls: 0,
+        });
+        summary.errors.push("Stream ended unexpectedly".to_string());
+
+        let json = summary.to_compact_json();
+
+        // 11 bytes are left before the ellipsis, which would split "°"
+        assert_eq!(json["final_answer"], "Es sind 18…");
+        assert_eq!(json["tool_calls"], json!([]));
+        assert_eq!(json["omitted_tool_calls"], 1);
+        assert_eq!(json["errors"], json!(["St"]));
+
+        for (text, limit) in [("18°C und sonnig", 4), ("{\"city\":\"Zü", 11), ("ü", 1)] {
+            let cut = truncate(text, limit);
+            assert!(cut.len() <= limit, "{:?} is over {} bytes", cut, limit);
+        }
+        assert_eq!(truncate("18°C und sonnig", 4), "1…");
+        assert_eq!(truncate("ü", 1), "");
+    }
+
+    #[test]
+    fn test_parallel_calls_to_one_tool_finish_out_of_order() {
+        let mut summary = AgentRunSummary::new();
+        for (id, city) in [("tool-1", "Paris"), ("tool-2", "Oslo")] {
+            summary.record(&AgentEvent::ToolExecutionStarted {
+                tool_use_id: id.to_string(),
+                name: "weather".to_string(),
+                input: json!({"city": city}),
+            });
+        }
+        summary.record(&AgentEvent::ToolExecutionFailed {
+            tool_use_id: "tool-2".to_string(),
+            name: "weather".to_string(),
+            error: "Oslo unavailable".to_string(),
+        });
+        summary.record(&AgentEvent::ToolExecutionCompleted {
+            tool_use_id: "tool-1".to_string(),
+            name: "weather".to_string(),
+            result: "18°C".to_string(),
+        });
+
+        let outcomes: Vec<_> = summary
+            .tool_calls
+            .iter()
+            .map(|call| {
+                (
+                    call.tool_use_id.as_str(),
+                    call.output.as_deref(),
+                    call.is_error,
+                )
+            })
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("tool-1", Some("18°C"), false),
+                ("tool-2", Some("Oslo unavailable"), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unfinished_run_has_no_final_answer() {
+        let mut summary = AgentRunSummary::new();
+        summary.record(&AgentEvent::AssistantMessageComplete(Message::assistant(
+            "Let me check",
+        )));
+        summary.record_error(&AgentError::UnexpectedStreamEnd);
+        summary.finish(false);
+
+        assert_eq!(summary.final_answer, None);
+        assert_eq!(
+            summary.errors,
+            vec!["Stream ended unexpectedly".to_string()]
+        );
+    }
+}
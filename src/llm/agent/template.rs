@@ -0,0 +1,90 @@
+//! `{{var}}` placeholders in system prompt templates
+
+use std::collections::HashMap;
+
+use super::error::AgentError;
+
+/// Render `template`, replacing each `{{name}}` with its value in `vars`
+///
+/// Whitespace inside the braces is ignored, so `{{ name }}` works too.
+/// `\{{` produces a literal `{{`, and single braces or an unclosed `{{` are
+/// left as they are. Values are inserted verbatim and never re-rendered.
+pub(crate) fn render_template(
+    template: &str,
+    vars: &HashMap<String, String>,
+) -> Result<String, AgentError> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        // An escaped opening is copied through without its backslash
+        if rest[..start].ends_with('\\') {
+            rendered.push_str(&rest[..start - 1]);
+            rendered.push_str("{{");
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + len].trim();
+        let value = vars
+            .get(name)
+            .ok_or_else(|| AgentError::TemplateVar(name.to_string()))?;
+
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(value);
+        rest = &rest[start + 2 + len + 2..];
+    }
+
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_replaces_placeholders() {
+        let rendered = render_template(
+            "Hello {{user}}, today is {{ date }}. Bye {{user}}!",
+            &vars(&[("user", "Ada"), ("date", "2026-10-17")]),
+        )
+        .unwrap();
+        assert_eq!(rendered, "Hello Ada, today is 2026-10-17. Bye Ada!");
+    }
+
+    #[test]
+    fn test_missing_variable_is_an_error() {
+        let result = render_template("Accounts: {{accounts}}", &vars(&[("user", "Ada")]));
+        assert!(matches!(result, Err(AgentError::TemplateVar(name)) if name == "accounts"));
+    }
+
+    #[test]
+    fn test_escaped_and_single_braces_are_literal() {
+        let rendered = render_template(
+            r#"Reply as {"name": "{{user}}"}. Write \{{user}} to mean the user. {{ unclosed"#,
+            &vars(&[("user", "Ada")]),
+        )
+        .unwrap();
+        assert_eq!(
+            rendered,
+            r#"Reply as {"name": "Ada"}. Write {{user}} to mean the user. {{ unclosed"#
+        );
+    }
+
+    #[test]
+    fn test_values_are_not_rendered_again() {
+        let rendered = render_template("{{a}}", &vars(&[("a", "{{b}}")])).unwrap();
+        assert_eq!(rendered, "{{b}}");
+    }
+}
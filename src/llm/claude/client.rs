@@ -5,16 +5,24 @@ use futures::stream::Stream;
 use futures::StreamExt;
 use reqwest::Client;
 use std::pin::Pin;
+use tokio::sync::mpsc;
 
 use crate::llm::auth::adc::AuthenticationManager;
+use crate::llm::http::capture::{tee_raw_chunks, RawChunk};
+use crate::llm::http::endpoint::{vertex_base_url, EndpointOverride};
+use crate::llm::http::retry::send_with_retry;
 use crate::llm::core::{
+    config::{ProviderCapabilities, RetryConfig, ToolResultOverflow},
     error::LlmError,
     provider::LlmProvider,
-    types::{GenerateRequest, StreamEvent, UsageMetadata},
+    types::{GenerateRequest, Model, StreamEvent, UsageMetadata},
 };
 
-use super::mapper::{from_claude_event, to_claude_request};
+use super::mapper::{
+    from_claude_event, to_claude_count_tokens_request, to_claude_request, JsonOutputExtractor,
+};
 use super::sse::parse_sse_stream;
+use super::types::CountTokensResponse;
 
 /// Claude model identifiers for Vertex AI
 #[derive(Debug, Clone)]
@@ -47,6 +55,18 @@ pub struct ClaudeClient {
     location: String,
     /// Model to use
     model: ClaudeModel,
+    /// Optional channel receiving raw response chunks for debugging
+    raw_capture: Option<mpsc::Sender<RawChunk>>,
+    /// Optional maximum size of a single tool result, in bytes
+    max_tool_result_bytes: Option<usize>,
+    /// What to do with tool results over the limit
+    tool_result_overflow: ToolResultOverflow,
+    /// Reject requests with parameters this provider ignores instead of warning
+    strict_parameters: bool,
+    /// Retry policy for rate limits and transient errors (default: none)
+    retry: Option<RetryConfig>,
+    /// Base URL replacing the default Vertex AI host
+    endpoint: Option<EndpointOverride>,
 }
 
 impl ClaudeClient {
@@ -82,23 +102,104 @@ impl ClaudeClient {
             project_id,
             location,
             model,
+            raw_capture: None,
+            max_tool_result_bytes: None,
+            tool_result_overflow: ToolResultOverflow::default(),
+            strict_parameters: false,
+            retry: Some(RetryConfig::default()),
+            endpoint: None,
         })
     }
 
+    /// Tee raw response chunks into `sender` alongside normal parsing
+    ///
+    /// Useful for attaching the exact provider payload to a support ticket.
+    /// Chunks are dropped rather than buffered when the receiver lags behind.
+    pub fn with_raw_capture(mut self, sender: mpsc::Sender<RawChunk>) -> Self {
+        self.raw_capture = Some(sender);
+        self
+    }
+
+    /// Reject (or truncate) tool results larger than `max_bytes` before sending
+    ///
+    /// Oversized results otherwise surface as an opaque 400 from the provider.
+    pub fn with_max_tool_result_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_tool_result_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Choose whether oversized tool results error or are truncated (default: error)
+    pub fn with_tool_result_overflow(mut self, overflow: ToolResultOverflow) -> Self {
+        self.tool_result_overflow = overflow;
+        self
+    }
+
+    /// Fail requests whose config sets parameters this provider ignores
+    ///
+    /// By default such parameters only produce a warning log; see
+    /// [`GenerationConfig::compatibility_report`](crate::llm::GenerationConfig::compatibility_report).
+    pub fn with_strict_parameters(mut self, strict: bool) -> Self {
+        self.strict_parameters = strict;
+        self
+    }
+
+    /// Set how failed responses are retried (default: `RetryConfig::default()`)
+    ///
+    /// By default a response with a [`RetryConfig::default_retry_on`] status
+    /// is tried up to 4 times, as with `GeminiClient`; set `max_attempts` to
+    /// 1 to turn retries off. Only opening the stream is retried; errors after
+    /// the response has started are returned as usual.
+    pub fn with_retry(mut self, config: RetryConfig) -> Self {
+        self.retry = Some(config);
+        self
+    }
+
+    /// Send requests to `endpoint` instead of the default Vertex AI host
+    ///
+    /// The default is `{location}-aiplatform.googleapis.com`, or
+    /// `aiplatform.googleapis.com` for the `global` location.
+    ///
+    /// Use this for Private Service Connect or other custom endpoints. The
+    /// request path and the credentials are the same as for the default host.
+    pub fn with_endpoint_override(mut self, endpoint: EndpointOverride) -> Self {
+        self.endpoint = Some(endpoint);
+        self
+    }
+
     /// Build the endpoint URL for streaming
     fn build_endpoint_url(&self) -> String {
-        format!(
-            "https://{}-aiplatform.googleapis.com/v1/projects/{}/locations/{}/publishers/anthropic/models/{}:streamRawPredict",
-            self.location, self.project_id, self.location, self.model.as_str()
+        endpoint_url(
+            &self.project_id,
+            &self.location,
+            self.model.as_str(),
+            self.endpoint.as_ref(),
         )
     }
 
     /// Make a streaming request to Claude
     async fn make_streaming_request(
         &self,
-        request: GenerateRequest,
+        mut request: GenerateRequest,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError> {
+        // Catch oversized tool results locally instead of as a provider 400
+        if let Some(max_bytes) = self.max_tool_result_bytes {
+            request.enforce_tool_result_limit(max_bytes, self.tool_result_overflow)?;
+        }
+
+        // Reject out-of-range parameters locally instead of as a provider 400
+        request
+            .config
+            .validate_for(&Model::Claude(self.model.clone()))?;
+        request
+            .config
+            .check_compatibility(&self.capabilities(), self.model.as_str(), self.strict_parameters)?;
+
         // Convert to Claude request format
+        let mut json_output = request
+            .config
+            .response_schema
+            .is_some()
+            .then(JsonOutputExtractor::default);
         let claude_request = to_claude_request(request);
 
         // Get auth token
@@ -106,28 +207,22 @@ impl ClaudeClient {
 
         // Build request
         let url = self.build_endpoint_url();
-        let response = self
-            .http_client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", token))
-            .header("Content-Type", "application/json")
-            .json(&claude_request)
-            .send()
-            .await?;
-
-        // Check status
-        let status = response.status();
-        if !status.is_success() {
-            let body = response.text().await.unwrap_or_else(|_| String::new());
-            return Err(LlmError::HttpError {
-                status: status.as_u16(),
-                body,
-            });
-        }
+        let response = send_with_retry(self.retry.as_ref(), self.model.as_str(), || {
+            self.http_client
+                .post(&url)
+                .header("Authorization", format!("Bearer {}", token))
+                .header("Content-Type", "application/json")
+                .json(&claude_request)
+        })
+        .await?;
 
         // Parse SSE stream
-        let byte_stream = response.bytes_stream();
-        let sse_stream = parse_sse_stream(Box::pin(byte_stream));
+        let mut byte_stream: Pin<Box<dyn Stream<Item = _> + Send>> =
+            Box::pin(response.bytes_stream());
+        if let Some(sender) = &self.raw_capture {
+            byte_stream = tee_raw_chunks(byte_stream, sender.clone());
+        }
+        let sse_stream = parse_sse_stream(byte_stream);
 
         // Convert to StreamEvent stream
         let mut accumulated_usage = UsageMetadata::new(0, 0);
@@ -136,7 +231,10 @@ impl ClaudeClient {
             match result {
                 Ok(claude_event) => {
                     // Convert Claude event to our abstraction events
-                    let events = from_claude_event(claude_event, &mut accumulated_usage);
+                    let mut events = from_claude_event(claude_event, &mut accumulated_usage);
+                    if let Some(extractor) = json_output.as_mut() {
+                        events = events.into_iter().map(|event| extractor.map(event)).collect();
+                    }
                     futures::stream::iter(
                         events
                             .into_iter()
@@ -150,6 +248,26 @@ impl ClaudeClient {
 
         Ok(Box::pin(event_stream))
     }
+
+    /// Count the input tokens of a request with the count-tokens endpoint
+    async fn make_count_tokens_request(&self, request: &GenerateRequest) -> Result<u32, LlmError> {
+        let count_request = to_claude_count_tokens_request(request.clone(), self.model.as_str());
+
+        let token = self.auth_manager.get_token().await?;
+
+        let url = count_tokens_url(&self.project_id, &self.location, self.endpoint.as_ref());
+        let response = send_with_retry(self.retry.as_ref(), self.model.as_str(), || {
+            self.http_client
+                .post(&url)
+                .header("Authorization", format!("Bearer {}", token))
+                .header("Content-Type", "application/json")
+                .json(&count_request)
+        })
+        .await?;
+
+        let count: CountTokensResponse = response.json().await?;
+        Ok(count.input_tokens)
+    }
 }
 
 #[async_trait]
@@ -160,6 +278,44 @@ impl LlmProvider for ClaudeClient {
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError> {
         self.make_streaming_request(request).await
     }
+
+    async fn count_tokens(&self, request: &GenerateRequest) -> Result<u32, LlmError> {
+        self.make_count_tokens_request(request).await
+    }
+
+    fn name(&self) -> &str {
+        self.model.as_str()
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities::CLAUDE
+    }
+}
+
+/// Streaming URL for `model`, on the default host for `location` or on `endpoint`
+fn endpoint_url(
+    project_id: &str,
+    location: &str,
+    model: &str,
+    endpoint: Option<&EndpointOverride>,
+) -> String {
+    format!(
+        "{}/v1/projects/{}/locations/{}/publishers/anthropic/models/{}:streamRawPredict",
+        vertex_base_url(location, endpoint),
+        project_id,
+        location,
+        model
+    )
+}
+
+/// Count-tokens URL, shared by all Claude models, which are named in the request body
+fn count_tokens_url(project_id: &str, location: &str, endpoint: Option<&EndpointOverride>) -> String {
+    format!(
+        "{}/v1/projects/{}/locations/{}/publishers/anthropic/models/count-tokens:rawPredict",
+        vertex_base_url(location, endpoint),
+        project_id,
+        location
+    )
 }
 
 #[cfg(test)]
@@ -174,20 +330,40 @@ mod tests {
 
     #[test]
     fn test_model_endpoint_url_format() {
-        // Test URL construction logic without creating a full client
-        let project_id = "my-project";
-        let location = "us-central1";
         let model = ClaudeModel::Sonnet45;
 
-        let url = format!(
-            "https://{}-aiplatform.googleapis.com/v1/projects/{}/locations/{}/publishers/anthropic/models/{}:streamRawPredict",
-            location, project_id, location, model.as_str()
+        assert_eq!(
+            endpoint_url("my-project", "us-central1", model.as_str(), None),
+            "https://us-central1-aiplatform.googleapis.com/v1/projects/my-project/locations/us-central1/publishers/anthropic/models/claude-sonnet-4-5@20250929:streamRawPredict"
+        );
+        assert_eq!(
+            endpoint_url("my-project", "europe-west4", model.as_str(), None),
+            "https://europe-west4-aiplatform.googleapis.com/v1/projects/my-project/locations/europe-west4/publishers/anthropic/models/claude-sonnet-4-5@20250929:streamRawPredict"
         );
 
-        assert!(url.contains("us-central1-aiplatform.googleapis.com"));
-        assert!(url.contains("my-project"));
-        assert!(url.contains("claude-sonnet-4-5@20250929"));
-        assert!(url.contains("publishers/anthropic"));
-        assert!(url.contains("streamRawPredict"));
+        assert_eq!(
+            endpoint_url("my-project", "global", model.as_str(), None),
+            "https://aiplatform.googleapis.com/v1/projects/my-project/locations/global/publishers/anthropic/models/claude-sonnet-4-5@20250929:streamRawPredict"
+        );
+
+        let psc = EndpointOverride::new("https://vertex-psc.p.example.internal/").unwrap();
+        assert_eq!(
+            endpoint_url("my-project", "europe-west4", model.as_str(), Some(&psc)),
+            "https://vertex-psc.p.example.internal/v1/projects/my-project/locations/europe-west4/publishers/anthropic/models/claude-sonnet-4-5@20250929:streamRawPredict"
+        );
+    }
+
+    #[test]
+    fn test_count_tokens_url_format() {
+        assert_eq!(
+            count_tokens_url("my-project", "us-east5", None),
+            "https://us-east5-aiplatform.googleapis.com/v1/projects/my-project/locations/us-east5/publishers/anthropic/models/count-tokens:rawPredict"
+        );
+
+        let psc = EndpointOverride::new("https://vertex-psc.p.example.internal").unwrap();
+        assert_eq!(
+            count_tokens_url("my-project", "us-east5", Some(&psc)),
+            "https://vertex-psc.p.example.internal/v1/projects/my-project/locations/us-east5/publishers/anthropic/models/count-tokens:rawPredict"
+        );
     }
 }
@@ -1,17 +1,64 @@
 //! Mapping between abstraction types and Claude-specific types
 
+use crate::llm::core::locale::system_with_locale;
 use crate::llm::core::types::{
-    ContentBlock, ContentBlockStart, ContentDelta, FinishReason, GenerateRequest, Message,
-    MessageMetadata, MessageRole, PartialToolUse, StreamEvent, ToolDeclaration, UsageMetadata,
+    ContentBlock, ContentBlockStart, ContentDelta, FinishReason, GenerateRequest, ImageData,
+    Message, MessageMetadata, MessageRole, PartialToolUse, StreamEvent, ToolChoice,
+    ToolDeclaration, UsageMetadata,
 };
 
 use super::types::{
-    ClaudeContent, ClaudeContentBlock, ClaudeContentBlockStart, ClaudeContentDelta,
-    ClaudeMessage, ClaudeStreamEvent, ClaudeTool, StreamRawPredictRequest,
+    ClaudeCacheControl, ClaudeContent, ClaudeContentBlock, ClaudeContentBlockStart,
+    ClaudeContentDelta, ClaudeImageSource, ClaudeMessage, ClaudeStreamEvent, ClaudeSystem,
+    ClaudeSystemBlock, ClaudeThinking, ClaudeTool, ClaudeToolChoice, CountTokensRequest,
+    StreamRawPredictRequest,
 };
 
+/// Tool that carries the response when `response_schema` is set
+///
+/// Claude has no native structured output, so the schema is offered as this
+/// tool's input schema and its call is turned back into text by
+/// [`JsonOutputExtractor`].
+pub(crate) const JSON_OUTPUT_TOOL: &str = "__json_output__";
+
 /// Convert our abstraction request to Claude's request format
+///
+/// With `prompt_cache` set, cache breakpoints are placed after the system
+/// prompt and after the last tool, the parts that repeat across turns.
+///
+/// A `locale` is sent as an instruction at the end of the system prompt.
+///
+/// With `response_schema` set, a [`JSON_OUTPUT_TOOL`] with that schema is
+/// added. It is forced when the request has no tools of its own and no
+/// thinking budget (Claude can't force a tool while thinking); otherwise
+/// the model is free to call it, and to call the other tools first.
 pub fn to_claude_request(request: GenerateRequest) -> StreamRawPredictRequest {
+    let cache = request.config.prompt_cache;
+    let mut tool_choice = request.tool_choice.map(to_claude_tool_choice);
+    let mut tools: Option<Vec<ClaudeTool>> = request
+        .tools
+        .map(|tools| tools.into_iter().map(to_claude_tool).collect());
+    if let Some(schema) = request.config.response_schema {
+        let tools = tools.get_or_insert_with(Vec::new);
+        if tools.is_empty() && request.config.thinking_budget.is_none() {
+            tool_choice = Some(ClaudeToolChoice::Tool {
+                name: JSON_OUTPUT_TOOL.to_string(),
+            });
+        }
+        tools.push(ClaudeTool {
+            name: JSON_OUTPUT_TOOL.to_string(),
+            description: "Give your final answer as this tool's input, matching its schema exactly."
+                .to_string(),
+            input_schema: schema,
+            cache_control: None,
+        });
+    }
+    if cache {
+        if let Some(last) = tools.as_mut().and_then(|tools| tools.last_mut()) {
+            last.cache_control = Some(ClaudeCacheControl::Ephemeral);
+        }
+    }
+
     StreamRawPredictRequest {
         anthropic_version: "vertex-2023-10-16".to_string(),
         max_tokens: request.config.max_tokens,
@@ -20,20 +67,53 @@ pub fn to_claude_request(request: GenerateRequest) -> StreamRawPredictRequest {
             .into_iter()
             .map(to_claude_message)
             .collect(),
-        system: request.system,
-        tools: request.tools.map(|tools| {
-            tools
-                .into_iter()
-                .map(to_claude_tool)
-                .collect()
-        }),
+        system: system_with_locale(request.system, request.locale.as_deref())
+            .map(|text| to_claude_system(text, cache)),
+        tools,
+        tool_choice,
         temperature: request.config.temperature,
         top_p: request.config.top_p,
+        top_k: request.config.top_k,
         stop_sequences: request.config.stop_sequences,
+        thinking: request
+            .config
+            .thinking_budget
+            .map(|budget_tokens| ClaudeThinking {
+                thinking_type: "enabled".to_string(),
+                budget_tokens,
+            }),
         stream: true,
     }
 }
 
+/// Convert our abstraction request to a count-tokens request for `model`
+///
+/// Counts exactly what [`to_claude_request`] would send, including the
+/// locale instruction and the [`JSON_OUTPUT_TOOL`].
+pub fn to_claude_count_tokens_request(request: GenerateRequest, model: &str) -> CountTokensRequest {
+    let request = to_claude_request(request);
+    CountTokensRequest {
+        model: model.to_string(),
+        messages: request.messages,
+        system: request.system,
+        tools: request.tools,
+        tool_choice: request.tool_choice,
+        thinking: request.thinking,
+    }
+}
+
+/// Convert the system prompt, as a cacheable block if `cache` is set
+fn to_claude_system(text: String, cache: bool) -> ClaudeSystem {
+    if !cache {
+        return ClaudeSystem::Text(text);
+    }
+    ClaudeSystem::Blocks(vec![ClaudeSystemBlock {
+        block_type: "text".to_string(),
+        text,
+        cache_control: Some(ClaudeCacheControl::Ephemeral),
+    }])
+}
+
 /// Convert our Message to Claude's ClaudeMessage
 fn to_claude_message(message: Message) -> ClaudeMessage {
     let role = match message.role {
@@ -79,6 +159,13 @@ fn to_claude_content_block(block: ContentBlock) -> ClaudeContentBlock {
             content,
             is_error: if is_error { Some(true) } else { None },
         },
+        ContentBlock::Image { media_type, data } => ClaudeContentBlock::Image {
+            source: match data {
+                ImageData::Base64(data) => ClaudeImageSource::Base64 { media_type, data },
+                ImageData::Url(url) => ClaudeImageSource::Url { url },
+            },
+        },
+        ContentBlock::RedactedThinking { data } => ClaudeContentBlock::RedactedThinking { data },
     }
 }
 
@@ -88,6 +175,71 @@ fn to_claude_tool(tool: ToolDeclaration) -> ClaudeTool {
         name: tool.name,
         description: tool.description,
         input_schema: tool.input_schema,
+        cache_control: None,
+    }
+}
+
+/// Convert our ToolChoice to Claude's tool_choice
+fn to_claude_tool_choice(choice: ToolChoice) -> ClaudeToolChoice {
+    match choice {
+        ToolChoice::Auto => ClaudeToolChoice::Auto,
+        ToolChoice::Any => ClaudeToolChoice::Any,
+        ToolChoice::Required { name } => ClaudeToolChoice::Tool { name },
+        ToolChoice::None => ClaudeToolChoice::None,
+    }
+}
+
+/// Turns the [`JSON_OUTPUT_TOOL`] call in a response back into text
+///
+/// The tool block becomes a text block whose deltas are the tool's input
+/// JSON, so the assembled text is the structured response. If no other tool
+/// was called, the `ToolUse` finish reason becomes `EndTurn`.
+#[derive(Debug, Default)]
+pub(crate) struct JsonOutputExtractor {
+    index: Option<usize>,
+    other_tool_called: bool,
+}
+
+impl JsonOutputExtractor {
+    pub(crate) fn map(&mut self, event: StreamEvent) -> StreamEvent {
+        match event {
+            StreamEvent::ContentBlockStart {
+                index,
+                block: ContentBlockStart::ToolUse { id, name },
+            } => {
+                if name != JSON_OUTPUT_TOOL {
+                    self.other_tool_called = true;
+                    return StreamEvent::ContentBlockStart {
+                        index,
+                        block: ContentBlockStart::ToolUse { id, name },
+                    };
+                }
+                self.index = Some(index);
+                StreamEvent::ContentBlockStart {
+                    index,
+                    block: ContentBlockStart::Text {
+                        text: String::new(),
+                    },
+                }
+            }
+            StreamEvent::ContentDelta {
+                index,
+                delta: ContentDelta::ToolUseDelta { partial },
+            } if self.index == Some(index) => StreamEvent::ContentDelta {
+                index,
+                delta: ContentDelta::TextDelta {
+                    text: partial.partial_json,
+                },
+            },
+            StreamEvent::MessageEnd {
+                finish_reason: FinishReason::ToolUse,
+                usage,
+            } if self.index.is_some() && !self.other_tool_called => StreamEvent::MessageEnd {
+                finish_reason: FinishReason::EndTurn,
+                usage,
+            },
+            other => other,
+        }
     }
 }
 
@@ -104,6 +256,10 @@ pub fn from_claude_event(
             accumulated_usage.output_tokens = message.usage.output_tokens;
             accumulated_usage.total_tokens =
                 accumulated_usage.input_tokens + accumulated_usage.output_tokens;
+            accumulated_usage.cache_creation_input_tokens =
+                message.usage.cache_creation_input_tokens.unwrap_or(0);
+            accumulated_usage.cache_read_input_tokens =
+                message.usage.cache_read_input_tokens.unwrap_or(0);
 
             vec![StreamEvent::MessageStart {
                 message: MessageMetadata {
@@ -122,6 +278,12 @@ pub fn from_claude_event(
                 ClaudeContentBlockStart::ToolUse { id, name } => {
                     ContentBlockStart::ToolUse { id, name }
                 }
+                ClaudeContentBlockStart::Thinking { thinking } => {
+                    ContentBlockStart::Thinking { thinking }
+                }
+                ClaudeContentBlockStart::RedactedThinking { data } => {
+                    ContentBlockStart::RedactedThinking { data }
+                }
             };
 
             vec![StreamEvent::ContentBlockStart { index, block }]
@@ -138,6 +300,11 @@ pub fn from_claude_event(
                         },
                     }
                 }
+                ClaudeContentDelta::ThinkingDelta { thinking } => {
+                    ContentDelta::ThinkingDelta { thinking }
+                }
+                // Only needed to send thinking back, which we don't do
+                ClaudeContentDelta::SignatureDelta { .. } => return vec![],
             };
 
             vec![StreamEvent::ContentDelta {
@@ -204,14 +371,19 @@ mod tests {
         let request = GenerateRequest {
             messages: vec![Message::user("Hello")],
             tools: None,
+            tool_choice: None,
             config: GenerationConfig {
                 max_tokens: 1024,
                 temperature: Some(0.7),
                 top_p: Some(0.9),
-                top_k: None,
+                top_k: Some(40),
                 stop_sequences: None,
+                response_schema: None,
+                thinking_budget: None,
+                prompt_cache: false,
             },
             system: Some("You are helpful".to_string()),
+            locale: None,
         };
 
         let claude_request = to_claude_request(request);
@@ -220,11 +392,278 @@ mod tests {
         assert_eq!(claude_request.max_tokens, 1024);
         assert_eq!(claude_request.temperature, Some(0.7));
         assert_eq!(claude_request.top_p, Some(0.9));
-        assert_eq!(claude_request.system, Some("You are helpful".to_string()));
+        assert_eq!(claude_request.top_k, Some(40));
+        assert_eq!(
+            claude_request.system,
+            Some(ClaudeSystem::Text("You are helpful".to_string()))
+        );
         assert!(claude_request.stream);
         assert_eq!(claude_request.messages.len(), 1);
     }
 
+    #[test]
+    fn test_to_claude_request_appends_locale_instruction() {
+        let request = GenerateRequest {
+            messages: vec![Message::user("What's the weather?")],
+            tools: None,
+            tool_choice: None,
+            config: GenerationConfig::new(1024),
+            system: Some("You are helpful".to_string()),
+            locale: Some("de-DE".to_string()),
+        };
+
+        let claude_request = to_claude_request(request);
+
+        assert_eq!(
+            claude_request.system,
+            Some(ClaudeSystem::Text(format!(
+                "You are helpful\n\n{}",
+                crate::llm::core::locale::locale_instruction("de-DE")
+            )))
+        );
+    }
+
+    #[test]
+    fn test_to_claude_count_tokens_request() {
+        let request = GenerateRequest {
+            messages: vec![Message::user("What's the weather?")],
+            tools: Some(vec![ToolDeclaration {
+                name: "get_weather".to_string(),
+                description: "Get weather".to_string(),
+                input_schema: serde_json::json!({"type": "object"}),
+            }]),
+            tool_choice: None,
+            config: GenerationConfig::new(1024),
+            system: Some("You are helpful".to_string()),
+            locale: None,
+        };
+
+        let json = serde_json::to_value(to_claude_count_tokens_request(
+            request,
+            "claude-haiku-4-5@20251001",
+        ))
+        .unwrap();
+
+        assert_eq!(json["model"], "claude-haiku-4-5@20251001");
+        assert_eq!(json["system"], "You are helpful");
+        assert_eq!(json["messages"].as_array().unwrap().len(), 1);
+        assert_eq!(json["tools"][0]["name"], "get_weather");
+        // Generation-only fields would be rejected by the endpoint
+        for field in ["anthropic_version", "max_tokens", "stream"] {
+            assert!(json.get(field).is_none(), "unexpected field {}", field);
+        }
+    }
+
+    #[test]
+    fn test_to_claude_request_with_thinking_budget() {
+        let request = GenerateRequest {
+            messages: vec![Message::user("Hello")],
+            tools: None,
+            tool_choice: None,
+            config: GenerationConfig::new(4096).with_thinking_budget(2048),
+            system: None,
+            locale: None,
+        };
+
+        let json = serde_json::to_value(to_claude_request(request)).unwrap();
+        assert_eq!(
+            json["thinking"],
+            serde_json::json!({"type": "enabled", "budget_tokens": 2048})
+        );
+
+        let request = GenerateRequest {
+            messages: vec![Message::user("Hello")],
+            tools: None,
+            tool_choice: None,
+            config: GenerationConfig::new(4096),
+            system: None,
+            locale: None,
+        };
+        let json = serde_json::to_value(to_claude_request(request)).unwrap();
+        assert!(json.get("thinking").is_none());
+    }
+
+    #[test]
+    fn test_to_claude_request_tool_choice() {
+        let serialized = |tool_choice| {
+            let request = GenerateRequest {
+                messages: vec![Message::user("What's the weather?")],
+                tools: None,
+                tool_choice,
+                config: GenerationConfig::new(1024),
+                system: None,
+                locale: None,
+            };
+            serde_json::to_value(to_claude_request(request)).unwrap()
+        };
+
+        assert!(serialized(None).get("tool_choice").is_none());
+        assert_eq!(
+            serialized(Some(ToolChoice::Auto))["tool_choice"],
+            serde_json::json!({"type": "auto"})
+        );
+        assert_eq!(
+            serialized(Some(ToolChoice::Any))["tool_choice"],
+            serde_json::json!({"type": "any"})
+        );
+        assert_eq!(
+            serialized(Some(ToolChoice::Required {
+                name: "get_weather".to_string()
+            }))["tool_choice"],
+            serde_json::json!({"type": "tool", "name": "get_weather"})
+        );
+        assert_eq!(
+            serialized(Some(ToolChoice::None))["tool_choice"],
+            serde_json::json!({"type": "none"})
+        );
+    }
+
+    #[test]
+    fn test_to_claude_request_with_response_schema_forces_json_tool() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"city": {"type": "string"}},
+            "required": ["city"]
+        });
+        let request = GenerateRequest {
+            messages: vec![Message::user("Where is the Eiffel Tower?")],
+            tools: None,
+            tool_choice: None,
+            config: GenerationConfig::new(1024).with_response_schema(schema.clone()),
+            system: None,
+            locale: None,
+        };
+
+        let json = serde_json::to_value(to_claude_request(request)).unwrap();
+
+        assert_eq!(json["tools"].as_array().unwrap().len(), 1);
+        assert_eq!(json["tools"][0]["name"], JSON_OUTPUT_TOOL);
+        assert_eq!(json["tools"][0]["input_schema"], schema);
+        assert_eq!(
+            json["tool_choice"],
+            serde_json::json!({"type": "tool", "name": "__json_output__"})
+        );
+    }
+
+    #[test]
+    fn test_to_claude_request_with_response_schema_and_tools_leaves_choice() {
+        let weather = ToolDeclaration {
+            name: "get_weather".to_string(),
+            description: "Get weather".to_string(),
+            input_schema: serde_json::json!({"type": "object"}),
+        };
+        let request = GenerateRequest {
+            messages: vec![Message::user("Weather in Paris, as JSON?")],
+            tools: Some(vec![weather]),
+            tool_choice: None,
+            config: GenerationConfig::new(1024).with_response_schema(serde_json::json!({"type": "object"})),
+            system: None,
+            locale: None,
+        };
+
+        let json = serde_json::to_value(to_claude_request(request)).unwrap();
+
+        let names: Vec<_> = json["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["get_weather", JSON_OUTPUT_TOOL]);
+        assert!(json.get("tool_choice").is_none());
+    }
+
+    #[test]
+    fn test_json_output_extractor_turns_tool_call_into_text() {
+        let mut extractor = JsonOutputExtractor::default();
+        let input_delta = |json: &str| StreamEvent::ContentDelta {
+            index: 0,
+            delta: ContentDelta::ToolUseDelta {
+                partial: PartialToolUse {
+                    id: None,
+                    name: None,
+                    partial_json: json.to_string(),
+                },
+            },
+        };
+        let events = vec![
+            StreamEvent::ContentBlockStart {
+                index: 0,
+                block: ContentBlockStart::ToolUse {
+                    id: "toolu_1".to_string(),
+                    name: JSON_OUTPUT_TOOL.to_string(),
+                },
+            },
+            input_delta(r#"{"city": "#),
+            input_delta(r#""Paris"}"#),
+            StreamEvent::ContentBlockEnd { index: 0 },
+            StreamEvent::MessageEnd {
+                finish_reason: FinishReason::ToolUse,
+                usage: UsageMetadata::new(10, 5),
+            },
+        ];
+
+        let mapped: Vec<_> = events.into_iter().map(|event| extractor.map(event)).collect();
+
+        assert!(matches!(
+            &mapped[0],
+            StreamEvent::ContentBlockStart {
+                index: 0,
+                block: ContentBlockStart::Text { text },
+            } if text.is_empty()
+        ));
+        let text: String = mapped
+            .iter()
+            .filter_map(|event| match event {
+                StreamEvent::ContentDelta {
+                    delta: ContentDelta::TextDelta { text },
+                    ..
+                } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, r#"{"city": "Paris"}"#);
+        assert!(matches!(mapped[3], StreamEvent::ContentBlockEnd { index: 0 }));
+        assert!(matches!(
+            mapped[4],
+            StreamEvent::MessageEnd {
+                finish_reason: FinishReason::EndTurn,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_json_output_extractor_keeps_other_tool_calls() {
+        let mut extractor = JsonOutputExtractor::default();
+        let start = StreamEvent::ContentBlockStart {
+            index: 0,
+            block: ContentBlockStart::ToolUse {
+                id: "toolu_1".to_string(),
+                name: "get_weather".to_string(),
+            },
+        };
+        let end = StreamEvent::MessageEnd {
+            finish_reason: FinishReason::ToolUse,
+            usage: UsageMetadata::new(10, 5),
+        };
+
+        assert!(matches!(
+            extractor.map(start),
+            StreamEvent::ContentBlockStart {
+                block: ContentBlockStart::ToolUse { ref name, .. },
+                ..
+            } if name == "get_weather"
+        ));
+        assert!(matches!(
+            extractor.map(end),
+            StreamEvent::MessageEnd {
+                finish_reason: FinishReason::ToolUse,
+                ..
+            }
+        ));
+    }
+
     #[test]
     fn test_to_claude_message_simple_text() {
         let message = Message::user("Hello");
@@ -275,6 +714,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_redacted_thinking_is_mapped_and_sent_back() {
+        let mut usage = UsageMetadata::new(0, 0);
+        let start = ClaudeStreamEvent::ContentBlockStart {
+            index: 0,
+            content_block: ClaudeContentBlockStart::RedactedThinking {
+                data: "EmwKAhgBEgy3va3pzix".to_string(),
+            },
+        };
+        let data = match &from_claude_event(start, &mut usage)[..] {
+            [StreamEvent::ContentBlockStart {
+                index: 0,
+                block: ContentBlockStart::RedactedThinking { data },
+            }] => data.clone(),
+            other => panic!("Expected redacted thinking block start, got {:?}", other),
+        };
+
+        let message = Message {
+            role: MessageRole::Assistant,
+            content: vec![
+                ContentBlock::RedactedThinking { data },
+                ContentBlock::Text {
+                    text: "The answer is 42.".to_string(),
+                },
+            ],
+        };
+        let json = serde_json::to_value(to_claude_message(message)).unwrap();
+
+        assert_eq!(
+            json["content"],
+            serde_json::json!([
+                {"type": "redacted_thinking", "data": "EmwKAhgBEgy3va3pzix"},
+                {"type": "text", "text": "The answer is 42."}
+            ])
+        );
+    }
+
     #[test]
     fn test_to_claude_message_tool_result() {
         let message = Message::tool_result("tool-1", "72°F");
@@ -303,6 +779,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_to_claude_message_images() {
+        let message = Message {
+            role: MessageRole::User,
+            content: vec![
+                ContentBlock::Image {
+                    media_type: "image/png".to_string(),
+                    data: ImageData::Base64("iVBORw0KGgo=".to_string()),
+                },
+                ContentBlock::Image {
+                    media_type: "image/jpeg".to_string(),
+                    data: ImageData::Url("https://example.com/cat.jpg".to_string()),
+                },
+            ],
+        };
+
+        let json = serde_json::to_value(to_claude_message(message)).unwrap();
+        assert_eq!(
+            json["content"],
+            serde_json::json!([
+                {
+                    "type": "image",
+                    "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}
+                },
+                {
+                    "type": "image",
+                    "source": {"type": "url", "url": "https://example.com/cat.jpg"}
+                }
+            ])
+        );
+    }
+
     #[test]
     fn test_to_claude_tool() {
         let tool = ToolDeclaration {
@@ -337,6 +845,8 @@ mod tests {
                 usage: ClaudeUsage {
                     input_tokens: 10,
                     output_tokens: 0,
+                    cache_creation_input_tokens: None,
+                    cache_read_input_tokens: None,
                 },
             },
         };
@@ -356,6 +866,69 @@ mod tests {
         assert_eq!(accumulated_usage.input_tokens, 10);
     }
 
+    #[test]
+    fn test_to_claude_request_with_prompt_cache() {
+        let tool = |name: &str| ToolDeclaration {
+            name: name.to_string(),
+            description: format!("{} tool", name),
+            input_schema: serde_json::json!({"type": "object"}),
+        };
+        let request = GenerateRequest {
+            messages: vec![Message::user("Hello")],
+            tools: Some(vec![tool("search"), tool("fetch")]),
+            tool_choice: None,
+            config: GenerationConfig::new(1024).with_prompt_cache(true),
+            system: Some("You are helpful".to_string()),
+            locale: None,
+        };
+
+        let json = serde_json::to_value(to_claude_request(request)).unwrap();
+
+        assert_eq!(
+            json["system"],
+            serde_json::json!([{
+                "type": "text",
+                "text": "You are helpful",
+                "cache_control": {"type": "ephemeral"}
+            }])
+        );
+        assert!(json["tools"][0].get("cache_control").is_none());
+        assert_eq!(
+            json["tools"][1]["cache_control"],
+            serde_json::json!({"type": "ephemeral"})
+        );
+    }
+
+    #[test]
+    fn test_from_claude_event_message_start_with_cache_usage() {
+        let event: ClaudeStreamEvent = serde_json::from_value(serde_json::json!({
+            "type": "message_start",
+            "message": {
+                "id": "msg_123",
+                "type": "message",
+                "role": "assistant",
+                "content": [],
+                "model": "claude-sonnet-4-5",
+                "stop_reason": null,
+                "stop_sequence": null,
+                "usage": {
+                    "input_tokens": 12,
+                    "output_tokens": 1,
+                    "cache_creation_input_tokens": 0,
+                    "cache_read_input_tokens": 2048
+                }
+            }
+        }))
+        .unwrap();
+
+        let mut accumulated_usage = UsageMetadata::new(0, 0);
+        from_claude_event(event, &mut accumulated_usage);
+
+        assert_eq!(accumulated_usage.input_tokens, 12);
+        assert_eq!(accumulated_usage.cache_creation_input_tokens, 0);
+        assert_eq!(accumulated_usage.cache_read_input_tokens, 2048);
+    }
+
     #[test]
     fn test_from_claude_event_content_block_start_text() {
         let event = ClaudeStreamEvent::ContentBlockStart {
@@ -406,6 +979,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_from_claude_event_thinking() {
+        let mut usage = UsageMetadata::new(0, 0);
+
+        let start = ClaudeStreamEvent::ContentBlockStart {
+            index: 0,
+            content_block: ClaudeContentBlockStart::Thinking {
+                thinking: String::new(),
+            },
+        };
+        match &from_claude_event(start, &mut usage)[..] {
+            [StreamEvent::ContentBlockStart {
+                index: 0,
+                block: ContentBlockStart::Thinking { thinking },
+            }] => assert_eq!(thinking, ""),
+            other => panic!("Expected thinking block start, got {:?}", other),
+        }
+
+        let delta = ClaudeStreamEvent::ContentBlockDelta {
+            index: 0,
+            delta: ClaudeContentDelta::ThinkingDelta {
+                thinking: "Let me add these up".to_string(),
+            },
+        };
+        match &from_claude_event(delta, &mut usage)[..] {
+            [StreamEvent::ContentDelta {
+                index: 0,
+                delta: ContentDelta::ThinkingDelta { thinking },
+            }] => assert_eq!(thinking, "Let me add these up"),
+            other => panic!("Expected thinking delta, got {:?}", other),
+        }
+
+        let signature = ClaudeStreamEvent::ContentBlockDelta {
+            index: 0,
+            delta: ClaudeContentDelta::SignatureDelta {
+                signature: "EqQBCgIYAhIM".to_string(),
+            },
+        };
+        assert!(from_claude_event(signature, &mut usage).is_empty());
+    }
+
     #[test]
     fn test_from_claude_event_message_delta_with_stop_reason() {
         use super::super::types::{ClaudeMessageDeltaData, ClaudeUsage};
@@ -418,6 +1032,8 @@ mod tests {
             usage: Some(ClaudeUsage {
                 input_tokens: 10,
                 output_tokens: 25,
+                cache_creation_input_tokens: None,
+                cache_read_input_tokens: None,
             }),
         };
 
@@ -458,6 +1074,8 @@ mod tests {
                 usage: Some(ClaudeUsage {
                     input_tokens: 10,
                     output_tokens: 20,
+                    cache_creation_input_tokens: None,
+                    cache_read_input_tokens: None,
                 }),
             };
 
@@ -3,16 +3,20 @@
 use bytes::Bytes;
 use futures::stream::Stream;
 use futures::StreamExt;
+use std::collections::VecDeque;
 use std::pin::Pin;
 
 use crate::llm::core::error::LlmError;
 
 use super::types::ClaudeStreamEvent;
 
+/// Largest incomplete event [`parse_sse_stream`] buffers: 1 MiB
+pub const DEFAULT_MAX_SSE_BUFFER_BYTES: usize = 1024 * 1024;
+
 /// Parse a stream of bytes as Claude SSE events
 ///
 /// Claude's SSE format uses:
-/// ```
+/// ```text
 /// event: message_start
 /// data: {"type":"message_start",...}
 ///
@@ -22,60 +26,126 @@ use super::types::ClaudeStreamEvent;
 ///
 /// This parser:
 /// 1. Buffers incoming bytes
-/// 2. Scans for event boundaries (double newline)
+/// 2. Scans for event boundaries (a blank line, with `\n` or `\r\n` line endings)
 /// 3. Extracts event type from `event:` line
-/// 4. Extracts and parses JSON from `data:` line
+/// 4. Joins the `data:` lines and parses them as JSON
 /// 5. Returns a stream of parsed events
+///
+/// When the byte stream ends, whatever is left in the buffer is parsed as a
+/// final event, so one sent without its trailing blank line isn't lost.
+///
+/// An incomplete event is buffered up to [`DEFAULT_MAX_SSE_BUFFER_BYTES`];
+/// see [`parse_sse_stream_with_limit`].
 pub fn parse_sse_stream(
     byte_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
 ) -> Pin<Box<dyn Stream<Item = Result<ClaudeStreamEvent, LlmError>> + Send>> {
-    // Buffer to accumulate partial events
-    let mut buffer = String::new();
-
-    let event_stream = byte_stream.flat_map(move |chunk_result| {
-        let chunk = match chunk_result {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                return futures::stream::iter(vec![Err(LlmError::StreamError(e.to_string()))]);
+    parse_sse_stream_with_limit(byte_stream, DEFAULT_MAX_SSE_BUFFER_BYTES)
+}
+
+/// Like [`parse_sse_stream`], buffering at most `max_buffer_bytes` of an incomplete event
+///
+/// A stream that sends more than that without an event boundary is
+/// malformed (or hostile), so instead of growing the buffer the parser
+/// yields `LlmError::StreamError` and ends the stream.
+pub fn parse_sse_stream_with_limit(
+    byte_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    max_buffer_bytes: usize,
+) -> Pin<Box<dyn Stream<Item = Result<ClaudeStreamEvent, LlmError>> + Send>> {
+    let state = ParserState {
+        bytes: byte_stream,
+        buffer: String::new(),
+        ready: VecDeque::new(),
+        finished: false,
+    };
+
+    let event_stream = futures::stream::unfold(state, move |mut state| async move {
+        loop {
+            if let Some(event) = state.ready.pop_front() {
+                return Some((event, state));
             }
-        };
-
-        // Convert bytes to string and append to buffer
-        let text = match std::str::from_utf8(&chunk) {
-            Ok(t) => t,
-            Err(e) => {
-                return futures::stream::iter(vec![Err(LlmError::StreamError(format!(
-                    "Invalid UTF-8 in stream: {}",
-                    e
-                )))]);
+            // Don't poll again once ended, since after an overflow the sender
+            // may never close the stream
+            if state.finished {
+                return None;
             }
-        };
 
-        buffer.push_str(text);
+            let chunk = match state.bytes.next().await {
+                Some(Ok(bytes)) => bytes,
+                // The sender may close without a final blank line, so parse what's left
+                None => {
+                    state.finished = true;
+                    let rest = std::mem::take(&mut state.buffer);
+                    if let Some(parsed_event) = parse_event(&rest) {
+                        state.ready.push_back(parsed_event);
+                    }
+                    continue;
+                }
+                Some(Err(e)) => return Some((Err(LlmError::StreamError(e.to_string())), state)),
+            };
+
+            // Convert bytes to string and append to buffer
+            let text = match std::str::from_utf8(&chunk) {
+                Ok(t) => t,
+                Err(e) => {
+                    let error = LlmError::StreamError(format!("Invalid UTF-8 in stream: {}", e));
+                    return Some((Err(error), state));
+                }
+            };
+
+            state.buffer.push_str(text);
 
-        // Process complete events (delimited by \n\n)
-        let mut events = Vec::new();
-        while let Some(event_end) = buffer.find("\n\n") {
-            let event_text = buffer[..event_end].to_string();
-            buffer.drain(..=event_end + 1); // Remove event + one of the newlines
+            // Process complete events (delimited by a blank line)
+            while let Some((event_end, boundary_len)) = find_event_boundary(&state.buffer) {
+                let event_text = state.buffer[..event_end].to_string();
+                state.buffer.drain(..event_end + boundary_len);
 
-            // Parse the event
-            if let Some(parsed_event) = parse_event(&event_text) {
-                events.push(parsed_event);
+                // Parse the event
+                if let Some(parsed_event) = parse_event(&event_text) {
+                    state.ready.push_back(parsed_event);
+                }
             }
-        }
 
-        // Return all events found in this chunk
-        futures::stream::iter(events)
+            if state.buffer.len() > max_buffer_bytes {
+                state.finished = true;
+                state.ready.push_back(Err(LlmError::StreamError(
+                    "SSE event exceeded max buffer size".to_string(),
+                )));
+            }
+        }
     });
 
     Box::pin(event_stream)
 }
 
+/// Parser progress between chunks
+struct ParserState {
+    bytes: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    /// Start of an event whose boundary hasn't arrived yet
+    buffer: String,
+    /// Parsed events not yet returned
+    ready: VecDeque<Result<ClaudeStreamEvent, LlmError>>,
+    /// Whether the byte stream ended or the buffer outgrew its limit
+    finished: bool,
+}
+
+/// Find the first blank line ending an event: its offset and length
+///
+/// Accepts both `\n\n` and `\r\n\r\n`, since proxies may rewrite line endings.
+fn find_event_boundary(buffer: &str) -> Option<(usize, usize)> {
+    let lf = buffer.find("\n\n").map(|end| (end, 2));
+    let crlf = buffer.find("\r\n\r\n").map(|end| (end, 4));
+    match (lf, crlf) {
+        (Some(lf), Some(crlf)) => Some(if crlf.0 < lf.0 { crlf } else { lf }),
+        (lf, crlf) => lf.or(crlf),
+    }
+}
+
 /// Parse a single SSE event from its text representation
+///
+/// Multiple `data:` lines are joined with newlines into one payload.
 fn parse_event(event_text: &str) -> Option<Result<ClaudeStreamEvent, LlmError>> {
     let mut event_type: Option<String> = None;
-    let mut data: Option<String> = None;
+    let mut data_lines: Vec<&str> = Vec::new();
 
     for line in event_text.lines() {
         let line = line.trim();
@@ -92,12 +162,15 @@ fn parse_event(event_text: &str) -> Option<Result<ClaudeStreamEvent, LlmError>>
 
         // Extract data
         if let Some(data_val) = line.strip_prefix("data:") {
-            data = Some(data_val.trim().to_string());
+            data_lines.push(data_val.trim());
         }
     }
 
     // We need data to parse an event
-    let data = data?;
+    if data_lines.is_empty() {
+        return None;
+    }
+    let data = data_lines.join("\n");
 
     // Skip ping events (no data)
     if data.is_empty() {
@@ -116,9 +189,10 @@ fn parse_event(event_text: &str) -> Option<Result<ClaudeStreamEvent, LlmError>>
 
 #[cfg(test)]
 mod tests {
-    use super::*;
     use super::super::types::{ClaudeContentBlockStart, ClaudeContentDelta};
+    use super::*;
     use futures::stream;
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_parse_message_start() {
@@ -151,7 +225,10 @@ mod tests {
         assert!(result.is_some());
         let event = result.unwrap().unwrap();
         match event {
-            ClaudeStreamEvent::ContentBlockStart { index, content_block } => {
+            ClaudeStreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => {
                 assert_eq!(index, 0);
                 match content_block {
                     ClaudeContentBlockStart::Text { text } => {
@@ -188,6 +265,64 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_parse_thinking_block() {
+        let data = b"event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"thinking\",\"thinking\":\"\"}}\n\nevent: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"thinking_delta\",\"thinking\":\"First, 2 + 2\"}}\n\nevent: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"signature_delta\",\"signature\":\"EqQB\"}}\n\n";
+        let byte_stream = Box::pin(stream::iter(vec![Ok(Bytes::from_static(data))]));
+
+        let events: Vec<_> = parse_sse_stream(byte_stream)
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(events.len(), 3);
+        match &events[0] {
+            ClaudeStreamEvent::ContentBlockStart {
+                content_block: ClaudeContentBlockStart::Thinking { thinking },
+                ..
+            } => assert_eq!(thinking, ""),
+            other => panic!("Expected thinking block start, got {:?}", other),
+        }
+        match &events[1] {
+            ClaudeStreamEvent::ContentBlockDelta {
+                index: 0,
+                delta: ClaudeContentDelta::ThinkingDelta { thinking },
+            } => assert_eq!(thinking, "First, 2 + 2"),
+            other => panic!("Expected thinking delta, got {:?}", other),
+        }
+        assert!(matches!(
+            &events[2],
+            ClaudeStreamEvent::ContentBlockDelta {
+                delta: ClaudeContentDelta::SignatureDelta { .. },
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_parse_redacted_thinking_block() {
+        let data = b"event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"redacted_thinking\",\"data\":\"EmwKAhgBEgy3va3pzix\"}}\n\nevent: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n";
+        let byte_stream = Box::pin(stream::iter(vec![Ok(Bytes::from_static(data))]));
+
+        let events: Vec<_> = parse_sse_stream(byte_stream)
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(events.len(), 2);
+        match &events[0] {
+            ClaudeStreamEvent::ContentBlockStart {
+                index: 0,
+                content_block: ClaudeContentBlockStart::RedactedThinking { data },
+            } => assert_eq!(data, "EmwKAhgBEgy3va3pzix"),
+            other => panic!("Expected redacted thinking block start, got {:?}", other),
+        }
+        assert!(matches!(
+            &events[1],
+            ClaudeStreamEvent::ContentBlockStop { index: 0 }
+        ));
+    }
+
     #[tokio::test]
     async fn test_parse_input_json_delta() {
         let data = b"event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"location\\\":\"}}\n\n";
@@ -199,14 +334,12 @@ mod tests {
         assert!(result.is_some());
         let event = result.unwrap().unwrap();
         match event {
-            ClaudeStreamEvent::ContentBlockDelta { delta, .. } => {
-                match delta {
-                    ClaudeContentDelta::InputJsonDelta { partial_json } => {
-                        assert_eq!(partial_json, r#"{"location":"#);
-                    }
-                    _ => panic!("Expected input json delta"),
+            ClaudeStreamEvent::ContentBlockDelta { delta, .. } => match delta {
+                ClaudeContentDelta::InputJsonDelta { partial_json } => {
+                    assert_eq!(partial_json, r#"{"location":"#);
                 }
-            }
+                _ => panic!("Expected input json delta"),
+            },
             _ => panic!("Expected ContentBlockDelta event"),
         }
     }
@@ -274,7 +407,8 @@ mod tests {
     async fn test_parse_chunked_events() {
         // Simulate event arriving in chunks
         let chunk1 = b"event: content_block_delta\ndata: {\"type\":\"content_block";
-        let chunk2 = b"_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n";
+        let chunk2 =
+            b"_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n";
 
         let byte_stream = Box::pin(stream::iter(vec![
             Ok(Bytes::from_static(chunk1)),
@@ -286,14 +420,12 @@ mod tests {
         let result = sse_stream.next().await;
         assert!(result.is_some());
         match result.unwrap().unwrap() {
-            ClaudeStreamEvent::ContentBlockDelta { delta, .. } => {
-                match delta {
-                    ClaudeContentDelta::TextDelta { text } => {
-                        assert_eq!(text, "Hello");
-                    }
-                    _ => panic!("Expected text delta"),
+            ClaudeStreamEvent::ContentBlockDelta { delta, .. } => match delta {
+                ClaudeContentDelta::TextDelta { text } => {
+                    assert_eq!(text, "Hello");
                 }
-            }
+                _ => panic!("Expected text delta"),
+            },
             _ => panic!("Expected ContentBlockDelta event"),
         }
     }
@@ -341,7 +473,10 @@ mod tests {
 
         assert!(result.is_some());
         match result.unwrap().unwrap() {
-            ClaudeStreamEvent::ContentBlockStart { index, content_block } => {
+            ClaudeStreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => {
                 assert_eq!(index, 1);
                 match content_block {
                     ClaudeContentBlockStart::ToolUse { id, name } => {
@@ -366,4 +501,111 @@ mod tests {
         assert!(result.is_some());
         assert!(result.unwrap().is_err());
     }
+
+    #[tokio::test]
+    async fn test_parse_crlf_delimited_events() {
+        // The boundary between the events is split across chunks
+        let chunk1 = b"event: message_stop\r\ndata: {\"type\":\"message_stop\"}\r\n\r";
+        let chunk2 = b"\nevent: content_block_stop\r\ndata: {\"type\":\"content_block_stop\",\"index\":0}\r\n\r\n";
+        let byte_stream = Box::pin(stream::iter(vec![
+            Ok(Bytes::from_static(chunk1)),
+            Ok(Bytes::from_static(chunk2)),
+        ]));
+
+        let events: Vec<_> = parse_sse_stream(byte_stream).collect().await;
+
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], Ok(ClaudeStreamEvent::MessageStop)));
+        assert!(matches!(
+            events[1],
+            Ok(ClaudeStreamEvent::ContentBlockStop { index: 0 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_final_event_without_trailing_delimiter() {
+        let data = b"event: ping\ndata: {\"type\":\"ping\"}\n\nevent: message_stop\ndata: {\"type\":\"message_stop\"}";
+        let byte_stream = Box::pin(stream::iter(vec![Ok(Bytes::from_static(data))]));
+
+        let events: Vec<_> = parse_sse_stream(byte_stream).collect().await;
+
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[1], Ok(ClaudeStreamEvent::MessageStop)));
+    }
+
+    #[tokio::test]
+    async fn test_trailing_whitespace_is_not_an_event() {
+        let data = b"event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n \r\n\n";
+        let byte_stream = Box::pin(stream::iter(vec![Ok(Bytes::from_static(data))]));
+
+        let events: Vec<_> = parse_sse_stream(byte_stream).collect().await;
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], Ok(ClaudeStreamEvent::MessageStop)));
+    }
+
+    #[tokio::test]
+    async fn test_parse_multi_line_data() {
+        let data = b"event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\ndata: \"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n";
+        let byte_stream = Box::pin(stream::iter(vec![Ok(Bytes::from_static(data))]));
+
+        let mut sse_stream = parse_sse_stream(byte_stream);
+
+        match sse_stream.next().await.unwrap().unwrap() {
+            ClaudeStreamEvent::ContentBlockDelta {
+                delta: ClaudeContentDelta::TextDelta { text },
+                ..
+            } => assert_eq!(text, "Hello"),
+            _ => panic!("Expected text delta"),
+        }
+        assert!(sse_stream.next().await.is_none());
+    }
+
+    #[test]
+    fn test_find_event_boundary_takes_the_earliest() {
+        assert_eq!(find_event_boundary("a\n\nb\r\n\r\n"), Some((1, 2)));
+        assert_eq!(find_event_boundary("a\r\n\r\nb\n\n"), Some((1, 4)));
+        assert_eq!(find_event_boundary("a\r\nb\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_event_larger_than_buffer_limit_is_an_error() {
+        // 2 MiB with no event boundary, on a connection that never closes
+        let flood = Bytes::from(vec![b'x'; 2 * 1024 * 1024]);
+        let byte_stream = Box::pin(stream::iter(vec![Ok(flood)]).chain(stream::pending()));
+
+        let mut sse_stream = parse_sse_stream(byte_stream);
+        let (first, second) = tokio::time::timeout(Duration::from_secs(5), async {
+            (sse_stream.next().await, sse_stream.next().await)
+        })
+        .await
+        .expect("parser kept waiting for a boundary");
+
+        match first {
+            Some(Err(LlmError::StreamError(msg))) => {
+                assert_eq!(msg, "SSE event exceeded max buffer size")
+            }
+            other => panic!("Expected buffer size error, got {:?}", other),
+        }
+        assert!(second.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_buffer_limit_applies_only_to_incomplete_events() {
+        let data = b"event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n";
+        // Split across chunks; each chunk completes within the limit
+        let chunks = data
+            .chunks(40)
+            .chain(data.chunks(40))
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        let byte_stream = Box::pin(stream::iter(chunks));
+
+        let events: Vec<_> = parse_sse_stream_with_limit(byte_stream, data.len())
+            .collect()
+            .await;
+
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|event| event.is_ok()));
+    }
 }
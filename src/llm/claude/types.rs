@@ -15,23 +15,101 @@ pub struct StreamRawPredictRequest {
     pub messages: Vec<ClaudeMessage>,
     /// System prompt (top-level field)
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub system: Option<String>,
+    pub system: Option<ClaudeSystem>,
     /// Available tools for the model to use
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<ClaudeTool>>,
+    /// How the model may use the tools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ClaudeToolChoice>,
     /// Temperature (0.0-1.0)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
     /// Top-p nucleus sampling
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
+    /// Top-k sampling
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
     /// Stop sequences
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stop_sequences: Option<Vec<String>>,
+    /// Extended thinking settings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<ClaudeThinking>,
     /// Always true for streaming
     pub stream: bool,
 }
 
+/// Request to count the input tokens of a conversation via Vertex AI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CountTokensRequest {
+    /// Model whose tokenizer is used
+    pub model: String,
+    /// Array of messages in the conversation
+    pub messages: Vec<ClaudeMessage>,
+    /// System prompt (top-level field)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<ClaudeSystem>,
+    /// Available tools for the model to use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<ClaudeTool>>,
+    /// How the model may use the tools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ClaudeToolChoice>,
+    /// Extended thinking settings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<ClaudeThinking>,
+}
+
+/// Response to a [`CountTokensRequest`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CountTokensResponse {
+    /// Tokens taken up by the messages, system prompt and tools
+    pub input_tokens: u32,
+}
+
+/// Extended thinking settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaudeThinking {
+    /// Always "enabled"
+    #[serde(rename = "type")]
+    pub thinking_type: String,
+    /// Tokens the model may spend thinking
+    pub budget_tokens: u32,
+}
+
+/// System prompt, as plain text or as blocks that can carry a cache breakpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ClaudeSystem {
+    /// Plain text
+    Text(String),
+    /// Text blocks
+    Blocks(Vec<ClaudeSystemBlock>),
+}
+
+/// A text block of the system prompt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClaudeSystemBlock {
+    /// Always "text"
+    #[serde(rename = "type")]
+    pub block_type: String,
+    /// Prompt text
+    pub text: String,
+    /// Cache the prompt up to and including this block
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<ClaudeCacheControl>,
+}
+
+/// Prompt cache breakpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClaudeCacheControl {
+    /// Short-lived cache entry (the only kind Claude offers)
+    Ephemeral,
+}
+
 /// A single message in the Claude conversation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClaudeMessage {
@@ -70,6 +148,20 @@ pub enum ClaudeContentBlock {
         #[serde(skip_serializing_if = "Option::is_none")]
         is_error: Option<bool>,
     },
+    /// Image block
+    Image { source: ClaudeImageSource },
+    /// Encrypted thinking, sent back as it was received
+    RedactedThinking { data: String },
+}
+
+/// Source of an image block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClaudeImageSource {
+    /// Inline base64 data
+    Base64 { media_type: String, data: String },
+    /// Image fetched from a URL
+    Url { url: String },
 }
 
 /// Tool definition for Claude
@@ -81,6 +173,23 @@ pub struct ClaudeTool {
     pub description: String,
     /// Input schema (JSON Schema)
     pub input_schema: serde_json::Value,
+    /// Cache the prompt up to and including this tool
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<ClaudeCacheControl>,
+}
+
+/// Tool choice for Claude
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClaudeToolChoice {
+    /// Model decides
+    Auto,
+    /// Model must use some tool
+    Any,
+    /// Model must use the named tool
+    Tool { name: String },
+    /// Model must not use tools
+    None,
 }
 
 /// SSE event types from Claude streaming API
@@ -156,6 +265,14 @@ pub enum ClaudeContentBlockStart {
         id: String,
         name: String,
     },
+    /// Extended thinking block starting
+    Thinking {
+        thinking: String,
+    },
+    /// Encrypted thinking block; has no deltas
+    RedactedThinking {
+        data: String,
+    },
 }
 
 /// Content delta (incremental update)
@@ -170,6 +287,14 @@ pub enum ClaudeContentDelta {
     InputJsonDelta {
         partial_json: String,
     },
+    /// Thinking delta
+    ThinkingDelta {
+        thinking: String,
+    },
+    /// Signature closing a thinking block
+    SignatureDelta {
+        signature: String,
+    },
 }
 
 /// Message delta data
@@ -189,6 +314,12 @@ pub struct ClaudeUsage {
     pub input_tokens: u32,
     /// Output tokens generated
     pub output_tokens: u32,
+    /// Input tokens written to the prompt cache
+    #[serde(default)]
+    pub cache_creation_input_tokens: Option<u32>,
+    /// Input tokens read from the prompt cache
+    #[serde(default)]
+    pub cache_read_input_tokens: Option<u32>,
 }
 
 /// Error data
@@ -214,11 +345,14 @@ mod tests {
                 role: "user".to_string(),
                 content: ClaudeContent::Text("Hello".to_string()),
             }],
-            system: Some("You are helpful".to_string()),
+            system: Some(ClaudeSystem::Text("You are helpful".to_string())),
             tools: None,
+            tool_choice: None,
             temperature: Some(0.7),
             top_p: None,
+            top_k: None,
             stop_sequences: None,
+            thinking: None,
             stream: true,
         };
 
@@ -226,6 +360,28 @@ mod tests {
         assert!(json.contains("\"anthropic_version\":\"vertex-2023-10-16\""));
         assert!(json.contains("\"max_tokens\":1024"));
         assert!(json.contains("\"stream\":true"));
+        assert!(!json.contains("top_k"));
+    }
+
+    #[test]
+    fn test_stream_raw_predict_request_serializes_top_k() {
+        let request = StreamRawPredictRequest {
+            anthropic_version: "vertex-2023-10-16".to_string(),
+            max_tokens: 1024,
+            messages: vec![],
+            system: None,
+            tools: None,
+            tool_choice: None,
+            temperature: None,
+            top_p: None,
+            top_k: Some(40),
+            stop_sequences: None,
+            thinking: None,
+            stream: true,
+        };
+
+        let json: serde_json::Value = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["top_k"], 40);
     }
 
     #[test]
@@ -286,6 +442,7 @@ mod tests {
                     "location": {"type": "string"}
                 }
             }),
+            cache_control: None,
         };
 
         let json = serde_json::to_string(&tool).unwrap();
@@ -330,4 +487,29 @@ mod tests {
             _ => panic!("Expected InputJsonDelta"),
         }
     }
+
+    #[test]
+    fn test_thinking_stream_events() {
+        let json = r#"{"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}"#;
+        let event: ClaudeStreamEvent = serde_json::from_str(json).unwrap();
+        assert!(matches!(
+            event,
+            ClaudeStreamEvent::ContentBlockStart {
+                index: 0,
+                content_block: ClaudeContentBlockStart::Thinking { .. }
+            }
+        ));
+
+        let json = r#"{"type":"thinking_delta","thinking":"First, 2 + 2"}"#;
+        match serde_json::from_str(json).unwrap() {
+            ClaudeContentDelta::ThinkingDelta { thinking } => assert_eq!(thinking, "First, 2 + 2"),
+            _ => panic!("Expected ThinkingDelta"),
+        }
+
+        let json = r#"{"type":"signature_delta","signature":"EqQBCgIYAhIM"}"#;
+        assert!(matches!(
+            serde_json::from_str(json).unwrap(),
+            ClaudeContentDelta::SignatureDelta { .. }
+        ));
+    }
 }
@@ -0,0 +1,227 @@
+//! Aborting a response stream from outside it
+//!
+//! Dropping the stream from [`LlmProvider::stream_generate`] closes its HTTP
+//! connection, but only once whoever owns the stream drops it. An
+//! [`AbortHandle`] lets another task close it right away, so the provider
+//! stops generating (and billing) as soon as the caller gives up.
+//!
+//! [`LlmProvider::stream_generate`]: super::provider::LlmProvider::stream_generate
+
+use super::{error::LlmError, types::StreamEvent};
+use futures::stream::Stream;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>;
+
+/// The inner stream, shared between the wrapper and its handles
+#[derive(Default)]
+struct Slot {
+    stream: Option<EventStream>,
+    waker: Option<Waker>,
+    aborted: bool,
+}
+
+/// Aborts the stream returned with it by [`abortable`]
+///
+/// Cloning the handle shares the stream; any clone can abort it.
+#[derive(Clone)]
+pub struct AbortHandle {
+    slot: Arc<Mutex<Slot>>,
+}
+
+impl AbortHandle {
+    /// Drop the underlying stream, closing its connection
+    ///
+    /// The abortable stream ends (yields `None`) on its next poll, and a
+    /// task already waiting on it is woken. Aborting a stream that has
+    /// already finished, or aborting twice, does nothing.
+    pub fn abort(&self) {
+        let (stream, waker) = {
+            let mut slot = self.slot.lock().unwrap();
+            if slot.stream.is_none() {
+                return;
+            }
+            slot.aborted = true;
+            (slot.stream.take(), slot.waker.take())
+        };
+        // Dropped outside the lock, since closing the connection may take a moment
+        drop(stream);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Whether [`abort`](Self::abort) stopped the stream before it finished
+    pub fn is_aborted(&self) -> bool {
+        self.slot.lock().unwrap().aborted
+    }
+}
+
+impl fmt::Debug for AbortHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AbortHandle")
+            .field("aborted", &self.is_aborted())
+            .finish()
+    }
+}
+
+/// Stream half of [`abortable`]
+struct Abortable {
+    slot: Arc<Mutex<Slot>>,
+}
+
+impl Stream for Abortable {
+    type Item = Result<StreamEvent, LlmError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut slot = self.slot.lock().unwrap();
+        let Some(stream) = slot.stream.as_mut() else {
+            return Poll::Ready(None);
+        };
+        match stream.as_mut().poll_next(cx) {
+            Poll::Pending => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            // Release the connection as soon as the response is complete
+            Poll::Ready(None) => {
+                slot.stream = None;
+                slot.waker = None;
+                Poll::Ready(None)
+            }
+            ready => ready,
+        }
+    }
+}
+
+/// Wrap `stream` so it can be aborted through the returned handle
+///
+/// # Example
+///
+/// ```
+/// use futures::StreamExt;
+/// use rust2::llm::abortable;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let (mut stream, handle) = abortable(Box::pin(futures::stream::pending()));
+/// handle.abort();
+/// assert!(stream.next().await.is_none());
+/// assert!(handle.is_aborted());
+/// # }
+/// ```
+pub fn abortable(stream: EventStream) -> (EventStream, AbortHandle) {
+    let slot = Arc::new(Mutex::new(Slot {
+        stream: Some(stream),
+        ..Slot::default()
+    }));
+    let handle = AbortHandle { slot: slot.clone() };
+    (Box::pin(Abortable { slot }), handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::core::types::ContentDelta;
+    use futures::StreamExt;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn text_event(text: &str) -> StreamEvent {
+        StreamEvent::ContentDelta {
+            index: 0,
+            delta: ContentDelta::TextDelta {
+                text: text.to_string(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_abort_after_completion_is_a_noop() {
+        let events = vec![Ok(text_event("Hi")), Ok(text_event("!"))];
+        let (stream, handle) = abortable(Box::pin(futures::stream::iter(events)));
+
+        let received: Vec<_> = stream.collect().await;
+        handle.abort();
+
+        assert_eq!(received.len(), 2);
+        assert!(!handle.is_aborted());
+    }
+
+    #[tokio::test]
+    async fn test_abort_wakes_a_pending_reader() {
+        let (mut stream, handle) = abortable(Box::pin(futures::stream::pending()));
+        let reader = tokio::spawn(async move { stream.next().await.is_none() });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        handle.abort();
+
+        let ended = tokio::time::timeout(Duration::from_secs(5), reader)
+            .await
+            .expect("reader was not woken")
+            .unwrap();
+        assert!(ended);
+        assert!(handle.is_aborted());
+    }
+
+    /// Serve one request with a chunked body that never ends, flagging when the client hangs up
+    async fn spawn_endless_server() -> (std::net::SocketAddr, Arc<AtomicBool>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let closed = Arc::new(AtomicBool::new(false));
+        let flag = closed.clone();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 4096];
+            let _ = socket.read(&mut request).await;
+            let headers = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ntransfer-encoding: chunked\r\n\r\n";
+            if socket.write_all(headers.as_bytes()).await.is_err() {
+                flag.store(true, Ordering::SeqCst);
+                return;
+            }
+            loop {
+                if socket.write_all(b"6\r\ntoken \r\n").await.is_err() {
+                    flag.store(true, Ordering::SeqCst);
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+
+        (addr, closed)
+    }
+
+    #[tokio::test]
+    async fn test_abort_closes_a_partially_read_connection() {
+        let (addr, closed) = spawn_endless_server().await;
+        let response = reqwest::Client::new()
+            .get(format!("http://{}/", addr))
+            .send()
+            .await
+            .unwrap();
+        let events = response.bytes_stream().map(|chunk| {
+            chunk
+                .map(|bytes| text_event(&String::from_utf8_lossy(&bytes)))
+                .map_err(LlmError::from)
+        });
+        let (mut stream, handle) = abortable(Box::pin(events));
+
+        // Read part of the endless response, then give up on it
+        assert!(stream.next().await.unwrap().is_ok());
+        handle.abort();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !closed.load(Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("server never saw the connection close");
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -1,9 +1,23 @@
 //! Generation configuration parameters
 
+use rand::Rng;
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+use super::error::LlmError;
+use super::types::Model;
+
+/// Names accepted by [`GenerationConfig::preset`]
+pub const PRESET_NAMES: &[&str] = &["precise", "balanced", "creative", "fast_draft", "careful_final"];
 
 /// Parameters for controlling text generation
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Every field has a default, so config files only need to list the
+/// settings they change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GenerationConfig {
     /// Maximum number of tokens to generate
     pub max_tokens: u32,
@@ -13,12 +27,35 @@ pub struct GenerationConfig {
     /// Nucleus sampling threshold
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
-    /// Top-k sampling (Gemini-specific, ignored for Claude)
+    /// Top-k sampling: only sample from the k most likely tokens
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_k: Option<u32>,
     /// Stop generation when these sequences are encountered
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stop_sequences: Option<Vec<String>>,
+    /// JSON schema the final text response must conform to
+    ///
+    /// Gemini enforces this natively via `responseSchema`. Claude has no
+    /// equivalent, so its client offers a `__json_output__` tool with this
+    /// schema (forced unless the request has other tools or a thinking
+    /// budget) and streams the tool's input back as text. The agent also
+    /// validates the final text against it and asks the model to repair
+    /// mismatches.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_schema: Option<serde_json::Value>,
+    /// Tokens the model may spend on extended thinking before answering
+    ///
+    /// Claude only. Must be less than `max_tokens`. The agent streams
+    /// thinking but keeps it out of history; Claude expects it back
+    /// alongside tool calls, so use this for runs without tools.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking_budget: Option<u32>,
+    /// Cache the system prompt and tool definitions between requests
+    ///
+    /// Claude only. Requests that repeat the same prefix are billed at the
+    /// cheaper cache-read rate; see the cache fields of [`UsageMetadata`](crate::llm::UsageMetadata).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub prompt_cache: bool,
 }
 
 impl GenerationConfig {
@@ -30,6 +67,9 @@ impl GenerationConfig {
             top_p: None,
             top_k: None,
             stop_sequences: None,
+            response_schema: None,
+            thinking_budget: None,
+            prompt_cache: false,
         }
     }
 
@@ -45,7 +85,7 @@ impl GenerationConfig {
         self
     }
 
-    /// Set the top_k value (Gemini only)
+    /// Set the top_k value
     pub fn with_top_k(mut self, top_k: u32) -> Self {
         self.top_k = Some(top_k);
         self
@@ -56,6 +96,153 @@ impl GenerationConfig {
         self.stop_sequences = Some(stop_sequences);
         self
     }
+
+    /// Request JSON output matching `schema`
+    pub fn with_response_schema(mut self, schema: serde_json::Value) -> Self {
+        self.response_schema = Some(schema);
+        self
+    }
+
+    /// Enable extended thinking with a budget of `tokens`
+    pub fn with_thinking_budget(mut self, tokens: u32) -> Self {
+        self.thinking_budget = Some(tokens);
+        self
+    }
+
+    /// Cache the system prompt and tool definitions (Claude only)
+    pub fn with_prompt_cache(mut self, enabled: bool) -> Self {
+        self.prompt_cache = enabled;
+        self
+    }
+
+    /// Look up a built-in preset by name
+    ///
+    /// Returns `None` for unknown names; see [`PRESET_NAMES`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust2::llm::GenerationConfig;
+    ///
+    /// let config = GenerationConfig::preset("balanced").unwrap().with_top_k(40);
+    /// assert_eq!(config.temperature, Some(0.7));
+    /// ```
+    pub fn preset(name: &str) -> Option<Self> {
+        let config = match name {
+            "precise" => Self::new(1024).with_temperature(0.0),
+            "balanced" => Self::new(2048).with_temperature(0.7).with_top_p(0.95),
+            "creative" => Self::new(4096).with_temperature(1.0).with_top_p(0.98),
+            "fast_draft" => Self::new(512).with_temperature(0.9),
+            "careful_final" => Self::new(4096).with_temperature(0.2).with_top_p(0.9),
+            _ => return None,
+        };
+        Some(config)
+    }
+
+    /// Check the values are usable by at least one provider
+    ///
+    /// Temperature must be within 0.0-2.0 (Gemini's range) and top_p within
+    /// 0.0-1.0. Use [`validate_for`](Self::validate_for) to apply the limits
+    /// of a specific model.
+    pub fn validate(&self) -> Result<(), LlmError> {
+        self.validate_with_max_temperature(2.0)
+    }
+
+    /// Check the values are within the ranges accepted by `model`'s provider
+    ///
+    /// Claude accepts temperatures in 0.0-1.0, Gemini in 0.0-2.0.
+    pub fn validate_for(&self, model: &Model) -> Result<(), LlmError> {
+        match model {
+            Model::Claude(_) => self.validate_with_max_temperature(1.0),
+            Model::Gemini(_) => self.validate_with_max_temperature(2.0),
+        }
+    }
+
+    /// List the configured parameters that a provider with `capabilities` would ignore
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust2::llm::{GenerationConfig, ProviderCapabilities};
+    ///
+    /// let config = GenerationConfig::new(4096)
+    ///     .with_top_k(40)
+    ///     .with_thinking_budget(1024);
+    ///
+    /// assert!(config.compatibility_report(&ProviderCapabilities::CLAUDE).is_compatible());
+    /// assert_eq!(
+    ///     config.compatibility_report(&ProviderCapabilities::GEMINI).ignored,
+    ///     vec!["thinking_budget"]
+    /// );
+    /// ```
+    pub fn compatibility_report(&self, capabilities: &ProviderCapabilities) -> CompatibilityReport {
+        let checks = [
+            ("temperature", self.temperature.is_some(), capabilities.temperature),
+            ("top_p", self.top_p.is_some(), capabilities.top_p),
+            ("top_k", self.top_k.is_some(), capabilities.top_k),
+            ("stop_sequences", self.stop_sequences.is_some(), capabilities.stop_sequences),
+            ("response_schema", self.response_schema.is_some(), capabilities.response_schema),
+            ("thinking_budget", self.thinking_budget.is_some(), capabilities.thinking),
+            ("prompt_cache", self.prompt_cache, capabilities.prompt_cache),
+        ];
+
+        CompatibilityReport {
+            ignored: checks
+                .into_iter()
+                .filter(|(_, set, supported)| *set && !supported)
+                .map(|(name, _, _)| name)
+                .collect(),
+        }
+    }
+
+    /// Warn about (or, when `strict`, reject) parameters `provider` would ignore
+    ///
+    /// Called by the clients at request time.
+    pub(crate) fn check_compatibility(
+        &self,
+        capabilities: &ProviderCapabilities,
+        provider: &str,
+        strict: bool,
+    ) -> Result<(), LlmError> {
+        let report = self.compatibility_report(capabilities);
+        if report.is_compatible() {
+            return Ok(());
+        }
+
+        if strict {
+            return Err(LlmError::InvalidRequest(format!("{}: {}", provider, report)));
+        }
+        tracing::warn!(provider, ignored = ?report.ignored, "{}", report);
+        Ok(())
+    }
+
+    fn validate_with_max_temperature(&self, max_temperature: f32) -> Result<(), LlmError> {
+        if self.max_tokens == 0 {
+            return Err(LlmError::InvalidRequest(
+                "max_tokens must be greater than 0".to_string(),
+            ));
+        }
+
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=max_temperature).contains(&temperature) {
+                return Err(LlmError::InvalidRequest(format!(
+                    "temperature {} is outside the range 0.0-{:.1}",
+                    temperature, max_temperature
+                )));
+            }
+        }
+
+        if let Some(top_p) = self.top_p {
+            if !(0.0..=1.0).contains(&top_p) {
+                return Err(LlmError::InvalidRequest(format!(
+                    "top_p {} is outside the range 0.0-1.0",
+                    top_p
+                )));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Default for GenerationConfig {
@@ -66,6 +253,155 @@ impl Default for GenerationConfig {
             top_p: None,
             top_k: None,
             stop_sequences: None,
+            response_schema: None,
+            thinking_budget: None,
+            prompt_cache: false,
+        }
+    }
+}
+
+/// Optional generation parameters a provider honours
+///
+/// `max_tokens` is required by every provider and so isn't listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProviderCapabilities {
+    pub temperature: bool,
+    pub top_p: bool,
+    pub top_k: bool,
+    pub stop_sequences: bool,
+    /// Structured output, native or emulated (the agent validates the schema regardless)
+    pub response_schema: bool,
+    /// Extended thinking (`thinking_budget`)
+    pub thinking: bool,
+    /// Prompt caching (`prompt_cache`)
+    pub prompt_cache: bool,
+}
+
+impl ProviderCapabilities {
+    /// Every parameter is supported; the default for custom providers
+    pub const ALL: Self = Self {
+        temperature: true,
+        top_p: true,
+        top_k: true,
+        stop_sequences: true,
+        response_schema: true,
+        thinking: true,
+        prompt_cache: true,
+    };
+
+    /// Claude on Vertex AI supports every parameter
+    ///
+    /// It has no native response schema; the client emulates one with a
+    /// forced tool call instead.
+    pub const CLAUDE: Self = Self::ALL;
+
+    /// Gemini on Vertex AI has no extended thinking budget or cache breakpoints
+    pub const GEMINI: Self = Self {
+        thinking: false,
+        prompt_cache: false,
+        ..Self::ALL
+    };
+
+    /// Capabilities of the provider serving `model`
+    pub fn for_model(model: &Model) -> Self {
+        match model {
+            Model::Claude(_) => Self::CLAUDE,
+            Model::Gemini(_) => Self::GEMINI,
+        }
+    }
+}
+
+/// Parameters in a `GenerationConfig` that a provider will ignore
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CompatibilityReport {
+    /// Names of the ignored parameters, in declaration order
+    pub ignored: Vec<&'static str>,
+}
+
+impl CompatibilityReport {
+    /// Whether every configured parameter is supported
+    pub fn is_compatible(&self) -> bool {
+        self.ignored.is_empty()
+    }
+}
+
+impl fmt::Display for CompatibilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ignored.is_empty() {
+            write!(f, "all generation parameters are supported")
+        } else {
+            write!(
+                f,
+                "unsupported generation parameters will be ignored: {}",
+                self.ignored.join(", ")
+            )
+        }
+    }
+}
+
+/// What a client does with a tool result larger than its configured limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToolResultOverflow {
+    /// Fail locally with `LlmError::ToolResultTooLarge` (default)
+    #[default]
+    Error,
+    /// Keep the first `limit` bytes and append a truncation marker
+    Truncate,
+}
+
+/// How a client retries rate limits and transient server errors
+///
+/// Applies to responses with a `retry_on` status when opening a stream.
+/// Each call to `stream_generate` starts again from the first attempt. A
+/// `Retry-After` header on the response overrides the backoff delay, up to
+/// `max_delay`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryConfig {
+    /// Requests sent in total, including the first (default: 4)
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after each one (default: 500ms)
+    pub initial_delay: Duration,
+    /// Longest delay between attempts (default: 30s)
+    pub max_delay: Duration,
+    /// Randomize each delay between half and all of its value, so
+    /// concurrent clients don't retry in lockstep (default: on)
+    pub jitter: bool,
+    /// Statuses worth retrying (default: [`RetryConfig::default_retry_on`])
+    pub retry_on: Vec<StatusCode>,
+}
+
+impl RetryConfig {
+    /// Timeouts (408), rate limits (429), transient server errors (500, 502,
+    /// 503) and Anthropic's 529 (overloaded)
+    pub fn default_retry_on() -> Vec<StatusCode> {
+        [408, 429, 500, 502, 503, 529]
+            .into_iter()
+            .map(|code| StatusCode::from_u16(code).expect("valid status code"))
+            .collect()
+    }
+
+    /// Delay before retry number `retry` (0-based)
+    pub(crate) fn delay<R: Rng>(&self, retry: u32, rng: &mut R) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        if self.jitter {
+            delay.mul_f64(rng.gen_range(0.5..=1.0))
+        } else {
+            delay
+        }
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            retry_on: Self::default_retry_on(),
         }
     }
 }
@@ -74,6 +410,35 @@ impl Default for GenerationConfig {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_retry_delay_is_exponential_and_capped() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let config = RetryConfig {
+            max_attempts: 6,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+            jitter: false,
+            ..RetryConfig::default()
+        };
+        let delays: Vec<u64> = (0..5)
+            .map(|retry| config.delay(retry, &mut rng).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+
+        let jittered = RetryConfig {
+            jitter: true,
+            ..config.clone()
+        };
+        for retry in 0..5 {
+            let max = config.delay(retry, &mut rng);
+            let delay = jittered.delay(retry, &mut rng);
+            assert!(delay >= max / 2 && delay <= max, "retry {}: {:?}", retry, delay);
+        }
+    }
+
     #[test]
     fn test_config_new() {
         let config = GenerationConfig::new(2048);
@@ -125,4 +490,147 @@ mod tests {
         assert_eq!(config.temperature, Some(0.8));
         assert!(config.top_p.is_none());
     }
+
+    #[test]
+    fn test_config_deserialization_defaults() {
+        let config: GenerationConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config, GenerationConfig::default());
+
+        let config: GenerationConfig = serde_json::from_str(r#"{"top_k":40}"#).unwrap();
+        assert_eq!(config.max_tokens, 1024);
+        assert_eq!(config.top_k, Some(40));
+    }
+
+    #[test]
+    fn test_config_json_round_trip() {
+        let config = GenerationConfig::new(4096)
+            .with_temperature(0.25)
+            .with_stop_sequences(vec!["END".to_string()]);
+        let json = serde_json::to_string(&config).unwrap();
+        let parsed: GenerationConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, config);
+    }
+
+    #[test]
+    fn test_config_toml_round_trip() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct UseCases {
+            fast_draft: GenerationConfig,
+            careful_final: GenerationConfig,
+        }
+
+        let file = r#"
+            [fast_draft]
+            max_tokens = 512
+            temperature = 0.75
+
+            [careful_final]
+            temperature = 0.25
+            stop_sequences = ["END"]
+        "#;
+
+        let parsed: UseCases = toml::from_str(file).unwrap();
+        assert_eq!(parsed.fast_draft, GenerationConfig::new(512).with_temperature(0.75));
+        assert_eq!(parsed.careful_final.max_tokens, 1024);
+        assert_eq!(parsed.careful_final.stop_sequences, Some(vec!["END".to_string()]));
+
+        let serialized = toml::to_string(&parsed).unwrap();
+        let reparsed: UseCases = toml::from_str(&serialized).unwrap();
+        assert_eq!(reparsed, parsed);
+    }
+
+    #[test]
+    fn test_presets() {
+        let balanced = GenerationConfig::preset("balanced").unwrap();
+        assert_eq!(balanced.max_tokens, 2048);
+        assert_eq!(balanced.temperature, Some(0.7));
+        assert_eq!(balanced.top_p, Some(0.95));
+
+        let precise = GenerationConfig::preset("precise").unwrap();
+        assert_eq!(precise.temperature, Some(0.0));
+
+        assert!(GenerationConfig::preset("unknown").is_none());
+
+        for name in PRESET_NAMES {
+            let config = GenerationConfig::preset(name).unwrap();
+            assert!(config.validate().is_ok(), "preset {} is invalid", name);
+        }
+    }
+
+    #[test]
+    fn test_validate_rejects_zero_max_tokens() {
+        let err = GenerationConfig::new(0).validate().unwrap_err();
+        assert!(matches!(err, LlmError::InvalidRequest(ref msg) if msg.contains("max_tokens")));
+    }
+
+    #[test]
+    fn test_validate_temperature_ranges() {
+        let claude = Model::Claude(crate::llm::ClaudeModel::Sonnet45);
+        let gemini = Model::Gemini(crate::llm::GeminiModel::Gemini25Flash);
+
+        let hot = GenerationConfig::new(1024).with_temperature(1.5);
+        assert!(hot.validate().is_ok());
+        assert!(hot.validate_for(&gemini).is_ok());
+        let err = hot.validate_for(&claude).unwrap_err();
+        assert!(matches!(err, LlmError::InvalidRequest(ref msg) if msg.contains("0.0-1.0")));
+
+        assert!(GenerationConfig::new(1024).with_temperature(2.5).validate().is_err());
+        assert!(GenerationConfig::new(1024).with_temperature(-0.1).validate().is_err());
+        assert!(GenerationConfig::new(1024).with_top_p(1.5).validate().is_err());
+    }
+
+    #[test]
+    fn test_compatibility_report_lists_ignored_parameters() {
+        let config = GenerationConfig::new(1024)
+            .with_top_k(40)
+            .with_stop_sequences(vec!["END".to_string()])
+            .with_response_schema(serde_json::json!({"type": "object"}));
+
+        let caps = ProviderCapabilities {
+            top_k: false,
+            response_schema: false,
+            ..ProviderCapabilities::ALL
+        };
+        let report = config.compatibility_report(&caps);
+        assert_eq!(report.ignored, vec!["top_k", "response_schema"]);
+        assert_eq!(
+            report.to_string(),
+            "unsupported generation parameters will be ignored: top_k, response_schema"
+        );
+
+        assert!(config.compatibility_report(&ProviderCapabilities::ALL).is_compatible());
+        assert!(GenerationConfig::new(1024)
+            .compatibility_report(&ProviderCapabilities::CLAUDE)
+            .is_compatible());
+    }
+
+    #[test]
+    fn test_check_compatibility_strict_mode() {
+        let config = GenerationConfig::new(4096).with_thinking_budget(1024);
+        let claude = ProviderCapabilities::for_model(&Model::Claude(crate::llm::ClaudeModel::Sonnet45));
+        let gemini = ProviderCapabilities::for_model(&Model::Gemini(crate::llm::GeminiModel::Gemini25Flash));
+
+        assert!(config.check_compatibility(&gemini, "gemini", false).is_ok());
+        assert!(config.check_compatibility(&claude, "claude", true).is_ok());
+
+        let err = config.check_compatibility(&gemini, "gemini", true).unwrap_err();
+        assert!(matches!(err, LlmError::InvalidRequest(ref msg) if msg.starts_with("gemini:") && msg.contains("thinking_budget")));
+
+        // Claude emulates structured output, so both providers accept a schema
+        let schema = GenerationConfig::new(1024).with_response_schema(serde_json::json!({"type": "object"}));
+        assert!(schema.check_compatibility(&claude, "claude", true).is_ok());
+        assert!(schema.check_compatibility(&gemini, "gemini", true).is_ok());
+    }
+
+    #[test]
+    fn test_thinking_budget_is_claude_only() {
+        let config = GenerationConfig::new(4096).with_thinking_budget(1024);
+        assert_eq!(config.thinking_budget, Some(1024));
+
+        assert!(config.compatibility_report(&ProviderCapabilities::CLAUDE).is_compatible());
+        assert_eq!(
+            config.compatibility_report(&ProviderCapabilities::GEMINI).ignored,
+            vec!["thinking_budget"]
+        );
+    }
 }
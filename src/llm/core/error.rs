@@ -30,9 +30,41 @@ pub enum LlmError {
     #[error("Rate limit exceeded (retry after {retry_after:?})")]
     RateLimitExceeded { retry_after: Option<Duration> },
 
+    /// A tool result exceeds the client's configured size limit
+    #[error("Tool result for '{name}' is {size} bytes, exceeding the limit of {limit} bytes")]
+    ToolResultTooLarge {
+        name: String,
+        size: usize,
+        limit: usize,
+    },
+
+    /// The provider finished the stream without producing any candidates
+    #[error("Empty response: the provider returned no candidates")]
+    EmptyResponse,
+
+    /// The provider doesn't implement the requested operation
+    #[error("Operation not supported by this provider")]
+    NotSupported,
+
     /// Provider-specific errors
     #[error("Provider error ({code}): {message}")]
     ProviderError { code: String, message: String },
+
+    /// The provider is temporarily unavailable (e.g. HTTP 503 or 529); the
+    /// same request may succeed later
+    ///
+    /// Returned once the client's own retries, if any, are used up.
+    #[error("Retryable error (status {status}): {body}")]
+    Retryable { status: u16, body: String },
+
+    /// The provider is still rate limiting (HTTP 429) after `retries`
+    /// retries by the client
+    #[error("Rate limited after {retries} retries")]
+    RateLimited { retries: u32 },
+
+    /// The provider rejected the request; sending it again won't help
+    #[error("Permanent error (status {status}): {body}")]
+    Permanent { status: u16, body: String },
 }
 
 // Implement conversion from common error types
@@ -85,6 +117,9 @@ mod tests {
             retry_after: Some(Duration::from_secs(60)),
         };
         assert!(err.to_string().contains("Rate limit exceeded"));
+
+        let err = LlmError::RateLimited { retries: 3 };
+        assert_eq!(err.to_string(), "Rate limited after 3 retries");
     }
 
     #[test]
@@ -97,6 +132,33 @@ mod tests {
         assert!(err.to_string().contains("API key is invalid"));
     }
 
+    #[test]
+    fn test_tool_result_too_large_error() {
+        let err = LlmError::ToolResultTooLarge {
+            name: "search".to_string(),
+            size: 2048,
+            limit: 1024,
+        };
+        assert!(err.to_string().contains("search"));
+        assert!(err.to_string().contains("2048"));
+        assert!(err.to_string().contains("1024"));
+    }
+
+    #[test]
+    fn test_retryable_and_permanent_errors() {
+        let err = LlmError::Retryable {
+            status: 503,
+            body: "overloaded".to_string(),
+        };
+        assert_eq!(err.to_string(), "Retryable error (status 503): overloaded");
+
+        let err = LlmError::Permanent {
+            status: 400,
+            body: "bad request".to_string(),
+        };
+        assert_eq!(err.to_string(), "Permanent error (status 400): bad request");
+    }
+
     #[test]
     fn test_from_serde_error() {
         let json_err = serde_json::from_str::<serde_json::Value>("invalid json").unwrap_err();
@@ -0,0 +1,189 @@
+//! Response locale instructions and a cheap language check
+//!
+//! Neither Claude nor Gemini has a request field that sets the language of a
+//! text response (Gemini's `speechConfig.languageCode` only applies to audio
+//! output), so `GenerateRequest::locale` is turned into an instruction
+//! appended to the system prompt. [`matches_locale`] then checks the answer's
+//! writing system for locales that don't use the Latin script.
+
+/// Instruction appended to the system prompt for `locale` (a BCP-47 tag)
+pub fn locale_instruction(locale: &str) -> String {
+    format!(
+        "Always respond in the language of the locale {}, even if the user writes in a different language.",
+        locale
+    )
+}
+
+/// Firmer instruction used when retrying an answer given in the wrong language
+pub fn strong_locale_instruction(locale: &str) -> String {
+    format!(
+        "IMPORTANT: Your entire response MUST be written in the language of the locale {}. \
+         Do not answer in any other language, whatever language the conversation is in.",
+        locale
+    )
+}
+
+/// `system` with the instruction for `locale` appended, if a locale is set
+pub fn system_with_locale(system: Option<String>, locale: Option<&str>) -> Option<String> {
+    let Some(locale) = locale else {
+        return system;
+    };
+    let instruction = locale_instruction(locale);
+    Some(match system {
+        Some(system) => format!("{}\n\n{}", system, instruction),
+        None => instruction,
+    })
+}
+
+/// Writing systems [`matches_locale`] can recognize
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Script {
+    Arabic,
+    Cyrillic,
+    Devanagari,
+    Greek,
+    Han,
+    Hangul,
+    Hebrew,
+    /// Kana and Han, as mixed in Japanese text
+    Japanese,
+    Thai,
+}
+
+impl Script {
+    fn contains(self, c: char) -> bool {
+        let c = c as u32;
+        let han = (0x4E00..=0x9FFF).contains(&c) || (0x3400..=0x4DBF).contains(&c);
+        match self {
+            Script::Arabic => (0x0600..=0x06FF).contains(&c) || (0x0750..=0x077F).contains(&c),
+            Script::Cyrillic => (0x0400..=0x052F).contains(&c),
+            Script::Devanagari => (0x0900..=0x097F).contains(&c),
+            Script::Greek => (0x0370..=0x03FF).contains(&c) || (0x1F00..=0x1FFF).contains(&c),
+            Script::Han => han,
+            Script::Hangul => (0xAC00..=0xD7AF).contains(&c) || (0x1100..=0x11FF).contains(&c),
+            Script::Hebrew => (0x0590..=0x05FF).contains(&c),
+            Script::Japanese => han || (0x3040..=0x30FF).contains(&c),
+            Script::Thai => (0x0E00..=0x0E7F).contains(&c),
+        }
+    }
+}
+
+/// The non-Latin script text in `locale` is written in, if any
+///
+/// An explicit script subtag such as `sr-Latn` or `zh-Hant` takes precedence
+/// over the language's usual script.
+pub fn expected_script(locale: &str) -> Option<Script> {
+    let mut subtags = locale.split(['-', '_']);
+    let language = subtags.next()?.to_ascii_lowercase();
+    let script = subtags.find(|subtag| subtag.len() == 4);
+
+    if let Some(script) = script {
+        return match script.to_ascii_lowercase().as_str() {
+            "arab" => Some(Script::Arabic),
+            "cyrl" => Some(Script::Cyrillic),
+            "deva" => Some(Script::Devanagari),
+            "grek" => Some(Script::Greek),
+            "hans" | "hant" => Some(Script::Han),
+            "hang" | "kore" => Some(Script::Hangul),
+            "hebr" => Some(Script::Hebrew),
+            "jpan" => Some(Script::Japanese),
+            "thai" => Some(Script::Thai),
+            _ => None,
+        };
+    }
+
+    match language.as_str() {
+        "ar" | "fa" | "ur" => Some(Script::Arabic),
+        "ru" | "uk" | "bg" | "be" | "mk" | "sr" | "kk" => Some(Script::Cyrillic),
+        "hi" | "mr" | "ne" => Some(Script::Devanagari),
+        "el" => Some(Script::Greek),
+        "zh" => Some(Script::Han),
+        "ko" => Some(Script::Hangul),
+        "he" => Some(Script::Hebrew),
+        "ja" => Some(Script::Japanese),
+        "th" => Some(Script::Thai),
+        _ => None,
+    }
+}
+
+/// Whether `text` looks written in `locale`'s language, judged by script
+///
+/// Returns `None` when the check doesn't apply: Latin-script locales, or
+/// text without letters. Otherwise the answer is whether at least half the
+/// letters belong to the locale's script, so names and code in Latin letters
+/// don't count against an answer.
+///
+/// # Example
+///
+/// ```
+/// use rust2::llm::core::locale::matches_locale;
+///
+/// assert_eq!(matches_locale("Привет, мир", "ru-RU"), Some(true));
+/// assert_eq!(matches_locale("Hello, world", "ru-RU"), Some(false));
+/// assert_eq!(matches_locale("Hello, world", "de-DE"), None);
+/// ```
+pub fn matches_locale(text: &str, locale: &str) -> Option<bool> {
+    let script = expected_script(locale)?;
+
+    let (mut letters, mut in_script) = (0usize, 0usize);
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        if script.contains(c) {
+            in_script += 1;
+        }
+    }
+
+    if letters == 0 {
+        return None;
+    }
+    Some(in_script * 2 >= letters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_with_locale() {
+        assert_eq!(
+            system_with_locale(Some("Be brief.".to_string()), None).unwrap(),
+            "Be brief."
+        );
+        assert_eq!(system_with_locale(None, None), None);
+
+        let system = system_with_locale(Some("Be brief.".to_string()), Some("de-DE")).unwrap();
+        assert!(system.starts_with("Be brief.\n\n"));
+        assert!(system.ends_with(&locale_instruction("de-DE")));
+
+        assert_eq!(
+            system_with_locale(None, Some("de-DE")).unwrap(),
+            locale_instruction("de-DE")
+        );
+    }
+
+    #[test]
+    fn test_expected_script() {
+        assert_eq!(expected_script("ja-JP"), Some(Script::Japanese));
+        assert_eq!(expected_script("zh_Hant_TW"), Some(Script::Han));
+        assert_eq!(expected_script("sr-Latn-RS"), None);
+        assert_eq!(expected_script("sr-RS"), Some(Script::Cyrillic));
+        assert_eq!(expected_script("de-DE"), None);
+        assert_eq!(expected_script(""), None);
+    }
+
+    #[test]
+    fn test_matches_locale() {
+        assert_eq!(matches_locale("東京の天気は晴れです", "ja-JP"), Some(true));
+        assert_eq!(
+            matches_locale("The weather in Tokyo is sunny", "ja-JP"),
+            Some(false)
+        );
+        // Mostly Korean with a Latin product name
+        assert_eq!(
+            matches_locale("Rust 프로그래밍 언어를 추천합니다", "ko-KR"),
+            Some(true)
+        );
+        assert_eq!(matches_locale("42 + 1 = 43", "ar-EG"), None);
+        assert_eq!(matches_locale("Bonjour", "fr-FR"), None);
+    }
+}
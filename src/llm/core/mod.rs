@@ -1,6 +1,10 @@
 //! Core abstractions for the LLM layer
 
+pub mod abort;
 pub mod config;
 pub mod error;
+pub mod locale;
 pub mod provider;
+pub mod schema;
+pub mod trim;
 pub mod types;
@@ -2,11 +2,21 @@
 
 use async_trait::async_trait;
 use futures::stream::Stream;
+use futures::StreamExt;
 use std::pin::Pin;
 
-use super::{error::LlmError, types::{GenerateRequest, Model, StreamEvent}};
+use super::{
+    abort::{abortable, AbortHandle},
+    config::ProviderCapabilities,
+    error::LlmError,
+    types::{
+        ContentBlock, ContentBlockStart, ContentDelta, FinishReason, GenerateRequest,
+        GenerateResponse, Model, StreamEvent, UsageMetadata,
+    },
+};
 use crate::llm::claude::ClaudeClient;
 use crate::llm::gemini::GeminiClient;
+use crate::llm::http::endpoint::EndpointOverride;
 
 /// Main interface that all LLM provider implementations must satisfy
 #[async_trait]
@@ -25,6 +35,238 @@ pub trait LlmProvider: Send + Sync {
         &self,
         request: GenerateRequest,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>;
+
+    /// Stream generate content, with a handle that aborts the request
+    ///
+    /// Aborting drops the underlying stream at once, closing the HTTP
+    /// connection so the provider stops generating, even while nobody is
+    /// polling the stream. The stream then ends. Aborting after the stream
+    /// has finished does nothing.
+    ///
+    /// Defaults to wrapping [`stream_generate`](Self::stream_generate) with
+    /// [`abortable`]; providers with a server-side cancel can override it.
+    async fn stream_generate_abortable(
+        &self,
+        request: GenerateRequest,
+    ) -> Result<(Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, AbortHandle), LlmError>
+    {
+        let stream = self.stream_generate(request).await?;
+        Ok(abortable(stream))
+    }
+
+    /// Generate a complete response without handling the stream
+    ///
+    /// Drives [`stream_generate`](Self::stream_generate) to the end and
+    /// assembles text deltas and tool use input into content blocks.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from the stream, a `StreamError` for a
+    /// `StreamEvent::Error` or a stream that ends before `MessageEnd`, and a
+    /// `SerializationError` if a tool call's input isn't valid JSON.
+    async fn generate(&self, request: GenerateRequest) -> Result<GenerateResponse, LlmError> {
+        let stream = self.stream_generate(request).await?;
+        collect_response(stream).await
+    }
+
+    /// Count the input tokens `request` would use, without generating anything
+    ///
+    /// Lets callers keep a conversation within the model's context window,
+    /// or check a token budget, before sending it. The count covers the
+    /// messages, system prompt and tools as the provider would send them.
+    ///
+    /// Defaults to `LlmError::NotSupported`.
+    async fn count_tokens(&self, _request: &GenerateRequest) -> Result<u32, LlmError> {
+        Err(LlmError::NotSupported)
+    }
+
+    /// Human-readable provider name used in logs and events
+    ///
+    /// Defaults to the implementing type's name.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Generation parameters this provider honours
+    ///
+    /// Defaults to [`ProviderCapabilities::ALL`].
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities::ALL
+    }
+}
+
+/// A shared provider, e.g. one client handed to the `Agent` of every request
+#[async_trait]
+impl<P: LlmProvider + ?Sized> LlmProvider for std::sync::Arc<P> {
+    async fn stream_generate(
+        &self,
+        request: GenerateRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError> {
+        (**self).stream_generate(request).await
+    }
+
+    async fn stream_generate_abortable(
+        &self,
+        request: GenerateRequest,
+    ) -> Result<(Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, AbortHandle), LlmError>
+    {
+        (**self).stream_generate_abortable(request).await
+    }
+
+    async fn generate(&self, request: GenerateRequest) -> Result<GenerateResponse, LlmError> {
+        (**self).generate(request).await
+    }
+
+    async fn count_tokens(&self, request: &GenerateRequest) -> Result<u32, LlmError> {
+        (**self).count_tokens(request).await
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        (**self).capabilities()
+    }
+}
+
+/// A content block still being streamed
+enum PendingBlock {
+    Text { index: usize, text: String },
+    ToolUse {
+        index: usize,
+        id: String,
+        name: String,
+        input: String,
+        complete: Option<serde_json::Value>,
+    },
+    RedactedThinking { index: usize, data: String },
+}
+
+impl PendingBlock {
+    fn index(&self) -> usize {
+        match self {
+            PendingBlock::Text { index, .. }
+            | PendingBlock::ToolUse { index, .. }
+            | PendingBlock::RedactedThinking { index, .. } => *index,
+        }
+    }
+}
+
+/// Assemble a stream into a `GenerateResponse`
+///
+/// Blocks are kept in arrival order. Consecutive text deltas for the same
+/// index join one text block; a text delta with no preceding block start
+/// (as Gemini sends) opens one.
+async fn collect_response(
+    mut stream: Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>,
+) -> Result<GenerateResponse, LlmError> {
+    let mut message_id = String::new();
+    let mut blocks: Vec<PendingBlock> = Vec::new();
+    let mut usage: Option<UsageMetadata> = None;
+    let mut finish_reason: Option<FinishReason> = None;
+
+    while let Some(event) = stream.next().await {
+        match event? {
+            StreamEvent::MessageStart { message } => {
+                message_id = message.id;
+                usage = message.usage.or(usage);
+            }
+            StreamEvent::ContentBlockStart { index, block } => match block {
+                ContentBlockStart::Text { text } => blocks.push(PendingBlock::Text { index, text }),
+                ContentBlockStart::ToolUse { id, name } => blocks.push(PendingBlock::ToolUse {
+                    index,
+                    id,
+                    name,
+                    input: String::new(),
+                    complete: None,
+                }),
+                // Thinking isn't part of the response content
+                ContentBlockStart::Thinking { .. } => {}
+                ContentBlockStart::RedactedThinking { data } => {
+                    blocks.push(PendingBlock::RedactedThinking { index, data })
+                }
+            },
+            StreamEvent::ContentDelta { index, delta } => match delta {
+                ContentDelta::TextDelta { text: delta } => match blocks.last_mut() {
+                    Some(PendingBlock::Text { index: last, text }) if *last == index => {
+                        text.push_str(&delta)
+                    }
+                    _ => blocks.push(PendingBlock::Text { index, text: delta }),
+                },
+                ContentDelta::ToolUseDelta { partial } => {
+                    let open_tool = blocks.iter_mut().rev().find(|b| {
+                        matches!(b, PendingBlock::ToolUse { complete: None, .. }) && b.index() == index
+                    });
+                    if let Some(PendingBlock::ToolUse { input, .. }) = open_tool {
+                        input.push_str(&partial.partial_json);
+                    }
+                }
+                ContentDelta::ThinkingDelta { .. } => {}
+            },
+            StreamEvent::ContentBlockEnd { index } => {
+                let open_tool = blocks.iter_mut().rev().find(|b| {
+                    matches!(b, PendingBlock::ToolUse { complete: None, .. }) && b.index() == index
+                });
+                if let Some(PendingBlock::ToolUse { input, complete, .. }) = open_tool {
+                    *complete = Some(parse_tool_input(input)?);
+                }
+            }
+            StreamEvent::MessageDelta { usage: delta } => {
+                usage = delta.or(usage);
+            }
+            StreamEvent::MessageEnd {
+                finish_reason: reason,
+                usage: final_usage,
+            } => {
+                finish_reason = Some(reason);
+                usage = Some(final_usage);
+            }
+            StreamEvent::Error { error } => return Err(LlmError::StreamError(error)),
+        }
+    }
+
+    let finish_reason = finish_reason.ok_or_else(|| {
+        LlmError::StreamError("stream ended before the message was complete".to_string())
+    })?;
+
+    let content = blocks
+        .into_iter()
+        .map(|block| match block {
+            PendingBlock::Text { text, .. } => Ok(ContentBlock::Text { text }),
+            PendingBlock::ToolUse {
+                id,
+                name,
+                input,
+                complete,
+                ..
+            } => {
+                let input = match complete {
+                    Some(input) => input,
+                    None => parse_tool_input(&input)?,
+                };
+                Ok(ContentBlock::ToolUse { id, name, input })
+            }
+            PendingBlock::RedactedThinking { data, .. } => {
+                Ok(ContentBlock::RedactedThinking { data })
+            }
+        })
+        .collect::<Result<Vec<_>, LlmError>>()?;
+
+    Ok(GenerateResponse {
+        message_id,
+        content,
+        finish_reason,
+        usage: usage.unwrap_or(UsageMetadata::new(0, 0)),
+    })
+}
+
+/// Parse accumulated tool input; a tool called with no arguments streams nothing
+fn parse_tool_input(input: &str) -> Result<serde_json::Value, LlmError> {
+    if input.trim().is_empty() {
+        return Ok(serde_json::json!({}));
+    }
+    Ok(serde_json::from_str(input)?)
 }
 
 /// Create an LLM provider from a model specification
@@ -72,3 +314,326 @@ pub async fn create_provider(
         }
     }
 }
+
+/// Create a provider that sends requests to a custom Vertex AI endpoint
+///
+/// Same as [`create_provider`], but requests go to `endpoint` (for example a
+/// Private Service Connect address) instead of the default regional host.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use rust2::llm::{create_provider_with_endpoint, EndpointOverride, GeminiModel, Model};
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let endpoint = EndpointOverride::new("https://vertex-psc.p.example.internal")?;
+/// let provider = create_provider_with_endpoint(
+///     Model::Gemini(GeminiModel::Gemini25Flash),
+///     "my-project".to_string(),
+///     "europe-west4".to_string(),
+///     endpoint,
+/// ).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn create_provider_with_endpoint(
+    model: Model,
+    project_id: String,
+    location: String,
+    endpoint: EndpointOverride,
+) -> Result<Box<dyn LlmProvider>, LlmError> {
+    match model {
+        Model::Claude(claude_model) => {
+            let client = ClaudeClient::new(project_id, location, claude_model)
+                .await?
+                .with_endpoint_override(endpoint);
+            Ok(Box::new(client))
+        }
+        Model::Gemini(gemini_model) => {
+            let client = GeminiClient::new(project_id, location, gemini_model)
+                .await?
+                .with_endpoint_override(endpoint);
+            Ok(Box::new(client))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::core::config::GenerationConfig;
+    use crate::llm::core::types::{MessageMetadata, MessageRole, PartialToolUse};
+
+    // Mock LLM provider that streams a scripted list of events
+    struct MockProvider {
+        events: Vec<Result<StreamEvent, LlmError>>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for MockProvider {
+        async fn stream_generate(
+            &self,
+            _request: GenerateRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>
+        {
+            let events: Vec<_> = self
+                .events
+                .iter()
+                .map(|event| match event {
+                    Ok(event) => Ok(event.clone()),
+                    Err(e) => Err(LlmError::StreamError(e.to_string())),
+                })
+                .collect();
+            Ok(Box::pin(futures::stream::iter(events)))
+        }
+    }
+
+    fn request() -> GenerateRequest {
+        GenerateRequest {
+            messages: vec![],
+            tools: None,
+            tool_choice: None,
+            config: GenerationConfig::new(1024),
+            system: None,
+            locale: None,
+        }
+    }
+
+    fn text_delta(index: usize, text: &str) -> StreamEvent {
+        StreamEvent::ContentDelta {
+            index,
+            delta: ContentDelta::TextDelta {
+                text: text.to_string(),
+            },
+        }
+    }
+
+    fn tool_delta(index: usize, json: &str) -> StreamEvent {
+        StreamEvent::ContentDelta {
+            index,
+            delta: ContentDelta::ToolUseDelta {
+                partial: PartialToolUse {
+                    id: None,
+                    name: None,
+                    partial_json: json.to_string(),
+                },
+            },
+        }
+    }
+
+    fn message_end(reason: FinishReason) -> StreamEvent {
+        StreamEvent::MessageEnd {
+            finish_reason: reason,
+            usage: UsageMetadata::new(12, 7),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_count_tokens_is_not_supported_by_default() {
+        let provider = std::sync::Arc::new(MockProvider { events: vec![] });
+        let err = provider.count_tokens(&request()).await.unwrap_err();
+        assert!(matches!(err, LlmError::NotSupported));
+    }
+
+    #[tokio::test]
+    async fn test_generate_assembles_text_and_tool_uses() {
+        let provider = MockProvider {
+            events: vec![
+                Ok(StreamEvent::MessageStart {
+                    message: MessageMetadata {
+                        id: "msg-1".to_string(),
+                        role: MessageRole::Assistant,
+                        usage: None,
+                    },
+                }),
+                Ok(StreamEvent::ContentBlockStart {
+                    index: 0,
+                    block: ContentBlockStart::Text { text: String::new() },
+                }),
+                Ok(text_delta(0, "Let me ")),
+                Ok(text_delta(0, "check.")),
+                Ok(StreamEvent::ContentBlockEnd { index: 0 }),
+                Ok(StreamEvent::ContentBlockStart {
+                    index: 1,
+                    block: ContentBlockStart::ToolUse {
+                        id: "tool-1".to_string(),
+                        name: "weather".to_string(),
+                    },
+                }),
+                Ok(tool_delta(1, r#"{"city": "#)),
+                Ok(tool_delta(1, r#""Paris"}"#)),
+                Ok(StreamEvent::ContentBlockEnd { index: 1 }),
+                Ok(StreamEvent::ContentBlockStart {
+                    index: 2,
+                    block: ContentBlockStart::ToolUse {
+                        id: "tool-2".to_string(),
+                        name: "time".to_string(),
+                    },
+                }),
+                Ok(StreamEvent::ContentBlockEnd { index: 2 }),
+                Ok(message_end(FinishReason::ToolUse)),
+            ],
+        };
+
+        let response = provider.generate(request()).await.unwrap();
+
+        assert_eq!(
+            response,
+            GenerateResponse {
+                message_id: "msg-1".to_string(),
+                content: vec![
+                    ContentBlock::Text {
+                        text: "Let me check.".to_string()
+                    },
+                    ContentBlock::ToolUse {
+                        id: "tool-1".to_string(),
+                        name: "weather".to_string(),
+                        input: serde_json::json!({"city": "Paris"}),
+                    },
+                    ContentBlock::ToolUse {
+                        id: "tool-2".to_string(),
+                        name: "time".to_string(),
+                        input: serde_json::json!({}),
+                    },
+                ],
+                finish_reason: FinishReason::ToolUse,
+                usage: UsageMetadata::new(12, 7),
+            }
+        );
+        assert_eq!(response.text(), "Let me check.");
+    }
+
+    #[tokio::test]
+    async fn test_generate_accepts_bare_text_deltas() {
+        // Gemini-style: no block start, tool at the same index as the text
+        let provider = MockProvider {
+            events: vec![
+                Ok(text_delta(0, "Hello ")),
+                Ok(text_delta(0, "there")),
+                Ok(StreamEvent::ContentBlockStart {
+                    index: 0,
+                    block: ContentBlockStart::ToolUse {
+                        id: "tool-1".to_string(),
+                        name: "weather".to_string(),
+                    },
+                }),
+                Ok(tool_delta(0, "{}")),
+                Ok(StreamEvent::ContentBlockEnd { index: 0 }),
+                Ok(text_delta(1, "!")),
+                Ok(message_end(FinishReason::Stop)),
+            ],
+        };
+
+        let response = provider.generate(request()).await.unwrap();
+
+        assert_eq!(response.content.len(), 3);
+        assert_eq!(response.text(), "Hello there!");
+        assert!(matches!(&response.content[1], ContentBlock::ToolUse { name, .. } if name == "weather"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_surfaces_errors() {
+        let error_event = MockProvider {
+            events: vec![
+                Ok(text_delta(0, "Hi")),
+                Ok(StreamEvent::Error {
+                    error: "overloaded".to_string(),
+                }),
+            ],
+        };
+        let err = error_event.generate(request()).await.unwrap_err();
+        assert!(matches!(err, LlmError::StreamError(ref msg) if msg == "overloaded"));
+
+        let stream_error = MockProvider {
+            events: vec![Ok(text_delta(0, "Hi")), Err(LlmError::EmptyResponse)],
+        };
+        assert!(stream_error.generate(request()).await.is_err());
+
+        let truncated = MockProvider {
+            events: vec![Ok(text_delta(0, "Hi"))],
+        };
+        let err = truncated.generate(request()).await.unwrap_err();
+        assert!(matches!(err, LlmError::StreamError(ref msg) if msg.contains("before the message was complete")));
+
+        let bad_json = MockProvider {
+            events: vec![
+                Ok(StreamEvent::ContentBlockStart {
+                    index: 0,
+                    block: ContentBlockStart::ToolUse {
+                        id: "tool-1".to_string(),
+                        name: "weather".to_string(),
+                    },
+                }),
+                Ok(tool_delta(0, r#"{"city": "#)),
+                Ok(StreamEvent::ContentBlockEnd { index: 0 }),
+                Ok(message_end(FinishReason::ToolUse)),
+            ],
+        };
+        let err = bad_json.generate(request()).await.unwrap_err();
+        assert!(matches!(err, LlmError::SerializationError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_generate_accumulates_interleaved_parallel_tool_calls() {
+        let tool_start = |index: usize, id: &str, name: &str| StreamEvent::ContentBlockStart {
+            index,
+            block: ContentBlockStart::ToolUse {
+                id: id.to_string(),
+                name: name.to_string(),
+            },
+        };
+        let final_usage = UsageMetadata::new(40, 25);
+        let events = vec![
+            StreamEvent::MessageStart {
+                message: MessageMetadata {
+                    id: "msg-2".to_string(),
+                    role: MessageRole::Assistant,
+                    usage: Some(UsageMetadata::new(40, 1)),
+                },
+            },
+            tool_start(0, "tool-a", "weather"),
+            tool_start(1, "tool-b", "weather"),
+            tool_delta(1, r#"{"city": "#),
+            tool_delta(0, r#"{"city": "#),
+            tool_delta(0, r#""Paris"}"#),
+            tool_delta(1, r#""Oslo"}"#),
+            StreamEvent::ContentBlockEnd { index: 1 },
+            StreamEvent::ContentBlockEnd { index: 0 },
+            StreamEvent::MessageDelta {
+                usage: Some(UsageMetadata::new(40, 20)),
+            },
+            StreamEvent::MessageEnd {
+                finish_reason: FinishReason::ToolUse,
+                usage: final_usage,
+            },
+        ];
+        let reported_usage = events.iter().find_map(|event| match event {
+            StreamEvent::MessageEnd { usage, .. } => Some(*usage),
+            _ => None,
+        });
+        let provider = MockProvider {
+            events: events.into_iter().map(Ok).collect(),
+        };
+
+        let response = provider.generate(request()).await.unwrap();
+
+        assert_eq!(response.message_id, "msg-2");
+        assert_eq!(
+            response.content,
+            vec![
+                ContentBlock::ToolUse {
+                    id: "tool-a".to_string(),
+                    name: "weather".to_string(),
+                    input: serde_json::json!({"city": "Paris"}),
+                },
+                ContentBlock::ToolUse {
+                    id: "tool-b".to_string(),
+                    name: "weather".to_string(),
+                    input: serde_json::json!({"city": "Oslo"}),
+                },
+            ]
+        );
+        assert_eq!(response.finish_reason, FinishReason::ToolUse);
+        assert_eq!(Some(response.usage), reported_usage);
+    }
+}
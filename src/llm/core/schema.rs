@@ -0,0 +1,228 @@
+//! Lightweight JSON schema validation for structured output
+//!
+//! Covers the subset of JSON Schema that Claude and Gemini accept for
+//! response and tool schemas: `type`, `properties`, `required`,
+//! `additionalProperties: false`, `items`, `enum`, `minimum`/`maximum`,
+//! `minItems`/`maxItems` and `minLength`/`maxLength`. Unknown keywords are
+//! ignored.
+
+use serde_json::Value;
+
+/// Validate `value` against `schema`, returning every violation found
+///
+/// Each violation is prefixed with the JSON path it refers to (`$` is the
+/// root). An empty result means the value conforms.
+///
+/// # Example
+///
+/// ```
+/// use rust2::llm::core::schema::validate_json;
+/// use serde_json::json;
+///
+/// let schema = json!({
+///     "type": "object",
+///     "properties": {"answer": {"type": "integer"}},
+///     "required": ["answer"]
+/// });
+///
+/// assert!(validate_json(&json!({"answer": 42}), &schema).is_empty());
+/// assert_eq!(
+///     validate_json(&json!({}), &schema),
+///     vec!["$: missing required property 'answer'".to_string()]
+/// );
+/// ```
+pub fn validate_json(value: &Value, schema: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    validate_at(value, schema, "$", &mut errors);
+    errors
+}
+
+fn validate_at(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| matches_type(value, t)) {
+            errors.push(format!(
+                "{}: expected {}, got {}",
+                path,
+                types.join(" or "),
+                type_name(value)
+            ));
+            // Further checks would only repeat the type mismatch
+            return;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            errors.push(format!("{}: {} is not one of {}", path, value, Value::Array(allowed.clone())));
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for name in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(name) {
+                        errors.push(format!("{}: missing required property '{}'", path, name));
+                    }
+                }
+            }
+
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (name, field) in object {
+                match properties.and_then(|p| p.get(name)) {
+                    Some(field_schema) => {
+                        validate_at(field, field_schema, &format!("{}.{}", path, name), errors)
+                    }
+                    None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                        errors.push(format!("{}: unexpected property '{}'", path, name));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            check_bound(items.len() as f64, schema, "minItems", "maxItems", "items", path, errors);
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_at(item, item_schema, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count() as f64;
+            check_bound(len, schema, "minLength", "maxLength", "characters", path, errors);
+        }
+        Value::Number(n) => {
+            if let Some(n) = n.as_f64() {
+                check_bound(n, schema, "minimum", "maximum", "", path, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_bound(
+    actual: f64,
+    schema: &serde_json::Map<String, Value>,
+    min_key: &str,
+    max_key: &str,
+    unit: &str,
+    path: &str,
+    errors: &mut Vec<String>,
+) {
+    let suffix = if unit.is_empty() { String::new() } else { format!(" {}", unit) };
+    if let Some(min) = schema.get(min_key).and_then(Value::as_f64) {
+        if actual < min {
+            errors.push(format!("{}: {}{} is below {} {}", path, actual, suffix, min_key, min));
+        }
+    }
+    if let Some(max) = schema.get(max_key).and_then(Value::as_f64) {
+        if actual > max {
+            errors.push(format!("{}: {}{} is above {} {}", path, actual, suffix, max_key, max));
+        }
+    }
+}
+
+fn matches_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn weather_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "city": {"type": "string", "minLength": 1},
+                "temperature": {"type": "number", "minimum": -100, "maximum": 100},
+                "conditions": {"type": "string", "enum": ["sunny", "cloudy", "rain"]},
+                "hourly": {"type": "array", "items": {"type": "integer"}, "maxItems": 3}
+            },
+            "required": ["city", "temperature"],
+            "additionalProperties": false
+        })
+    }
+
+    #[test]
+    fn test_valid_value() {
+        let value = json!({
+            "city": "SF",
+            "temperature": 18.5,
+            "conditions": "sunny",
+            "hourly": [17, 18, 19]
+        });
+        assert!(validate_json(&value, &weather_schema()).is_empty());
+    }
+
+    #[test]
+    fn test_reports_every_violation_with_paths() {
+        let value = json!({
+            "city": "",
+            "conditions": "snow",
+            "hourly": [17, "warm", 19, 20],
+            "wind": 5
+        });
+
+        let errors = validate_json(&value, &weather_schema());
+        assert!(errors.contains(&"$: missing required property 'temperature'".to_string()));
+        assert!(errors.iter().any(|e| e.starts_with("$.city: 0 characters is below minLength")));
+        assert!(errors.iter().any(|e| e.starts_with("$.conditions: \"snow\" is not one of")));
+        assert!(errors.contains(&"$.hourly[1]: expected integer, got string".to_string()));
+        assert!(errors.iter().any(|e| e.starts_with("$.hourly: 4 items is above maxItems")));
+        assert!(errors.contains(&"$: unexpected property 'wind'".to_string()));
+        assert_eq!(errors.len(), 6);
+    }
+
+    #[test]
+    fn test_type_mismatch_at_root() {
+        let errors = validate_json(&json!("not an object"), &weather_schema());
+        assert_eq!(errors, vec!["$: expected object, got string".to_string()]);
+    }
+
+    #[test]
+    fn test_type_unions_and_integer_numbers() {
+        let schema = json!({"type": ["integer", "null"]});
+        assert!(validate_json(&json!(3), &schema).is_empty());
+        assert!(validate_json(&json!(null), &schema).is_empty());
+        assert_eq!(
+            validate_json(&json!(3.5), &schema),
+            vec!["$: expected integer or null, got number".to_string()]
+        );
+
+        // Integers satisfy "number"
+        assert!(validate_json(&json!(3), &json!({"type": "number"})).is_empty());
+    }
+}
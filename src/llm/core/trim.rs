@@ -0,0 +1,270 @@
+//! Trimming a request's history to fit a token budget
+
+use super::{
+    error::LlmError,
+    provider::LlmProvider,
+    types::{ContentBlock, GenerateRequest, Message, MessageRole},
+};
+
+/// What [`GenerateRequest::trim_to_fit`] removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrimReport {
+    /// Indices of the dropped messages in the history before trimming
+    pub dropped: Vec<usize>,
+    /// Input tokens of the request as trimmed, as counted by the provider
+    pub input_tokens: u32,
+}
+
+impl GenerateRequest {
+    /// Drop the oldest exchanges until the request fits in `budget_tokens`
+    ///
+    /// An exchange starts at a user message that isn't a tool result and runs
+    /// up to the next one, so tool uses are only ever dropped together with
+    /// their results. The system prompt, the tools and the exchange holding
+    /// the most recent user message are always kept.
+    ///
+    /// The number of exchanges to drop is binary-searched, so `provider` is
+    /// asked to [`count_tokens`](LlmProvider::count_tokens) O(log n) times
+    /// for n exchanges. If the request still doesn't fit with only the last
+    /// exchange left, it is trimmed that far and the report's `input_tokens`
+    /// is over the budget.
+    ///
+    /// # Errors
+    ///
+    /// Returns the provider's error from `count_tokens`, including
+    /// `LlmError::NotSupported` for providers that can't count. The request
+    /// is left untouched in that case.
+    pub async fn trim_to_fit<P: LlmProvider + ?Sized>(
+        &mut self,
+        provider: &P,
+        budget_tokens: u32,
+    ) -> Result<TrimReport, LlmError> {
+        let input_tokens = provider.count_tokens(self).await?;
+        if input_tokens <= budget_tokens {
+            return Ok(TrimReport {
+                dropped: Vec::new(),
+                input_tokens,
+            });
+        }
+
+        // Cutting at cuts[k - 1] drops the k oldest exchanges
+        let cuts = exchange_starts(&self.messages);
+        let mut counted = Vec::new();
+        let (mut low, mut high) = (1, cuts.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            let tokens = self.count_from(provider, cuts[mid - 1]).await?;
+            counted.push((mid, tokens));
+            if tokens <= budget_tokens {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+
+        if low > cuts.len() {
+            // Nothing can be dropped without losing the latest user message
+            return Ok(TrimReport {
+                dropped: Vec::new(),
+                input_tokens,
+            });
+        }
+
+        let cut = cuts[low - 1];
+        let input_tokens = match counted.iter().find(|(k, _)| *k == low) {
+            Some((_, tokens)) => *tokens,
+            None => self.count_from(provider, cut).await?,
+        };
+        self.messages.drain(..cut);
+
+        Ok(TrimReport {
+            dropped: (0..cut).collect(),
+            input_tokens,
+        })
+    }
+
+    /// Count the tokens of this request with the messages before `start` removed
+    async fn count_from<P: LlmProvider + ?Sized>(
+        &self,
+        provider: &P,
+        start: usize,
+    ) -> Result<u32, LlmError> {
+        let trimmed = GenerateRequest {
+            messages: self.messages[start..].to_vec(),
+            ..self.clone()
+        };
+        provider.count_tokens(&trimmed).await
+    }
+}
+
+/// Indices where the history may be cut: the start of every exchange after
+/// the first, up to and including the one with the most recent user message
+fn exchange_starts(messages: &[Message]) -> Vec<usize> {
+    messages
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, message)| {
+            message.role == MessageRole::User
+                && !message
+                    .content
+                    .iter()
+                    .any(|block| matches!(block, ContentBlock::ToolResult { .. }))
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::core::config::GenerationConfig;
+    use crate::llm::core::types::StreamEvent;
+    use async_trait::async_trait;
+    use futures::stream::Stream;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts 100 tokens per message and remembers how often it was asked
+    #[derive(Default)]
+    struct PerMessageCounter {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LlmProvider for PerMessageCounter {
+        async fn stream_generate(
+            &self,
+            _request: GenerateRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>
+        {
+            Err(LlmError::NotSupported)
+        }
+
+        async fn count_tokens(&self, request: &GenerateRequest) -> Result<u32, LlmError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(100 * request.messages.len() as u32)
+        }
+    }
+
+    /// Provider without token counting
+    struct NoCounter;
+
+    #[async_trait]
+    impl LlmProvider for NoCounter {
+        async fn stream_generate(
+            &self,
+            _request: GenerateRequest,
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError>
+        {
+            Err(LlmError::NotSupported)
+        }
+    }
+
+    fn request(messages: Vec<Message>) -> GenerateRequest {
+        GenerateRequest {
+            messages,
+            tools: None,
+            tool_choice: None,
+            config: GenerationConfig::new(1024),
+            system: Some("Be brief.".to_string()),
+            locale: None,
+        }
+    }
+
+    /// A question answered with one tool call: user, tool use, tool result, answer
+    fn tool_exchange(n: usize) -> Vec<Message> {
+        let id = format!("tool-{}", n);
+        vec![
+            Message::user(format!("question {}", n)),
+            Message {
+                role: MessageRole::Assistant,
+                content: vec![ContentBlock::ToolUse {
+                    id: id.clone(),
+                    name: "lookup".to_string(),
+                    input: serde_json::json!({}),
+                }],
+            },
+            Message {
+                role: MessageRole::User,
+                content: vec![ContentBlock::ToolResult {
+                    tool_use_id: id,
+                    content: "found".to_string(),
+                    is_error: false,
+                }],
+            },
+            Message::assistant(format!("answer {}", n)),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_request_within_budget_is_untouched() {
+        let provider = PerMessageCounter::default();
+        let mut request = request(tool_exchange(0));
+
+        let report = request.trim_to_fit(&provider, 400).await.unwrap();
+
+        assert!(report.dropped.is_empty());
+        assert_eq!(report.input_tokens, 400);
+        assert_eq!(request.messages.len(), 4);
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_drops_oldest_whole_exchanges() {
+        let provider = PerMessageCounter::default();
+        let mut messages: Vec<Message> = (0..16).flat_map(tool_exchange).collect();
+        messages.push(Message::user("latest question"));
+        let mut request = request(messages);
+
+        // 65 messages; 9 fit, i.e. two whole exchanges plus the question
+        let report = request.trim_to_fit(&provider, 1000).await.unwrap();
+
+        assert_eq!(report.dropped, (0..56).collect::<Vec<_>>());
+        assert_eq!(report.input_tokens, 900);
+        assert_eq!(request.messages.len(), 9);
+        assert_eq!(request.messages[0], Message::user("question 14"));
+        assert_eq!(request.messages[8], Message::user("latest question"));
+        assert_eq!(request.system.as_deref(), Some("Be brief."));
+        Message::validate_transcript(&request.messages).unwrap();
+
+        // One initial count plus a binary search over 16 cut points
+        assert!(provider.calls.load(Ordering::SeqCst) <= 1 + 5, "{:?}", provider.calls);
+    }
+
+    #[tokio::test]
+    async fn test_keeps_the_latest_exchange_even_over_budget() {
+        let provider = PerMessageCounter::default();
+        let mut messages = tool_exchange(0);
+        messages.extend(tool_exchange(1));
+        let mut request = request(messages);
+
+        let report = request.trim_to_fit(&provider, 100).await.unwrap();
+
+        assert_eq!(report.dropped, vec![0, 1, 2, 3]);
+        assert_eq!(report.input_tokens, 400);
+        assert_eq!(request.messages[0], Message::user("question 1"));
+    }
+
+    #[tokio::test]
+    async fn test_single_exchange_over_budget_drops_nothing() {
+        let provider = PerMessageCounter::default();
+        let mut request = request(tool_exchange(0));
+
+        let report = request.trim_to_fit(&provider, 100).await.unwrap();
+
+        assert!(report.dropped.is_empty());
+        assert_eq!(report.input_tokens, 400);
+        assert_eq!(request.messages.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_provider_without_counting_leaves_request_alone() {
+        let mut request = request(tool_exchange(0));
+
+        let result = request.trim_to_fit(&NoCounter, 100).await;
+
+        assert!(matches!(result, Err(LlmError::NotSupported)));
+        assert_eq!(request.messages.len(), 4);
+    }
+}
@@ -1,8 +1,12 @@
 //! Core types for the LLM abstraction layer
 
+use std::collections::HashSet;
+
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use super::config::GenerationConfig;
+use super::config::{GenerationConfig, ToolResultOverflow};
+use super::error::LlmError;
 use crate::llm::claude::ClaudeModel;
 use crate::llm::gemini::GeminiModel;
 
@@ -13,14 +17,82 @@ pub struct GenerateRequest {
     pub messages: Vec<Message>,
     /// Available tools the model can call
     pub tools: Option<Vec<ToolDeclaration>>,
+    /// Whether the model must, may, or must not call a tool (provider default if unset)
+    pub tool_choice: Option<ToolChoice>,
     /// Generation parameters
     pub config: GenerationConfig,
     /// System prompt/instructions
     pub system: Option<String>,
+    /// Locale the response must be written in, as a BCP-47 tag (e.g. `de-DE`)
+    ///
+    /// Providers append an instruction to the system prompt; see
+    /// [`locale`](super::locale).
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+impl GenerateRequest {
+    /// Enforce a maximum byte size on every tool result in the request
+    ///
+    /// Oversized results either fail with `LlmError::ToolResultTooLarge` or are
+    /// cut at a UTF-8 boundary and followed by a marker, depending on `overflow`.
+    /// The error names the tool that produced the result when it can be found
+    /// in the conversation, falling back to the tool use id.
+    pub fn enforce_tool_result_limit(
+        &mut self,
+        max_bytes: usize,
+        overflow: ToolResultOverflow,
+    ) -> Result<(), LlmError> {
+        let mut tool_names = std::collections::HashMap::new();
+
+        for message in &mut self.messages {
+            for block in &mut message.content {
+                match block {
+                    ContentBlock::ToolUse { id, name, .. } => {
+                        tool_names.insert(id.clone(), name.clone());
+                    }
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        ..
+                    } if content.len() > max_bytes => {
+                        let size = content.len();
+                        match overflow {
+                            ToolResultOverflow::Error => {
+                                let name = tool_names
+                                    .get(tool_use_id.as_str())
+                                    .cloned()
+                                    .unwrap_or_else(|| tool_use_id.clone());
+                                return Err(LlmError::ToolResultTooLarge {
+                                    name,
+                                    size,
+                                    limit: max_bytes,
+                                });
+                            }
+                            ToolResultOverflow::Truncate => {
+                                let mut cut = max_bytes;
+                                while !content.is_char_boundary(cut) {
+                                    cut -= 1;
+                                }
+                                content.truncate(cut);
+                                content.push_str(&format!(
+                                    "\n[truncated: result was {} bytes, limit is {} bytes]",
+                                    size, max_bytes
+                                ));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// A single message in the conversation
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Message {
     /// Role of the message sender
     pub role: MessageRole,
@@ -49,6 +121,19 @@ impl Message {
         }
     }
 
+    /// Create a new user message with a single image
+    ///
+    /// `media_type` is the image's MIME type, e.g. `image/png`.
+    pub fn image(media_type: impl Into<String>, data: ImageData) -> Self {
+        Self {
+            role: MessageRole::User,
+            content: vec![ContentBlock::Image {
+                media_type: media_type.into(),
+                data,
+            }],
+        }
+    }
+
     /// Create a new tool message with a tool result
     pub fn tool_result(tool_use_id: impl Into<String>, content: impl Into<String>) -> Self {
         Self {
@@ -72,6 +157,67 @@ impl Message {
             }],
         }
     }
+
+    /// Check that every tool use in a transcript is paired with a tool result
+    ///
+    /// Each assistant `ToolUse` block must be answered by a `ToolResult` with the
+    /// same id in a later message, and every `ToolResult` must refer to a tool use
+    /// from an earlier message. Providers reject unbalanced transcripts with an
+    /// opaque 400, so this is meant to be called before `stream_generate`.
+    pub fn validate_transcript(messages: &[Message]) -> Result<(), TranscriptError> {
+        let mut seen_uses = HashSet::new();
+        let mut pending_uses: Vec<String> = Vec::new();
+
+        for message in messages {
+            // Results must refer to uses from earlier messages, so check them first
+            for block in &message.content {
+                if let ContentBlock::ToolResult { tool_use_id, .. } = block {
+                    match pending_uses.iter().position(|id| id == tool_use_id) {
+                        Some(pos) => {
+                            pending_uses.remove(pos);
+                        }
+                        None => {
+                            return Err(TranscriptError::OrphanToolResult {
+                                tool_use_id: tool_use_id.clone(),
+                            })
+                        }
+                    }
+                }
+            }
+
+            for block in &message.content {
+                if let ContentBlock::ToolUse { id, .. } = block {
+                    if !seen_uses.insert(id.clone()) {
+                        return Err(TranscriptError::DuplicateToolUse {
+                            tool_use_id: id.clone(),
+                        });
+                    }
+                    pending_uses.push(id.clone());
+                }
+            }
+        }
+
+        match pending_uses.into_iter().next() {
+            Some(tool_use_id) => Err(TranscriptError::OrphanToolUse { tool_use_id }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Structural problems in a conversation transcript
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TranscriptError {
+    /// A tool use has no matching tool result in a later message
+    #[error("Tool use '{tool_use_id}' has no matching tool result")]
+    OrphanToolUse { tool_use_id: String },
+
+    /// A tool result does not refer to any earlier tool use
+    #[error("Tool result '{tool_use_id}' has no matching tool use")]
+    OrphanToolResult { tool_use_id: String },
+
+    /// The same tool use id appears more than once
+    #[error("Tool use id '{tool_use_id}' appears more than once")]
+    DuplicateToolUse { tool_use_id: String },
 }
 
 /// Role of a message sender
@@ -87,7 +233,7 @@ pub enum MessageRole {
 }
 
 /// Content block within a message
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ContentBlock {
     /// Plain text content
@@ -105,6 +251,53 @@ pub enum ContentBlock {
         #[serde(default)]
         is_error: bool,
     },
+    /// Image input
+    Image {
+        /// MIME type, e.g. `image/png`
+        media_type: String,
+        data: ImageData,
+    },
+    /// Thinking the provider encrypted because it was flagged for safety
+    ///
+    /// Only Claude produces it. It must be sent back unchanged with the rest
+    /// of the assistant turn, so it is kept in history.
+    RedactedThinking { data: String },
+}
+
+/// Where an image's bytes come from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageData {
+    /// Base64-encoded image bytes, without a `data:` prefix
+    Base64(String),
+    /// URL the provider fetches the image from
+    Url(String),
+}
+
+/// Complete response assembled from a stream by [`LlmProvider::generate`](crate::llm::LlmProvider::generate)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenerateResponse {
+    /// Provider's id for the message; empty if the stream didn't report one
+    pub message_id: String,
+    /// Text, tool use and redacted thinking blocks, in the order they were streamed
+    pub content: Vec<ContentBlock>,
+    /// Why generation stopped
+    pub finish_reason: FinishReason,
+    /// Token usage for the request
+    pub usage: UsageMetadata,
+}
+
+impl GenerateResponse {
+    /// Concatenated text of every text block
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 /// Declaration of a tool available to the model
@@ -118,6 +311,20 @@ pub struct ToolDeclaration {
     pub input_schema: serde_json::Value,
 }
 
+/// How the model may use the declared tools
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// The model decides whether to call a tool
+    Auto,
+    /// The model must call at least one tool
+    Any,
+    /// The model must call the named tool
+    Required { name: String },
+    /// The model must not call any tool
+    None,
+}
+
 /// Events emitted during streaming generation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -175,6 +382,10 @@ pub enum ContentBlockStart {
     Text { text: String },
     /// Tool use block starting
     ToolUse { id: String, name: String },
+    /// Extended thinking block starting
+    Thinking { thinking: String },
+    /// Encrypted thinking block, complete in its start event
+    RedactedThinking { data: String },
 }
 
 /// Incremental content update
@@ -185,6 +396,8 @@ pub enum ContentDelta {
     TextDelta { text: String },
     /// Partial tool call data
     ToolUseDelta { partial: PartialToolUse },
+    /// Extended thinking token(s)
+    ThinkingDelta { thinking: String },
 }
 
 /// Partial tool use information (accumulating)
@@ -219,7 +432,7 @@ pub enum FinishReason {
 }
 
 /// Token usage information
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UsageMetadata {
     /// Prompt tokens consumed
     pub input_tokens: u32,
@@ -227,6 +440,16 @@ pub struct UsageMetadata {
     pub output_tokens: u32,
     /// Sum of input and output
     pub total_tokens: u32,
+    /// Prompt tokens written to the prompt cache, not included in `input_tokens`
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub cache_creation_input_tokens: u32,
+    /// Prompt tokens read from the prompt cache, not included in `input_tokens`
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub cache_read_input_tokens: u32,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
 }
 
 impl UsageMetadata {
@@ -236,6 +459,8 @@ impl UsageMetadata {
             input_tokens,
             output_tokens,
             total_tokens: input_tokens + output_tokens,
+            cache_creation_input_tokens: 0,
+            cache_read_input_tokens: 0,
         }
     }
 
@@ -244,6 +469,8 @@ impl UsageMetadata {
         self.input_tokens += other.input_tokens;
         self.output_tokens += other.output_tokens;
         self.total_tokens = self.input_tokens + self.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
     }
 }
 
@@ -311,6 +538,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_message_image_constructor() {
+        let msg = Message::image("image/png", ImageData::Base64("iVBORw0KGgo=".to_string()));
+        assert_eq!(msg.role, MessageRole::User);
+        assert_eq!(
+            serde_json::to_value(&msg.content[0]).unwrap(),
+            serde_json::json!({
+                "type": "image",
+                "media_type": "image/png",
+                "data": {"base64": "iVBORw0KGgo="}
+            })
+        );
+    }
+
     #[test]
     fn test_message_tool_error_constructor() {
         let msg = Message::tool_error("tool-456", "error message");
@@ -329,6 +570,143 @@ mod tests {
         }
     }
 
+    fn tool_use_message(ids: &[&str]) -> Message {
+        Message {
+            role: MessageRole::Assistant,
+            content: ids
+                .iter()
+                .map(|id| ContentBlock::ToolUse {
+                    id: id.to_string(),
+                    name: "get_weather".to_string(),
+                    input: serde_json::json!({}),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_validate_transcript_balanced() {
+        let messages = vec![
+            Message::user("Weather in SF and NYC?"),
+            tool_use_message(&["tool-1", "tool-2"]),
+            Message::tool_result("tool-1", "72°F"),
+            Message::tool_error("tool-2", "timeout"),
+            Message::assistant("SF is 72°F"),
+        ];
+        assert_eq!(Message::validate_transcript(&messages), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_transcript_orphan_tool_use() {
+        let messages = vec![
+            Message::user("Weather in SF and NYC?"),
+            tool_use_message(&["tool-1", "tool-2"]),
+            Message::tool_result("tool-1", "72°F"),
+        ];
+        assert_eq!(
+            Message::validate_transcript(&messages),
+            Err(TranscriptError::OrphanToolUse {
+                tool_use_id: "tool-2".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_transcript_orphan_tool_result() {
+        let messages = vec![
+            Message::user("Hello"),
+            Message::tool_result("tool-9", "72°F"),
+        ];
+        assert_eq!(
+            Message::validate_transcript(&messages),
+            Err(TranscriptError::OrphanToolResult {
+                tool_use_id: "tool-9".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_transcript_result_before_use() {
+        let messages = vec![
+            Message::tool_result("tool-1", "72°F"),
+            tool_use_message(&["tool-1"]),
+        ];
+        assert!(matches!(
+            Message::validate_transcript(&messages),
+            Err(TranscriptError::OrphanToolResult { .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_transcript_duplicate_result() {
+        let messages = vec![
+            tool_use_message(&["tool-1"]),
+            Message::tool_result("tool-1", "72°F"),
+            Message::tool_result("tool-1", "72°F"),
+        ];
+        assert!(matches!(
+            Message::validate_transcript(&messages),
+            Err(TranscriptError::OrphanToolResult { .. })
+        ));
+    }
+
+    fn oversized_request() -> GenerateRequest {
+        GenerateRequest {
+            messages: vec![
+                Message::user("Search for it"),
+                tool_use_message(&["tool-1"]),
+                Message::tool_result("tool-1", "é".repeat(10)),
+            ],
+            tools: None,
+            tool_choice: None,
+            config: GenerationConfig::default(),
+            system: None,
+            locale: None,
+        }
+    }
+
+    #[test]
+    fn test_tool_result_limit_error() {
+        let mut request = oversized_request();
+        let err = request
+            .enforce_tool_result_limit(8, ToolResultOverflow::Error)
+            .unwrap_err();
+
+        match err {
+            LlmError::ToolResultTooLarge { name, size, limit } => {
+                assert_eq!(name, "get_weather");
+                assert_eq!(size, 20);
+                assert_eq!(limit, 8);
+            }
+            other => panic!("Expected ToolResultTooLarge, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tool_result_limit_truncate() {
+        let mut request = oversized_request();
+        // 9 bytes falls inside a two-byte character, so the cut moves back to 8
+        request
+            .enforce_tool_result_limit(9, ToolResultOverflow::Truncate)
+            .unwrap();
+
+        match &request.messages[2].content[0] {
+            ContentBlock::ToolResult { content, .. } => {
+                assert!(content.starts_with("éééé\n[truncated"));
+                assert!(content.contains("20 bytes"));
+            }
+            _ => panic!("Expected tool result"),
+        }
+    }
+
+    #[test]
+    fn test_tool_result_limit_under_limit() {
+        let mut request = oversized_request();
+        request
+            .enforce_tool_result_limit(20, ToolResultOverflow::Error)
+            .unwrap();
+    }
+
     #[test]
     fn test_usage_metadata_new() {
         let usage = UsageMetadata::new(100, 50);
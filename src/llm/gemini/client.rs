@@ -1,21 +1,31 @@
 //! Gemini client implementation
 
+use async_stream::stream;
 use async_trait::async_trait;
 use futures::stream::Stream;
 use futures::StreamExt;
 use reqwest::Client;
 use std::pin::Pin;
+use std::time::Duration;
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
 use crate::llm::auth::adc::AuthenticationManager;
+use crate::llm::http::capture::{tee_raw_chunks, RawChunk};
+use crate::llm::http::endpoint::{vertex_base_url, EndpointOverride};
+use crate::llm::http::retry::send_with_retry;
 use crate::llm::core::{
+    config::{ProviderCapabilities, RetryConfig, ToolResultOverflow},
     error::LlmError,
     provider::LlmProvider,
-    types::{GenerateRequest, StreamEvent},
+    types::{GenerateRequest, Model, StreamEvent},
 };
 
-use super::mapper::{create_message_start, from_gemini_response, to_gemini_request};
+use super::mapper::{
+    create_message_start, from_gemini_response, to_gemini_count_tokens_request, to_gemini_request,
+};
 use super::sse::parse_sse_stream;
+use super::types::{CountTokensResponse, GenerateContentResponse};
 
 /// Gemini model identifiers
 #[derive(Debug, Clone)]
@@ -51,6 +61,18 @@ pub struct GeminiClient {
     location: String,
     /// Model to use
     model: GeminiModel,
+    /// Optional channel receiving raw response chunks for debugging
+    raw_capture: Option<mpsc::Sender<RawChunk>>,
+    /// Optional maximum size of a single tool result, in bytes
+    max_tool_result_bytes: Option<usize>,
+    /// What to do with tool results over the limit
+    tool_result_overflow: ToolResultOverflow,
+    /// Reject requests with parameters this provider ignores instead of warning
+    strict_parameters: bool,
+    /// Retry policy for rate limits and transient errors (default: `RetryConfig::default()`)
+    retry: Option<RetryConfig>,
+    /// Base URL replacing the default Vertex AI host
+    endpoint: Option<EndpointOverride>,
 }
 
 impl GeminiClient {
@@ -88,88 +110,214 @@ impl GeminiClient {
             project_id,
             location,
             model,
+            raw_capture: None,
+            max_tool_result_bytes: None,
+            tool_result_overflow: ToolResultOverflow::default(),
+            strict_parameters: false,
+            retry: Some(RetryConfig::default()),
+            endpoint: None,
         })
     }
 
+    /// Tee raw response chunks into `sender` alongside normal parsing
+    ///
+    /// Useful for attaching the exact provider payload to a support ticket.
+    /// Chunks are dropped rather than buffered when the receiver lags behind.
+    pub fn with_raw_capture(mut self, sender: mpsc::Sender<RawChunk>) -> Self {
+        self.raw_capture = Some(sender);
+        self
+    }
+
+    /// Reject (or truncate) tool results larger than `max_bytes` before sending
+    ///
+    /// Oversized results otherwise surface as an opaque 400 from the provider.
+    pub fn with_max_tool_result_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_tool_result_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Choose whether oversized tool results error or are truncated (default: error)
+    pub fn with_tool_result_overflow(mut self, overflow: ToolResultOverflow) -> Self {
+        self.tool_result_overflow = overflow;
+        self
+    }
+
+    /// Fail requests whose config sets parameters this provider ignores
+    ///
+    /// By default such parameters only produce a warning log; see
+    /// [`GenerationConfig::compatibility_report`](crate::llm::GenerationConfig::compatibility_report).
+    pub fn with_strict_parameters(mut self, strict: bool) -> Self {
+        self.strict_parameters = strict;
+        self
+    }
+
+    /// Set how failed responses are retried (default: `RetryConfig::default()`)
+    ///
+    /// By default a response with a [`RetryConfig::default_retry_on`] status
+    /// is tried up to 4 times, as with `ClaudeClient`; set `max_attempts` to
+    /// 1 to turn retries off. Gemini returns these intermittently under load
+    /// (`RESOURCE_EXHAUSTED`, `UNAVAILABLE`). Only opening the stream is
+    /// retried; errors after the response has started are returned as usual.
+    pub fn with_retry(mut self, config: RetryConfig) -> Self {
+        self.retry = Some(config);
+        self
+    }
+
+    /// Set how many times a retryable response is retried (default: 3)
+    ///
+    /// Shorthand for setting [`RetryConfig::max_attempts`] to `max_retries + 1`.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.retry.get_or_insert_with(RetryConfig::default).max_attempts =
+            max_retries.saturating_add(1);
+        self
+    }
+
+    /// Set the base retry delay (default: 500ms)
+    ///
+    /// Shorthand for setting [`RetryConfig::initial_delay`].
+    pub fn with_retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry.get_or_insert_with(RetryConfig::default).initial_delay = backoff;
+        self
+    }
+
+    /// Send requests to `endpoint` instead of the default Vertex AI host
+    ///
+    /// The default is `{location}-aiplatform.googleapis.com`, or
+    /// `aiplatform.googleapis.com` for the `global` location.
+    ///
+    /// Use this for Private Service Connect or other custom endpoints. The
+    /// request path and the credentials are the same as for the default host.
+    pub fn with_endpoint_override(mut self, endpoint: EndpointOverride) -> Self {
+        self.endpoint = Some(endpoint);
+        self
+    }
+
     /// Build the endpoint URL for streaming
     fn build_endpoint_url(&self) -> String {
-        format!(
-            "https://{}-aiplatform.googleapis.com/v1/projects/{}/locations/{}/publishers/google/models/{}:streamGenerateContent?alt=sse",
-            self.location, self.project_id, self.location, self.model.as_str()
+        endpoint_url(
+            &self.project_id,
+            &self.location,
+            self.model.as_str(),
+            self.endpoint.as_ref(),
         )
     }
 
     /// Make a streaming request to Gemini
     async fn make_streaming_request(
         &self,
-        request: GenerateRequest,
+        mut request: GenerateRequest,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError> {
+        // Catch oversized tool results locally instead of as a provider 400
+        if let Some(max_bytes) = self.max_tool_result_bytes {
+            request.enforce_tool_result_limit(max_bytes, self.tool_result_overflow)?;
+        }
+
+        // Reject out-of-range parameters locally instead of as a provider 400
+        request
+            .config
+            .validate_for(&Model::Gemini(self.model.clone()))?;
+        request
+            .config
+            .check_compatibility(&self.capabilities(), self.model.as_str(), self.strict_parameters)?;
+
         // Convert to Gemini request format
-        let gemini_request = to_gemini_request(request);
+        let gemini_request = to_gemini_request(request)?;
 
         // Get auth token
         let token = self.auth_manager.get_token().await?;
 
         // Build request
         let url = self.build_endpoint_url();
-        let response = self
-            .http_client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", token))
-            .header("Content-Type", "application/json")
-            .json(&gemini_request)
-            .send()
-            .await?;
-
-        // Check status
-        let status = response.status();
-        if !status.is_success() {
-            let body = response.text().await.unwrap_or_else(|_| String::new());
-            return Err(LlmError::HttpError {
-                status: status.as_u16(),
-                body,
-            });
-        }
+        let response = send_with_retry(self.retry.as_ref(), self.model.as_str(), || {
+            self.http_client
+                .post(&url)
+                .header("Authorization", format!("Bearer {}", token))
+                .header("Content-Type", "application/json")
+                .json(&gemini_request)
+        })
+        .await?;
 
         // Parse SSE stream
-        let byte_stream = response.bytes_stream();
-        let sse_stream = parse_sse_stream(Box::pin(byte_stream));
+        let mut byte_stream: Pin<Box<dyn Stream<Item = _> + Send>> =
+            Box::pin(response.bytes_stream());
+        if let Some(sender) = &self.raw_capture {
+            byte_stream = tee_raw_chunks(byte_stream, sender.clone());
+        }
+
+        Ok(to_event_stream(parse_sse_stream(byte_stream)))
+    }
+
+    /// Count the tokens of a request with the `countTokens` endpoint
+    async fn make_count_tokens_request(&self, request: &GenerateRequest) -> Result<u32, LlmError> {
+        let count_request = to_gemini_count_tokens_request(request.clone())?;
+
+        let token = self.auth_manager.get_token().await?;
+
+        let url = count_tokens_url(
+            &self.project_id,
+            &self.location,
+            self.model.as_str(),
+            self.endpoint.as_ref(),
+        );
+        let response = send_with_retry(self.retry.as_ref(), self.model.as_str(), || {
+            self.http_client
+                .post(&url)
+                .header("Authorization", format!("Bearer {}", token))
+                .header("Content-Type", "application/json")
+                .json(&count_request)
+        })
+        .await?;
 
-        // Convert to StreamEvent stream
+        let count: CountTokensResponse = response.json().await?;
+        Ok(count.total_tokens)
+    }
+}
+
+/// Convert parsed Gemini chunks into stream events
+///
+/// Chunks without candidates are skipped: Gemini sometimes opens the stream
+/// with one, and real content follows. `MessageStart` is emitted with the
+/// first chunk that has candidates. A stream that ends without any candidates
+/// yields `LlmError::EmptyResponse` instead of finishing silently.
+fn to_event_stream(
+    mut sse_stream: Pin<Box<dyn Stream<Item = Result<GenerateContentResponse, LlmError>> + Send>>,
+) -> Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>> {
+    Box::pin(stream! {
         let message_id = Uuid::new_v4().to_string();
         let mut emitted_start = false;
+        let mut failed = false;
         let mut current_index = 0;
 
-        let event_stream = sse_stream.map(move |result| match result {
-            Ok(gemini_response) => {
-                let mut events = Vec::new();
-
-                // Emit message start on first chunk
-                if !emitted_start {
-                    events.push(create_message_start(message_id.clone()));
-                    emitted_start = true;
+        while let Some(result) = sse_stream.next().await {
+            let gemini_response = match result {
+                Ok(gemini_response) => gemini_response,
+                Err(e) => {
+                    failed = true;
+                    yield Err(e);
+                    continue;
                 }
+            };
 
-                // Convert Gemini response to our events
-                let mut response_events =
-                    from_gemini_response(gemini_response, &mut current_index);
-                events.append(&mut response_events);
+            if gemini_response.candidates.is_empty() {
+                continue;
+            }
 
-                Ok(events)
+            // Emit message start on first chunk with content
+            if !emitted_start {
+                yield Ok(create_message_start(message_id.clone()));
+                emitted_start = true;
             }
-            Err(e) => Err(e),
-        });
 
-        // Flatten the stream of event vectors into individual events
-        let flattened = event_stream.flat_map(|result| {
-            futures::stream::iter(match result {
-                Ok(events) => events.into_iter().map(Ok).collect::<Vec<_>>(),
-                Err(e) => vec![Err(e)],
-            })
-        });
+            // Convert Gemini response to our events
+            for event in from_gemini_response(gemini_response, &mut current_index) {
+                yield Ok(event);
+            }
+        }
 
-        Ok(Box::pin(flattened))
-    }
+        if !emitted_start && !failed {
+            yield Err(LlmError::EmptyResponse);
+        }
+    })
 }
 
 #[async_trait]
@@ -180,6 +328,50 @@ impl LlmProvider for GeminiClient {
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>, LlmError> {
         self.make_streaming_request(request).await
     }
+
+    async fn count_tokens(&self, request: &GenerateRequest) -> Result<u32, LlmError> {
+        self.make_count_tokens_request(request).await
+    }
+
+    fn name(&self) -> &str {
+        self.model.as_str()
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities::GEMINI
+    }
+}
+
+/// Streaming URL for `model`, on the default host for `location` or on `endpoint`
+fn endpoint_url(
+    project_id: &str,
+    location: &str,
+    model: &str,
+    endpoint: Option<&EndpointOverride>,
+) -> String {
+    format!(
+        "{}/v1/projects/{}/locations/{}/publishers/google/models/{}:streamGenerateContent?alt=sse",
+        vertex_base_url(location, endpoint),
+        project_id,
+        location,
+        model
+    )
+}
+
+/// `countTokens` URL for `model`, on the default host for `location` or on `endpoint`
+fn count_tokens_url(
+    project_id: &str,
+    location: &str,
+    model: &str,
+    endpoint: Option<&EndpointOverride>,
+) -> String {
+    format!(
+        "{}/v1/projects/{}/locations/{}/publishers/google/models/{}:countTokens",
+        vertex_base_url(location, endpoint),
+        project_id,
+        location,
+        model
+    )
 }
 
 #[cfg(test)]
@@ -198,20 +390,75 @@ mod tests {
 
     #[test]
     fn test_model_endpoint_url_format() {
-        // Test URL construction logic without creating a full client
-        let project_id = "my-project";
-        let location = "us-central1";
         let model = GeminiModel::Gemini25Flash;
 
-        let url = format!(
-            "https://{}-aiplatform.googleapis.com/v1/projects/{}/locations/{}/publishers/google/models/{}:streamGenerateContent?alt=sse",
-            location, project_id, location, model.as_str()
+        assert_eq!(
+            endpoint_url("my-project", "us-central1", model.as_str(), None),
+            "https://us-central1-aiplatform.googleapis.com/v1/projects/my-project/locations/us-central1/publishers/google/models/gemini-2.5-flash:streamGenerateContent?alt=sse"
+        );
+        assert_eq!(
+            endpoint_url("my-project", "europe-west4", model.as_str(), None),
+            "https://europe-west4-aiplatform.googleapis.com/v1/projects/my-project/locations/europe-west4/publishers/google/models/gemini-2.5-flash:streamGenerateContent?alt=sse"
+        );
+
+        assert_eq!(
+            endpoint_url("my-project", "global", model.as_str(), None),
+            "https://aiplatform.googleapis.com/v1/projects/my-project/locations/global/publishers/google/models/gemini-2.5-flash:streamGenerateContent?alt=sse"
+        );
+
+        let psc = EndpointOverride::new("https://vertex-psc.p.example.internal/").unwrap();
+        assert_eq!(
+            endpoint_url("my-project", "europe-west4", model.as_str(), Some(&psc)),
+            "https://vertex-psc.p.example.internal/v1/projects/my-project/locations/europe-west4/publishers/google/models/gemini-2.5-flash:streamGenerateContent?alt=sse"
+        );
+    }
+
+    #[test]
+    fn test_count_tokens_url_format() {
+        assert_eq!(
+            count_tokens_url("my-project", "us-central1", GeminiModel::Gemini25Flash.as_str(), None),
+            "https://us-central1-aiplatform.googleapis.com/v1/projects/my-project/locations/us-central1/publishers/google/models/gemini-2.5-flash:countTokens"
         );
+    }
+
+    /// Run SSE `chunks` through the parser and event conversion
+    async fn events_from(chunks: &[&str]) -> Vec<Result<StreamEvent, LlmError>> {
+        let bytes: Vec<Result<bytes::Bytes, reqwest::Error>> = chunks
+            .iter()
+            .map(|chunk| Ok(bytes::Bytes::from(format!("data: {}\n\n", chunk))))
+            .collect();
+        let byte_stream = Box::pin(futures::stream::iter(bytes));
+        to_event_stream(parse_sse_stream(byte_stream)).collect().await
+    }
+
+    #[tokio::test]
+    async fn test_waits_past_empty_first_chunk() {
+        let events = events_from(&[
+            r#"{"candidates": []}"#,
+            r#"{"usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 0, "totalTokenCount": 3}}"#,
+            r#"{"candidates": [{"content": {"role": "model", "parts": [{"text": "Hi"}]}, "finishReason": "STOP"}], "usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 1, "totalTokenCount": 4}}"#,
+        ])
+        .await;
+
+        let events: Vec<StreamEvent> = events.into_iter().map(Result::unwrap).collect();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(events[0], StreamEvent::MessageStart { .. }));
+        assert!(matches!(
+            &events[1],
+            StreamEvent::ContentDelta { delta: crate::llm::ContentDelta::TextDelta { text }, .. } if text == "Hi"
+        ));
+        assert!(matches!(&events[2], StreamEvent::MessageEnd { usage, .. } if usage.total_tokens == 4));
+    }
+
+    #[tokio::test]
+    async fn test_stream_without_candidates_is_an_error() {
+        let events = events_from(&[
+            r#"{"candidates": []}"#,
+            r#"{"usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 0, "totalTokenCount": 3}}"#,
+        ])
+        .await;
 
-        assert!(url.contains("us-central1-aiplatform.googleapis.com"));
-        assert!(url.contains("my-project"));
-        assert!(url.contains("gemini-2.5-flash"));
-        assert!(url.contains("streamGenerateContent"));
-        assert!(url.contains("alt=sse"));
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], Err(LlmError::EmptyResponse)));
     }
 }
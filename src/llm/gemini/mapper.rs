@@ -1,39 +1,89 @@
 //! Mapping between abstraction types and Gemini types
 
+use std::collections::HashMap;
+
 use uuid::Uuid;
 
 use crate::llm::core::{
     config::GenerationConfig,
+    error::LlmError,
+    locale::system_with_locale,
     types::{
-        ContentBlock, ContentBlockStart, ContentDelta, FinishReason, GenerateRequest, Message,
-        MessageMetadata, MessageRole, PartialToolUse, StreamEvent, ToolDeclaration, UsageMetadata,
+        ContentBlock, ContentBlockStart, ContentDelta, FinishReason, GenerateRequest, ImageData,
+        Message, MessageMetadata, MessageRole, PartialToolUse, StreamEvent, ToolChoice,
+        ToolDeclaration, UsageMetadata,
     },
 };
 
 use super::types::{
-    Content, FunctionCall, FunctionDeclaration, FunctionResponse,
-    GeminiGenerationConfig, GenerateContentRequest, GenerateContentResponse, Part,
-    SystemInstruction, Tool,
+    Blob, Content, CountTokensRequest, FileData, FunctionCall, FunctionCallingConfig,
+    FunctionDeclaration, FunctionResponse, GeminiGenerationConfig, GenerateContentRequest,
+    GenerateContentResponse, Part, SystemInstruction, Tool, ToolConfig,
 };
 
 /// Convert our abstraction request to Gemini's request format
-pub fn to_gemini_request(request: GenerateRequest) -> GenerateContentRequest {
-    GenerateContentRequest {
-        contents: request.messages.into_iter().map(to_gemini_content).collect(),
-        system_instruction: request.system.map(|s| SystemInstruction {
-            parts: vec![Part::Text { text: s }],
-        }),
+///
+/// Gemini matches function responses to calls by function name, so each
+/// tool result is sent under the name of the tool use with its `tool_use_id`.
+///
+/// A `locale` is sent as an instruction at the end of the system
+/// instruction; Gemini's own language field only applies to speech output.
+///
+/// # Errors
+///
+/// Returns `LlmError::InvalidRequest` if a tool result has no earlier tool
+/// use with the same id.
+pub fn to_gemini_request(request: GenerateRequest) -> Result<GenerateContentRequest, LlmError> {
+    let mut tool_names = HashMap::new();
+    let contents = request
+        .messages
+        .into_iter()
+        .map(|message| to_gemini_content(message, &mut tool_names))
+        .collect::<Result<_, _>>()?;
+
+    Ok(GenerateContentRequest {
+        contents,
+        system_instruction: system_with_locale(request.system, request.locale.as_deref()).map(
+            |s| SystemInstruction {
+                parts: vec![Part::Text { text: s }],
+            },
+        ),
         tools: request.tools.map(|tools| {
             vec![Tool {
                 function_declarations: tools.into_iter().map(to_gemini_function_declaration).collect(),
             }]
         }),
+        tool_config: request.tool_choice.map(to_gemini_tool_config),
         generation_config: Some(to_gemini_generation_config(request.config)),
-    }
+    })
+}
+
+/// Convert our abstraction request to a `countTokens` request
+///
+/// Counts the contents, system instruction (with any locale instruction)
+/// and tools that [`to_gemini_request`] would send.
+///
+/// # Errors
+///
+/// The same as [`to_gemini_request`].
+pub fn to_gemini_count_tokens_request(
+    request: GenerateRequest,
+) -> Result<CountTokensRequest, LlmError> {
+    let request = to_gemini_request(request)?;
+    Ok(CountTokensRequest {
+        contents: request.contents,
+        system_instruction: request.system_instruction,
+        tools: request.tools,
+    })
 }
 
 /// Convert a message to Gemini's content format
-fn to_gemini_content(message: Message) -> Content {
+///
+/// `tool_names` maps the ids of tool uses seen so far to their function names.
+fn to_gemini_content(
+    message: Message,
+    tool_names: &mut HashMap<String, String>,
+) -> Result<Content, LlmError> {
     let role = match message.role {
         MessageRole::User => "user".to_string(),
         MessageRole::Assistant => "model".to_string(),
@@ -44,18 +94,22 @@ fn to_gemini_content(message: Message) -> Content {
     let parts = message
         .content
         .into_iter()
-        .map(to_gemini_part)
-        .collect();
+        .filter_map(|block| to_gemini_part(block, tool_names).transpose())
+        .collect::<Result<_, _>>()?;
 
-    Content { role, parts }
+    Ok(Content { role, parts })
 }
 
-/// Convert a content block to a Gemini part
-fn to_gemini_part(block: ContentBlock) -> Part {
-    match block {
+/// Convert a content block to a Gemini part, or `None` if Gemini has no equivalent
+fn to_gemini_part(
+    block: ContentBlock,
+    tool_names: &mut HashMap<String, String>,
+) -> Result<Option<Part>, LlmError> {
+    let part = match block {
         ContentBlock::Text { text } => Part::Text { text },
-        ContentBlock::ToolUse { id: _, name, input } => {
-            // Note: Gemini doesn't use tool_use_id in the request, it's only in responses
+        ContentBlock::ToolUse { id, name, input } => {
+            // Gemini has no call ids; remember the name for the matching result
+            tool_names.insert(id, name.clone());
             Part::FunctionCall {
                 function_call: FunctionCall {
                     name,
@@ -64,13 +118,16 @@ fn to_gemini_part(block: ContentBlock) -> Part {
             }
         }
         ContentBlock::ToolResult {
-            tool_use_id: _,
+            tool_use_id,
             content,
             is_error,
         } => {
-            // Extract the name from the content if it was stored, otherwise use a placeholder
-            // In practice, the application needs to track which tool_use_id maps to which name
-            // For now, we'll encode the result as a JSON object
+            let name = tool_names.get(&tool_use_id).cloned().ok_or_else(|| {
+                LlmError::InvalidRequest(format!(
+                    "Tool result '{}' has no matching tool use",
+                    tool_use_id
+                ))
+            })?;
             let response = if is_error {
                 serde_json::json!({
                     "error": content
@@ -85,17 +142,27 @@ fn to_gemini_part(block: ContentBlock) -> Part {
             };
 
             Part::FunctionResponse {
-                function_response: FunctionResponse {
-                    // Note: We need the function name here, but it's not in ToolResult.
-                    // This is a limitation - the application must provide this context.
-                    // For now, we'll use a placeholder. Real implementation would need
-                    // to track the mapping from tool_use_id to function name.
-                    name: "function".to_string(),
-                    response,
-                },
+                function_response: FunctionResponse { name, response },
             }
         }
-    }
+        ContentBlock::Image { media_type, data } => match data {
+            ImageData::Base64(data) => Part::InlineData {
+                inline_data: Blob {
+                    mime_type: media_type,
+                    data,
+                },
+            },
+            ImageData::Url(file_uri) => Part::FileData {
+                file_data: FileData {
+                    mime_type: media_type,
+                    file_uri,
+                },
+            },
+        },
+        // Claude's encrypted thinking means nothing to Gemini
+        ContentBlock::RedactedThinking { .. } => return Ok(None),
+    };
+    Ok(Some(part))
 }
 
 /// Convert a tool declaration to Gemini's function declaration
@@ -107,6 +174,25 @@ fn to_gemini_function_declaration(tool: ToolDeclaration) -> FunctionDeclaration
     }
 }
 
+/// Convert a tool choice to Gemini's function calling config
+///
+/// Gemini has no single-tool mode, so `Required` is `ANY` restricted to that tool.
+fn to_gemini_tool_config(choice: ToolChoice) -> ToolConfig {
+    let (mode, allowed_function_names) = match choice {
+        ToolChoice::Auto => ("AUTO", None),
+        ToolChoice::Any => ("ANY", None),
+        ToolChoice::Required { name } => ("ANY", Some(vec![name])),
+        ToolChoice::None => ("NONE", None),
+    };
+
+    ToolConfig {
+        function_calling_config: FunctionCallingConfig {
+            mode: mode.to_string(),
+            allowed_function_names,
+        },
+    }
+}
+
 /// Convert generation config to Gemini's format
 fn to_gemini_generation_config(config: GenerationConfig) -> GeminiGenerationConfig {
     GeminiGenerationConfig {
@@ -115,6 +201,11 @@ fn to_gemini_generation_config(config: GenerationConfig) -> GeminiGenerationConf
         top_p: config.top_p,
         top_k: config.top_k,
         stop_sequences: config.stop_sequences,
+        response_mime_type: config
+            .response_schema
+            .as_ref()
+            .map(|_| "application/json".to_string()),
+        response_schema: config.response_schema,
     }
 }
 
@@ -173,8 +264,8 @@ pub fn from_gemini_response(
 
                 *current_index += 1;
             }
-            Part::FunctionResponse { .. } => {
-                // Function responses are not expected in model output
+            Part::FunctionResponse { .. } | Part::InlineData { .. } | Part::FileData { .. } => {
+                // Function responses and files are not expected in model output
                 // They're only in the request
             }
         }
@@ -191,17 +282,14 @@ pub fn from_gemini_response(
                     input_tokens: usage.prompt_token_count,
                     output_tokens: usage.candidates_token_count,
                     total_tokens: usage.total_token_count,
+                    ..UsageMetadata::default()
                 },
             });
         } else {
             // If no usage metadata, create a zero usage
             events.push(StreamEvent::MessageEnd {
                 finish_reason,
-                usage: UsageMetadata {
-                    input_tokens: 0,
-                    output_tokens: 0,
-                    total_tokens: 0,
-                },
+                usage: UsageMetadata::default(),
             });
         }
     }
@@ -239,7 +327,7 @@ mod tests {
     #[test]
     fn test_to_gemini_content_user() {
         let message = Message::user("Hello");
-        let content = to_gemini_content(message);
+        let content = to_gemini_content(message, &mut HashMap::new()).unwrap();
         assert_eq!(content.role, "user");
         assert_eq!(content.parts.len(), 1);
         match &content.parts[0] {
@@ -248,10 +336,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_to_gemini_content_images() {
+        let message = Message {
+            role: MessageRole::User,
+            content: vec![
+                ContentBlock::Image {
+                    media_type: "image/png".to_string(),
+                    data: ImageData::Base64("iVBORw0KGgo=".to_string()),
+                },
+                ContentBlock::Image {
+                    media_type: "image/jpeg".to_string(),
+                    data: ImageData::Url("gs://bucket/cat.jpg".to_string()),
+                },
+            ],
+        };
+
+        let content = to_gemini_content(message, &mut HashMap::new()).unwrap();
+        let json = serde_json::to_value(content).unwrap();
+        assert_eq!(
+            json["parts"],
+            serde_json::json!([
+                {"inlineData": {"mimeType": "image/png", "data": "iVBORw0KGgo="}},
+                {"fileData": {"mimeType": "image/jpeg", "fileUri": "gs://bucket/cat.jpg"}}
+            ])
+        );
+    }
+
     #[test]
     fn test_to_gemini_content_assistant() {
         let message = Message::assistant("Hi there");
-        let content = to_gemini_content(message);
+        let content = to_gemini_content(message, &mut HashMap::new()).unwrap();
         assert_eq!(content.role, "model");
     }
 
@@ -281,6 +396,25 @@ mod tests {
         assert_eq!(gemini_config.max_output_tokens, Some(2048));
         assert_eq!(gemini_config.temperature, Some(0.7));
         assert_eq!(gemini_config.top_k, Some(40));
+        assert!(gemini_config.response_mime_type.is_none());
+        assert!(gemini_config.response_schema.is_none());
+    }
+
+    #[test]
+    fn test_to_gemini_generation_config_with_response_schema() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"answer": {"type": "integer"}},
+            "required": ["answer"]
+        });
+        let config = GenerationConfig::new(1024).with_response_schema(schema.clone());
+        let gemini_config = to_gemini_generation_config(config);
+        assert_eq!(gemini_config.response_mime_type.as_deref(), Some("application/json"));
+        assert_eq!(gemini_config.response_schema, Some(schema));
+
+        let json = serde_json::to_value(&gemini_config).unwrap();
+        assert_eq!(json["responseMimeType"], "application/json");
+        assert_eq!(json["responseSchema"]["required"][0], "answer");
     }
 
     #[test]
@@ -358,6 +492,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_to_gemini_request_appends_locale_instruction() {
+        let request = GenerateRequest {
+            messages: vec![Message::user("What's the weather?")],
+            tools: None,
+            tool_choice: None,
+            config: GenerationConfig::default(),
+            system: None,
+            locale: Some("ja-JP".to_string()),
+        };
+
+        let json = serde_json::to_value(to_gemini_request(request).unwrap()).unwrap();
+        assert_eq!(
+            json["systemInstruction"]["parts"][0]["text"],
+            crate::llm::core::locale::locale_instruction("ja-JP")
+        );
+    }
+
+    #[test]
+    fn test_to_gemini_count_tokens_request() {
+        let request = GenerateRequest {
+            messages: vec![Message::user("What's the weather?")],
+            tools: Some(vec![ToolDeclaration {
+                name: "get_weather".to_string(),
+                description: "Get weather".to_string(),
+                input_schema: serde_json::json!({"type": "object"}),
+            }]),
+            tool_choice: None,
+            config: GenerationConfig::default(),
+            system: Some("You are helpful".to_string()),
+            locale: None,
+        };
+
+        let json = serde_json::to_value(to_gemini_count_tokens_request(request).unwrap()).unwrap();
+
+        assert_eq!(json["contents"].as_array().unwrap().len(), 1);
+        assert_eq!(json["systemInstruction"]["parts"][0]["text"], "You are helpful");
+        assert_eq!(json["tools"][0]["functionDeclarations"][0]["name"], "get_weather");
+        assert!(json.get("generationConfig").is_none());
+    }
+
     #[test]
     fn test_to_gemini_request_with_tools() {
         let request = GenerateRequest {
@@ -367,11 +542,13 @@ mod tests {
                 description: "Get weather".to_string(),
                 input_schema: serde_json::json!({"type": "object"}),
             }]),
+            tool_choice: None,
             config: GenerationConfig::default(),
             system: Some("You are helpful".to_string()),
+            locale: None,
         };
 
-        let gemini_request = to_gemini_request(request);
+        let gemini_request = to_gemini_request(request).unwrap();
         assert!(gemini_request.system_instruction.is_some());
         assert!(gemini_request.tools.is_some());
         let tools = gemini_request.tools.unwrap();
@@ -379,4 +556,105 @@ mod tests {
         assert_eq!(tools[0].function_declarations.len(), 1);
         assert_eq!(tools[0].function_declarations[0].name, "get_weather");
     }
+
+    #[test]
+    fn test_to_gemini_request_names_function_responses() {
+        let tool_use = |id: &str, name: &str, city: &str| ContentBlock::ToolUse {
+            id: id.to_string(),
+            name: name.to_string(),
+            input: serde_json::json!({"city": city}),
+        };
+        let tool_result = |id: &str, content: &str| ContentBlock::ToolResult {
+            tool_use_id: id.to_string(),
+            content: content.to_string(),
+            is_error: false,
+        };
+        let request = GenerateRequest {
+            messages: vec![
+                Message::user("Weather and time in Paris?"),
+                Message {
+                    role: MessageRole::Assistant,
+                    content: vec![
+                        tool_use("call_1", "get_weather", "Paris"),
+                        tool_use("call_2", "get_time", "Paris"),
+                    ],
+                },
+                // Results arrive in the opposite order of the calls
+                Message {
+                    role: MessageRole::Tool,
+                    content: vec![
+                        tool_result("call_2", "14:05"),
+                        tool_result("call_1", "18°C"),
+                    ],
+                },
+            ],
+            tools: None,
+            tool_choice: None,
+            config: GenerationConfig::default(),
+            system: None,
+            locale: None,
+        };
+
+        let json = serde_json::to_value(to_gemini_request(request).unwrap()).unwrap();
+        assert_eq!(
+            json["contents"][2]["parts"],
+            serde_json::json!([
+                {"functionResponse": {"name": "get_time", "response": {"result": "14:05"}}},
+                {"functionResponse": {"name": "get_weather", "response": {"result": "18°C"}}}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_to_gemini_request_rejects_unmatched_tool_result() {
+        let request = GenerateRequest {
+            messages: vec![Message::tool_result("call_9", "18°C")],
+            tools: None,
+            tool_choice: None,
+            config: GenerationConfig::default(),
+            system: None,
+            locale: None,
+        };
+
+        let err = to_gemini_request(request).unwrap_err();
+        assert!(matches!(err, LlmError::InvalidRequest(ref msg) if msg.contains("call_9")));
+    }
+
+    #[test]
+    fn test_to_gemini_request_tool_choice() {
+        let serialized = |tool_choice| {
+            let request = GenerateRequest {
+                messages: vec![Message::user("What's the weather?")],
+                tools: None,
+                tool_choice,
+                config: GenerationConfig::default(),
+                system: None,
+                locale: None,
+            };
+            serde_json::to_value(to_gemini_request(request).unwrap()).unwrap()
+        };
+
+        assert!(serialized(None).get("toolConfig").is_none());
+        assert_eq!(
+            serialized(Some(ToolChoice::Auto))["toolConfig"],
+            serde_json::json!({"functionCallingConfig": {"mode": "AUTO"}})
+        );
+        assert_eq!(
+            serialized(Some(ToolChoice::Any))["toolConfig"],
+            serde_json::json!({"functionCallingConfig": {"mode": "ANY"}})
+        );
+        assert_eq!(
+            serialized(Some(ToolChoice::Required {
+                name: "get_weather".to_string()
+            }))["toolConfig"],
+            serde_json::json!({"functionCallingConfig": {
+                "mode": "ANY",
+                "allowedFunctionNames": ["get_weather"]
+            }})
+        );
+        assert_eq!(
+            serialized(Some(ToolChoice::None))["toolConfig"],
+            serde_json::json!({"functionCallingConfig": {"mode": "NONE"}})
+        );
+    }
 }
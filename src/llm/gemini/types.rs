@@ -16,6 +16,9 @@ pub struct GenerateContentRequest {
     /// Available tools for the model to use
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<Tool>>,
+    /// Restrictions on how the tools are used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_config: Option<ToolConfig>,
     /// Generation configuration parameters
     #[serde(skip_serializing_if = "Option::is_none")]
     pub generation_config: Option<GeminiGenerationConfig>,
@@ -54,6 +57,36 @@ pub enum Part {
         #[serde(rename = "functionResponse")]
         function_response: FunctionResponse,
     },
+    /// Inline file bytes (e.g. an image)
+    InlineData {
+        #[serde(rename = "inlineData")]
+        inline_data: Blob,
+    },
+    /// File referenced by URI
+    FileData {
+        #[serde(rename = "fileData")]
+        file_data: FileData,
+    },
+}
+
+/// Inline file bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Blob {
+    /// MIME type, e.g. "image/png"
+    pub mime_type: String,
+    /// Base64-encoded bytes
+    pub data: String,
+}
+
+/// A file referenced by URI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileData {
+    /// MIME type, e.g. "image/png"
+    pub mime_type: String,
+    /// URI of the file (Cloud Storage or HTTP)
+    pub file_uri: String,
 }
 
 /// A function call made by the model
@@ -82,6 +115,25 @@ pub struct Tool {
     pub function_declarations: Vec<FunctionDeclaration>,
 }
 
+/// Tool usage configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolConfig {
+    /// Function calling settings
+    pub function_calling_config: FunctionCallingConfig,
+}
+
+/// Function calling settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionCallingConfig {
+    /// "AUTO", "ANY" or "NONE"
+    pub mode: String,
+    /// Functions the model may call when `mode` is "ANY"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_function_names: Option<Vec<String>>,
+}
+
 /// A function declaration describing a tool
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDeclaration {
@@ -112,13 +164,42 @@ pub struct GeminiGenerationConfig {
     /// Stop sequences
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stop_sequences: Option<Vec<String>>,
+    /// Output MIME type (`application/json` for structured output)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_mime_type: Option<String>,
+    /// Schema the JSON output must follow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_schema: Option<serde_json::Value>,
+}
+
+/// Request to count the tokens of a conversation with Gemini's `countTokens`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CountTokensRequest {
+    /// Array of content items representing the conversation
+    pub contents: Vec<Content>,
+    /// Optional system instruction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<SystemInstruction>,
+    /// Available tools for the model to use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+}
+
+/// Response to a [`CountTokensRequest`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CountTokensResponse {
+    /// Tokens taken up by the contents, system instruction and tools
+    pub total_tokens: u32,
 }
 
 /// Response from Gemini's streaming endpoint
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GenerateContentResponse {
-    /// Candidates (usually just one)
+    /// Candidates (usually just one); omitted entirely on some chunks
+    #[serde(default)]
     pub candidates: Vec<Candidate>,
     /// Usage metadata
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -222,6 +303,8 @@ mod tests {
             top_p: Some(0.9),
             top_k: Some(40),
             stop_sequences: None,
+            response_mime_type: None,
+            response_schema: None,
         };
         let json = serde_json::to_string(&config).unwrap();
         assert!(json.contains("\"maxOutputTokens\":1024"));
@@ -240,12 +323,15 @@ mod tests {
             }],
             system_instruction: None,
             tools: None,
+            tool_config: None,
             generation_config: Some(GeminiGenerationConfig {
                 max_output_tokens: Some(1024),
                 temperature: None,
                 top_p: None,
                 top_k: None,
                 stop_sequences: None,
+                response_mime_type: None,
+                response_schema: None,
             }),
         };
         let json = serde_json::to_string(&request).unwrap();
@@ -0,0 +1,109 @@
+//! Raw response capture for debugging provider traffic
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::Stream;
+use futures::StreamExt;
+use std::pin::Pin;
+use tokio::sync::mpsc;
+
+/// A raw chunk of bytes received from a provider, before any parsing
+#[derive(Debug, Clone)]
+pub struct RawChunk {
+    /// Bytes exactly as received from the HTTP response body
+    pub bytes: Bytes,
+    /// When the chunk was received
+    pub timestamp: DateTime<Utc>,
+    /// Position of the chunk within its response (starting at 0)
+    pub sequence: u64,
+}
+
+/// Byte stream type produced by `reqwest::Response::bytes_stream`
+pub(crate) type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>;
+
+/// Tee every chunk of a byte stream into a capture channel
+///
+/// Chunks are sent with `try_send`, so a full or closed channel drops the
+/// chunk instead of stalling the main stream. Sequence numbers start at 0 for
+/// every call, so captures from independent requests don't interleave counts.
+pub(crate) fn tee_raw_chunks(byte_stream: ByteStream, sender: mpsc::Sender<RawChunk>) -> ByteStream {
+    let mut sequence = 0u64;
+
+    let stream = byte_stream.inspect(move |chunk_result| {
+        if let Ok(bytes) = chunk_result {
+            // Dropping is intentional: capture must never slow down generation
+            let _ = sender.try_send(RawChunk {
+                bytes: bytes.clone(),
+                timestamp: Utc::now(),
+                sequence,
+            });
+            sequence += 1;
+        }
+    });
+
+    Box::pin(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::claude::sse::parse_sse_stream;
+    use futures::stream;
+
+    fn scripted_stream(chunks: &[&'static [u8]]) -> ByteStream {
+        let items: Vec<Result<Bytes, reqwest::Error>> = chunks
+            .iter()
+            .map(|c| Ok(Bytes::from_static(c)))
+            .collect();
+        Box::pin(stream::iter(items))
+    }
+
+    #[tokio::test]
+    async fn test_capture_matches_parsed_chunks() {
+        let chunks: &[&'static [u8]] = &[
+            b"event: ping\ndata: {\"type\":\"ping\"}\n\n",
+            b"event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+            b"event: ping\ndata: {\"type\":\"ping\"}\n\n",
+        ];
+        let (tx, mut rx) = mpsc::channel(16);
+
+        let parsed: Vec<_> = parse_sse_stream(tee_raw_chunks(scripted_stream(chunks), tx))
+            .collect()
+            .await;
+
+        let mut captured = Vec::new();
+        while let Ok(chunk) = rx.try_recv() {
+            captured.push(chunk);
+        }
+
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(captured.len(), parsed.len());
+        for (i, chunk) in captured.iter().enumerate() {
+            assert_eq!(chunk.sequence, i as u64);
+            assert_eq!(chunk.bytes.as_ref(), chunks[i]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_receiver_does_not_stall_stream() {
+        let chunks: &[&'static [u8]] = &[
+            b"event: ping\ndata: {\"type\":\"ping\"}\n\n",
+            b"event: ping\ndata: {\"type\":\"ping\"}\n\n",
+            b"event: ping\ndata: {\"type\":\"ping\"}\n\n",
+            b"event: ping\ndata: {\"type\":\"ping\"}\n\n",
+        ];
+        // Capacity 1 and nobody reading: everything past the first chunk is dropped
+        let (tx, mut rx) = mpsc::channel(1);
+
+        let parsed: Vec<_> = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            parse_sse_stream(tee_raw_chunks(scripted_stream(chunks), tx)).collect::<Vec<_>>(),
+        )
+        .await
+        .expect("capture must not stall the main stream");
+
+        assert_eq!(parsed.len(), 4);
+        assert_eq!(rx.recv().await.unwrap().sequence, 0);
+        assert!(rx.try_recv().is_err());
+    }
+}
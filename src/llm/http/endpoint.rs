@@ -0,0 +1,177 @@
+//! Vertex AI endpoint hosts
+//!
+//! Requests go to `https://{location}-aiplatform.googleapis.com` by default,
+//! or to `https://aiplatform.googleapis.com` for the `global` location, which
+//! has no regional host.
+//! An [`EndpointOverride`] swaps that base URL for another one, such as a
+//! Private Service Connect endpoint or a local emulator, while the
+//! `/v1/projects/...` path is still built by the client.
+//!
+//! Authentication is unchanged by an override: ADC access tokens are issued
+//! for the `cloud-platform` scope and carry no audience, so the same token is
+//! valid for any Vertex AI host.
+
+use reqwest::Url;
+
+use crate::llm::core::error::LlmError;
+
+/// Base URL replacing the default Vertex AI host
+///
+/// # Example
+///
+/// ```
+/// use rust2::llm::EndpointOverride;
+///
+/// let psc = EndpointOverride::new("https://vertex.p.example.internal").unwrap();
+/// assert_eq!(psc.base_url(), "https://vertex.p.example.internal");
+///
+/// // Plain HTTP must be allowed explicitly
+/// assert!(EndpointOverride::new("http://localhost:8080").is_err());
+/// assert!(EndpointOverride::insecure("http://localhost:8080").is_ok());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointOverride {
+    base_url: String,
+}
+
+impl EndpointOverride {
+    /// Use `base_url`, which must be an `https` URL with a host
+    ///
+    /// A path prefix is kept; a trailing `/` is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `LlmError::InvalidRequest` if the URL doesn't parse, isn't
+    /// `https`, has no host, or has a query or fragment.
+    pub fn new(base_url: &str) -> Result<Self, LlmError> {
+        Self::parse(base_url, false)
+    }
+
+    /// Like [`new`](Self::new), but also accept `http` URLs
+    ///
+    /// Meant for local emulators; the access token is sent in the clear.
+    pub fn insecure(base_url: &str) -> Result<Self, LlmError> {
+        Self::parse(base_url, true)
+    }
+
+    /// The validated base URL, without a trailing `/`
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn parse(base_url: &str, allow_insecure: bool) -> Result<Self, LlmError> {
+        let invalid = |reason: &str| {
+            LlmError::InvalidRequest(format!(
+                "Invalid endpoint override '{}': {}",
+                base_url, reason
+            ))
+        };
+
+        let url = Url::parse(base_url).map_err(|e| invalid(&e.to_string()))?;
+        match url.scheme() {
+            "https" => {}
+            "http" if allow_insecure => {}
+            "http" => {
+                return Err(invalid(
+                    "must use https (use EndpointOverride::insecure for local emulators)",
+                ))
+            }
+            other => return Err(invalid(&format!("unsupported scheme '{}'", other))),
+        }
+        if url.host_str().is_none_or(str::is_empty) {
+            return Err(invalid("missing host"));
+        }
+        if url.query().is_some() || url.fragment().is_some() {
+            return Err(invalid("must not have a query or fragment"));
+        }
+
+        Ok(Self {
+            base_url: url.as_str().trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+/// Base URL for requests in `location`, honoring `endpoint` if set
+///
+/// The `global` location is served from the host without a regional
+/// prefix; `global-aiplatform.googleapis.com` does not exist.
+pub(crate) fn vertex_base_url(location: &str, endpoint: Option<&EndpointOverride>) -> String {
+    match endpoint {
+        Some(endpoint) => endpoint.base_url.clone(),
+        None if location == "global" => "https://aiplatform.googleapis.com".to_string(),
+        None => format!("https://{}-aiplatform.googleapis.com", location),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_and_regional_hosts() {
+        assert_eq!(
+            vertex_base_url("us-central1", None),
+            "https://us-central1-aiplatform.googleapis.com"
+        );
+        assert_eq!(
+            vertex_base_url("europe-west4", None),
+            "https://europe-west4-aiplatform.googleapis.com"
+        );
+    }
+
+    #[test]
+    fn test_global_location_has_no_regional_prefix() {
+        assert_eq!(
+            vertex_base_url("global", None),
+            "https://aiplatform.googleapis.com"
+        );
+
+        // An override still wins over the global host
+        let endpoint = EndpointOverride::new("https://vertex.p.example.internal").unwrap();
+        assert_eq!(
+            vertex_base_url("global", Some(&endpoint)),
+            "https://vertex.p.example.internal"
+        );
+    }
+
+    #[test]
+    fn test_override_replaces_host_and_keeps_prefix() {
+        let endpoint = EndpointOverride::new("https://vertex.p.example.internal/").unwrap();
+        assert_eq!(
+            vertex_base_url("us-central1", Some(&endpoint)),
+            "https://vertex.p.example.internal"
+        );
+
+        let prefixed = EndpointOverride::new("https://gateway.example.com/vertex/").unwrap();
+        assert_eq!(prefixed.base_url(), "https://gateway.example.com/vertex");
+    }
+
+    #[test]
+    fn test_override_validation() {
+        let err = EndpointOverride::new("http://localhost:8080").unwrap_err();
+        assert!(matches!(err, LlmError::InvalidRequest(ref msg) if msg.contains("must use https")));
+
+        assert_eq!(
+            EndpointOverride::insecure("http://localhost:8080")
+                .unwrap()
+                .base_url(),
+            "http://localhost:8080"
+        );
+
+        for bad in [
+            "vertex.example.com",
+            "ftp://vertex.example.com",
+            "https://vertex.example.com/?alt=sse",
+            "https://vertex.example.com/#frag",
+        ] {
+            assert!(
+                matches!(
+                    EndpointOverride::insecure(bad),
+                    Err(LlmError::InvalidRequest(_))
+                ),
+                "{} should be rejected",
+                bad
+            );
+        }
+    }
+}
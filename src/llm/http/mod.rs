@@ -1,5 +1,10 @@
 //! Shared HTTP client logic
 //!
-//! This module will be implemented in Phase 3-5
+//! Helpers used by both the Claude and Gemini clients.
 
-// Placeholder for future implementation
+pub mod capture;
+pub mod endpoint;
+pub(crate) mod retry;
+
+pub use capture::RawChunk;
+pub use endpoint::EndpointOverride;
@@ -0,0 +1,269 @@
+//! Retrying requests that hit rate limits or transient server errors
+
+use std::time::Duration;
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::llm::core::config::RetryConfig;
+use crate::llm::core::error::LlmError;
+
+/// Whether a failed status is worth retrying
+///
+/// Uses the config's `retry_on`, or [`RetryConfig::default_retry_on`]
+/// without a config.
+fn is_retryable(retry: Option<&RetryConfig>, status: StatusCode) -> bool {
+    match retry {
+        Some(config) => config.retry_on.contains(&status),
+        None => RetryConfig::default_retry_on().contains(&status),
+    }
+}
+
+/// Delay asked for by a `Retry-After` header in seconds
+///
+/// The HTTP-date form isn't used by Vertex AI and is ignored.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    seconds.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// Send the request built by `build`, retrying retryable statuses per `retry`
+///
+/// Without a `retry` config the request is sent once. A `Retry-After`
+/// header replaces the backoff delay, up to the config's `max_delay`. A failed response
+/// becomes `LlmError::Retryable` or `LlmError::Permanent` depending on its
+/// status, or `LlmError::RateLimited` if it is still a 429. Backoff state lives only in this call, so concurrent or later
+/// requests always start from the first attempt.
+pub(crate) async fn send_with_retry<F>(
+    retry: Option<&RetryConfig>,
+    provider: &str,
+    build: F,
+) -> Result<Response, LlmError>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let max_attempts = retry.map_or(1, |config| config.max_attempts.max(1));
+    let mut attempt = 1;
+    loop {
+        let response = build().send().await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        if !is_retryable(retry, status) {
+            let body = response.text().await.unwrap_or_else(|_| String::new());
+            return Err(LlmError::Permanent {
+                status: status.as_u16(),
+                body,
+            });
+        }
+        let config = match retry {
+            Some(config) if attempt < max_attempts => config,
+            _ if status == StatusCode::TOO_MANY_REQUESTS => {
+                return Err(LlmError::RateLimited {
+                    retries: attempt - 1,
+                });
+            }
+            _ => {
+                let body = response.text().await.unwrap_or_else(|_| String::new());
+                return Err(LlmError::Retryable {
+                    status: status.as_u16(),
+                    body,
+                });
+            }
+        };
+
+        let delay = match retry_after(&response) {
+            Some(delay) => delay.min(config.max_delay),
+            None => config.delay(attempt - 1, &mut rand::thread_rng()),
+        };
+        tracing::warn!(
+            provider,
+            status = status.as_u16(),
+            attempt,
+            delay_ms = delay.as_millis() as u64,
+            "retrying request"
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Serve the given statuses in order (repeating the last), counting requests
+    async fn spawn_scripted_server(statuses: Vec<u16>) -> (SocketAddr, Arc<AtomicUsize>) {
+        spawn_server_with_retry_after(statuses, None).await
+    }
+
+    /// Like `spawn_scripted_server`, adding `Retry-After` to failed responses
+    async fn spawn_server_with_retry_after(
+        statuses: Vec<u16>,
+        retry_after: Option<&'static str>,
+    ) -> (SocketAddr, Arc<AtomicUsize>) {
+        use warp::Filter;
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let route = warp::post().map(move || {
+            let n = counter.fetch_add(1, Ordering::SeqCst);
+            let status = statuses[n.min(statuses.len() - 1)];
+            let mut response = warp::http::Response::builder().status(status);
+            if let Some(seconds) = retry_after.filter(|_| status >= 400) {
+                response = response.header("Retry-After", seconds);
+            }
+            response.body(format!("response {}", n)).unwrap()
+        });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(warp::serve(route).incoming(listener).run());
+        (addr, hits)
+    }
+
+    fn retry(max_attempts: u32) -> RetryConfig {
+        RetryConfig {
+            max_attempts,
+            initial_delay: Duration::from_millis(1),
+            ..RetryConfig::default()
+        }
+    }
+
+    async fn send_to(addr: SocketAddr, retry: Option<&RetryConfig>) -> Result<Response, LlmError> {
+        let client = Client::new();
+        let url = format!("http://{}/", addr);
+        send_with_retry(retry, "test", || client.post(&url)).await
+    }
+
+    #[tokio::test]
+    async fn test_retries_rate_limits_and_unavailable() {
+        let (addr, hits) = spawn_scripted_server(vec![429, 503, 529, 200]).await;
+
+        let response = send_to(addr, Some(&retry(4))).await.unwrap();
+
+        assert_eq!(response.text().await.unwrap(), "response 3");
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_retries_timeouts_and_server_errors() {
+        let (addr, hits) = spawn_scripted_server(vec![408, 500, 502, 200]).await;
+
+        let response = send_to(addr, Some(&retry(4))).await.unwrap();
+
+        assert_eq!(response.text().await.unwrap(), "response 3");
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_waits_for_retry_after() {
+        let (addr, hits) = spawn_server_with_retry_after(vec![429, 429, 200], Some("1")).await;
+
+        let started = tokio::time::Instant::now();
+        let response = send_to(addr, Some(&retry(3))).await.unwrap();
+
+        // Two retries of one second each, instead of the 1ms backoff
+        assert_eq!(response.text().await.unwrap(), "response 2");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert!(started.elapsed() >= Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_is_capped_by_max_delay() {
+        let (addr, hits) = spawn_server_with_retry_after(vec![503, 200], Some("3600")).await;
+        let config = RetryConfig {
+            max_delay: Duration::from_millis(10),
+            ..retry(2)
+        };
+
+        let started = std::time::Instant::now();
+        send_to(addr, Some(&config)).await.unwrap();
+
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let (addr, hits) = spawn_scripted_server(vec![503]).await;
+
+        let err = send_to(addr, Some(&retry(3))).await.unwrap_err();
+
+        assert!(
+            matches!(err, LlmError::Retryable { status: 503, ref body } if body == "response 2")
+        );
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_rate_limit_reports_retries() {
+        let (addr, hits) = spawn_scripted_server(vec![429]).await;
+
+        let err = send_to(addr, Some(&retry(3))).await.unwrap_err();
+
+        assert!(matches!(err, LlmError::RateLimited { retries: 2 }));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_selects_retried_statuses() {
+        let (addr, hits) = spawn_scripted_server(vec![500, 200]).await;
+        let config = RetryConfig {
+            retry_on: vec![StatusCode::TOO_MANY_REQUESTS],
+            ..retry(3)
+        };
+
+        let err = send_to(addr, Some(&config)).await.unwrap_err();
+        assert!(matches!(err, LlmError::Permanent { status: 500, .. }));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        let (addr, hits) = spawn_scripted_server(vec![409, 200]).await;
+        let config = RetryConfig {
+            retry_on: vec![StatusCode::CONFLICT],
+            ..retry(3)
+        };
+
+        send_to(addr, Some(&config)).await.unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_are_permanent_and_not_retried() {
+        let (addr, hits) = spawn_scripted_server(vec![400, 200]).await;
+
+        let err = send_to(addr, Some(&retry(3))).await.unwrap_err();
+
+        assert!(matches!(err, LlmError::Permanent { status: 400, ref body } if body == "response 0"));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_config_sends_once() {
+        let (addr, hits) = spawn_scripted_server(vec![503, 200]).await;
+
+        let err = send_to(addr, None).await.unwrap_err();
+
+        assert!(matches!(err, LlmError::Retryable { status: 503, .. }));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_each_request_starts_from_the_first_attempt() {
+        // Each request needs both of its attempts
+        let (addr, hits) = spawn_scripted_server(vec![429, 200, 429, 200]).await;
+        let config = retry(2);
+
+        send_to(addr, Some(&config)).await.unwrap();
+        let second = send_to(addr, Some(&config)).await.unwrap();
+
+        assert_eq!(second.text().await.unwrap(), "response 3");
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+    }
+}
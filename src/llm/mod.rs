@@ -10,19 +10,28 @@ pub mod claude;
 pub mod tools;
 pub mod http;
 pub mod agent;
+pub mod moderation;
 
 // Re-export commonly used types
 pub use core::{
-    config::GenerationConfig,
+    abort::{abortable, AbortHandle},
+    config::{
+        CompatibilityReport, GenerationConfig, ProviderCapabilities, RetryConfig,
+        ToolResultOverflow, PRESET_NAMES,
+    },
     error::LlmError,
-    provider::{create_provider, LlmProvider},
+    provider::{create_provider, create_provider_with_endpoint, LlmProvider},
+    trim::TrimReport,
     types::{
-        ContentBlock, ContentDelta, FinishReason, GenerateRequest, Message, MessageRole,
-        Model, StreamEvent, ToolDeclaration, UsageMetadata,
+        ContentBlock, ContentDelta, FinishReason, GenerateRequest, GenerateResponse, ImageData,
+        Message, MessageRole, Model, StreamEvent, ToolChoice, ToolDeclaration, TranscriptError,
+        UsageMetadata,
     },
 };
 
 pub use claude::ClaudeModel;
 pub use gemini::GeminiModel;
+pub use http::{EndpointOverride, RawChunk};
 pub use tools::{create_tool_declaration, FunctionRegistry, ToolExecutor};
-pub use agent::{Agent, AgentError, AgentEvent};
+pub use agent::{Agent, AgentError, AgentEvent, Citation, DebugBundle, Redactor};
+pub use moderation::{KeywordModerator, ModerationDecision, Moderator, NoopModerator};
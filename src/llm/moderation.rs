@@ -0,0 +1,201 @@
+//! Content moderation hooks
+//!
+//! A [`Moderator`] screens user input before a run starts and the model's
+//! final answer before it is stored. The HTTP server checks input in the
+//! send-message handler; the agent checks output via
+//! [`Agent::with_moderator`](crate::llm::Agent::with_moderator).
+
+use async_trait::async_trait;
+
+/// Outcome of a moderation check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModerationDecision {
+    /// Content is acceptable as-is
+    Allow,
+    /// Content must be rejected, with a reason suitable for the caller
+    Block(String),
+    /// Content must be replaced with the given text
+    Redact(String),
+}
+
+/// Screens text going into and coming out of the model
+#[async_trait]
+pub trait Moderator: Send + Sync {
+    /// Check a user message before the agent runs
+    async fn check_input(&self, text: &str) -> ModerationDecision;
+
+    /// Check the final assistant text before it is stored
+    async fn check_output(&self, text: &str) -> ModerationDecision;
+}
+
+/// Moderator that allows everything
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopModerator;
+
+#[async_trait]
+impl Moderator for NoopModerator {
+    async fn check_input(&self, _text: &str) -> ModerationDecision {
+        ModerationDecision::Allow
+    }
+
+    async fn check_output(&self, _text: &str) -> ModerationDecision {
+        ModerationDecision::Allow
+    }
+}
+
+/// Case-insensitive keyword list moderator
+///
+/// Input containing any keyword is blocked; output has every occurrence
+/// replaced with the replacement text (`[redacted]` by default).
+///
+/// # Example
+///
+/// ```
+/// use rust2::llm::moderation::{KeywordModerator, ModerationDecision, Moderator};
+///
+/// # tokio_test::block_on(async {
+/// let moderator = KeywordModerator::new(["secret"]);
+///
+/// assert!(matches!(
+///     moderator.check_input("tell me the SECRET").await,
+///     ModerationDecision::Block(_)
+/// ));
+/// assert_eq!(
+///     moderator.check_output("The secret is 42").await,
+///     ModerationDecision::Redact("The [redacted] is 42".to_string())
+/// );
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct KeywordModerator {
+    keywords: Vec<String>,
+    replacement: String,
+}
+
+impl KeywordModerator {
+    /// Create a moderator for the given keywords
+    pub fn new<I, S>(keywords: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            keywords: keywords
+                .into_iter()
+                .map(|k| k.into().to_lowercase())
+                .filter(|k| !k.is_empty())
+                .collect(),
+            replacement: "[redacted]".to_string(),
+        }
+    }
+
+    /// Set the text that replaces keywords in output
+    pub fn with_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.replacement = replacement.into();
+        self
+    }
+
+    /// Byte ranges of every keyword occurrence in `text`, merged and in order
+    ///
+    /// Returns `None` when lowercasing changes byte offsets (some non-ASCII
+    /// text), since the ranges can't then be mapped back onto `text`.
+    fn matches(&self, text: &str) -> Option<Vec<(usize, usize)>> {
+        let lower = text.to_lowercase();
+        if lower.len() != text.len() {
+            return None;
+        }
+
+        let mut ranges: Vec<(usize, usize)> = self
+            .keywords
+            .iter()
+            .flat_map(|k| lower.match_indices(k.as_str()).map(|(i, m)| (i, i + m.len())))
+            .collect();
+        ranges.sort();
+
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        Some(merged)
+    }
+
+    fn contains_keyword(&self, text: &str) -> Option<&str> {
+        let lower = text.to_lowercase();
+        self.keywords
+            .iter()
+            .find(|k| lower.contains(k.as_str()))
+            .map(String::as_str)
+    }
+}
+
+#[async_trait]
+impl Moderator for KeywordModerator {
+    async fn check_input(&self, text: &str) -> ModerationDecision {
+        match self.contains_keyword(text) {
+            Some(keyword) => ModerationDecision::Block(format!(
+                "Message contains blocked term '{}'",
+                keyword
+            )),
+            None => ModerationDecision::Allow,
+        }
+    }
+
+    async fn check_output(&self, text: &str) -> ModerationDecision {
+        let Some(ranges) = self.matches(text) else {
+            // Can't redact in place, so err on the side of replacing everything
+            return match self.contains_keyword(text) {
+                Some(_) => ModerationDecision::Redact(self.replacement.clone()),
+                None => ModerationDecision::Allow,
+            };
+        };
+        if ranges.is_empty() {
+            return ModerationDecision::Allow;
+        }
+
+        let mut redacted = String::with_capacity(text.len());
+        let mut cursor = 0;
+        for (start, end) in ranges {
+            redacted.push_str(&text[cursor..start]);
+            redacted.push_str(&self.replacement);
+            cursor = end;
+        }
+        redacted.push_str(&text[cursor..]);
+        ModerationDecision::Redact(redacted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_noop_allows_everything() {
+        assert_eq!(NoopModerator.check_input("anything").await, ModerationDecision::Allow);
+        assert_eq!(NoopModerator.check_output("anything").await, ModerationDecision::Allow);
+    }
+
+    #[tokio::test]
+    async fn test_keyword_input_blocked_case_insensitively() {
+        let moderator = KeywordModerator::new(["badword", "worse"]);
+
+        assert_eq!(
+            moderator.check_input("This has a BadWord in it").await,
+            ModerationDecision::Block("Message contains blocked term 'badword'".to_string())
+        );
+        assert_eq!(moderator.check_input("Perfectly fine").await, ModerationDecision::Allow);
+    }
+
+    #[tokio::test]
+    async fn test_keyword_output_redacts_every_occurrence() {
+        let moderator = KeywordModerator::new(["card", "card number"]).with_replacement("***");
+
+        assert_eq!(
+            moderator.check_output("Your Card Number is on the card.").await,
+            ModerationDecision::Redact("Your *** is on the ***.".to_string())
+        );
+        assert_eq!(moderator.check_output("Nothing to see").await, ModerationDecision::Allow);
+    }
+}
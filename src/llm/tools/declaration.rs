@@ -1,13 +1,22 @@
 //! Tool declaration helpers using JSON Schema generation
 
-use schemars::{schema_for, JsonSchema};
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde_json::{Map, Value};
 
 use crate::llm::core::types::ToolDeclaration;
 
+/// How many levels of a recursive type are spelled out before giving up
+const MAX_REF_DEPTH: usize = 8;
+
 /// Create a tool declaration from a type that implements JsonSchema
 ///
 /// This is a helper function to automatically generate the input schema
-/// from a Rust type using the schemars crate.
+/// from a Rust type using the schemars crate. Doc comments become
+/// `description`s, and `#[schemars(...)]` attributes such as `range` add
+/// constraints. Claude and Gemini both reject `$ref`, so nested types are
+/// inlined and the schema has no `definitions`. Argument types without
+/// fields, such as unit structs, get an empty object schema.
 ///
 /// # Example
 ///
@@ -34,12 +43,70 @@ pub fn create_tool_declaration<T: JsonSchema>(
     name: impl Into<String>,
     description: impl Into<String>,
 ) -> ToolDeclaration {
-    let schema = schema_for!(T);
+    let schema = SchemaSettings::draft07()
+        .with(|settings| settings.inline_subschemas = true)
+        .into_generator()
+        .into_root_schema_for::<T>();
+    let mut input_schema = serde_json::to_value(&schema)
+        .expect("Failed to serialize schema - this is a bug in schemars or the JsonSchema impl");
+
+    // Recursive types still come out as references
+    if let Some(Value::Object(definitions)) = input_schema
+        .as_object_mut()
+        .and_then(|root| root.remove("definitions"))
+    {
+        inline_refs(&mut input_schema, &definitions, 0);
+    }
+
+    // Unit structs come out as `null` and empty structs without
+    // `properties`, but providers expect every input to be an object
+    if let Some(root) = input_schema.as_object_mut() {
+        if root.get("type") == Some(&Value::from("null")) {
+            root.insert("type".to_string(), Value::from("object"));
+        }
+        if root.get("type") == Some(&Value::from("object")) {
+            root.entry("properties")
+                .or_insert_with(|| Value::Object(Map::new()));
+        }
+    }
+
     ToolDeclaration {
         name: name.into(),
         description: description.into(),
-        input_schema: serde_json::to_value(&schema)
-            .expect("Failed to serialize schema - this is a bug in schemars or the JsonSchema impl"),
+        input_schema,
+    }
+}
+
+/// Replace every `{"$ref": "#/definitions/..."}` in `schema` with the definition
+///
+/// Past `MAX_REF_DEPTH` nested references, or for unknown ones, the
+/// reference is replaced with an unconstrained schema.
+fn inline_refs(schema: &mut Value, definitions: &Map<String, Value>, depth: usize) {
+    match schema {
+        Value::Object(object) => {
+            if let Some(Value::String(reference)) = object.remove("$ref") {
+                let definition = reference
+                    .strip_prefix("#/definitions/")
+                    .and_then(|name| definitions.get(name))
+                    .filter(|_| depth < MAX_REF_DEPTH);
+                if let Some(Value::Object(definition)) = definition {
+                    for (key, value) in definition {
+                        object.entry(key.clone()).or_insert_with(|| value.clone());
+                    }
+                    inline_refs(schema, definitions, depth + 1);
+                    return;
+                }
+            }
+            for value in object.values_mut() {
+                inline_refs(value, definitions, depth);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                inline_refs(item, definitions, depth);
+            }
+        }
+        _ => {}
     }
 }
 
@@ -48,6 +115,7 @@ mod tests {
     use super::*;
     use serde::Deserialize;
 
+    #[allow(dead_code)]
     #[derive(Deserialize, JsonSchema)]
     struct TestArgs {
         /// A string field
@@ -84,4 +152,99 @@ mod tests {
         assert!(schema_str.contains("A string field"));
         assert!(schema_str.contains("A number field"));
     }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, JsonSchema)]
+    #[serde(rename_all = "lowercase")]
+    enum Unit {
+        Celsius,
+        Fahrenheit,
+    }
+
+    /// Look up the forecast
+    #[allow(dead_code)]
+    #[derive(Deserialize, JsonSchema)]
+    struct ForecastArgs {
+        /// City name
+        city: String,
+        /// Temperature unit
+        unit: Unit,
+        /// Days ahead to forecast
+        #[schemars(range(min = 1, max = 14))]
+        days: Option<u8>,
+    }
+
+    #[test]
+    fn test_schema_is_inlined_with_constraints() {
+        let decl = create_tool_declaration::<ForecastArgs>("forecast", "Weather forecast");
+
+        assert_eq!(
+            decl.input_schema,
+            serde_json::json!({
+                "$schema": "http://json-schema.org/draft-07/schema#",
+                "title": "ForecastArgs",
+                "description": "Look up the forecast",
+                "type": "object",
+                "required": ["city", "unit"],
+                "properties": {
+                    "city": {
+                        "description": "City name",
+                        "type": "string"
+                    },
+                    "unit": {
+                        "description": "Temperature unit",
+                        "type": "string",
+                        "enum": ["celsius", "fahrenheit"]
+                    },
+                    "days": {
+                        "description": "Days ahead to forecast",
+                        "type": ["integer", "null"],
+                        "format": "uint8",
+                        "minimum": 1.0,
+                        "maximum": 14.0
+                    }
+                }
+            })
+        );
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, JsonSchema)]
+    struct Folder {
+        name: String,
+        children: Vec<Folder>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, JsonSchema)]
+    struct UnitArgs;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, JsonSchema)]
+    struct EmptyArgs {}
+
+    #[test]
+    fn test_argless_tools_get_an_empty_object_schema() {
+        for input_schema in [
+            create_tool_declaration::<UnitArgs>("ping", "Ping").input_schema,
+            create_tool_declaration::<EmptyArgs>("ping", "Ping").input_schema,
+            create_tool_declaration::<()>("ping", "Ping").input_schema,
+        ] {
+            assert_eq!(input_schema["type"], "object");
+            assert_eq!(input_schema["properties"], serde_json::json!({}));
+        }
+    }
+
+    #[test]
+    fn test_recursive_schema_has_no_refs() {
+        let decl = create_tool_declaration::<Folder>("tree", "Folder tree");
+        let schema_str = decl.input_schema.to_string();
+
+        assert!(!schema_str.contains("$ref"));
+        assert!(!schema_str.contains("definitions"));
+        assert_eq!(
+            decl.input_schema["properties"]["children"]["items"]["properties"]["name"]["type"],
+            "string"
+        );
+    }
 }
@@ -1,6 +1,7 @@
 //! Tool executor trait and implementations
 
 use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
 
 /// Trait for executing tool calls from the LLM
 ///
@@ -27,4 +28,140 @@ pub trait ToolExecutor: Send + Sync {
         name: String,
         arguments: serde_json::Value,
     ) -> Result<String, String>;
+
+    /// Whether a successful call to `name` changes something outside the conversation
+    ///
+    /// The agent records these calls during a run and compensates them if
+    /// the run fails. Defaults to `false`.
+    fn has_side_effects(&self, _name: &str) -> bool {
+        false
+    }
+
+    /// Undo the side effect of a successful call to `name`
+    ///
+    /// Called by the agent, newest call first, when a run fails after
+    /// side-effecting tools ran. Returns `None` if the tool has no
+    /// compensation (the default).
+    async fn compensate(
+        &self,
+        _name: String,
+        _input: serde_json::Value,
+        _result: String,
+    ) -> Option<Result<(), String>> {
+        None
+    }
+
+    /// Execute several tool calls from one dispatch
+    ///
+    /// Each call is `(tool_use_id, name, arguments)`. Results are returned in
+    /// the same order as `calls`, whatever order they finish in, so they can
+    /// be zipped back to their `ToolUse` blocks by index.
+    ///
+    /// The default runs every call through [`ToolExecutor::execute`]
+    /// concurrently. Override it to coalesce calls, e.g. to answer several
+    /// lookups with one database query.
+    async fn execute_batch(
+        &self,
+        calls: Vec<(String, String, serde_json::Value)>,
+    ) -> Vec<Result<String, String>> {
+        let mut results: Vec<Option<Result<String, String>>> = vec![None; calls.len()];
+
+        let mut pending: FuturesUnordered<_> = calls
+            .into_iter()
+            .enumerate()
+            .map(|(i, (tool_use_id, name, arguments))| async move {
+                (i, self.execute(tool_use_id, name, arguments).await)
+            })
+            .collect();
+
+        while let Some((i, result)) = pending.next().await {
+            results[i] = Some(result);
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.expect("every call produces a result"))
+            .collect()
+    }
+}
+
+/// A shared executor, e.g. one registry handed to the `Agent` of every request
+#[async_trait]
+impl<T: ToolExecutor + ?Sized> ToolExecutor for std::sync::Arc<T> {
+    async fn execute(
+        &self,
+        tool_use_id: String,
+        name: String,
+        arguments: serde_json::Value,
+    ) -> Result<String, String> {
+        (**self).execute(tool_use_id, name, arguments).await
+    }
+
+    fn has_side_effects(&self, name: &str) -> bool {
+        (**self).has_side_effects(name)
+    }
+
+    async fn compensate(
+        &self,
+        name: String,
+        input: serde_json::Value,
+        result: String,
+    ) -> Option<Result<(), String>> {
+        (**self).compensate(name, input, result).await
+    }
+
+    async fn execute_batch(
+        &self,
+        calls: Vec<(String, String, serde_json::Value)>,
+    ) -> Vec<Result<String, String>> {
+        (**self).execute_batch(calls).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Sleeps for the number of milliseconds in `arguments["ms"]`, then echoes the id
+    struct SleepyExecutor;
+
+    #[async_trait]
+    impl ToolExecutor for SleepyExecutor {
+        async fn execute(
+            &self,
+            tool_use_id: String,
+            name: String,
+            arguments: serde_json::Value,
+        ) -> Result<String, String> {
+            let ms = arguments["ms"].as_u64().unwrap_or(0);
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            match name.as_str() {
+                "fail" => Err(format!("{} failed", tool_use_id)),
+                _ => Ok(tool_use_id),
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_execute_batch_keeps_input_order_and_runs_concurrently() {
+        let calls = vec![
+            ("a".to_string(), "echo".to_string(), serde_json::json!({"ms": 300})),
+            ("b".to_string(), "fail".to_string(), serde_json::json!({"ms": 100})),
+            ("c".to_string(), "echo".to_string(), serde_json::json!({"ms": 200})),
+        ];
+
+        let start = tokio::time::Instant::now();
+        let results = SleepyExecutor.execute_batch(calls).await;
+
+        assert_eq!(
+            results,
+            vec![
+                Ok("a".to_string()),
+                Err("b failed".to_string()),
+                Ok("c".to_string())
+            ]
+        );
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+    }
 }
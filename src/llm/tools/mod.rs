@@ -7,11 +7,39 @@
 pub mod declaration;
 pub mod executor;
 pub mod registry;
+pub mod remote;
+pub mod snapshot;
 
 // Re-export commonly used types
 pub use declaration::create_tool_declaration;
 pub use executor::ToolExecutor;
 pub use registry::{FunctionRegistry, RegistryError, ToolRegistration};
+pub use remote::RemoteExecutor;
+pub use snapshot::{check_snapshot, DriftPolicy, RegistryDiff, RegistrySnapshot, SnapshotError};
+
+/// Dependencies used by code generated by `#[derive(ToolInput)]` and `#[tool]`
+#[doc(hidden)]
+pub mod __private {
+    pub use schemars;
+    pub use serde;
+
+    /// Deserialize a tool call's arguments
+    ///
+    /// Argument types without fields are declared as an empty object, so
+    /// `{}` is also accepted where the type expects unit (e.g. a unit struct).
+    /// Other errors, such as a missing field, are returned as they are.
+    pub fn deserialize_args<T: serde::de::DeserializeOwned>(
+        args: serde_json::Value,
+    ) -> Result<T, serde_json::Error> {
+        let empty_object = args.as_object().is_some_and(|args| args.is_empty());
+        match serde_json::from_value(args) {
+            Err(e) if empty_object && e.to_string().starts_with("invalid type") => {
+                serde_json::from_value(serde_json::Value::Null).map_err(|_| e)
+            }
+            result => result,
+        }
+    }
+}
 
 /// Helper macro to register multiple tools at once
 ///
@@ -44,7 +72,7 @@ macro_rules! register_tools {
         $(
             {
                 use $tool_mod as tool;
-                $registry.register_async_tool(tool::NAME, tool::execute, tool::declaration())?;
+                $registry.register_async_tool(tool::execute, tool::declaration())?;
             }
         )+
     };
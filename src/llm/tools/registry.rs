@@ -2,13 +2,17 @@
 
 use std::collections::HashMap;
 use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
-use futures::future::BoxFuture;
+use futures::future::{join_all, BoxFuture};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 use super::executor::ToolExecutor;
+use super::remote::RemoteExecutor;
+use super::snapshot::{declaration_hash, RegistrySnapshot, ToolSnapshot};
 use crate::llm::ToolDeclaration;
 
 /// Errors that can occur during tool registration
@@ -22,6 +26,9 @@ pub enum RegistryError {
 
     #[error("Tool '{name}' is already registered")]
     DuplicateTool { name: String },
+
+    #[error("Tool '{name}' is not registered")]
+    UnknownTool { name: String },
 }
 
 /// Type alias for boxed async functions
@@ -29,10 +36,45 @@ type AsyncToolFn = Box<
     dyn Fn(serde_json::Value) -> BoxFuture<'static, Result<String, String>> + Send + Sync,
 >;
 
+/// Type alias for boxed compensation functions, called with the tool's input and result
+type CompensationFn = Box<
+    dyn Fn(serde_json::Value, String) -> BoxFuture<'static, Result<(), String>> + Send + Sync,
+>;
+
+/// How a registered tool is executed (internal)
+enum ToolFunction {
+    /// In-process Rust function
+    Local(AsyncToolFn),
+    /// Forwarded to a remote worker over HTTP
+    Remote(RemoteExecutor),
+}
+
 /// Entry holding both function and its declaration (internal)
 struct ToolEntry {
-    function: AsyncToolFn,
+    function: ToolFunction,
     declaration: ToolDeclaration,
+    tags: Vec<String>,
+    enabled: bool,
+    /// Overrides the registry's default timeout
+    timeout: Option<Duration>,
+    /// Whether a successful call changes something outside the conversation
+    side_effects: bool,
+    /// Undoes a successful call when the run later fails
+    compensation: Option<CompensationFn>,
+}
+
+impl ToolEntry {
+    fn new(function: ToolFunction, declaration: ToolDeclaration) -> Self {
+        Self {
+            function,
+            declaration,
+            tags: Vec::new(),
+            enabled: true,
+            timeout: None,
+            side_effects: false,
+            compensation: None,
+        }
+    }
 }
 
 /// Public struct for registering tools (generated by #[tool] macro)
@@ -86,6 +128,7 @@ pub struct ToolRegistration {
 /// ```
 pub struct FunctionRegistry {
     tools: HashMap<String, ToolEntry>,
+    default_timeout: Option<Duration>,
 }
 
 impl FunctionRegistry {
@@ -93,9 +136,27 @@ impl FunctionRegistry {
     pub fn new() -> Self {
         Self {
             tools: HashMap::new(),
+            default_timeout: None,
         }
     }
 
+    /// Time limit for tools registered without their own timeout (default: none)
+    ///
+    /// A tool that runs past its limit fails with
+    /// `"Tool '<name>' timed out after <duration>"`.
+    pub fn with_default_timeout(mut self, timeout: Duration) -> Self {
+        self.default_timeout = Some(timeout);
+        self
+    }
+
+    /// Set or clear the default time limit on an existing registry
+    ///
+    /// See [`FunctionRegistry::with_default_timeout`]. Applies to calls made
+    /// after this returns.
+    pub fn set_default_timeout(&mut self, timeout: Option<Duration>) {
+        self.default_timeout = timeout;
+    }
+
     /// Register an async tool function with its declaration
     ///
     /// # Type Parameters
@@ -162,15 +223,46 @@ impl FunctionRegistry {
         let name = declaration.name.clone();
         self.tools.insert(
             name,
-            ToolEntry {
-                function: Box::new(wrapper),
-                declaration,
-            },
+            ToolEntry::new(ToolFunction::Local(Box::new(wrapper)), declaration),
         );
 
         Ok(())
     }
 
+    /// Register an async tool function that receives shared state on every call
+    ///
+    /// `state` is cloned into each call, so tools can share a database pool
+    /// or HTTP client without global statics.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// async fn lookup(state: Arc<AppState>, args: LookupArgs) -> Result<User, String> {
+    ///     state.users.get(&args.id).await
+    /// }
+    ///
+    /// registry.register_async_with_state(state.clone(), lookup, declaration)?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `RegistryError::DuplicateTool` if the tool is already registered
+    pub fn register_async_with_state<S, F, Args, R, Fut>(
+        &mut self,
+        state: Arc<S>,
+        func: F,
+        declaration: ToolDeclaration,
+    ) -> Result<(), RegistryError>
+    where
+        S: Send + Sync + 'static,
+        F: Fn(Arc<S>, Args) -> Fut + Send + Sync + 'static,
+        Args: DeserializeOwned + Send + 'static,
+        R: Serialize + Send + 'static,
+        Fut: Future<Output = Result<R, String>> + Send + 'static,
+    {
+        self.register_async_tool(move |args: Args| func(state.clone(), args), declaration)
+    }
+
     /// Convenience method to register a tool from a ToolRegistration struct
     /// (generated by the #[tool] macro)
     ///
@@ -206,10 +298,7 @@ impl FunctionRegistry {
         // Store atomically
         self.tools.insert(
             tool.name.to_string(),
-            ToolEntry {
-                function: tool.function,
-                declaration: tool.declaration,
-            },
+            ToolEntry::new(ToolFunction::Local(tool.function), tool.declaration),
         );
 
         Ok(())
@@ -276,18 +365,161 @@ impl FunctionRegistry {
         };
 
         // Store atomically
+        let name = declaration.name.clone();
+        self.tools.insert(
+            name,
+            ToolEntry::new(ToolFunction::Local(Box::new(wrapper)), declaration),
+        );
+
+        Ok(())
+    }
+
+    /// Register an async tool that fails if it runs longer than `timeout`
+    ///
+    /// Like [`FunctionRegistry::register_async_tool`]; the timeout takes
+    /// precedence over [`FunctionRegistry::with_default_timeout`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `RegistryError::DuplicateTool` if the tool is already registered
+    pub fn register_async_with_timeout<F, Args, R, Fut>(
+        &mut self,
+        func: F,
+        declaration: ToolDeclaration,
+        timeout: Duration,
+    ) -> Result<(), RegistryError>
+    where
+        F: Fn(Args) -> Fut + Send + Sync + 'static,
+        Args: DeserializeOwned + Send + 'static,
+        R: Serialize + Send + 'static,
+        Fut: Future<Output = Result<R, String>> + Send + 'static,
+    {
+        let name = declaration.name.clone();
+        self.register_async_tool(func, declaration)?;
+        self.entry_mut(&name)?.timeout = Some(timeout);
+        Ok(())
+    }
+
+    /// Register a sync tool that fails if it runs longer than `timeout`
+    ///
+    /// The function runs on tokio's blocking thread pool so the timeout can
+    /// fire while it is still busy. A timed-out call is abandoned rather
+    /// than stopped: the thread runs until the function returns.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RegistryError::DuplicateTool` if the tool is already registered
+    pub fn register_sync_with_timeout<F, Args, R>(
+        &mut self,
+        func: F,
+        declaration: ToolDeclaration,
+        timeout: Duration,
+    ) -> Result<(), RegistryError>
+    where
+        F: Fn(Args) -> Result<R, String> + Send + Sync + 'static,
+        Args: DeserializeOwned + Send + 'static,
+        R: Serialize + Send + 'static,
+    {
+        // Check for duplicates
+        if self.tools.contains_key(&declaration.name) {
+            return Err(RegistryError::DuplicateTool {
+                name: declaration.name.clone(),
+            });
+        }
+
+        let func = Arc::new(func);
+        let wrapper = move |args_json: serde_json::Value| {
+            let func = func.clone();
+            Box::pin(async move {
+                let args = serde_json::from_value::<Args>(args_json)
+                    .map_err(|e| format!("Failed to deserialize arguments: {}", e))?;
+
+                let result = tokio::task::spawn_blocking(move || func(args))
+                    .await
+                    .map_err(|e| format!("Tool panicked: {}", e))??;
+
+                serde_json::to_string(&result)
+                    .map_err(|e| format!("Failed to serialize result: {}", e))
+            }) as BoxFuture<'static, _>
+        };
+
         let name = declaration.name.clone();
         self.tools.insert(
             name,
             ToolEntry {
-                function: Box::new(wrapper),
-                declaration,
+                timeout: Some(timeout),
+                ..ToolEntry::new(ToolFunction::Local(Box::new(wrapper)), declaration)
             },
         );
 
         Ok(())
     }
 
+    /// Register a tool that executes in a remote worker service
+    ///
+    /// Only the declaration lives in this process; calls are forwarded to the
+    /// endpoint by a `RemoteExecutor`. Pass a URL for the defaults, or a
+    /// configured `RemoteExecutor` to set auth, timeout, and retries.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// registry.register_remote(weather_declaration, "https://tools.internal/execute")?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `RegistryError::DuplicateTool` if the tool is already registered
+    pub fn register_remote(
+        &mut self,
+        declaration: ToolDeclaration,
+        endpoint: impl Into<RemoteExecutor>,
+    ) -> Result<(), RegistryError> {
+        // Check for duplicates
+        if self.tools.contains_key(&declaration.name) {
+            return Err(RegistryError::DuplicateTool {
+                name: declaration.name.clone(),
+            });
+        }
+
+        let name = declaration.name.clone();
+        self.tools.insert(
+            name,
+            ToolEntry::new(ToolFunction::Remote(endpoint.into()), declaration),
+        );
+
+        Ok(())
+    }
+
+    /// Move every tool from `other` into this registry
+    ///
+    /// Useful for composing tool sets defined in different modules. The merge
+    /// is all-or-nothing: if any name collides, nothing is moved.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut tools = math_tools();
+    /// tools.merge(weather_tools())?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `RegistryError::DuplicateTool` for the first colliding tool name
+    pub fn merge(&mut self, other: FunctionRegistry) -> Result<(), RegistryError> {
+        if let Some(name) = other.tools.keys().find(|name| self.tools.contains_key(*name)) {
+            return Err(RegistryError::DuplicateTool { name: name.clone() });
+        }
+
+        self.tools.extend(other.tools);
+        Ok(())
+    }
+
+    /// Move every tool from `other` into this registry, replacing tools with the same name
+    pub fn merge_overwrite(&mut self, other: FunctionRegistry) {
+        self.tools.extend(other.tools);
+    }
+
     /// Get all tool declarations registered with this registry
     ///
     /// This returns a clone of all declarations that were registered.
@@ -311,10 +543,107 @@ impl FunctionRegistry {
     pub fn get_declarations(&self) -> Vec<ToolDeclaration> {
         self.tools
             .values()
+            .filter(|entry| entry.enabled)
             .map(|entry| entry.declaration.clone())
             .collect()
     }
 
+    /// Label a registered tool, replacing any existing tags
+    ///
+    /// Tags are informational; they show up in [`FunctionRegistry::snapshot`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `RegistryError::UnknownTool` if no tool has this name
+    pub fn set_tags<I, S>(&mut self, name: &str, tags: I) -> Result<(), RegistryError>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let entry = self.entry_mut(name)?;
+        entry.tags = tags.into_iter().map(Into::into).collect();
+        entry.tags.sort();
+        entry.tags.dedup();
+        Ok(())
+    }
+
+    /// Enable or disable a registered tool
+    ///
+    /// Disabled tools stay registered but are left out of
+    /// `get_declarations()` and refuse to execute.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RegistryError::UnknownTool` if no tool has this name
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<(), RegistryError> {
+        self.entry_mut(name)?.enabled = enabled;
+        Ok(())
+    }
+
+    /// Check if a tool is registered and enabled
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.tools.get(name).is_some_and(|entry| entry.enabled)
+    }
+
+    /// Mark whether a tool changes something outside the conversation
+    ///
+    /// The agent keeps track of successful calls to side-effecting tools
+    /// during a run so they can be compensated if the run fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RegistryError::UnknownTool` if no tool has this name
+    pub fn set_side_effects(&mut self, name: &str, side_effects: bool) -> Result<(), RegistryError> {
+        self.entry_mut(name)?.side_effects = side_effects;
+        Ok(())
+    }
+
+    /// Register how to undo a tool's side effect, marking it as side-effecting
+    ///
+    /// When a run fails after the tool succeeded, the agent calls
+    /// `compensate` with the call's input and result.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// registry.set_compensation("send_email", |input, result| async move {
+    ///     mailer.recall(&result).await.map_err(|e| e.to_string())
+    /// })?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `RegistryError::UnknownTool` if no tool has this name
+    pub fn set_compensation<F, Fut>(&mut self, name: &str, compensate: F) -> Result<(), RegistryError>
+    where
+        F: Fn(serde_json::Value, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let entry = self.entry_mut(name)?;
+        entry.side_effects = true;
+        entry.compensation = Some(Box::new(move |input, result| {
+            Box::pin(compensate(input, result)) as BoxFuture<'static, _>
+        }));
+        Ok(())
+    }
+
+    /// Capture the registered tools for comparison with another deployment
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let expected: RegistrySnapshot = serde_json::from_str(&fs::read_to_string("tools.json")?)?;
+    /// let diff = expected.diff(&registry.snapshot());
+    /// ```
+    pub fn snapshot(&self) -> RegistrySnapshot {
+        RegistrySnapshot::new(self.tools.values().map(|entry| ToolSnapshot {
+            name: entry.declaration.name.clone(),
+            declaration_hash: declaration_hash(&entry.declaration),
+            tags: entry.tags.clone(),
+            enabled: entry.enabled,
+        }))
+    }
+
     /// Check if a tool is registered
     pub fn contains(&self, name: &str) -> bool {
         self.tools.contains_key(name)
@@ -330,16 +659,42 @@ impl FunctionRegistry {
         self.tools.is_empty()
     }
 
+    fn entry_mut(&mut self, name: &str) -> Result<&mut ToolEntry, RegistryError> {
+        self.tools
+            .get_mut(name)
+            .ok_or_else(|| RegistryError::UnknownTool {
+                name: name.to_string(),
+            })
+    }
+
     /// Execute a registered function by name
     ///
     /// This is an internal method used by the `ToolExecutor` implementation.
     async fn execute_function(
         &self,
+        tool_use_id: &str,
         name: &str,
         arguments: serde_json::Value,
     ) -> Result<String, String> {
         match self.tools.get(name) {
-            Some(entry) => (entry.function)(arguments).await,
+            Some(entry) if !entry.enabled => Err(format!("Tool is disabled: {}", name)),
+            Some(entry) => {
+                let call = async {
+                    match &entry.function {
+                        ToolFunction::Local(function) => function(arguments).await,
+                        ToolFunction::Remote(remote) => {
+                            remote.call(tool_use_id, name, &arguments).await
+                        }
+                    }
+                };
+
+                match entry.timeout.or(self.default_timeout) {
+                    Some(limit) => tokio::time::timeout(limit, call).await.unwrap_or_else(|_| {
+                        Err(format!("Tool '{}' timed out after {:?}", name, limit))
+                    }),
+                    None => call.await,
+                }
+            }
             None => Err(format!("Unknown tool: {}", name)),
         }
     }
@@ -355,11 +710,37 @@ impl Default for FunctionRegistry {
 impl ToolExecutor for FunctionRegistry {
     async fn execute(
         &self,
-        _tool_use_id: String,
+        tool_use_id: String,
         name: String,
         arguments: serde_json::Value,
     ) -> Result<String, String> {
-        self.execute_function(&name, arguments).await
+        self.execute_function(&tool_use_id, &name, arguments).await
+    }
+
+    fn has_side_effects(&self, name: &str) -> bool {
+        self.tools.get(name).is_some_and(|entry| entry.side_effects)
+    }
+
+    async fn compensate(
+        &self,
+        name: String,
+        input: serde_json::Value,
+        result: String,
+    ) -> Option<Result<(), String>> {
+        let compensation = self.tools.get(&name)?.compensation.as_ref()?;
+        Some(compensation(input, result).await)
+    }
+
+    async fn execute_batch(
+        &self,
+        calls: Vec<(String, String, serde_json::Value)>,
+    ) -> Vec<Result<String, String>> {
+        join_all(
+            calls
+                .into_iter()
+                .map(|(tool_use_id, name, arguments)| self.execute(tool_use_id, name, arguments)),
+        )
+        .await
     }
 }
 
@@ -421,7 +802,7 @@ mod tests {
             .unwrap();
 
         let args = serde_json::json!({"a": 5, "b": 3});
-        let result = registry.execute_function("add", args).await.unwrap();
+        let result = registry.execute_function("test-id", "add", args).await.unwrap();
 
         let parsed: AddResult = serde_json::from_str(&result).unwrap();
         assert_eq!(parsed, AddResult { sum: 8 });
@@ -443,7 +824,7 @@ mod tests {
             .unwrap();
 
         let args = serde_json::json!({"a": 10, "b": 20});
-        let result = registry.execute_function("add_async", args).await.unwrap();
+        let result = registry.execute_function("test-id", "add_async", args).await.unwrap();
 
         let parsed: AddResult = serde_json::from_str(&result).unwrap();
         assert_eq!(parsed, AddResult { sum: 30 });
@@ -467,7 +848,7 @@ mod tests {
             .unwrap();
 
         let args = serde_json::json!({"a": 10, "b": 0});
-        let result = registry.execute_function("divide", args).await;
+        let result = registry.execute_function("test-id", "divide", args).await;
 
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), "Division by zero");
@@ -486,7 +867,7 @@ mod tests {
 
         // Invalid arguments (missing field)
         let args = serde_json::json!({"a": 5});
-        let result = registry.execute_function("add", args).await;
+        let result = registry.execute_function("test-id", "add", args).await;
 
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Failed to deserialize arguments"));
@@ -497,7 +878,7 @@ mod tests {
         let registry = FunctionRegistry::new();
 
         let args = serde_json::json!({"a": 5, "b": 3});
-        let result = registry.execute_function("unknown", args).await;
+        let result = registry.execute_function("test-id", "unknown", args).await;
 
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), "Unknown tool: unknown");
@@ -548,7 +929,7 @@ mod tests {
         assert!(registry.contains("multiply"));
 
         let args = serde_json::json!({"a": 3, "b": 4});
-        let result = registry.execute_function("multiply", args).await.unwrap();
+        let result = registry.execute_function("test-id", "multiply", args).await.unwrap();
 
         let parsed: AddResult = serde_json::from_str(&result).unwrap();
         assert_eq!(parsed, AddResult { sum: 12 });
@@ -595,7 +976,7 @@ mod tests {
             .unwrap();
 
         let args = serde_json::json!({});
-        let result = registry.execute_function("get_data", args).await.unwrap();
+        let result = registry.execute_function("test-id", "get_data", args).await.unwrap();
 
         // Verify it's valid JSON
         let parsed: serde_json::Value = serde_json::from_str(&result).unwrap();
@@ -734,7 +1115,7 @@ mod tests {
         // Verify it works
         assert!(registry.contains("add"));
         let args = serde_json::json!({"a": 5, "b": 3});
-        let result = registry.execute_function("add", args).await.unwrap();
+        let result = registry.execute_function("test-id", "add", args).await.unwrap();
         let parsed: AddResult = serde_json::from_str(&result).unwrap();
         assert_eq!(parsed, AddResult { sum: 8 });
     }
@@ -765,4 +1146,446 @@ mod tests {
             _ => panic!("Expected NameMismatch error"),
         }
     }
+
+    #[tokio::test]
+    async fn test_register_remote() {
+        let mut registry = FunctionRegistry::new();
+        let declaration = create_test_declaration("remote_add", "Adds remotely");
+
+        registry
+            .register_remote(declaration.clone(), "http://127.0.0.1:9/execute")
+            .unwrap();
+
+        assert!(registry.contains("remote_add"));
+        assert_eq!(registry.get_declarations()[0].name, "remote_add");
+
+        let result = registry.register_remote(declaration, "http://127.0.0.1:9/execute");
+        assert!(matches!(result, Err(RegistryError::DuplicateTool { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_remote_tool_forwards_call() {
+        use warp::Filter;
+
+        let route = warp::post()
+            .and(warp::body::json())
+            .map(|body: serde_json::Value| {
+                let sum = body["input"]["a"].as_i64().unwrap() + body["input"]["b"].as_i64().unwrap();
+                serde_json::json!({"sum": sum, "id": body["tool_use_id"]}).to_string()
+            });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(warp::serve(route).incoming(listener).run());
+
+        let mut registry = FunctionRegistry::new();
+        registry
+            .register_remote(
+                create_test_declaration("remote_add", "Adds remotely"),
+                format!("http://{}/", addr),
+            )
+            .unwrap();
+
+        let result = registry
+            .execute(
+                "call-7".to_string(),
+                "remote_add".to_string(),
+                serde_json::json!({"a": 2, "b": 3}),
+            )
+            .await
+            .unwrap();
+
+        let body: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(body["sum"], 5);
+        assert_eq!(body["id"], "call-7");
+    }
+
+    #[tokio::test]
+    async fn test_merge_registries() {
+        let mut math = FunctionRegistry::new();
+        math.register_sync_tool(
+            |args: AddArgs| Ok(AddResult { sum: args.a + args.b }),
+            create_test_declaration("add", "Adds"),
+        )
+        .unwrap();
+
+        let mut other = FunctionRegistry::new();
+        other
+            .register_sync_tool(
+                |args: AddArgs| Ok(AddResult { sum: args.a - args.b }),
+                create_test_declaration("subtract", "Subtracts"),
+            )
+            .unwrap();
+
+        math.merge(other).unwrap();
+
+        assert_eq!(math.len(), 2);
+        let result = math
+            .execute("id".to_string(), "subtract".to_string(), serde_json::json!({"a": 5, "b": 3}))
+            .await
+            .unwrap();
+        assert_eq!(result, r#"{"sum":2}"#);
+    }
+
+    #[tokio::test]
+    async fn test_merge_collision_moves_nothing() {
+        let mut first = FunctionRegistry::new();
+        first
+            .register_sync_tool(
+                |args: AddArgs| Ok(AddResult { sum: args.a + args.b }),
+                create_test_declaration("add", "Adds"),
+            )
+            .unwrap();
+
+        let mut second = FunctionRegistry::new();
+        second
+            .register_sync_tool(
+                |args: AddArgs| Ok(AddResult { sum: args.a * args.b }),
+                create_test_declaration("multiply", "Multiplies"),
+            )
+            .unwrap();
+        second
+            .register_sync_tool(
+                |_: AddArgs| Ok(AddResult { sum: 0 }),
+                create_test_declaration("add", "Broken add"),
+            )
+            .unwrap();
+
+        let err = first.merge(second).unwrap_err();
+        assert!(matches!(err, RegistryError::DuplicateTool { ref name } if name == "add"));
+        assert_eq!(first.len(), 1);
+        assert!(!first.contains("multiply"));
+    }
+
+    #[tokio::test]
+    async fn test_merge_overwrite() {
+        let mut first = FunctionRegistry::new();
+        first
+            .register_sync_tool(
+                |args: AddArgs| Ok(AddResult { sum: args.a + args.b }),
+                create_test_declaration("add", "Adds"),
+            )
+            .unwrap();
+
+        let mut second = FunctionRegistry::new();
+        second
+            .register_sync_tool(
+                |args: AddArgs| Ok(AddResult { sum: args.a + args.b + 100 }),
+                create_test_declaration("add", "Adds with bonus"),
+            )
+            .unwrap();
+
+        first.merge_overwrite(second);
+
+        assert_eq!(first.len(), 1);
+        assert_eq!(first.get_declarations()[0].description, "Adds with bonus");
+        let result = first
+            .execute("id".to_string(), "add".to_string(), serde_json::json!({"a": 1, "b": 2}))
+            .await
+            .unwrap();
+        assert_eq!(result, r#"{"sum":103}"#);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_tools_are_hidden_and_refuse_to_run() {
+        let mut registry = FunctionRegistry::new();
+        registry
+            .register_sync_tool(
+                |args: AddArgs| Ok(AddResult { sum: args.a + args.b }),
+                create_test_declaration("add", "Adds"),
+            )
+            .unwrap();
+
+        registry.set_enabled("add", false).unwrap();
+        assert!(registry.contains("add"));
+        assert!(!registry.is_enabled("add"));
+        assert!(registry.get_declarations().is_empty());
+        let result = registry
+            .execute_function("id", "add", serde_json::json!({"a": 1, "b": 2}))
+            .await;
+        assert_eq!(result.unwrap_err(), "Tool is disabled: add");
+
+        registry.set_enabled("add", true).unwrap();
+        assert_eq!(registry.get_declarations().len(), 1);
+
+        let err = registry.set_enabled("missing", true).unwrap_err();
+        assert!(matches!(err, RegistryError::UnknownTool { .. }));
+    }
+
+    // Hand-written equivalents of what `#[tool]` generates
+    mod add_tool {
+        use super::*;
+
+        pub const NAME: &str = "add";
+
+        pub async fn execute(args: AddArgs) -> Result<AddResult, String> {
+            Ok(AddResult { sum: args.a + args.b })
+        }
+
+        pub fn declaration() -> ToolDeclaration {
+            create_test_declaration(NAME, "Add two numbers")
+        }
+    }
+
+    mod sub_tool {
+        use super::*;
+
+        pub const NAME: &str = "sub";
+
+        pub async fn execute(args: AddArgs) -> Result<AddResult, String> {
+            Ok(AddResult { sum: args.a - args.b })
+        }
+
+        pub fn declaration() -> ToolDeclaration {
+            create_test_declaration(NAME, "Subtract two numbers")
+        }
+    }
+
+    fn register_both(registry: &mut FunctionRegistry) -> Result<(), RegistryError> {
+        crate::register_tools!(registry, add_tool, sub_tool);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_register_tools_macro_stores_declarations() {
+        let mut registry = FunctionRegistry::new();
+        register_both(&mut registry).unwrap();
+
+        let mut names: Vec<String> = registry
+            .get_declarations()
+            .into_iter()
+            .map(|declaration| declaration.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec![add_tool::NAME, sub_tool::NAME]);
+
+        let result = registry
+            .execute_function("id", "sub", serde_json::json!({"a": 5, "b": 3}))
+            .await
+            .unwrap();
+        assert_eq!(result, r#"{"sum":2}"#);
+
+        let err = register_both(&mut registry).unwrap_err();
+        assert!(matches!(err, RegistryError::DuplicateTool { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_register_async_with_state_shares_state() {
+        use std::sync::atomic::{AtomicI32, Ordering};
+
+        async fn add_to_total(total: Arc<AtomicI32>, args: AddArgs) -> Result<AddResult, String> {
+            let sum = total.fetch_add(args.a + args.b, Ordering::SeqCst) + args.a + args.b;
+            Ok(AddResult { sum })
+        }
+
+        let total = Arc::new(AtomicI32::new(0));
+        let mut registry = FunctionRegistry::new();
+        registry
+            .register_async_with_state(
+                total.clone(),
+                add_to_total,
+                create_test_declaration("add", "Add to a running total"),
+            )
+            .unwrap();
+
+        for expected in [3, 6] {
+            let result = registry
+                .execute_function("id", "add", serde_json::json!({"a": 1, "b": 2}))
+                .await
+                .unwrap();
+            assert_eq!(result, format!("{{\"sum\":{}}}", expected));
+        }
+        assert_eq!(total.load(Ordering::SeqCst), 6);
+    }
+
+    async fn slow_add(args: AddArgs) -> Result<AddResult, String> {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        Ok(AddResult { sum: args.a + args.b })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tool_timeout() {
+        let mut registry = FunctionRegistry::new();
+        registry
+            .register_async_with_timeout(
+                slow_add,
+                create_test_declaration("fetch_url", "Slow"),
+                Duration::from_millis(50),
+            )
+            .unwrap();
+        registry
+            .register_async_with_timeout(
+                slow_add,
+                create_test_declaration("patient", "Slow but allowed"),
+                Duration::from_secs(1),
+            )
+            .unwrap();
+
+        let args = serde_json::json!({"a": 1, "b": 2});
+        let result = registry.execute_function("id", "fetch_url", args.clone()).await;
+        assert_eq!(result.unwrap_err(), "Tool 'fetch_url' timed out after 50ms");
+
+        let result = registry.execute_function("id", "patient", args).await;
+        assert_eq!(result.unwrap(), r#"{"sum":3}"#);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_default_timeout_applies_without_explicit_timeout() {
+        let mut registry = FunctionRegistry::new().with_default_timeout(Duration::from_millis(100));
+        registry
+            .register_async_tool(slow_add, create_test_declaration("slow", "Slow"))
+            .unwrap();
+        registry
+            .register_async_with_timeout(
+                slow_add,
+                create_test_declaration("exempt", "Slow with its own limit"),
+                Duration::from_secs(5),
+            )
+            .unwrap();
+
+        let args = serde_json::json!({"a": 1, "b": 2});
+        let result = registry.execute_function("id", "slow", args.clone()).await;
+        assert_eq!(result.unwrap_err(), "Tool 'slow' timed out after 100ms");
+        assert!(registry.execute_function("id", "exempt", args).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_tool_is_dropped_promptly() {
+        static DROPPED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+        struct DropFlag;
+        impl Drop for DropFlag {
+            fn drop(&mut self) {
+                DROPPED.store(true, std::sync::atomic::Ordering::SeqCst);
+            }
+        }
+
+        async fn hang(args: AddArgs) -> Result<AddResult, String> {
+            let _flag = DropFlag;
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok(AddResult { sum: args.a + args.b })
+        }
+
+        let mut registry = FunctionRegistry::new();
+        registry
+            .register_async_tool(hang, create_test_declaration("hang", "Never finishes"))
+            .unwrap();
+        registry.set_default_timeout(Some(Duration::from_millis(50)));
+
+        let start = std::time::Instant::now();
+        let result = registry
+            .execute_function("id", "hang", serde_json::json!({"a": 1, "b": 2}))
+            .await;
+
+        assert_eq!(result.unwrap_err(), "Tool 'hang' timed out after 50ms");
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(DROPPED.load(std::sync::atomic::Ordering::SeqCst));
+
+        registry.set_default_timeout(None);
+        assert!(registry.default_timeout.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sync_tool_timeout() {
+        let mut registry = FunctionRegistry::new();
+        registry
+            .register_sync_with_timeout(
+                |args: AddArgs| {
+                    std::thread::sleep(Duration::from_millis(200));
+                    Ok(AddResult { sum: args.a + args.b })
+                },
+                create_test_declaration("blocking", "Blocks"),
+                Duration::from_millis(50),
+            )
+            .unwrap();
+        registry
+            .register_sync_with_timeout(
+                |args: AddArgs| Ok(AddResult { sum: args.a + args.b }),
+                create_test_declaration("quick", "Returns at once"),
+                Duration::from_millis(50),
+            )
+            .unwrap();
+
+        let args = serde_json::json!({"a": 1, "b": 2});
+        let result = registry.execute_function("id", "blocking", args.clone()).await;
+        assert_eq!(result.unwrap_err(), "Tool 'blocking' timed out after 50ms");
+        assert_eq!(
+            registry.execute_function("id", "quick", args).await.unwrap(),
+            r#"{"sum":3}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compensation_marks_tool_and_receives_call() {
+        let mut registry = FunctionRegistry::new();
+        register_both(&mut registry).unwrap();
+        assert!(!registry.has_side_effects("add"));
+
+        let undone = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = undone.clone();
+        registry
+            .set_compensation("add", move |input, result| {
+                let log = log.clone();
+                async move {
+                    log.lock().unwrap().push((input, result));
+                    Ok(())
+                }
+            })
+            .unwrap();
+        registry.set_side_effects("sub", true).unwrap();
+
+        assert!(registry.has_side_effects("add"));
+        assert!(registry.has_side_effects("sub"));
+        assert!(!registry.has_side_effects("missing"));
+
+        let input = serde_json::json!({"a": 1, "b": 2});
+        let outcome = registry
+            .compensate("add".to_string(), input.clone(), r#"{"sum":3}"#.to_string())
+            .await;
+        assert_eq!(outcome, Some(Ok(())));
+        assert_eq!(
+            *undone.lock().unwrap(),
+            vec![(input.clone(), r#"{"sum":3}"#.to_string())]
+        );
+
+        // Side-effecting but nothing registered to undo it
+        let outcome = registry
+            .compensate("sub".to_string(), input, String::new())
+            .await;
+        assert_eq!(outcome, None);
+
+        assert!(matches!(
+            registry.set_side_effects("missing", true),
+            Err(RegistryError::UnknownTool { .. })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_execute_batch_returns_results_in_call_order() {
+        let mut registry = FunctionRegistry::new();
+        registry
+            .register_async_tool(slow_add, create_test_declaration("slow", "Slow"))
+            .unwrap();
+        registry
+            .register_sync_tool(
+                |args: AddArgs| Ok(AddResult { sum: args.a + args.b }),
+                create_test_declaration("fast", "Fast"),
+            )
+            .unwrap();
+
+        let calls = vec![
+            ("1".to_string(), "slow".to_string(), serde_json::json!({"a": 1, "b": 1})),
+            ("2".to_string(), "fast".to_string(), serde_json::json!({"a": 2, "b": 2})),
+            ("3".to_string(), "missing".to_string(), serde_json::json!({})),
+        ];
+        let results = registry.execute_batch(calls).await;
+
+        assert_eq!(
+            results,
+            vec![
+                Ok(r#"{"sum":2}"#.to_string()),
+                Ok(r#"{"sum":4}"#.to_string()),
+                Err("Unknown tool: missing".to_string()),
+            ]
+        );
+    }
 }
@@ -0,0 +1,312 @@
+//! Remote tool execution over HTTP
+//!
+//! Some deployments run tools in a separate worker service. The agent process
+//! only needs the declarations and a way to forward calls, which is what
+//! `RemoteExecutor` provides.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Serialize;
+
+use super::executor::ToolExecutor;
+use crate::llm::core::config::RetryConfig;
+
+/// Request body sent to the remote tool endpoint
+#[derive(Debug, Serialize)]
+struct RemoteToolCall<'a> {
+    tool_use_id: &'a str,
+    name: &'a str,
+    input: &'a serde_json::Value,
+}
+
+/// Tool executor that forwards calls to an HTTP endpoint
+///
+/// Each call is POSTed as `{"tool_use_id", "name", "input"}` and the response
+/// body is returned as the tool result. Non-2xx responses become tool errors
+/// that include the status code; responses with a `retry_on` status (by
+/// default timeouts, rate limits and transient 5xx) are first retried with
+/// exponential backoff.
+///
+/// # Example
+///
+/// ```ignore
+/// let executor = RemoteExecutor::new("https://tools.internal/execute")
+///     .with_auth_header("Bearer secret")
+///     .with_timeout(Duration::from_secs(10))
+///     .with_max_retries(2);
+///
+/// registry.register_remote(declaration, executor)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RemoteExecutor {
+    client: Client,
+    endpoint: String,
+    auth_header: Option<String>,
+    timeout: Duration,
+    retry: RetryConfig,
+}
+
+impl RemoteExecutor {
+    /// Create an executor for the given endpoint URL
+    ///
+    /// Defaults to a 30 second timeout and 2 retries, otherwise with
+    /// `RetryConfig::default()`.
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self {
+            client: Client::new(),
+            endpoint: endpoint.into(),
+            auth_header: None,
+            timeout: Duration::from_secs(30),
+            retry: RetryConfig {
+                max_attempts: 3,
+                ..RetryConfig::default()
+            },
+        }
+    }
+
+    /// Set the value sent in the `Authorization` header (builder pattern)
+    pub fn with_auth_header(mut self, value: impl Into<String>) -> Self {
+        self.auth_header = Some(value.into());
+        self
+    }
+
+    /// Set the per-attempt request timeout (builder pattern)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set which responses are retried and how long to wait between attempts (builder pattern)
+    pub fn with_retry(mut self, config: RetryConfig) -> Self {
+        self.retry = config;
+        self
+    }
+
+    /// Set how many times a retryable response is retried (builder pattern)
+    ///
+    /// Shorthand for setting [`RetryConfig::max_attempts`] to `max_retries + 1`.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.retry.max_attempts = max_retries.saturating_add(1);
+        self
+    }
+
+    /// Get the endpoint URL
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Forward a tool call to the remote endpoint
+    pub(crate) async fn call(
+        &self,
+        tool_use_id: &str,
+        name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<String, String> {
+        let body = RemoteToolCall {
+            tool_use_id,
+            name,
+            input: arguments,
+        };
+
+        let mut attempt = 0;
+        loop {
+            let mut request = self
+                .client
+                .post(&self.endpoint)
+                .timeout(self.timeout)
+                .json(&body);
+            if let Some(auth) = &self.auth_header {
+                request = request.header("Authorization", auth);
+            }
+
+            let response = request
+                .send()
+                .await
+                .map_err(|e| format!("Remote tool '{}' request failed: {}", name, e))?;
+
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+
+            if status.is_success() {
+                return Ok(text);
+            }
+
+            if self.retry.retry_on.contains(&status) && attempt + 1 < self.retry.max_attempts {
+                let delay = self.retry.delay(attempt, &mut rand::thread_rng());
+                tracing::warn!(
+                    tool = name,
+                    status = status.as_u16(),
+                    attempt = attempt + 1,
+                    delay_ms = delay.as_millis() as u64,
+                    "retrying remote tool call"
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+
+            return Err(format!(
+                "Remote tool '{}' failed with status {}: {}",
+                name,
+                status.as_u16(),
+                text
+            ));
+        }
+    }
+}
+
+impl From<&str> for RemoteExecutor {
+    fn from(endpoint: &str) -> Self {
+        Self::new(endpoint)
+    }
+}
+
+impl From<String> for RemoteExecutor {
+    fn from(endpoint: String) -> Self {
+        Self::new(endpoint)
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for RemoteExecutor {
+    async fn execute(
+        &self,
+        tool_use_id: String,
+        name: String,
+        arguments: serde_json::Value,
+    ) -> Result<String, String> {
+        self.call(&tool_use_id, &name, &arguments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    /// Spawn a warp server that routes by the requested tool name
+    async fn spawn_tool_server(server_error_calls: Arc<AtomicUsize>) -> SocketAddr {
+        let route = warp::post()
+            .and(warp::path("execute"))
+            .and(warp::header::optional::<String>("authorization"))
+            .and(warp::body::json())
+            .map(move |auth: Option<String>, body: serde_json::Value| {
+                let name = body["name"].as_str().unwrap_or_default().to_string();
+                match name.as_str() {
+                    "echo" => warp::reply::with_status(
+                        serde_json::json!({
+                            "tool_use_id": body["tool_use_id"],
+                            "input": body["input"],
+                            "auth": auth,
+                        })
+                        .to_string(),
+                        StatusCode::OK,
+                    ),
+                    "bad_input" => warp::reply::with_status(
+                        "missing field 'location'".to_string(),
+                        StatusCode::BAD_REQUEST,
+                    ),
+                    _ => {
+                        server_error_calls.fetch_add(1, Ordering::SeqCst);
+                        warp::reply::with_status(
+                            "worker crashed".to_string(),
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                    }
+                }
+            });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(warp::serve(route).incoming(listener).run());
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_remote_success() {
+        let addr = spawn_tool_server(Arc::new(AtomicUsize::new(0))).await;
+        let executor = RemoteExecutor::new(format!("http://{}/execute", addr))
+            .with_auth_header("Bearer secret");
+
+        let result = executor
+            .execute(
+                "tool-1".to_string(),
+                "echo".to_string(),
+                serde_json::json!({"location": "SF"}),
+            )
+            .await
+            .unwrap();
+
+        let body: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(body["tool_use_id"], "tool-1");
+        assert_eq!(body["input"]["location"], "SF");
+        assert_eq!(body["auth"], "Bearer secret");
+    }
+
+    #[tokio::test]
+    async fn test_remote_client_error_is_not_retried() {
+        let server_errors = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_tool_server(server_errors.clone()).await;
+        let executor = RemoteExecutor::new(format!("http://{}/execute", addr));
+
+        let err = executor
+            .execute("tool-1".to_string(), "bad_input".to_string(), serde_json::json!({}))
+            .await
+            .unwrap_err();
+
+        assert!(err.contains("status 400"));
+        assert!(err.contains("missing field 'location'"));
+        assert_eq!(server_errors.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_remote_server_error_is_retried() {
+        let server_errors = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_tool_server(server_errors.clone()).await;
+        let executor = RemoteExecutor::new(format!("http://{}/execute", addr))
+            .with_retry(RetryConfig {
+                initial_delay: Duration::from_millis(1),
+                ..RetryConfig::default()
+            })
+            .with_max_retries(2);
+
+        let err = executor
+            .execute("tool-1".to_string(), "crash".to_string(), serde_json::json!({}))
+            .await
+            .unwrap_err();
+
+        assert!(err.contains("status 500"));
+        assert!(err.contains("worker crashed"));
+        assert_eq!(server_errors.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_remote_retries_back_off() {
+        let server_errors = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_tool_server(server_errors.clone()).await;
+        let executor = RemoteExecutor::new(format!("http://{}/execute", addr)).with_retry(
+            RetryConfig {
+                max_attempts: 3,
+                initial_delay: Duration::from_millis(100),
+                jitter: false,
+                ..RetryConfig::default()
+            },
+        );
+
+        let started = std::time::Instant::now();
+        executor
+            .execute("tool-1".to_string(), "crash".to_string(), serde_json::json!({}))
+            .await
+            .unwrap_err();
+
+        // 100ms before the first retry, then 200ms before the second
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert_eq!(server_errors.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,407 @@
+//! Snapshots of a registry's tools for detecting drift between deployments
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::llm::ToolDeclaration;
+
+/// A registered tool as recorded in a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolSnapshot {
+    pub name: String,
+    /// SHA-256 of the tool's declaration (name, description and input schema)
+    pub declaration_hash: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// The tools a registry exposes, sorted by name
+///
+/// Snapshots are cheap to serialize, so a deployment can commit the snapshot
+/// it expects and compare it with the live registry at startup.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistrySnapshot {
+    pub tools: Vec<ToolSnapshot>,
+}
+
+impl RegistrySnapshot {
+    /// Build a snapshot from tools in any order
+    pub fn new(tools: impl IntoIterator<Item = ToolSnapshot>) -> Self {
+        let mut tools: Vec<ToolSnapshot> = tools.into_iter().collect();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        Self { tools }
+    }
+
+    /// Read a snapshot previously saved as JSON
+    ///
+    /// # Errors
+    ///
+    /// Returns `SnapshotError::Io` if the file can't be read and
+    /// `SnapshotError::Parse` if it isn't a snapshot
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|source| SnapshotError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let snapshot: Self =
+            serde_json::from_str(&contents).map_err(|source| SnapshotError::Parse {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        Ok(Self::new(snapshot.tools))
+    }
+
+    /// Look up a tool by name
+    pub fn get(&self, name: &str) -> Option<&ToolSnapshot> {
+        self.tools
+            .binary_search_by(|tool| tool.name.as_str().cmp(name))
+            .ok()
+            .map(|i| &self.tools[i])
+    }
+
+    /// Names of the snapshot's tools, in order
+    pub fn names(&self) -> Vec<&str> {
+        self.tools.iter().map(|tool| tool.name.as_str()).collect()
+    }
+
+    /// Changes that turn this snapshot into `other`
+    ///
+    /// Typically called as `expected.diff(&live)`. Tags are informational
+    /// and not compared.
+    pub fn diff(&self, other: &RegistrySnapshot) -> RegistryDiff {
+        let mut diff = RegistryDiff::default();
+
+        for tool in &other.tools {
+            match self.get(&tool.name) {
+                None => diff.added.push(tool.name.clone()),
+                Some(before) => {
+                    if before.declaration_hash != tool.declaration_hash {
+                        diff.changed.push(tool.name.clone());
+                    }
+                    if before.enabled != tool.enabled {
+                        diff.toggled.push(tool.name.clone());
+                    }
+                }
+            }
+        }
+        diff.removed = self
+            .tools
+            .iter()
+            .filter(|tool| other.get(&tool.name).is_none())
+            .map(|tool| tool.name.clone())
+            .collect();
+
+        diff
+    }
+}
+
+/// Differences between two registry snapshots; each list is sorted by name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RegistryDiff {
+    /// Tools only in the newer snapshot
+    pub added: Vec<String>,
+    /// Tools only in the older snapshot
+    pub removed: Vec<String>,
+    /// Tools whose declaration (description or input schema) changed
+    pub changed: Vec<String>,
+    /// Tools that were enabled or disabled
+    pub toggled: Vec<String>,
+}
+
+impl RegistryDiff {
+    /// Whether the snapshots describe the same tools
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.toggled.is_empty()
+    }
+}
+
+impl fmt::Display for RegistryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+
+        let sections = [
+            ("added", &self.added),
+            ("removed", &self.removed),
+            ("changed", &self.changed),
+            ("toggled", &self.toggled),
+        ];
+        let parts: Vec<String> = sections
+            .iter()
+            .filter(|(_, names)| !names.is_empty())
+            .map(|(label, names)| format!("{}: {}", label, names.join(", ")))
+            .collect();
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+/// What to do when the live registry differs from the expected snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriftPolicy {
+    /// Return `SnapshotError::Drift`
+    Fail,
+    /// Log a warning and carry on
+    Warn,
+}
+
+/// Errors from loading or checking a registry snapshot
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("Failed to read tool snapshot {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid tool snapshot {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[error("Registered tools differ from {path} ({diff})")]
+    Drift { path: PathBuf, diff: RegistryDiff },
+}
+
+/// Compare the live registry with the snapshot saved at `expected_path`
+///
+/// Meant to run at startup, so a deployment whose compiled or enabled tools
+/// differ from what its prompts were written against is caught early.
+/// Returns the diff (empty when they match) unless `policy` is
+/// `DriftPolicy::Fail` and they differ.
+///
+/// # Example
+///
+/// ```ignore
+/// check_snapshot(&registry.snapshot(), "config/tools.json", DriftPolicy::Fail)?;
+/// ```
+///
+/// # Errors
+///
+/// Returns `SnapshotError::Io` or `SnapshotError::Parse` if the file can't be
+/// loaded, and `SnapshotError::Drift` on a mismatch under `DriftPolicy::Fail`
+pub fn check_snapshot(
+    live: &RegistrySnapshot,
+    expected_path: impl AsRef<Path>,
+    policy: DriftPolicy,
+) -> Result<RegistryDiff, SnapshotError> {
+    let path = expected_path.as_ref();
+    let diff = RegistrySnapshot::load(path)?.diff(live);
+
+    if !diff.is_empty() {
+        match policy {
+            DriftPolicy::Fail => {
+                return Err(SnapshotError::Drift {
+                    path: path.to_path_buf(),
+                    diff,
+                })
+            }
+            DriftPolicy::Warn => tracing::warn!(
+                path = %path.display(),
+                %diff,
+                "registered tools differ from the expected snapshot"
+            ),
+        }
+    }
+
+    Ok(diff)
+}
+
+/// Hex SHA-256 of a declaration, independent of JSON key order
+pub(crate) fn declaration_hash(declaration: &ToolDeclaration) -> String {
+    let value = serde_json::json!({
+        "name": declaration.name,
+        "description": declaration.description,
+        "input_schema": declaration.input_schema,
+    });
+    let mut canonical = String::new();
+    write_canonical(&value, &mut canonical);
+    hex::encode(Sha256::digest(canonical.as_bytes()))
+}
+
+/// Serialize `value` with object keys sorted at every level
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::tools::FunctionRegistry;
+    use serde_json::json;
+
+    #[derive(serde::Deserialize)]
+    struct Args {}
+
+    fn declaration(name: &str, schema: Value) -> ToolDeclaration {
+        ToolDeclaration {
+            name: name.to_string(),
+            description: format!("The {} tool", name),
+            input_schema: schema,
+        }
+    }
+
+    fn registry(tools: &[(&str, Value)]) -> FunctionRegistry {
+        let mut registry = FunctionRegistry::new();
+        for (name, schema) in tools {
+            registry
+                .register_sync_tool(|_: Args| Ok("ok"), declaration(name, schema.clone()))
+                .unwrap();
+        }
+        registry
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_snapshot_is_sorted_and_reflects_flags() {
+        let mut registry = registry(&[
+            ("weather", json!({"type": "object"})),
+            ("calculator", json!({"type": "object"})),
+        ]);
+        registry.set_tags("weather", ["external", "beta"]).unwrap();
+        registry.set_enabled("calculator", false).unwrap();
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.names(), vec!["calculator", "weather"]);
+        assert!(!snapshot.tools[0].enabled);
+        assert_eq!(snapshot.tools[1].tags, vec!["beta", "external"]);
+        assert_eq!(snapshot.tools[1].declaration_hash.len(), 64);
+        assert_eq!(registry.get_declarations().len(), 1);
+
+        let round_trip: RegistrySnapshot =
+            serde_json::from_value(serde_json::to_value(&snapshot).unwrap()).unwrap();
+        assert_eq!(round_trip, snapshot);
+    }
+
+    #[test]
+    fn test_declaration_hash_ignores_key_order() {
+        let a: Value = serde_json::from_str(
+            r#"{"type":"object","properties":{"x":{"type":"string"},"y":{"type":"number"}}}"#,
+        )
+        .unwrap();
+        let b: Value = serde_json::from_str(
+            r#"{"properties":{"y":{"type":"number"},"x":{"type":"string"}},"type":"object"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            declaration_hash(&declaration("t", a.clone())),
+            declaration_hash(&declaration("t", b))
+        );
+        assert_ne!(
+            declaration_hash(&declaration("t", a)),
+            declaration_hash(&declaration("t", json!({"type": "object"})))
+        );
+    }
+
+    #[test]
+    fn test_diff_reports_changed_schemas() {
+        let schema = json!({"type": "object", "properties": {"city": {"type": "string"}}});
+        let expected = registry(&[
+            ("weather", schema.clone()),
+            ("calculator", json!({"type": "object"})),
+            ("search", json!({"type": "object"})),
+        ])
+        .snapshot();
+
+        let mut live = registry(&[
+            (
+                "weather",
+                json!({"type": "object", "properties": {"city": {"type": "integer"}}}),
+            ),
+            ("calculator", json!({"type": "object"})),
+            ("translate", json!({"type": "object"})),
+        ]);
+        live.set_enabled("calculator", false).unwrap();
+        live.set_tags("translate", ["new"]).unwrap();
+
+        let diff = expected.diff(&live.snapshot());
+        assert_eq!(diff.added, vec!["translate"]);
+        assert_eq!(diff.removed, vec!["search"]);
+        assert_eq!(diff.changed, vec!["weather"]);
+        assert_eq!(diff.toggled, vec!["calculator"]);
+        assert_eq!(
+            diff.to_string(),
+            "added: translate; removed: search; changed: weather; toggled: calculator"
+        );
+        assert!(expected.diff(&expected).is_empty());
+    }
+
+    #[test]
+    fn test_startup_check_with_mismatched_fixture() {
+        // The fixture lists `calculator` with a stale hash and a `search` tool
+        // this deployment doesn't have
+        let live = registry(&[
+            ("calculator", json!({"type": "object"})),
+            ("weather", json!({"type": "object"})),
+        ])
+        .snapshot();
+        let path = fixture("tool_snapshot.json");
+
+        let err = check_snapshot(&live, &path, DriftPolicy::Fail).unwrap_err();
+        let SnapshotError::Drift { diff, .. } = &err else {
+            panic!("expected drift, got {:?}", err);
+        };
+        assert_eq!(diff.added, vec!["weather"]);
+        assert_eq!(diff.removed, vec!["search"]);
+        assert_eq!(diff.changed, vec!["calculator"]);
+        assert!(err.to_string().contains("tool_snapshot.json"));
+
+        let diff = check_snapshot(&live, &path, DriftPolicy::Warn).unwrap();
+        assert_eq!(diff.removed, vec!["search"]);
+    }
+
+    #[test]
+    fn test_startup_check_reports_unreadable_files() {
+        let live = RegistrySnapshot::default();
+
+        let err = check_snapshot(&live, fixture("missing.json"), DriftPolicy::Warn).unwrap_err();
+        assert!(matches!(err, SnapshotError::Io { .. }));
+    }
+}
@@ -1,14 +1,47 @@
-mod handlers;
-mod models;
-mod routes;
-mod sse;
+use rust2::deps::AppDeps;
+use rust2::llm::{create_provider, ClaudeModel, Model};
+use rust2::message_db::{MessageDbClient, MessageDbConfig};
+use rust2::routes::configure_routes;
+use std::env;
+use std::sync::Arc;
 
-use routes::configure_routes;
+/// Build the server's dependencies from the environment
+///
+/// - `GCP_PROJECT_ID` (and optionally `GCP_LOCATION`, default `us-central1`)
+///   selects Claude Sonnet 4.5 on Vertex AI; without it runs are mocked.
+/// - `MESSAGE_DB_URL` stores thread history in Message DB; without it
+///   threads aren't persisted.
+async fn deps_from_env() -> Result<AppDeps, Box<dyn std::error::Error>> {
+    let mut deps = AppDeps::new();
+
+    match env::var("GCP_PROJECT_ID") {
+        Ok(project_id) => {
+            let location = env::var("GCP_LOCATION").unwrap_or_else(|_| "us-central1".to_string());
+            println!("Using Claude Sonnet 4.5 in {} ({})", project_id, location);
+            let provider =
+                create_provider(Model::Claude(ClaudeModel::Sonnet45), project_id, location).await?;
+            deps = deps.with_provider(Arc::from(provider));
+        }
+        Err(_) => eprintln!("Warning: GCP_PROJECT_ID not set, serving mock responses"),
+    }
+
+    match env::var("MESSAGE_DB_URL") {
+        Ok(url) => {
+            let config = MessageDbConfig::from_connection_string(&url)?;
+            deps = deps.with_message_db(MessageDbClient::new(config).await?);
+        }
+        Err(_) => eprintln!("Warning: MESSAGE_DB_URL not set, threads won't be saved"),
+    }
+
+    Ok(deps)
+}
 
 #[tokio::main]
-async fn main() {
-    let routes = configure_routes();
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let routes = configure_routes(deps_from_env().await?);
 
     println!("Starting server on http://127.0.0.1:3030");
     warp::serve(routes).run(([127, 0, 0, 1], 3030)).await;
+
+    Ok(())
 }
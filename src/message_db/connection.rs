@@ -1,4 +1,4 @@
-use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod, Runtime};
+use deadpool_postgres::{Manager, ManagerConfig, Pool, PoolBuilder, RecyclingMethod, Runtime};
 use tokio_postgres::NoTls;
 
 use crate::message_db::error::{Error, Result};
@@ -97,9 +97,9 @@ impl MessageDbConfig {
         let host_port: Vec<&str> = location_parts[0].split(':').collect();
         let host = host_port[0].to_string();
         let port = if host_port.len() > 1 {
-            host_port[1].parse::<u16>().map_err(|_| {
-                Error::ValidationError("Invalid port number".to_string())
-            })?
+            host_port[1]
+                .parse::<u16>()
+                .map_err(|_| Error::ValidationError("Invalid port number".to_string()))?
         } else {
             5432
         };
@@ -118,6 +118,16 @@ impl MessageDbConfig {
 
     /// Build a connection pool from this configuration
     pub fn build_pool(&self) -> Result<Pool> {
+        self.pool_builder()
+            .build()
+            .map_err(|e| Error::ConnectionError(e.to_string()))
+    }
+
+    /// Pool builder for this configuration, to customise before building
+    ///
+    /// [`build_pool`](Self::build_pool) builds it as is. Pass the result to
+    /// `MessageDbClient::from_pool`.
+    pub fn pool_builder(&self) -> PoolBuilder {
         let mut cfg = tokio_postgres::Config::new();
         cfg.host(&self.host);
         cfg.port(self.port);
@@ -127,7 +137,7 @@ impl MessageDbConfig {
 
         // Set search_path to include message_store schema
         // This is critical for Message DB functions to work properly
-        cfg.options(&format!("-c search_path={},public", self.schema_name));
+        cfg.options(format!("-c search_path={},public", self.schema_name));
 
         let manager_config = ManagerConfig {
             recycling_method: RecyclingMethod::Fast,
@@ -135,13 +145,9 @@ impl MessageDbConfig {
 
         let manager = Manager::from_config(cfg, NoTls, manager_config);
 
-        let pool = Pool::builder(manager)
+        Pool::builder(manager)
             .max_size(self.max_pool_size)
             .runtime(Runtime::Tokio1)
-            .build()
-            .map_err(|e| Error::ConnectionError(e.to_string()))?;
-
-        Ok(pool)
     }
 }
 
@@ -0,0 +1,90 @@
+//! Batch-level hooks run around each polled batch
+//!
+//! Handlers that buffer work, such as bulk inserts into another database,
+//! can use [`Consumer::on_batch_start`](super::Consumer::on_batch_start) and
+//! [`Consumer::on_batch_end`](super::Consumer::on_batch_end) to flush once
+//! per batch instead of once per message.
+
+use crate::message_db::{error::Result, types::Message};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Hook called before the messages of a non-empty batch are dispatched
+pub type BatchStartHook =
+    Arc<dyn Fn(BatchInfo) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send + Sync>;
+
+/// Hook called after the messages of a non-empty batch were dispatched
+pub type BatchEndHook =
+    Arc<dyn Fn(BatchResult) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send + Sync>;
+
+/// A polled batch, before it is dispatched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchInfo {
+    /// Number of messages in the batch
+    pub size: usize,
+    /// Global position of the first message
+    pub first_global_position: i64,
+    /// Global position of the last message
+    pub last_global_position: i64,
+}
+
+impl BatchInfo {
+    /// Describe `messages`, or `None` if there are none
+    pub(crate) fn of(messages: &[Message]) -> Option<Self> {
+        Some(Self {
+            size: messages.len(),
+            first_global_position: messages.first()?.global_position,
+            last_global_position: messages.last()?.global_position,
+        })
+    }
+}
+
+/// Outcome of dispatching a batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchResult {
+    /// Messages handled successfully
+    pub processed: usize,
+    /// Messages whose handler failed, including those skipped or
+    /// dead-lettered under an `ErrorPolicy`; with `Stop`, the batch ends at
+    /// the first failure
+    pub failed: usize,
+    /// Time spent dispatching the batch, excluding the hooks
+    pub duration: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn message(global_position: i64) -> Message {
+        Message {
+            id: Uuid::new_v4(),
+            stream_name: "account-1".to_string(),
+            message_type: "Deposited".to_string(),
+            data: json!({}),
+            metadata: None,
+            position: 0,
+            global_position,
+            time: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_batch_info_of_messages() {
+        let batch = [message(7), message(8), message(12)];
+        assert_eq!(
+            BatchInfo::of(&batch),
+            Some(BatchInfo {
+                size: 3,
+                first_global_position: 7,
+                last_global_position: 12,
+            })
+        );
+        assert_eq!(BatchInfo::of(&[]), None);
+    }
+}
@@ -0,0 +1,318 @@
+use crate::message_db::{
+    consumer::{
+        projected::{ProjectedHandler, ProjectionFn, StreamSelector},
+        MessageHandler,
+    },
+    error::Result,
+    types::{Message, WriteMessage},
+};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+
+/// Run state requested for a spawned consumer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsumerState {
+    /// Polling and dispatching messages
+    Running,
+    /// Idle until resumed; no messages are read
+    Paused,
+    /// Stopping; the current position is flushed before the task exits
+    Shutdown,
+}
+
+/// Handler registered for a message type
+#[derive(Clone)]
+pub(crate) enum Registered {
+    Handler(MessageHandler),
+    Projected(ProjectedHandler),
+}
+
+/// Message type -> handler map shared between a consumer and its handles
+#[derive(Default)]
+pub(crate) struct HandlerRegistry {
+    handlers: HashMap<String, Registered>,
+    disabled: HashSet<String>,
+    fallback: Option<MessageHandler>,
+}
+
+impl HandlerRegistry {
+    pub(crate) fn insert(&mut self, message_type: &str, handler: Registered) {
+        self.handlers.insert(message_type.to_string(), handler);
+    }
+
+    pub(crate) fn remove(&mut self, message_type: &str) -> bool {
+        self.disabled.remove(message_type);
+        self.handlers.remove(message_type).is_some()
+    }
+
+    pub(crate) fn set_enabled(&mut self, message_type: &str, enabled: bool) {
+        if enabled {
+            self.disabled.remove(message_type);
+        } else {
+            self.disabled.insert(message_type.to_string());
+        }
+    }
+
+    pub(crate) fn set_fallback(&mut self, handler: Option<MessageHandler>) -> bool {
+        std::mem::replace(&mut self.fallback, handler).is_some()
+    }
+
+    /// Handler to run for `message_type`, unless it is disabled
+    ///
+    /// Types without a handler of their own get the fallback, if any.
+    pub(crate) fn get(&self, message_type: &str) -> Option<Registered> {
+        if self.disabled.contains(message_type) {
+            return None;
+        }
+        match self.handlers.get(message_type) {
+            Some(handler) => Some(handler.clone()),
+            None => self.fallback.clone().map(Registered::Handler),
+        }
+    }
+
+    pub(crate) fn message_types(&self) -> Vec<String> {
+        let mut types: Vec<String> = self.handlers.keys().cloned().collect();
+        types.sort();
+        types
+    }
+}
+
+pub(crate) type SharedRegistry = Arc<RwLock<HandlerRegistry>>;
+
+/// Control handle for a running consumer
+///
+/// Returned by [`Consumer::spawn`](super::Consumer::spawn) and
+/// [`Consumer::handle`](super::Consumer::handle). Clones control the same
+/// consumer. Run-state commands take effect between batches, so a message
+/// being handled is always finished first. Handler changes take effect from
+/// the next message dispatched.
+///
+/// Dropping every handle leaves the consumer running; it can then only be
+/// stopped by aborting its `JoinHandle`.
+#[derive(Clone)]
+pub struct ConsumerHandle {
+    state: Arc<watch::Sender<ConsumerState>>,
+    handlers: SharedRegistry,
+}
+
+impl std::fmt::Debug for ConsumerHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConsumerHandle")
+            .field("state", &self.state())
+            .field("message_types", &self.message_types())
+            .finish()
+    }
+}
+
+impl ConsumerHandle {
+    pub(crate) fn new(handlers: SharedRegistry) -> (Self, watch::Receiver<ConsumerState>) {
+        let (tx, rx) = watch::channel(ConsumerState::Running);
+        (
+            Self {
+                state: Arc::new(tx),
+                handlers,
+            },
+            rx,
+        )
+    }
+
+    pub(crate) fn from_parts(state: Arc<watch::Sender<ConsumerState>>, handlers: SharedRegistry) -> Self {
+        Self { state, handlers }
+    }
+
+    pub(crate) fn state_sender(&self) -> &Arc<watch::Sender<ConsumerState>> {
+        &self.state
+    }
+
+    /// Stop polling after the current batch until `resume` is called
+    pub fn pause(&self) {
+        self.set_state(ConsumerState::Paused);
+    }
+
+    /// Resume polling after a `pause`
+    pub fn resume(&self) {
+        self.set_state(ConsumerState::Running);
+    }
+
+    /// Stop the consumer, flushing its position before the task exits
+    ///
+    /// Await the `JoinHandle` returned by `spawn` to wait for the flush.
+    pub fn shutdown(&self) {
+        self.state.send_replace(ConsumerState::Shutdown);
+    }
+
+    /// Get the most recently requested state
+    pub fn state(&self) -> ConsumerState {
+        *self.state.borrow()
+    }
+
+    /// Register a handler while the consumer is running
+    ///
+    /// Same as [`Consumer::on`](super::Consumer::on).
+    pub fn on<F>(&self, message_type: &str, handler: F)
+    where
+        F: Fn(Message) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send + Sync + 'static,
+    {
+        self.register(message_type, Registered::Handler(Arc::new(handler)));
+    }
+
+    /// Register a projected handler while the consumer is running
+    ///
+    /// Same as [`Consumer::on_projected`](super::Consumer::on_projected).
+    pub fn on_projected<S, F>(&self, message_type: &str, stream_fn: S, project: F)
+    where
+        S: Fn(&Message) -> String + Send + Sync + 'static,
+        F: Fn(&Message, &[Message]) -> Result<Vec<WriteMessage>> + Send + Sync + 'static,
+    {
+        let stream_fn: StreamSelector = Arc::new(stream_fn);
+        let project: ProjectionFn = Arc::new(project);
+        self.register(
+            message_type,
+            Registered::Projected(ProjectedHandler { stream_fn, project }),
+        );
+    }
+
+    /// Register a handler for message types without one, while the consumer is running
+    ///
+    /// Same as [`Consumer::on_any`](super::Consumer::on_any).
+    pub fn on_any<F>(&self, handler: F)
+    where
+        F: Fn(Message) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send + Sync + 'static,
+    {
+        self.handlers.write().unwrap().set_fallback(Some(Arc::new(handler)));
+    }
+
+    /// Unregister the `on_any` handler
+    ///
+    /// Returns whether one was registered.
+    pub fn remove_any_handler(&self) -> bool {
+        self.handlers.write().unwrap().set_fallback(None)
+    }
+
+    /// Unregister the handler for `message_type`
+    ///
+    /// Later messages of that type go to the `on_any` handler, or are skipped
+    /// without one, like any unhandled type.
+    /// Returns whether a handler was registered.
+    pub fn remove_handler(&self, message_type: &str) -> bool {
+        self.handlers.write().unwrap().remove(message_type)
+    }
+
+    /// Enable or disable the handler for `message_type` without removing it
+    ///
+    /// Messages of a disabled type are skipped and the position moves past
+    /// them, so they are not replayed when the handler is re-enabled.
+    pub fn set_handler_enabled(&self, message_type: &str, enabled: bool) {
+        self.handlers.write().unwrap().set_enabled(message_type, enabled);
+    }
+
+    /// Message types with a registered handler, sorted
+    pub fn message_types(&self) -> Vec<String> {
+        self.handlers.read().unwrap().message_types()
+    }
+
+    fn register(&self, message_type: &str, handler: Registered) {
+        self.handlers.write().unwrap().insert(message_type, handler);
+    }
+
+    /// Shutdown is final, so pause/resume after it are ignored
+    fn set_state(&self, state: ConsumerState) {
+        self.state.send_if_modified(|current| {
+            if *current == ConsumerState::Shutdown || *current == state {
+                return false;
+            }
+            *current = state;
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_handle() -> (ConsumerHandle, watch::Receiver<ConsumerState>) {
+        ConsumerHandle::new(SharedRegistry::default())
+    }
+
+    #[test]
+    fn test_state_transitions() {
+        let (handle, rx) = new_handle();
+        assert_eq!(handle.state(), ConsumerState::Running);
+
+        handle.pause();
+        assert_eq!(*rx.borrow(), ConsumerState::Paused);
+
+        handle.clone().resume();
+        assert_eq!(*rx.borrow(), ConsumerState::Running);
+    }
+
+    #[test]
+    fn test_shutdown_is_final() {
+        let (handle, rx) = new_handle();
+
+        handle.shutdown();
+        handle.resume();
+        handle.pause();
+
+        assert_eq!(handle.state(), ConsumerState::Shutdown);
+        assert_eq!(*rx.borrow(), ConsumerState::Shutdown);
+    }
+
+    #[test]
+    fn test_handler_registration_is_shared() {
+        let (handle, _rx) = new_handle();
+        let other = handle.clone();
+
+        handle.on("Withdrawn", |_msg| Box::pin(async { Ok(()) }));
+        other.on_projected("Deposited", |_msg| "balance-1".to_string(), |_msg, _events| Ok(vec![]));
+        assert_eq!(other.message_types(), vec!["Deposited", "Withdrawn"]);
+
+        let registry = handle.handlers.read().unwrap();
+        assert!(matches!(registry.get("Withdrawn"), Some(Registered::Handler(_))));
+        assert!(matches!(registry.get("Deposited"), Some(Registered::Projected(_))));
+        drop(registry);
+
+        assert!(other.remove_handler("Withdrawn"));
+        assert!(!other.remove_handler("Withdrawn"));
+        assert_eq!(handle.message_types(), vec!["Deposited"]);
+    }
+
+    #[test]
+    fn test_disabled_handlers_are_skipped() {
+        let (handle, _rx) = new_handle();
+        handle.on("Withdrawn", |_msg| Box::pin(async { Ok(()) }));
+
+        handle.set_handler_enabled("Withdrawn", false);
+        assert!(handle.handlers.read().unwrap().get("Withdrawn").is_none());
+        assert_eq!(handle.message_types(), vec!["Withdrawn"]);
+
+        handle.set_handler_enabled("Withdrawn", true);
+        assert!(handle.handlers.read().unwrap().get("Withdrawn").is_some());
+    }
+
+    #[test]
+    fn test_on_any_covers_only_unregistered_types() {
+        let (handle, _rx) = new_handle();
+        handle.on_projected("Deposited", |_msg| "balance-1".to_string(), |_msg, _events| Ok(vec![]));
+        assert!(handle.handlers.read().unwrap().get("Closed").is_none());
+
+        handle.on_any(|_msg| Box::pin(async { Ok(()) }));
+        let registry = handle.handlers.read().unwrap();
+        assert!(matches!(registry.get("Closed"), Some(Registered::Handler(_))));
+        assert!(matches!(registry.get("Deposited"), Some(Registered::Projected(_))));
+        drop(registry);
+
+        // Disabled types are skipped rather than handed to the fallback
+        handle.set_handler_enabled("Deposited", false);
+        assert!(handle.handlers.read().unwrap().get("Deposited").is_none());
+        assert_eq!(handle.message_types(), vec!["Deposited"]);
+
+        assert!(handle.remove_any_handler());
+        assert!(!handle.remove_any_handler());
+        assert!(handle.handlers.read().unwrap().get("Closed").is_none());
+    }
+}
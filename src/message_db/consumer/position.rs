@@ -1,17 +1,195 @@
 use crate::message_db::{
-    error::Result,
+    error::{Error, Result},
     types::WriteMessage,
     MessageDbClient,
 };
+use async_trait::async_trait;
 use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
+/// Storage backend for consumer positions
+///
+/// Positions are keyed by the position stream name
+/// (`{category}:position-{consumer_id}`) regardless of where they are stored.
+#[async_trait]
+pub trait PositionStore: Send + Sync {
+    /// Load the last stored position, or `None` if nothing has been stored
+    async fn load(&self, key: &str) -> Result<Option<i64>>;
+
+    /// Store the latest position
+    async fn save(&self, key: &str, position: i64) -> Result<()>;
+}
+
+impl fmt::Debug for dyn PositionStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("dyn PositionStore")
+    }
+}
+
+/// Message recording `position` on the position stream `key`
+pub(crate) fn position_message(key: &str, position: i64) -> WriteMessage {
+    WriteMessage::new(Uuid::new_v4(), key, "PositionUpdated")
+        .with_data(json!({ "position": position }))
+}
+
+/// Position store backed by Message DB position streams
+///
+/// Each save appends a `PositionUpdated` message to the position stream.
+/// This is the default store and requires a writable client.
+#[derive(Clone)]
+pub struct MessageDbPositionStore {
+    client: MessageDbClient,
+}
+
+impl MessageDbPositionStore {
+    /// Create a store that writes positions through the given client
+    pub fn new(client: MessageDbClient) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl PositionStore for MessageDbPositionStore {
+    async fn load(&self, key: &str) -> Result<Option<i64>> {
+        let position = self
+            .client
+            .get_last_stream_message(key, None)
+            .await?
+            .map(|msg| msg.data.get("position").and_then(|v| v.as_i64()).unwrap_or(1));
+        Ok(position)
+    }
+
+    async fn save(&self, key: &str, position: i64) -> Result<()> {
+        self.client.write_message(position_message(key, position)).await?;
+        Ok(())
+    }
+}
+
+/// In-memory position store
+///
+/// Positions survive for the lifetime of the process only. Clones share the
+/// same map, so a consumer recreated with a clone of the store resumes where
+/// the previous one stopped. Intended for read-only clients and tests.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryPositionStore {
+    positions: Arc<Mutex<HashMap<String, i64>>>,
+}
+
+impl MemoryPositionStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl PositionStore for MemoryPositionStore {
+    async fn load(&self, key: &str) -> Result<Option<i64>> {
+        Ok(self.positions.lock().unwrap().get(key).copied())
+    }
+
+    async fn save(&self, key: &str, position: i64) -> Result<()> {
+        self.positions.lock().unwrap().insert(key.to_string(), position);
+        Ok(())
+    }
+}
+
+/// File-backed position store
+///
+/// Each key is stored as a small text file in `directory`, named after the
+/// percent-encoded key. Writes go to a temporary file that is then renamed
+/// over the old one, so a crash never leaves a torn position behind.
+/// Intended for edge deployments without a writable Message DB.
+#[derive(Debug, Clone)]
+pub struct FilePositionStore {
+    directory: PathBuf,
+}
+
+impl FilePositionStore {
+    /// Create a store that keeps position files in `directory`
+    ///
+    /// The directory is created on first write if it doesn't exist.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// Get the directory holding the position files
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Path of the file holding `key`'s position
+    fn path_for(&self, key: &str) -> PathBuf {
+        let mut file_name = String::with_capacity(key.len() + 9);
+        for byte in key.bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' => {
+                    file_name.push(byte as char)
+                }
+                _ => file_name.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        file_name.push_str(".position");
+        self.directory.join(file_name)
+    }
+}
+
+fn io_error(action: &str, path: &Path, err: std::io::Error) -> Error {
+    Error::PositionStoreError(format!("failed to {} {}: {}", action, path.display(), err))
+}
+
+#[async_trait]
+impl PositionStore for FilePositionStore {
+    async fn load(&self, key: &str) -> Result<Option<i64>> {
+        let path = self.path_for(key);
+        let contents = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error("read", &path, e)),
+        };
+
+        let position = contents.trim().parse::<i64>().map_err(|e| {
+            Error::PositionStoreError(format!("invalid position in {}: {}", path.display(), e))
+        })?;
+        Ok(Some(position))
+    }
+
+    async fn save(&self, key: &str, position: i64) -> Result<()> {
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .map_err(|e| io_error("create", &self.directory, e))?;
+
+        let path = self.path_for(key);
+        let tmp_path = path.with_extension("position.tmp");
+
+        let mut file = tokio::fs::File::create(&tmp_path)
+            .await
+            .map_err(|e| io_error("create", &tmp_path, e))?;
+        tokio::io::AsyncWriteExt::write_all(&mut file, position.to_string().as_bytes())
+            .await
+            .map_err(|e| io_error("write", &tmp_path, e))?;
+        file.sync_all()
+            .await
+            .map_err(|e| io_error("sync", &tmp_path, e))?;
+
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .map_err(|e| io_error("rename", &tmp_path, e))
+    }
+}
+
 /// Position tracking for consumers
 ///
-/// Manages reading and writing consumer position to a position stream.
+/// Manages reading and writing consumer position through a [`PositionStore`].
 /// Position streams follow the naming convention: `{category}:position-{consumer_id}`
 pub struct PositionTracker {
-    client: MessageDbClient,
+    store: Arc<dyn PositionStore>,
     position_stream_name: String,
     update_interval: usize,
     messages_since_update: usize,
@@ -56,11 +234,37 @@ impl PositionTracker {
         category: &str,
         consumer_id: &str,
         update_interval: usize,
+    ) -> Self {
+        Self::with_store(
+            Arc::new(MessageDbPositionStore::new(client)),
+            category,
+            consumer_id,
+            update_interval,
+        )
+    }
+
+    /// Create a position tracker that persists through a custom store
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use rust2::message_db::consumer::{MemoryPositionStore, PositionTracker};
+    ///
+    /// let store = MemoryPositionStore::new();
+    /// let tracker = PositionTracker::with_store(Arc::new(store), "account", "worker-1", 100);
+    /// assert_eq!(tracker.position_stream_name(), "account:position-worker-1");
+    /// ```
+    pub fn with_store(
+        store: Arc<dyn PositionStore>,
+        category: &str,
+        consumer_id: &str,
+        update_interval: usize,
     ) -> Self {
         let position_stream_name = format!("{}:position-{}", category, consumer_id);
 
         Self {
-            client,
+            store,
             position_stream_name,
             update_interval,
             messages_since_update: 0,
@@ -96,21 +300,10 @@ impl PositionTracker {
     /// # }
     /// ```
     pub async fn read_position(&mut self) -> Result<i64> {
-        match self.client.get_last_stream_message(&self.position_stream_name, None).await? {
-            Some(msg) => {
-                let position = msg.data
-                    .get("position")
-                    .and_then(|v| v.as_i64())
-                    .unwrap_or(1);
-                self.current_position = position;
-                Ok(position)
-            }
-            None => {
-                // No position stored yet, start from beginning
-                self.current_position = 1;
-                Ok(1)
-            }
-        }
+        // No position stored yet means start from beginning
+        let position = self.store.load(&self.position_stream_name).await?.unwrap_or(1);
+        self.current_position = position;
+        Ok(position)
     }
 
     /// Update the current position and potentially write to position stream
@@ -138,14 +331,22 @@ impl PositionTracker {
     /// # }
     /// ```
     pub async fn update_position(&mut self, global_position: i64) -> Result<()> {
+        self.advance(global_position);
+        self.write_if_due().await
+    }
+
+    /// Record a processed message without writing, even if a write is due
+    pub(crate) fn advance(&mut self, global_position: i64) {
         self.current_position = global_position;
         self.messages_since_update += 1;
+    }
 
+    /// Write the position if `update_interval` messages were recorded since the last write
+    pub(crate) async fn write_if_due(&mut self) -> Result<()> {
         if self.messages_since_update >= self.update_interval {
             self.write_position().await?;
             self.messages_since_update = 0;
         }
-
         Ok(())
     }
 
@@ -174,15 +375,15 @@ impl PositionTracker {
     /// # }
     /// ```
     pub async fn write_position(&self) -> Result<()> {
-        let msg = WriteMessage::new(
-            Uuid::new_v4(),
-            &self.position_stream_name,
-            "PositionUpdated",
-        )
-        .with_data(json!({ "position": self.current_position }));
+        self.store
+            .save(&self.position_stream_name, self.current_position)
+            .await
+    }
 
-        self.client.write_message(msg).await?;
-        Ok(())
+    /// Record a position that was already persisted elsewhere (e.g. in a transaction)
+    pub(crate) fn mark_written(&mut self, position: i64) {
+        self.current_position = position;
+        self.messages_since_update = 0;
     }
 
     /// Get the current position value
@@ -190,6 +391,35 @@ impl PositionTracker {
         self.current_position
     }
 
+    /// How many global positions the tracker is behind `head_position`
+    ///
+    /// `head_position` is the newest global position in the category, from
+    /// [`MessageDbClient::get_category_head_position`]. Returns `None` when
+    /// the category is empty. Global positions are shared by all categories,
+    /// so this is an upper bound on the unprocessed messages rather than an
+    /// exact count.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use rust2::message_db::consumer::{MemoryPositionStore, PositionTracker};
+    ///
+    /// let tracker = PositionTracker::with_store(
+    ///     Arc::new(MemoryPositionStore::new()),
+    ///     "account",
+    ///     "worker-1",
+    ///     100,
+    /// );
+    /// // Nothing processed yet: every position up to the head is pending
+    /// assert_eq!(tracker.lag(Some(10)), Some(10));
+    /// assert_eq!(tracker.lag(None), None);
+    /// ```
+    pub fn lag(&self, head_position: Option<i64>) -> Option<i64> {
+        // `current_position` is the next position to read
+        head_position.map(|head| (head - (self.current_position - 1)).max(0))
+    }
+
     /// Get the number of messages processed since last position write
     pub fn messages_since_update(&self) -> usize {
         self.messages_since_update
@@ -198,7 +428,7 @@ impl PositionTracker {
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
 
     #[test]
     fn test_position_stream_name() {
@@ -213,4 +443,130 @@ mod tests {
         let stream_name = format!("{}:position-{}", "account:command", "consumer-2");
         assert_eq!(stream_name, "account:command:position-consumer-2");
     }
+
+    #[tokio::test]
+    async fn test_memory_store_load_and_save() {
+        let store = MemoryPositionStore::new();
+        assert_eq!(store.load("account:position-worker-1").await.unwrap(), None);
+
+        store.save("account:position-worker-1", 42).await.unwrap();
+        assert_eq!(store.load("account:position-worker-1").await.unwrap(), Some(42));
+        assert_eq!(store.load("account:position-worker-2").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_tracker_resumes_from_memory_store() {
+        let store = MemoryPositionStore::new();
+
+        let mut tracker =
+            PositionTracker::with_store(Arc::new(store.clone()), "account", "worker-1", 2);
+        assert_eq!(tracker.read_position().await.unwrap(), 1);
+
+        tracker.update_position(10).await.unwrap();
+        tracker.update_position(11).await.unwrap();
+        tracker.update_position(12).await.unwrap();
+        drop(tracker);
+
+        // Only the interval write (position 11) was persisted
+        let mut resumed =
+            PositionTracker::with_store(Arc::new(store.clone()), "account", "worker-1", 2);
+        assert_eq!(resumed.read_position().await.unwrap(), 11);
+
+        resumed.update_position(12).await.unwrap();
+        resumed.write_position().await.unwrap();
+        assert_eq!(
+            store.load("account:position-worker-1").await.unwrap(),
+            Some(12)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_advance_defers_write_until_asked() {
+        let store = MemoryPositionStore::new();
+        let mut tracker =
+            PositionTracker::with_store(Arc::new(store.clone()), "account", "worker-1", 2);
+
+        tracker.advance(10);
+        tracker.advance(11);
+        tracker.advance(12);
+        assert_eq!(store.load("account:position-worker-1").await.unwrap(), None);
+
+        tracker.write_if_due().await.unwrap();
+        assert_eq!(
+            store.load("account:position-worker-1").await.unwrap(),
+            Some(12)
+        );
+        assert_eq!(tracker.messages_since_update(), 0);
+    }
+
+    #[test]
+    fn test_lag_counts_positions_after_the_last_processed() {
+        let store = MemoryPositionStore::new();
+        let mut tracker = PositionTracker::with_store(Arc::new(store), "account", "worker-1", 100);
+        assert_eq!(tracker.lag(Some(10)), Some(10));
+
+        // Processed through global position 6
+        tracker.advance(7);
+        assert_eq!(tracker.lag(Some(10)), Some(4));
+        assert_eq!(tracker.lag(Some(6)), Some(0));
+        assert_eq!(tracker.lag(None), None);
+    }
+
+    /// Fresh directory under the system temp dir, removed by the caller
+    fn temp_store_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("rust2-positions-{}", Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_file_store_load_and_save() {
+        let dir = temp_store_dir();
+        let store = FilePositionStore::new(&dir);
+
+        assert_eq!(store.load("account:position-worker-1").await.unwrap(), None);
+
+        store.save("account:position-worker-1", 42).await.unwrap();
+        store.save("account:position-worker-1", 43).await.unwrap();
+        assert_eq!(store.load("account:position-worker-1").await.unwrap(), Some(43));
+        assert_eq!(store.load("account:position-worker-2").await.unwrap(), None);
+
+        // Only the final file remains; temporary files are renamed away
+        let files: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(files, vec!["account%3Aposition-worker-1.position"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_store_rejects_corrupt_file() {
+        let dir = temp_store_dir();
+        let store = FilePositionStore::new(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(store.path_for("account:position-worker-1"), "not a number").unwrap();
+
+        let err = store.load("account:position-worker-1").await.unwrap_err();
+        assert!(matches!(err, Error::PositionStoreError(_)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tracker_resumes_from_file_store() {
+        let dir = temp_store_dir();
+
+        let store: Arc<dyn PositionStore> = Arc::new(FilePositionStore::new(&dir));
+        let mut tracker = PositionTracker::with_store(store, "account", "edge-1", 100);
+        tracker.update_position(17).await.unwrap();
+        tracker.write_position().await.unwrap();
+        drop(tracker);
+
+        // A new store instance (e.g. after a restart) reads the same file
+        let store: Arc<dyn PositionStore> = Arc::new(FilePositionStore::new(&dir));
+        let mut resumed = PositionTracker::with_store(store, "account", "edge-1", 100);
+        assert_eq!(resumed.read_position().await.unwrap(), 17);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
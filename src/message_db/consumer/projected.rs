@@ -0,0 +1,102 @@
+use crate::message_db::{
+    error::Result,
+    operations::StreamReadOptions,
+    transaction::Transaction,
+    types::{Message, WriteMessage},
+    MessageDbClient,
+};
+use std::sync::Arc;
+
+/// Maps a consumed message to the entity stream it projects into
+pub type StreamSelector = Arc<dyn Fn(&Message) -> String + Send + Sync>;
+
+/// Derives the messages to append from a consumed message and the entity's current stream
+pub type ProjectionFn =
+    Arc<dyn Fn(&Message, &[Message]) -> Result<Vec<WriteMessage>> + Send + Sync>;
+
+/// Handler registered with `Consumer::on_projected`
+#[derive(Clone)]
+pub(crate) struct ProjectedHandler {
+    pub(crate) stream_fn: StreamSelector,
+    pub(crate) project: ProjectionFn,
+}
+
+/// Page size used when loading the entity stream inside the transaction
+const ENTITY_READ_BATCH: i64 = 1000;
+
+/// Run one projection attempt in its own transaction
+///
+/// Reads the entity stream, invokes the projection, appends its output with
+/// expected versions, writes `position_message` (if any) and commits. Any
+/// error rolls the whole attempt back.
+pub(crate) async fn project_once(
+    client: &MessageDbClient,
+    handler: &ProjectedHandler,
+    message: &Message,
+    position_message: Option<WriteMessage>,
+) -> Result<()> {
+    let mut txn = client.begin_transaction().await?;
+
+    match project_in_transaction(&mut txn, handler, message, position_message).await {
+        Ok(()) => txn.commit().await,
+        Err(e) => {
+            // Dropping an open transaction doesn't roll it back, so do it explicitly
+            let _ = txn.rollback().await;
+            Err(e)
+        }
+    }
+}
+
+async fn project_in_transaction(
+    txn: &mut Transaction,
+    handler: &ProjectedHandler,
+    message: &Message,
+    position_message: Option<WriteMessage>,
+) -> Result<()> {
+    let stream_name = (handler.stream_fn)(message);
+    let current = read_entity_stream(txn, &stream_name).await?;
+
+    // Writes to the entity stream are pinned to the version we just read, so a
+    // concurrent writer turns into a ConcurrencyError instead of a lost update
+    let mut version = current.last().map(|m| m.position).unwrap_or(-1);
+    for mut write in (handler.project)(message, &current)? {
+        let targets_entity = write.stream_name == stream_name;
+        if targets_entity && write.expected_version.is_none() {
+            write.expected_version = Some(version);
+        }
+
+        let position = txn.write_message(write).await?;
+        if targets_entity {
+            version = position;
+        }
+    }
+
+    if let Some(position_message) = position_message {
+        txn.write_message(position_message).await?;
+    }
+
+    Ok(())
+}
+
+/// Read every message in `stream_name` within the transaction
+async fn read_entity_stream(txn: &Transaction, stream_name: &str) -> Result<Vec<Message>> {
+    let mut messages = Vec::new();
+    let mut position = 0;
+
+    loop {
+        let options = StreamReadOptions::new(stream_name)
+            .with_position(position)
+            .with_batch_size(ENTITY_READ_BATCH);
+        let page = txn.get_stream_messages(options).await?;
+        let page_len = page.len() as i64;
+
+        if let Some(last) = page.last() {
+            position = last.position + 1;
+        }
+        messages.extend(page);
+
+        if page_len < ENTITY_READ_BATCH {
+            return Ok(messages);
+        }
+    }
+}
@@ -30,6 +30,38 @@ pub enum Error {
 
     /// Transaction error - transaction-specific errors
     TransactionError(String),
+
+    /// Id reuse error - a message id was written again with different content
+    IdReuse {
+        message_id: uuid::Uuid,
+        stream_name: String,
+    },
+
+    /// Read-only error - a write was attempted through a read-only client
+    ReadOnly(String),
+
+    /// Position store error - a non-database position backend failed
+    PositionStoreError(String),
+
+    /// I/O error - reading or writing an export file failed
+    IoError(String),
+
+    /// Payload too large - a message's data or metadata is over the client's limit
+    PayloadTooLarge {
+        field: &'static str,
+        size: usize,
+        limit: usize,
+    },
+
+    /// Timeout - an awaited message didn't arrive in time
+    Timeout(String),
+
+    /// Deserialization error - a message's data or metadata doesn't fit the requested type
+    DeserializationError {
+        message_id: uuid::Uuid,
+        type_name: &'static str,
+        source: serde_json::Error,
+    },
 }
 
 impl fmt::Display for Error {
@@ -50,11 +82,46 @@ impl fmt::Display for Error {
             Error::DatabaseError(msg) => write!(f, "Database error: {}", msg),
             Error::PoolError(msg) => write!(f, "Pool error: {}", msg),
             Error::TransactionError(msg) => write!(f, "Transaction error: {}", msg),
+            Error::IdReuse {
+                message_id,
+                stream_name,
+            } => write!(
+                f,
+                "Message id {} reused with different content (existing message in stream '{}')",
+                message_id, stream_name
+            ),
+            Error::ReadOnly(operation) => {
+                write!(f, "Read-only client: {} is not permitted", operation)
+            }
+            Error::PositionStoreError(msg) => write!(f, "Position store error: {}", msg),
+            Error::IoError(msg) => write!(f, "I/O error: {}", msg),
+            Error::PayloadTooLarge { field, size, limit } => write!(
+                f,
+                "Payload too large: {} is {} bytes, exceeding the limit of {} bytes",
+                field, size, limit
+            ),
+            Error::Timeout(msg) => write!(f, "Timed out: {}", msg),
+            Error::DeserializationError {
+                message_id,
+                type_name,
+                source,
+            } => write!(
+                f,
+                "Failed to deserialize message {} as {}: {}",
+                message_id, type_name, source
+            ),
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::DeserializationError { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
 
 /// Convert tokio-postgres errors to Message DB errors
 impl From<tokio_postgres::Error> for Error {
@@ -104,6 +171,13 @@ impl From<uuid::Error> for Error {
     }
 }
 
+/// Convert I/O errors to Message DB errors
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::IoError(err.to_string())
+    }
+}
+
 /// Convert JSON errors to Message DB errors
 impl From<serde_json::Error> for Error {
     fn from(err: serde_json::Error) -> Self {
@@ -0,0 +1,198 @@
+//! Size limits on the JSON payloads of written messages
+//!
+//! Postgres accepts multi-megabyte JSON values, but a few oversized events
+//! slow down every category read that touches them. The client rejects them
+//! before the SQL call with `Error::PayloadTooLarge`; messages that are
+//! meant to be large can opt out with
+//! [`WriteMessage::with_ignore_size_limits`].
+
+use crate::message_db::{
+    error::{Error, Result},
+    types::WriteMessage,
+};
+use serde_json::Value;
+
+/// Largest serialized `data` and `metadata` a client will write, in bytes
+///
+/// # Example
+///
+/// ```
+/// use rust2::message_db::PayloadLimits;
+///
+/// let limits = PayloadLimits::default().with_max_data_bytes(256 * 1024);
+/// assert_eq!(limits.max_data_bytes, 256 * 1024);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadLimits {
+    /// Longest serialized `data` (default: 1 MiB)
+    pub max_data_bytes: usize,
+    /// Longest serialized `metadata` (default: 64 KiB)
+    pub max_metadata_bytes: usize,
+}
+
+impl PayloadLimits {
+    /// Set the data limit (builder pattern)
+    pub fn with_max_data_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_data_bytes = max_bytes;
+        self
+    }
+
+    /// Set the metadata limit (builder pattern)
+    pub fn with_max_metadata_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_metadata_bytes = max_bytes;
+        self
+    }
+
+    /// Check `msg` against the limits, unless it opted out
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::PayloadTooLarge` naming the first field over its limit.
+    pub fn check(&self, msg: &WriteMessage) -> Result<()> {
+        if msg.ignore_size_limits {
+            return Ok(());
+        }
+        check_field("data", &msg.data, self.max_data_bytes)?;
+        match &msg.metadata {
+            Some(metadata) => check_field("metadata", metadata, self.max_metadata_bytes),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        Self {
+            max_data_bytes: 1024 * 1024,
+            max_metadata_bytes: 64 * 1024,
+        }
+    }
+}
+
+fn check_field(field: &'static str, value: &Value, limit: usize) -> Result<()> {
+    let size = serialized_len(value);
+    if size > limit {
+        return Err(Error::PayloadTooLarge { field, size, limit });
+    }
+    Ok(())
+}
+
+/// Appended to text shortened by [`truncate_by`]
+pub(crate) const TRUNCATION_MARKER: &str = "… [truncated]";
+
+/// Shorten `text` by at least `excess` bytes, marking the cut
+///
+/// Cuts at a character boundary. Text no longer than the marker is left
+/// alone, since replacing it wouldn't make it shorter.
+pub(crate) fn truncate_by(text: &mut String, excess: usize) {
+    if excess == 0 || text.len() <= TRUNCATION_MARKER.len() {
+        return;
+    }
+    let mut keep = text.len().saturating_sub(excess + TRUNCATION_MARKER.len());
+    while !text.is_char_boundary(keep) {
+        keep -= 1;
+    }
+    text.truncate(keep);
+    text.push_str(TRUNCATION_MARKER);
+}
+
+/// Length of `value` serialized as compact JSON, without allocating it
+fn serialized_len(value: &Value) -> usize {
+    struct Counter(usize);
+
+    impl std::io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    serde_json::to_writer(&mut counter, value).expect("JSON values always serialize");
+    counter.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    /// A message whose data serializes to exactly `size` bytes
+    fn message_with_data_len(size: usize) -> WriteMessage {
+        // `"` + text + `"`
+        let data = Value::String("x".repeat(size - 2));
+        WriteMessage::new(Uuid::new_v4(), "account-123", "Deposited").with_data(data)
+    }
+
+    #[test]
+    fn test_data_limit_boundary() {
+        let limits = PayloadLimits::default().with_max_data_bytes(100);
+
+        assert!(limits.check(&message_with_data_len(100)).is_ok());
+
+        let err = limits.check(&message_with_data_len(101)).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::PayloadTooLarge {
+                field: "data",
+                size: 101,
+                limit: 100
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Payload too large: data is 101 bytes, exceeding the limit of 100 bytes"
+        );
+    }
+
+    #[test]
+    fn test_metadata_limit_boundary() {
+        let limits = PayloadLimits::default().with_max_metadata_bytes(20);
+        // {"key":"0123456789"} is 20 bytes
+        let msg = |value: &str| {
+            WriteMessage::new(Uuid::new_v4(), "account-123", "Deposited")
+                .with_metadata(json!({ "key": value }))
+        };
+
+        assert!(limits.check(&msg("0123456789")).is_ok());
+        assert!(matches!(
+            limits.check(&msg("0123456789a")),
+            Err(Error::PayloadTooLarge {
+                field: "metadata",
+                size: 21,
+                limit: 20
+            })
+        ));
+    }
+
+    #[test]
+    fn test_ignore_size_limits_skips_the_check() {
+        let limits = PayloadLimits::default().with_max_data_bytes(10);
+        let msg = message_with_data_len(1000).with_ignore_size_limits(true);
+        assert!(limits.check(&msg).is_ok());
+    }
+
+    #[test]
+    fn test_truncate_by_cuts_at_char_boundary() {
+        let mut text = "ééééééééééééééééééééé".to_string(); // 42 bytes
+        truncate_by(&mut text, 10);
+        assert!(text.ends_with(TRUNCATION_MARKER));
+        assert!(text.len() <= 42 - 10);
+        assert_eq!(text, format!("{}{}", "é".repeat(8), TRUNCATION_MARKER));
+
+        let mut short = "tiny".to_string();
+        truncate_by(&mut short, 2);
+        assert_eq!(short, "tiny");
+    }
+
+    #[test]
+    fn test_serialized_len_counts_escapes() {
+        assert_eq!(serialized_len(&json!("a\"b")), 6);
+        assert_eq!(serialized_len(&json!({"a": [1, 2]})), 11);
+    }
+}
@@ -24,16 +24,27 @@ pub mod client;
 pub mod connection;
 pub mod consumer;
 pub mod error;
+pub mod limits;
 pub mod operations;
+pub mod portable;
+pub mod projection;
 pub mod transaction;
 pub mod types;
 pub mod utils;
+pub mod version_cache;
 
 // Re-export main types for convenience
 pub use client::MessageDbClient;
 pub use connection::MessageDbConfig;
 pub use error::{Error, Result};
+pub use limits::PayloadLimits;
 pub use operations::{CategoryReadOptions, StreamReadOptions};
-pub use transaction::Transaction;
-pub use types::{Message, WriteMessage};
+pub use portable::{ExportOptions, IdPolicy};
+pub use projection::Projection;
+pub use transaction::{Savepoint, Transaction};
+pub use types::{
+    CategoryStream, DeadLetterKeys, IdConflictPolicy, Message, MetadataKeys, WriteMessage,
+    WriteResult,
+};
 pub use utils::{category, cardinal_id, get_base_category, get_category_types, id, is_category};
+pub use version_cache::VersionCacheStats;
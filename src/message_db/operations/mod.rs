@@ -2,6 +2,14 @@ pub mod query;
 pub mod read;
 pub mod write;
 
-pub use query::{get_last_stream_message, stream_version};
-pub use read::{get_category_messages, get_stream_messages, CategoryReadOptions, StreamReadOptions};
-pub use write::write_message;
+pub use query::{
+    category_head_position, correlated_message, get_category_streams, get_last_stream_message, get_streams_in_category,
+    sql_condition_enabled, stream_version,
+};
+pub use read::{
+    get_category_messages, get_stream_messages, stream_category_messages, stream_messages,
+    CategoryReadOptions, StreamReadOptions,
+};
+pub use write::{write_message, write_message_ex};
+pub(crate) use query::{category_streams_sql, parse_category_stream_row};
+pub(crate) use write::sequence_batch;
@@ -2,8 +2,10 @@ use crate::message_db::{
     error::{Error, Result},
     types::Message,
 };
+use async_stream::stream;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use deadpool_postgres::Pool;
+use futures::stream::Stream;
 use serde_json::Value;
 use tokio_postgres::Row;
 use uuid::Uuid;
@@ -293,6 +295,77 @@ pub async fn get_category_messages(
     rows.iter().map(parse_message_row).collect()
 }
 
+/// Lazily read every message in a stream from `options.position` on
+///
+/// Reads `options.batch_size` messages at a time with
+/// [`get_stream_messages`], continuing from the last message's position + 1,
+/// and ends after a batch smaller than `batch_size`. Each page takes a
+/// connection from the pool and returns it before any message is yielded. A
+/// read error is yielded once and ends the stream.
+pub fn stream_messages(
+    pool: &Pool,
+    schema_name: &str,
+    mut options: StreamReadOptions,
+) -> impl Stream<Item = Result<Message>> + Send + 'static {
+    let pool = pool.clone();
+    let schema_name = schema_name.to_string();
+    stream! {
+        loop {
+            let batch = match get_stream_messages(&pool, &schema_name, options.clone()).await {
+                Ok(batch) => batch,
+                Err(e) => {
+                    yield Err(e);
+                    break;
+                }
+            };
+            let Some(last) = batch.last() else { break };
+            options.position = last.position + 1;
+            let done = (batch.len() as i64) < options.batch_size;
+
+            for message in batch {
+                yield Ok(message);
+            }
+            if done {
+                break;
+            }
+        }
+    }
+}
+
+/// Lazily read every message in a category from `options.position` on
+///
+/// Pages like [`stream_messages`] with [`get_category_messages`], continuing
+/// from the last message's global position + 1.
+pub fn stream_category_messages(
+    pool: &Pool,
+    schema_name: &str,
+    mut options: CategoryReadOptions,
+) -> impl Stream<Item = Result<Message>> + Send + 'static {
+    let pool = pool.clone();
+    let schema_name = schema_name.to_string();
+    stream! {
+        loop {
+            let batch = match get_category_messages(&pool, &schema_name, options.clone()).await {
+                Ok(batch) => batch,
+                Err(e) => {
+                    yield Err(e);
+                    break;
+                }
+            };
+            let Some(last) = batch.last() else { break };
+            options.position = last.global_position + 1;
+            let done = (batch.len() as i64) < options.batch_size;
+
+            for message in batch {
+                yield Ok(message);
+            }
+            if done {
+                break;
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,228 @@
+//! Portable JSONL export/import of categories
+//!
+//! Each line of an export is one [`Message`] serialized as JSON, with the
+//! fields `id`, `stream_name`, `type`, `data`, `metadata`, `position`,
+//! `global_position` and `time`. Importing re-writes the messages through
+//! the normal write path, so positions are assigned by the target store.
+
+use std::fmt;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::message_db::error::{Error, Result};
+use crate::message_db::types::{Message, WriteMessage};
+
+/// Rewrites a message's data and metadata during export
+type Redaction = Arc<dyn Fn(&str, &mut Value, &mut Option<Value>) + Send + Sync>;
+
+/// Options for [`MessageDbClient::export_category`](crate::message_db::MessageDbClient::export_category)
+#[derive(Clone)]
+pub struct ExportOptions {
+    /// Messages read per query (default: 1000)
+    pub batch_size: i64,
+
+    /// Applied to every message before it is written out (optional)
+    redaction: Option<Redaction>,
+}
+
+impl ExportOptions {
+    /// Create export options with defaults
+    pub fn new() -> Self {
+        Self {
+            batch_size: 1000,
+            redaction: None,
+        }
+    }
+
+    /// Set the number of messages read per query (builder pattern)
+    pub fn with_batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Scrub message contents before they are exported (builder pattern)
+    ///
+    /// The callback receives the message type and mutable references to the
+    /// data and metadata.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust2::message_db::portable::ExportOptions;
+    /// use serde_json::Value;
+    ///
+    /// let options = ExportOptions::new().with_redaction(|_message_type, data, _metadata| {
+    ///     if let Some(email) = data.get_mut("email") {
+    ///         *email = Value::String("redacted@example.com".to_string());
+    ///     }
+    /// });
+    /// ```
+    pub fn with_redaction<F>(mut self, redaction: F) -> Self
+    where
+        F: Fn(&str, &mut Value, &mut Option<Value>) + Send + Sync + 'static,
+    {
+        self.redaction = Some(Arc::new(redaction));
+        self
+    }
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ExportOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExportOptions")
+            .field("batch_size", &self.batch_size)
+            .field("redaction", &self.redaction.is_some())
+            .finish()
+    }
+}
+
+/// How imported messages are identified
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdPolicy {
+    /// Keep the exported ids; re-importing the same file is idempotent (default)
+    #[default]
+    Preserve,
+    /// Give every message a fresh id, recording the exported id in metadata
+    /// as `original_message_id`
+    Regenerate,
+}
+
+/// One export line for `message`, with redaction applied and no trailing newline
+pub(crate) fn export_line(message: &Message, options: &ExportOptions) -> Result<String> {
+    match &options.redaction {
+        None => Ok(serde_json::to_string(message)?),
+        Some(redact) => {
+            let mut message = message.clone();
+            redact(
+                &message.message_type,
+                &mut message.data,
+                &mut message.metadata,
+            );
+            Ok(serde_json::to_string(&message)?)
+        }
+    }
+}
+
+/// The message to write for export line `line_number` (1-based)
+///
+/// Returns `None` for blank lines.
+pub(crate) fn import_line(
+    line: &str,
+    line_number: usize,
+    id_policy: IdPolicy,
+    stream_prefix: Option<&str>,
+) -> Result<Option<WriteMessage>> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let message: Message = serde_json::from_str(line).map_err(|e| {
+        Error::ValidationError(format!("invalid export line {}: {}", line_number, e))
+    })?;
+
+    let stream_name = match stream_prefix {
+        Some(prefix) => format!("{}{}", prefix, message.stream_name),
+        None => message.stream_name,
+    };
+
+    let mut write =
+        WriteMessage::new(message.id, stream_name, message.message_type).with_data(message.data);
+    write.metadata = message.metadata;
+
+    Ok(Some(match id_policy {
+        IdPolicy::Preserve => write,
+        IdPolicy::Regenerate => write.with_regenerated_id(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn message() -> Message {
+        Message {
+            id: Uuid::new_v4(),
+            stream_name: "account-123".to_string(),
+            message_type: "Opened".to_string(),
+            data: json!({"owner": "Ada", "email": "ada@example.com"}),
+            metadata: Some(json!({"correlation_id": "abc"})),
+            position: 3,
+            global_position: 42,
+            time: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_export_line_has_portable_fields() {
+        let msg = message();
+        let line = export_line(&msg, &ExportOptions::new()).unwrap();
+        let value: Value = serde_json::from_str(&line).unwrap();
+
+        assert!(!line.contains('\n'));
+        assert_eq!(value["id"], msg.id.to_string());
+        assert_eq!(value["stream_name"], "account-123");
+        assert_eq!(value["type"], "Opened");
+        assert_eq!(value["position"], 3);
+        assert_eq!(value["global_position"], 42);
+        assert!(value["time"].is_string());
+    }
+
+    #[test]
+    fn test_export_applies_redaction() {
+        let options = ExportOptions::new().with_redaction(|message_type, data, metadata| {
+            assert_eq!(message_type, "Opened");
+            data["email"] = json!("redacted");
+            *metadata = None;
+        });
+
+        let line = export_line(&message(), &options).unwrap();
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["data"], json!({"owner": "Ada", "email": "redacted"}));
+        assert!(value["metadata"].is_null());
+    }
+
+    #[test]
+    fn test_import_line_remaps_streams_and_ids() {
+        let msg = message();
+        let line = export_line(&msg, &ExportOptions::new()).unwrap();
+
+        let kept = import_line(&line, 1, IdPolicy::Preserve, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(kept.id, msg.id);
+        assert_eq!(kept.stream_name, "account-123");
+        assert_eq!(kept.message_type, "Opened");
+        assert_eq!(kept.data, msg.data);
+        assert_eq!(kept.metadata, msg.metadata);
+        assert_eq!(kept.expected_version, None);
+
+        let fresh = import_line(&line, 1, IdPolicy::Regenerate, Some("qa"))
+            .unwrap()
+            .unwrap();
+        assert_ne!(fresh.id, msg.id);
+        assert_eq!(fresh.stream_name, "qaaccount-123");
+        assert_eq!(
+            fresh.metadata.unwrap()["original_message_id"],
+            msg.id.to_string()
+        );
+    }
+
+    #[test]
+    fn test_import_line_skips_blanks_and_reports_bad_lines() {
+        assert!(import_line("  ", 1, IdPolicy::Preserve, None)
+            .unwrap()
+            .is_none());
+
+        let err = import_line("{\"id\": 1}", 7, IdPolicy::Preserve, None).unwrap_err();
+        assert!(matches!(err, Error::ValidationError(ref msg) if msg.contains("line 7")));
+    }
+}
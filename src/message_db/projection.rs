@@ -0,0 +1,152 @@
+//! Rebuilding entity state from its stream
+//!
+//! A [`Projection`] folds the messages of an entity stream into a value,
+//! one handler per message type. [`MessageDbClient::fetch_entity`] runs it
+//! over a whole stream and also returns the stream version, ready to use as
+//! the expected version of the next write.
+//!
+//! [`MessageDbClient::fetch_entity`]: crate::message_db::MessageDbClient::fetch_entity
+
+use crate::message_db::{error::Result, types::Message};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Applies one message to an entity's state
+pub type ProjectionHandler<E> = Box<dyn Fn(E, &Message) -> Result<E> + Send + Sync>;
+
+/// How to fold an entity stream into a state of type `E`
+///
+/// # Example
+///
+/// ```
+/// use rust2::message_db::Projection;
+///
+/// let balance = Projection::new(0i64)
+///     .when("Deposited", |balance, msg| {
+///         Ok(balance + msg.data["amount"].as_i64().unwrap_or(0))
+///     })
+///     .when("Withdrawn", |balance, msg| {
+///         Ok(balance - msg.data["amount"].as_i64().unwrap_or(0))
+///     });
+///
+/// assert!(balance.handles("Deposited"));
+/// assert!(!balance.handles("Opened"));
+/// ```
+pub struct Projection<E> {
+    initial: E,
+    handlers: HashMap<String, ProjectionHandler<E>>,
+}
+
+impl<E: Clone> Projection<E> {
+    /// Create a projection starting from `initial`
+    pub fn new(initial: E) -> Self {
+        Self {
+            initial,
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Apply `handler` to messages of `message_type` (builder pattern)
+    ///
+    /// Replaces any handler already registered for the type.
+    pub fn when<F>(mut self, message_type: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(E, &Message) -> Result<E> + Send + Sync + 'static,
+    {
+        self.handlers.insert(message_type.into(), Box::new(handler));
+        self
+    }
+
+    /// Whether messages of `message_type` change the state
+    pub fn handles(&self, message_type: &str) -> bool {
+        self.handlers.contains_key(message_type)
+    }
+
+    /// The state before any message is applied
+    pub fn initial_state(&self) -> E {
+        self.initial.clone()
+    }
+
+    /// Apply one message to `state`
+    ///
+    /// Messages of a type without a handler leave the state unchanged.
+    pub fn apply(&self, state: E, message: &Message) -> Result<E> {
+        match self.handlers.get(&message.message_type) {
+            Some(handler) => handler(state, message),
+            None => Ok(state),
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Debug for Projection<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut types: Vec<&String> = self.handlers.keys().collect();
+        types.sort();
+        f.debug_struct("Projection")
+            .field("initial", &self.initial)
+            .field("handlers", &types)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_db::Error;
+    use chrono::Utc;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn message(message_type: &str, amount: i64, position: i64) -> Message {
+        Message {
+            id: Uuid::new_v4(),
+            stream_name: "account-123".to_string(),
+            message_type: message_type.to_string(),
+            data: json!({ "amount": amount }),
+            metadata: None,
+            position,
+            global_position: position + 1,
+            time: Utc::now(),
+        }
+    }
+
+    fn balance() -> Projection<i64> {
+        Projection::new(0)
+            .when("Deposited", |balance, msg| {
+                Ok(balance + msg.data["amount"].as_i64().unwrap())
+            })
+            .when("Withdrawn", |balance, msg| {
+                Ok(balance - msg.data["amount"].as_i64().unwrap())
+            })
+    }
+
+    #[test]
+    fn test_apply_folds_in_order_and_skips_unhandled_types() {
+        let projection = balance();
+        let messages = [
+            message("Deposited", 100, 0),
+            message("Renamed", 999, 1),
+            message("Withdrawn", 30, 2),
+        ];
+
+        let state = messages
+            .iter()
+            .try_fold(projection.initial_state(), |state, msg| {
+                projection.apply(state, msg)
+            })
+            .unwrap();
+
+        assert_eq!(state, 70);
+    }
+
+    #[test]
+    fn test_handler_errors_are_returned() {
+        let projection = Projection::new(0i64).when("Deposited", |_, _| {
+            Err(Error::ValidationError("bad amount".to_string()))
+        });
+
+        let result = projection.apply(0, &message("Deposited", 1, 0));
+
+        assert!(matches!(result, Err(Error::ValidationError(_))));
+    }
+}
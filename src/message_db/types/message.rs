@@ -1,8 +1,12 @@
 use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use uuid::Uuid;
 
+use super::metadata::{self, MetadataKeys};
+use crate::message_db::error::{Error, Result};
+
 /// Message data for writing to Message DB
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WriteMessage {
@@ -27,6 +31,30 @@ pub struct WriteMessage {
     /// Expected current version for concurrency control
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expected_version: Option<i64>,
+
+    /// What to do when a message with the same id already exists
+    #[serde(default)]
+    pub id_conflict_policy: IdConflictPolicy,
+
+    /// Write even if data or metadata is over the client's `PayloadLimits`
+    #[serde(skip)]
+    pub ignore_size_limits: bool,
+}
+
+/// How a write handles a message id that already exists in the store
+///
+/// A duplicate id with the same stream, type, and data is always treated as an
+/// idempotent retry. The policies only differ when the existing message has
+/// different content, which usually means an upstream id generator reused ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum IdConflictPolicy {
+    /// Return the existing message's position without comparing content (default)
+    #[default]
+    Idempotent,
+    /// Fail with `Error::IdReuse` if the existing message has different content
+    Error,
+    /// Write under a fresh id, recording the original id in metadata
+    Regenerate,
 }
 
 impl WriteMessage {
@@ -56,6 +84,8 @@ impl WriteMessage {
             data: Value::Object(serde_json::Map::new()),
             metadata: None,
             expected_version: None,
+            id_conflict_policy: IdConflictPolicy::default(),
+            ignore_size_limits: false,
         }
     }
 
@@ -65,6 +95,34 @@ impl WriteMessage {
         self
     }
 
+    /// Set the data payload from any serializable value (builder pattern)
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ValidationError` if `data` can't be serialized to JSON.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust2::message_db::types::WriteMessage;
+    /// use serde::Serialize;
+    /// use uuid::Uuid;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Withdrawn {
+    ///     amount: u64,
+    /// }
+    ///
+    /// let msg = WriteMessage::new(Uuid::new_v4(), "account-123", "Withdrawn")
+    ///     .with_typed_data(&Withdrawn { amount: 50 })?;
+    /// assert_eq!(msg.data["amount"], 50);
+    /// # Ok::<(), rust2::message_db::Error>(())
+    /// ```
+    pub fn with_typed_data<T: Serialize>(mut self, data: &T) -> Result<Self> {
+        self.data = serde_json::to_value(data)?;
+        Ok(self)
+    }
+
     /// Set the metadata (builder pattern)
     pub fn with_metadata(mut self, metadata: Value) -> Self {
         self.metadata = Some(metadata);
@@ -76,6 +134,116 @@ impl WriteMessage {
         self.expected_version = Some(version);
         self
     }
+
+    /// Set how a duplicate message id is handled (builder pattern)
+    pub fn with_id_conflict_policy(mut self, policy: IdConflictPolicy) -> Self {
+        self.id_conflict_policy = policy;
+        self
+    }
+
+    /// Skip the client's payload size limits for this message (builder pattern)
+    ///
+    /// For messages that are meant to be large, such as imported documents.
+    pub fn with_ignore_size_limits(mut self, ignore: bool) -> Self {
+        self.ignore_size_limits = ignore;
+        self
+    }
+
+    /// Set the correlation stream name (builder pattern)
+    ///
+    /// Consumers reading with a correlation category only see messages
+    /// whose correlation stream is in that category.
+    pub fn with_correlation_stream_name(mut self, stream_name: impl Into<String>) -> Self {
+        metadata::insert(
+            &mut self.metadata,
+            MetadataKeys::CORRELATION_STREAM_NAME,
+            Value::String(stream_name.into()),
+        );
+        self
+    }
+
+    /// Set the stream a reply should be written to (builder pattern)
+    pub fn with_reply_stream_name(mut self, stream_name: impl Into<String>) -> Self {
+        metadata::insert(
+            &mut self.metadata,
+            MetadataKeys::REPLY_STREAM_NAME,
+            Value::String(stream_name.into()),
+        );
+        self
+    }
+
+    /// Record `preceding` as the cause of this message (builder pattern)
+    ///
+    /// Sets the causation stream name and positions from `preceding`, and
+    /// carries over its correlation and reply stream names, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust2::message_db::types::{Message, WriteMessage};
+    /// # use chrono::Utc;
+    /// # use serde_json::json;
+    /// use uuid::Uuid;
+    ///
+    /// # let command = Message {
+    /// #     id: Uuid::new_v4(),
+    /// #     stream_name: "withdrawal:command-123".to_string(),
+    /// #     message_type: "Withdraw".to_string(),
+    /// #     data: json!({}),
+    /// #     metadata: Some(json!({"correlationStreamName": "transfer-9"})),
+    /// #     position: 0,
+    /// #     global_position: 41,
+    /// #     time: Utc::now(),
+    /// # };
+    /// let event = WriteMessage::new(Uuid::new_v4(), "account-123", "Withdrawn").follow(&command);
+    ///
+    /// let metadata = event.metadata.unwrap();
+    /// assert_eq!(metadata["causationMessageStreamName"], "withdrawal:command-123");
+    /// assert_eq!(metadata["correlationStreamName"], "transfer-9");
+    /// ```
+    pub fn follow(mut self, preceding: &Message) -> Self {
+        metadata::insert(
+            &mut self.metadata,
+            MetadataKeys::CAUSATION_MESSAGE_STREAM_NAME,
+            Value::String(preceding.stream_name.clone()),
+        );
+        metadata::insert(
+            &mut self.metadata,
+            MetadataKeys::CAUSATION_MESSAGE_POSITION,
+            Value::from(preceding.position),
+        );
+        metadata::insert(
+            &mut self.metadata,
+            MetadataKeys::CAUSATION_MESSAGE_GLOBAL_POSITION,
+            Value::from(preceding.global_position),
+        );
+
+        if let Some(correlation) = preceding.correlation_stream_name() {
+            self = self.with_correlation_stream_name(correlation);
+        }
+        if let Some(reply) = preceding.reply_stream_name() {
+            self = self.with_reply_stream_name(reply);
+        }
+        self
+    }
+
+    /// Copy of this message under a new id, with the original id kept in
+    /// metadata as `original_message_id`
+    pub(crate) fn with_regenerated_id(&self) -> Self {
+        let mut metadata = match &self.metadata {
+            Some(Value::Object(map)) => map.clone(),
+            _ => serde_json::Map::new(),
+        };
+        metadata.insert(
+            "original_message_id".to_string(),
+            Value::String(self.id.to_string()),
+        );
+
+        let mut regenerated = self.clone();
+        regenerated.id = Uuid::new_v4();
+        regenerated.metadata = Some(Value::Object(metadata));
+        regenerated
+    }
 }
 
 /// Message data read from Message DB
@@ -107,37 +275,136 @@ pub struct Message {
     pub time: DateTime<Utc>,
 }
 
+/// Where and when a message was written, returned by `write_message_ex`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriteResult {
+    /// Position of the message in its stream (0-based)
+    pub position: i64,
+
+    /// Position of the message in the entire message store
+    pub global_position: i64,
+
+    /// UTC timestamp the server recorded for the message
+    pub time: DateTime<Utc>,
+}
+
+/// A stream listed by `get_category_streams`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CategoryStream {
+    /// Name of the stream
+    pub stream_name: String,
+
+    /// Global position of the stream's first message; the next page starts
+    /// one past the last of these
+    pub first_global_position: i64,
+}
+
 impl Message {
+    /// Deserialize the data payload as `T`
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::DeserializationError` naming this message and `T` if
+    /// the data doesn't match.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rust2::message_db::Message;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Withdrawn {
+    ///     amount: u64,
+    /// }
+    ///
+    /// fn handle(message: &Message) -> rust2::message_db::Result<()> {
+    ///     let event: Withdrawn = message.data_as()?;
+    ///     println!("withdrew {}", event.amount);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn data_as<T: DeserializeOwned>(&self) -> Result<T> {
+        self.deserialize(self.data.clone())
+    }
+
+    /// Deserialize the metadata as `T`, or `None` if the message has no metadata
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::DeserializationError` naming this message and `T` if
+    /// the metadata doesn't match.
+    pub fn metadata_as<T: DeserializeOwned>(&self) -> Result<Option<T>> {
+        match &self.metadata {
+            None | Some(Value::Null) => Ok(None),
+            Some(metadata) => self.deserialize(metadata.clone()).map(Some),
+        }
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, value: Value) -> Result<T> {
+        serde_json::from_value(value).map_err(|source| Error::DeserializationError {
+            message_id: self.id,
+            type_name: std::any::type_name::<T>(),
+            source,
+        })
+    }
+
+    /// String metadata value under canonical `key` or its snake_case spelling
+    fn metadata_str(&self, key: &str) -> Option<&str> {
+        metadata::lookup(self.metadata.as_ref(), key).and_then(|v| v.as_str())
+    }
+
     /// Get the correlation ID from metadata if present
     pub fn correlation_id(&self) -> Option<&str> {
-        self.metadata
-            .as_ref()
-            .and_then(|m| m.get("correlation_id"))
-            .and_then(|v| v.as_str())
+        self.metadata_str(MetadataKeys::CORRELATION_ID)
     }
 
     /// Get the causation ID from metadata if present
     pub fn causation_id(&self) -> Option<&str> {
-        self.metadata
-            .as_ref()
-            .and_then(|m| m.get("causation_id"))
-            .and_then(|v| v.as_str())
+        self.metadata_str(MetadataKeys::CAUSATION_ID)
+    }
+
+    /// Get the correlation stream name from metadata if present
+    pub fn correlation_stream_name(&self) -> Option<&str> {
+        self.metadata_str(MetadataKeys::CORRELATION_STREAM_NAME)
+    }
+
+    /// Get the causing message's stream name from metadata if present
+    pub fn causation_message_stream_name(&self) -> Option<&str> {
+        self.metadata_str(MetadataKeys::CAUSATION_MESSAGE_STREAM_NAME)
+    }
+
+    /// Get the causing message's stream position from metadata if present
+    pub fn causation_message_position(&self) -> Option<i64> {
+        metadata::lookup(self.metadata.as_ref(), MetadataKeys::CAUSATION_MESSAGE_POSITION)
+            .and_then(|v| v.as_i64())
+    }
+
+    /// Get the causing message's global position from metadata if present
+    pub fn causation_message_global_position(&self) -> Option<i64> {
+        metadata::lookup(
+            self.metadata.as_ref(),
+            MetadataKeys::CAUSATION_MESSAGE_GLOBAL_POSITION,
+        )
+        .and_then(|v| v.as_i64())
     }
 
     /// Get the reply stream name from metadata if present
     pub fn reply_stream_name(&self) -> Option<&str> {
-        self.metadata
-            .as_ref()
-            .and_then(|m| m.get("reply_stream_name"))
-            .and_then(|v| v.as_str())
+        self.metadata_str(MetadataKeys::REPLY_STREAM_NAME)
     }
 
     /// Get the schema version from metadata if present
     pub fn schema_version(&self) -> Option<&str> {
-        self.metadata
-            .as_ref()
-            .and_then(|m| m.get("schema_version"))
-            .and_then(|v| v.as_str())
+        self.metadata_str(MetadataKeys::SCHEMA_VERSION)
+    }
+
+    /// Metadata with known keys in their canonical camelCase spelling
+    ///
+    /// Unknown keys are kept as written. Returns an empty map when the
+    /// message has no metadata object.
+    pub fn normalized_metadata(&self) -> serde_json::Map<String, Value> {
+        metadata::normalize(self.metadata.as_ref())
     }
 }
 
@@ -160,6 +427,23 @@ mod tests {
         assert_eq!(msg.data["amount"], 50);
         assert_eq!(msg.metadata.as_ref().unwrap()["correlation_id"], "xyz");
         assert_eq!(msg.expected_version, Some(4));
+        assert_eq!(msg.id_conflict_policy, IdConflictPolicy::Idempotent);
+    }
+
+    #[test]
+    fn test_with_regenerated_id() {
+        let id = Uuid::new_v4();
+        let msg = WriteMessage::new(id, "account-123", "Withdrawn")
+            .with_metadata(json!({ "correlation_id": "xyz" }))
+            .with_id_conflict_policy(IdConflictPolicy::Regenerate);
+
+        let regenerated = msg.with_regenerated_id();
+
+        assert_ne!(regenerated.id, id);
+        let metadata = regenerated.metadata.unwrap();
+        assert_eq!(metadata["original_message_id"], id.to_string());
+        assert_eq!(metadata["correlation_id"], "xyz");
+        assert_eq!(regenerated.id_conflict_policy, IdConflictPolicy::Regenerate);
     }
 
     #[test]
@@ -186,6 +470,76 @@ mod tests {
         assert_eq!(msg.schema_version(), Some("2"));
     }
 
+    fn message_with_metadata(metadata: Value) -> Message {
+        Message {
+            id: Uuid::new_v4(),
+            stream_name: "withdrawal:command-123".to_string(),
+            message_type: "Withdraw".to_string(),
+            data: json!({}),
+            metadata: Some(metadata),
+            position: 3,
+            global_position: 42,
+            time: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_metadata_helpers_read_both_conventions() {
+        let camel = message_with_metadata(json!({
+            "correlationStreamName": "transfer-9",
+            "causationMessageStreamName": "transfer-9",
+            "causationMessagePosition": 1,
+            "causationMessageGlobalPosition": 7,
+            "replyStreamName": "replies-1",
+            "schemaVersion": "3",
+            "correlationId": "corr-1"
+        }));
+        let snake = message_with_metadata(json!({
+            "correlation_stream_name": "transfer-9",
+            "causation_message_stream_name": "transfer-9",
+            "causation_message_position": 1,
+            "causation_message_global_position": 7,
+            "reply_stream_name": "replies-1",
+            "schema_version": "3",
+            "correlation_id": "corr-1"
+        }));
+
+        for msg in [&camel, &snake] {
+            assert_eq!(msg.correlation_stream_name(), Some("transfer-9"));
+            assert_eq!(msg.causation_message_stream_name(), Some("transfer-9"));
+            assert_eq!(msg.causation_message_position(), Some(1));
+            assert_eq!(msg.causation_message_global_position(), Some(7));
+            assert_eq!(msg.reply_stream_name(), Some("replies-1"));
+            assert_eq!(msg.schema_version(), Some("3"));
+            assert_eq!(msg.correlation_id(), Some("corr-1"));
+        }
+        assert_eq!(camel.normalized_metadata(), snake.normalized_metadata());
+    }
+
+    #[test]
+    fn test_follow_writes_canonical_keys() {
+        let command = message_with_metadata(json!({
+            "correlation_stream_name": "transfer-9",
+            "reply_stream_name": "replies-1"
+        }));
+
+        let event = WriteMessage::new(Uuid::new_v4(), "account-123", "Withdrawn")
+            .with_metadata(json!({"correlation_stream_name": "stale", "tenant": "acme"}))
+            .follow(&command);
+
+        assert_eq!(
+            event.metadata.unwrap(),
+            json!({
+                "tenant": "acme",
+                "causationMessageStreamName": "withdrawal:command-123",
+                "causationMessagePosition": 3,
+                "causationMessageGlobalPosition": 42,
+                "correlationStreamName": "transfer-9",
+                "replyStreamName": "replies-1"
+            })
+        );
+    }
+
     #[test]
     fn test_message_metadata_helpers_none() {
         let msg = Message {
@@ -203,5 +557,83 @@ mod tests {
         assert_eq!(msg.causation_id(), None);
         assert_eq!(msg.reply_stream_name(), None);
         assert_eq!(msg.schema_version(), None);
+        assert_eq!(msg.correlation_stream_name(), None);
+        assert!(msg.normalized_metadata().is_empty());
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Withdrawn {
+        amount: u64,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Trace {
+        #[serde(rename = "correlationId")]
+        correlation_id: String,
+    }
+
+    fn read_message(data: Value, metadata: Option<Value>) -> Message {
+        Message {
+            id: Uuid::new_v4(),
+            stream_name: "account-123".to_string(),
+            message_type: "Withdrawn".to_string(),
+            data,
+            metadata,
+            position: 0,
+            global_position: 1,
+            time: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_data_as() {
+        let msg = read_message(json!({ "amount": 50 }), Some(json!({ "correlationId": "xyz" })));
+
+        assert_eq!(msg.data_as::<Withdrawn>().unwrap(), Withdrawn { amount: 50 });
+        assert_eq!(
+            msg.metadata_as::<Trace>().unwrap(),
+            Some(Trace {
+                correlation_id: "xyz".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_data_as_type_mismatch_names_message() {
+        let msg = read_message(json!({ "amount": "fifty" }), Some(json!({ "correlationId": 7 })));
+
+        match msg.data_as::<Withdrawn>() {
+            Err(Error::DeserializationError {
+                message_id,
+                type_name,
+                ..
+            }) => {
+                assert_eq!(message_id, msg.id);
+                assert!(type_name.ends_with("Withdrawn"));
+            }
+            other => panic!("expected DeserializationError, got {:?}", other),
+        }
+        assert!(matches!(
+            msg.metadata_as::<Trace>(),
+            Err(Error::DeserializationError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_metadata_as_absent() {
+        let msg = read_message(json!({}), None);
+        assert_eq!(msg.metadata_as::<Trace>().unwrap(), None);
+
+        let msg = read_message(json!({}), Some(Value::Null));
+        assert_eq!(msg.metadata_as::<Trace>().unwrap(), None);
+    }
+
+    #[test]
+    fn test_with_typed_data() {
+        let msg = WriteMessage::new(Uuid::new_v4(), "account-123", "Withdrawn")
+            .with_typed_data(&Withdrawn { amount: 50 })
+            .unwrap();
+
+        assert_eq!(msg.data, json!({ "amount": 50 }));
     }
 }
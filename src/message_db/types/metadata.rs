@@ -0,0 +1,209 @@
+//! Message metadata key names
+//!
+//! Message DB and the Eventide ecosystem spell metadata keys in camelCase
+//! (`correlationStreamName`, `causationMessageStreamName`, ...), and the
+//! store's correlation filter only looks at `correlationStreamName`. The
+//! helpers on [`Message`](super::Message) and
+//! [`WriteMessage`](super::WriteMessage) write these canonical names and
+//! also read the snake_case spelling, so messages written by older Rust code
+//! are still understood.
+
+use serde_json::{Map, Value};
+
+/// Canonical (camelCase) metadata keys
+///
+/// # Example
+///
+/// ```
+/// use rust2::message_db::types::MetadataKeys;
+///
+/// assert_eq!(MetadataKeys::CORRELATION_STREAM_NAME, "correlationStreamName");
+/// assert_eq!(
+///     MetadataKeys::canonical("correlation_stream_name"),
+///     Some(MetadataKeys::CORRELATION_STREAM_NAME)
+/// );
+/// ```
+pub struct MetadataKeys;
+
+impl MetadataKeys {
+    /// Stream of the message that started the workflow; used by the correlation filter
+    pub const CORRELATION_STREAM_NAME: &'static str = "correlationStreamName";
+    /// Stream of the message this one was written in response to
+    pub const CAUSATION_MESSAGE_STREAM_NAME: &'static str = "causationMessageStreamName";
+    /// Stream position of the causing message
+    pub const CAUSATION_MESSAGE_POSITION: &'static str = "causationMessagePosition";
+    /// Global position of the causing message
+    pub const CAUSATION_MESSAGE_GLOBAL_POSITION: &'static str = "causationMessageGlobalPosition";
+    /// Stream a reply should be written to
+    pub const REPLY_STREAM_NAME: &'static str = "replyStreamName";
+    /// Version of the message's data schema
+    pub const SCHEMA_VERSION: &'static str = "schemaVersion";
+    /// Application-level correlation id
+    pub const CORRELATION_ID: &'static str = "correlationId";
+    /// Application-level causation id
+    pub const CAUSATION_ID: &'static str = "causationId";
+
+    /// Every canonical key
+    pub const ALL: &'static [&'static str] = &[
+        Self::CORRELATION_STREAM_NAME,
+        Self::CAUSATION_MESSAGE_STREAM_NAME,
+        Self::CAUSATION_MESSAGE_POSITION,
+        Self::CAUSATION_MESSAGE_GLOBAL_POSITION,
+        Self::REPLY_STREAM_NAME,
+        Self::SCHEMA_VERSION,
+        Self::CORRELATION_ID,
+        Self::CAUSATION_ID,
+    ];
+
+    /// The canonical spelling of `key`, if it is a known key in either convention
+    pub fn canonical(key: &str) -> Option<&'static str> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|canonical| *canonical == key || snake_case(canonical) == key)
+    }
+}
+
+/// Metadata keys added to a dead-lettered copy of a message
+///
+/// Spelled in camelCase like [`MetadataKeys`], so Eventide tooling reading
+/// the dead letter stream sees the same convention as everywhere else.
+///
+/// # Example
+///
+/// ```
+/// use rust2::message_db::types::DeadLetterKeys;
+///
+/// assert_eq!(DeadLetterKeys::ORIGINAL_STREAM_NAME, "originalStreamName");
+/// ```
+pub struct DeadLetterKeys;
+
+impl DeadLetterKeys {
+    /// Error the handler failed with
+    pub const ERROR: &'static str = "error";
+    /// Stream the failed message was read from
+    pub const ORIGINAL_STREAM_NAME: &'static str = "originalStreamName";
+    /// Stream position of the failed message
+    pub const ORIGINAL_POSITION: &'static str = "originalPosition";
+    /// Global position of the failed message
+    pub const ORIGINAL_GLOBAL_POSITION: &'static str = "originalGlobalPosition";
+    /// Id of the failed message
+    pub const ORIGINAL_ID: &'static str = "originalId";
+}
+
+/// `correlationStreamName` -> `correlation_stream_name`
+pub(crate) fn snake_case(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 4);
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            snake.push('_');
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// Value of canonical `key`, falling back to its snake_case spelling
+pub(crate) fn lookup<'a>(metadata: Option<&'a Value>, key: &str) -> Option<&'a Value> {
+    let metadata = metadata?;
+    metadata
+        .get(key)
+        .or_else(|| metadata.get(snake_case(key).as_str()))
+}
+
+/// Set canonical `key`, removing any snake_case spelling of it
+pub(crate) fn insert(metadata: &mut Option<Value>, key: &str, value: Value) {
+    let map = match metadata {
+        Some(Value::Object(map)) => map,
+        _ => {
+            *metadata = Some(Value::Object(Map::new()));
+            match metadata {
+                Some(Value::Object(map)) => map,
+                _ => unreachable!(),
+            }
+        }
+    };
+    map.remove(&snake_case(key));
+    map.insert(key.to_string(), value);
+}
+
+/// Copy of `metadata` with known keys renamed to their canonical spelling
+///
+/// Other keys are kept as they are. If both spellings of a key are present,
+/// the canonical one wins.
+pub(crate) fn normalize(metadata: Option<&Value>) -> Map<String, Value> {
+    let Some(Value::Object(map)) = metadata else {
+        return Map::new();
+    };
+
+    let mut normalized = Map::new();
+    for (key, value) in map {
+        match MetadataKeys::canonical(key) {
+            Some(canonical) if canonical != key => {
+                if !map.contains_key(canonical) {
+                    normalized.insert(canonical.to_string(), value.clone());
+                }
+            }
+            _ => {
+                normalized.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_canonical_accepts_both_conventions() {
+        assert_eq!(
+            MetadataKeys::canonical("causationMessageGlobalPosition"),
+            Some(MetadataKeys::CAUSATION_MESSAGE_GLOBAL_POSITION)
+        );
+        assert_eq!(
+            MetadataKeys::canonical("causation_message_global_position"),
+            Some(MetadataKeys::CAUSATION_MESSAGE_GLOBAL_POSITION)
+        );
+        assert_eq!(MetadataKeys::canonical("tenant"), None);
+    }
+
+    #[test]
+    fn test_insert_replaces_snake_case_spelling() {
+        let mut metadata = Some(json!({"reply_stream_name": "old", "tenant": "acme"}));
+        insert(&mut metadata, MetadataKeys::REPLY_STREAM_NAME, json!("new"));
+        assert_eq!(
+            metadata,
+            Some(json!({"replyStreamName": "new", "tenant": "acme"}))
+        );
+
+        let mut empty = None;
+        insert(&mut empty, MetadataKeys::SCHEMA_VERSION, json!("2"));
+        assert_eq!(empty, Some(json!({"schemaVersion": "2"})));
+    }
+
+    #[test]
+    fn test_normalize_prefers_canonical_keys() {
+        let metadata = json!({
+            "correlation_stream_name": "snake",
+            "correlationStreamName": "camel",
+            "causation_id": "cause-1",
+            "tenant_id": "acme"
+        });
+
+        let normalized = normalize(Some(&metadata));
+        assert_eq!(
+            Value::Object(normalized),
+            json!({
+                "correlationStreamName": "camel",
+                "causationId": "cause-1",
+                "tenant_id": "acme"
+            })
+        );
+        assert!(normalize(None).is_empty());
+    }
+}
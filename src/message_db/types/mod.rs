@@ -1,3 +1,5 @@
 pub mod message;
+pub mod metadata;
 
-pub use message::{Message, WriteMessage};
+pub use message::{CategoryStream, IdConflictPolicy, Message, WriteMessage, WriteResult};
+pub use metadata::{DeadLetterKeys, MetadataKeys};
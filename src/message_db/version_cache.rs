@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Hit/miss counters for a client's stream version cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VersionCacheStats {
+    /// Lookups answered from the cache
+    pub hits: u64,
+    /// Lookups that queried the database
+    pub misses: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    version: Option<i64>,
+    stored_at: Instant,
+    last_used: u64,
+}
+
+/// Bounded, time-limited cache of stream versions
+///
+/// Entries expire `ttl` after they were stored; when full, the least
+/// recently used entry is evicted.
+#[derive(Debug)]
+pub(crate) struct VersionCache {
+    entries: HashMap<String, Entry>,
+    capacity: usize,
+    ttl: Duration,
+    clock: u64,
+    stats: VersionCacheStats,
+}
+
+impl VersionCache {
+    pub(crate) fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity: capacity.max(1),
+            ttl,
+            clock: 0,
+            stats: VersionCacheStats::default(),
+        }
+    }
+
+    /// Look up a cached version, counting a hit or a miss
+    ///
+    /// The outer `Option` is the cache result; the inner one is the stream
+    /// version itself (`None` for a stream with no messages).
+    pub(crate) fn get(&mut self, stream_name: &str) -> Option<Option<i64>> {
+        self.clock += 1;
+
+        let fresh = match self.entries.get_mut(stream_name) {
+            Some(entry) if entry.stored_at.elapsed() < self.ttl => {
+                entry.last_used = self.clock;
+                Some(entry.version)
+            }
+            Some(_) => {
+                self.entries.remove(stream_name);
+                None
+            }
+            None => None,
+        };
+
+        match fresh {
+            Some(_) => self.stats.hits += 1,
+            None => self.stats.misses += 1,
+        }
+        fresh
+    }
+
+    /// Store a version read from the database
+    pub(crate) fn insert(&mut self, stream_name: &str, version: Option<i64>) {
+        self.clock += 1;
+
+        if !self.entries.contains_key(stream_name) && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(name, _)| name.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(
+            stream_name.to_string(),
+            Entry {
+                version,
+                stored_at: Instant::now(),
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Store a version read from the database, unless a newer one is cached
+    ///
+    /// The read may have started before a concurrent write recorded its
+    /// position, so the larger of the two versions wins.
+    pub(crate) fn fill(&mut self, stream_name: &str, version: Option<i64>) {
+        let cached = self.entries.get(stream_name).and_then(|entry| entry.version);
+        self.insert(stream_name, version.max(cached));
+    }
+
+    /// Record a successful write at `position`
+    ///
+    /// Versions only grow, so an older position (e.g. an idempotent rewrite
+    /// of an earlier message) never replaces a newer cached version.
+    pub(crate) fn record_write(&mut self, stream_name: &str, position: i64) {
+        let cached = self
+            .entries
+            .get(stream_name)
+            .and_then(|entry| entry.version)
+            .unwrap_or(-1);
+        self.insert(stream_name, Some(position.max(cached)));
+    }
+
+    /// Forget a stream's version, e.g. after a conflict proved it stale
+    pub(crate) fn invalidate(&mut self, stream_name: &str) {
+        self.entries.remove(stream_name);
+    }
+
+    pub(crate) fn stats(&self) -> VersionCacheStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hits_and_misses() {
+        let mut cache = VersionCache::new(8, Duration::from_secs(60));
+
+        assert_eq!(cache.get("account-1"), None);
+        cache.insert("account-1", Some(3));
+        cache.insert("account-2", None);
+
+        assert_eq!(cache.get("account-1"), Some(Some(3)));
+        assert_eq!(cache.get("account-2"), Some(None));
+        assert_eq!(cache.stats(), VersionCacheStats { hits: 2, misses: 1 });
+    }
+
+    #[test]
+    fn test_entries_expire_after_ttl() {
+        let mut cache = VersionCache::new(8, Duration::ZERO);
+
+        cache.insert("account-1", Some(3));
+        assert_eq!(cache.get("account-1"), None);
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = VersionCache::new(2, Duration::from_secs(60));
+
+        cache.insert("account-1", Some(1));
+        cache.insert("account-2", Some(2));
+        cache.get("account-1");
+        cache.insert("account-3", Some(3));
+
+        assert_eq!(cache.get("account-1"), Some(Some(1)));
+        assert_eq!(cache.get("account-2"), None);
+        assert_eq!(cache.get("account-3"), Some(Some(3)));
+    }
+
+    #[test]
+    fn test_writes_only_advance_the_version() {
+        let mut cache = VersionCache::new(8, Duration::from_secs(60));
+
+        cache.record_write("account-1", 0);
+        assert_eq!(cache.get("account-1"), Some(Some(0)));
+
+        cache.record_write("account-1", 5);
+        cache.record_write("account-1", 2);
+        assert_eq!(cache.get("account-1"), Some(Some(5)));
+
+        cache.invalidate("account-1");
+        assert_eq!(cache.get("account-1"), None);
+    }
+
+    #[test]
+    fn test_fill_keeps_a_newer_recorded_write() {
+        let mut cache = VersionCache::new(8, Duration::from_secs(60));
+
+        // A write lands while a read of the older version is in flight
+        cache.record_write("account-1", 7);
+        cache.fill("account-1", Some(6));
+        assert_eq!(cache.get("account-1"), Some(Some(7)));
+
+        cache.fill("account-1", Some(9));
+        assert_eq!(cache.get("account-1"), Some(Some(9)));
+
+        cache.fill("account-2", None);
+        assert_eq!(cache.get("account-2"), Some(None));
+    }
+}
@@ -1,5 +1,6 @@
 // Data structures (Message, Thread, etc.)
 
+use crate::webhooks::WebhookRegistration;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -12,6 +13,8 @@ pub enum MessageType {
     Agent,
     ToolCall,
     ToolResponse,
+    /// Stored event that couldn't be decoded
+    Unsupported,
 }
 
 // Message Content Variants
@@ -32,6 +35,11 @@ pub enum MessageContent {
         tool_call_id: String,
         result: serde_json::Value,
     },
+    /// Placeholder for a stored event written with an unknown or old shape
+    Unsupported {
+        text: String,
+        schema_version: Option<u64>,
+    },
 }
 
 // Message Struct
@@ -54,6 +62,22 @@ pub struct ThreadResponse {
 #[derive(Debug, Clone, Deserialize)]
 pub struct SendMessageRequest {
     pub text: String,
+    /// URL to POST to when the run finishes (optional)
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    /// HMAC secret for signing the callback payload (optional)
+    #[serde(default)]
+    pub callback_secret: Option<String>,
+}
+
+impl SendMessageRequest {
+    /// Webhook registration, if a callback URL was supplied
+    pub fn webhook(&self) -> Option<WebhookRegistration> {
+        self.callback_url.as_ref().map(|url| WebhookRegistration {
+            callback_url: url.clone(),
+            secret: self.callback_secret.clone(),
+        })
+    }
 }
 
 // SSE Event Types
@@ -210,6 +234,16 @@ mod tests {
         let json = r#"{"text":"Hello, world!"}"#;
         let request: SendMessageRequest = serde_json::from_str(json).unwrap();
         assert_eq!(request.text, "Hello, world!");
+        assert!(request.webhook().is_none());
+    }
+
+    #[test]
+    fn test_send_message_request_with_callback() {
+        let json = r#"{"text":"Hi","callback_url":"https://example.com/hook","callback_secret":"s3cret"}"#;
+        let request: SendMessageRequest = serde_json::from_str(json).unwrap();
+        let webhook = request.webhook().unwrap();
+        assert_eq!(webhook.callback_url, "https://example.com/hook");
+        assert_eq!(webhook.secret.as_deref(), Some("s3cret"));
     }
 
     #[test]
@@ -1,12 +1,27 @@
 // Route definitions and handlers
 
+use crate::deps::AppDeps;
 use crate::handlers;
+use crate::llm::moderation::Moderator;
+use crate::llm::tools::RegistrySnapshot;
+use crate::usage::{UsageLedger, API_KEY_HEADER};
+use std::sync::Arc;
 use uuid::Uuid;
 use warp::Filter;
 
-pub fn configure_routes() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
-{
+/// Configure every route against `deps`
+///
+/// Handlers get their model, tools, storage and policies from `deps`
+/// instead of building them, so tests can pass mocks.
+pub fn configure_routes(
+    deps: AppDeps,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     let api = warp::path("api").and(warp::path("v1"));
+    let api_key = warp::header::optional::<String>(API_KEY_HEADER);
+    let ledger = deps.usage.clone();
+    let usage = warp::any().map(move || ledger.clone());
+    let tools = deps.tool_snapshot();
+    let deps = warp::any().map(move || deps.clone());
 
     // GET /threads/{threadId}
     let get_thread = api
@@ -14,6 +29,7 @@ pub fn configure_routes() -> impl Filter<Extract = impl warp::Reply, Error = war
         .and(warp::path::param::<Uuid>())
         .and(warp::path::end())
         .and(warp::get())
+        .and(deps.clone())
         .and_then(handlers::get_thread_handler);
 
     // POST /threads/{threadId}
@@ -23,8 +39,87 @@ pub fn configure_routes() -> impl Filter<Extract = impl warp::Reply, Error = war
         .and(warp::path::end())
         .and(warp::post())
         .and(warp::body::json())
+        .and(api_key)
+        .and(deps.clone())
         .and_then(handlers::send_message_handler);
 
+    // POST /threads/{threadId}/messages/stream-input
+    let stream_input = api
+        .and(warp::path("threads"))
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path("messages"))
+        .and(warp::path("stream-input"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::header::optional::<String>("content-type"))
+        .and(warp::header::optional::<String>("content-encoding"))
+        .and(warp::body::stream())
+        .and(api_key)
+        .and(deps)
+        .and_then(handlers::stream_input_handler);
+
+    // GET /usage
+    let get_usage = api
+        .and(warp::path("usage"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(api_key)
+        .and(usage)
+        .and_then(handlers::get_usage_handler);
+
+    // GET /tools
+    let list_tools = api
+        .and(warp::path("tools"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<handlers::list_tools::ToolsQuery>())
+        .and(warp::any().map(move || tools.clone()))
+        .and_then(handlers::list_tools_handler);
+
     // Combine routes
-    get_thread.or(post_message)
+    get_thread
+        .or(post_message)
+        .or(stream_input)
+        .or(get_usage).or(list_tools)
+}
+
+/// Routes with user input screened by `moderator` before a run starts
+///
+/// Shorthand for [`configure_routes`] with only a moderator.
+#[deprecated(note = "use `configure_routes(AppDeps::new().with_moderator(moderator))`")]
+pub fn configure_routes_with_moderator(
+    moderator: Arc<dyn Moderator>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    configure_routes(AppDeps::new().with_moderator(moderator))
+}
+
+/// Routes with per-principal usage tracking
+///
+/// When `usage` is set, sending a message requires an `X-Api-Key` header,
+/// each run is recorded against that key and requests over the monthly
+/// budget are rejected with 429. `GET /usage` reports the key's consumption.
+#[deprecated(note = "use `configure_routes` with `AppDeps::with_usage`")]
+pub fn configure_routes_with_usage(
+    moderator: Arc<dyn Moderator>,
+    usage: Option<UsageLedger>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let mut deps = AppDeps::new().with_moderator(moderator);
+    deps.usage = usage;
+    configure_routes(deps)
+}
+
+/// Routes that also report the server's registered tools
+///
+/// `GET /tools` lists tool names; `GET /tools?format=snapshot` returns the
+/// full `RegistrySnapshot` so deployments can be compared for drift.
+#[deprecated(note = "use `configure_routes` with `AppDeps::with_tool_snapshot`")]
+pub fn configure_routes_with_tools(
+    moderator: Arc<dyn Moderator>,
+    usage: Option<UsageLedger>,
+    tools: Option<RegistrySnapshot>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let mut deps = AppDeps::new().with_moderator(moderator);
+    deps.usage = usage;
+    deps.tools = tools;
+    configure_routes(deps)
 }
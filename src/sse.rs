@@ -50,6 +50,69 @@ pub fn create_tool_response_event(
         .data(payload.to_string()))
 }
 
+/// Create a waiting SSE event while the model has not produced its first token
+///
+/// Frontends can use this to render a typing indicator with elapsed time.
+pub fn create_waiting_event(elapsed_ms: u64) -> Result<Event, std::convert::Infallible> {
+    let payload = serde_json::json!({
+        "elapsed_ms": elapsed_ms
+    });
+
+    Ok(Event::default()
+        .event("waiting")
+        .data(payload.to_string()))
+}
+
+/// Create an agent_text_discarded SSE event for text the agent dropped
+///
+/// Sent when the agent throws away the answer streamed so far and asks the
+/// model again, e.g. to repair invalid JSON. Frontends should remove the
+/// text received for message `id`.
+pub fn create_agent_text_discarded_event(
+    id: String,
+    reason: &str,
+) -> Result<Event, std::convert::Infallible> {
+    let payload = serde_json::json!({
+        "id": id,
+        "reason": reason
+    });
+
+    Ok(Event::default()
+        .event("agent_text_discarded")
+        .data(payload.to_string()))
+}
+
+/// Create an agent_text_redacted SSE event for a moderated final answer
+///
+/// Sent when the moderator redacted the answer after it was streamed.
+/// Frontends should replace the text received for message `id` with `text`.
+pub fn create_agent_text_redacted_event(
+    id: String,
+    text: String,
+) -> Result<Event, std::convert::Infallible> {
+    let payload = serde_json::json!({
+        "id": id,
+        "text": text
+    });
+
+    Ok(Event::default()
+        .event("agent_text_redacted")
+        .data(payload.to_string()))
+}
+
+/// Create an error SSE event for a run that failed
+///
+/// Sent just before `done`.
+pub fn create_error_event(message: String) -> Result<Event, std::convert::Infallible> {
+    let payload = serde_json::json!({
+        "message": message
+    });
+
+    Ok(Event::default()
+        .event("error")
+        .data(payload.to_string()))
+}
+
 /// Create a done SSE event to signal stream completion
 pub fn create_done_event() -> Result<Event, std::convert::Infallible> {
     let payload = serde_json::json!({});
@@ -104,6 +167,30 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_create_error_event() {
+        let result = create_error_event("Provider unavailable".to_string());
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_create_agent_text_discarded_event() {
+        let result = create_agent_text_discarded_event("msg-123".to_string(), "json_repair");
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_create_agent_text_redacted_event() {
+        let result = create_agent_text_redacted_event("msg-123".to_string(), "[redacted]".to_string());
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_create_waiting_event() {
+        let result = create_waiting_event(1500);
+        assert!(result.is_ok());
+    }
+
     #[test]
     fn test_agent_text_payload_format() {
         // Test JSON payload structure
@@ -0,0 +1,542 @@
+// Versioned conversation events on thread streams
+
+use crate::llm::{ContentBlock, Message as LlmMessage, MessageRole};
+use crate::message_db::limits::truncate_by;
+use crate::message_db::{Error, Message as StoredMessage, MessageDbClient, WriteMessage};
+use crate::models::{Message, MessageContent, MessageType};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Message type of a conversation message stored on a thread stream
+pub const MESSAGE_APPENDED: &str = "MessageAppended";
+
+/// Message type of the first event on a thread forked from another
+pub const THREAD_FORKED: &str = "ThreadForked";
+
+/// Metadata key holding the payload's schema version
+pub const PAYLOAD_SCHEMA_VERSION_KEY: &str = "payload_schema_version";
+
+/// Schema version of `llm::Message` payloads written by this build
+pub const PAYLOAD_SCHEMA_VERSION: u64 = 1;
+
+/// Upgrades a payload from one schema version to the next
+type Adapter = fn(Value) -> Result<Value, String>;
+
+/// Adapters indexed by the version they upgrade from, starting at v1
+///
+/// When the shape of `llm::Message` changes, bump `PAYLOAD_SCHEMA_VERSION`
+/// and append an adapter from the previous version.
+const ADAPTERS: &[Adapter] = &[
+    // v1 is the current shape
+    Ok,
+];
+
+/// Thread stream name: `thread-{thread_id}`
+pub fn thread_stream_name(thread_id: Uuid) -> String {
+    format!("thread-{}", thread_id)
+}
+
+/// Event recording `message` on the thread, stamped with the current schema version
+pub fn to_write_message(thread_id: Uuid, message: &LlmMessage) -> WriteMessage {
+    WriteMessage::new(
+        Uuid::new_v4(),
+        thread_stream_name(thread_id),
+        MESSAGE_APPENDED,
+    )
+    .with_data(serde_json::to_value(message).expect("messages are always serializable"))
+    .with_metadata(serde_json::json!({
+        PAYLOAD_SCHEMA_VERSION_KEY: PAYLOAD_SCHEMA_VERSION
+    }))
+}
+
+/// Append `message` to the thread stream, returning its position
+///
+/// A message over the client's data limit is written with its longest text
+/// and tool results truncated instead of failing, so one runaway tool result
+/// doesn't lose the conversation.
+///
+/// # Errors
+///
+/// Returns the client's error if the write fails, including
+/// `Error::PayloadTooLarge` if the message is still too large after
+/// truncating its text.
+pub async fn append_message(
+    client: &MessageDbClient,
+    thread_id: Uuid,
+    message: &LlmMessage,
+) -> Result<i64, Error> {
+    match client.write_message(to_write_message(thread_id, message)).await {
+        Err(Error::PayloadTooLarge {
+            field: "data",
+            size,
+            limit,
+        }) => {
+            tracing::warn!(%thread_id, size, limit, "truncating oversized conversation message");
+            let truncated = truncate_message(message, size - limit);
+            client
+                .write_message(to_write_message(thread_id, &truncated))
+                .await
+        }
+        result => result,
+    }
+}
+
+/// Where a forked thread branched off, the `forked_from` of a `ThreadForked` event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForkedFrom {
+    /// Stream of the thread the fork was taken from
+    pub stream: String,
+    /// Position in `stream` of the last message the fork kept, -1 if none
+    pub position: i64,
+}
+
+/// Lineage of a fork of `events` that keeps their first `message_index` messages
+///
+/// Counts only conversation messages, like
+/// [`Agent::fork_at`](crate::llm::Agent::fork_at), so other events on the
+/// thread don't shift the index. Returns `None` if the thread has fewer
+/// than `message_index` messages.
+pub fn fork_point(
+    source_thread_id: Uuid,
+    events: &[StoredMessage],
+    message_index: usize,
+) -> Option<ForkedFrom> {
+    let position = match message_index {
+        0 => -1,
+        n => {
+            events
+                .iter()
+                .filter(|e| e.message_type == MESSAGE_APPENDED)
+                .nth(n - 1)?
+                .position
+        }
+    };
+    Some(ForkedFrom {
+        stream: thread_stream_name(source_thread_id),
+        position,
+    })
+}
+
+/// Event starting thread `thread_id` as a fork of `forked_from`
+///
+/// Written with an expected version of -1, so it can only be the thread's
+/// first event.
+pub fn to_fork_message(thread_id: Uuid, forked_from: &ForkedFrom) -> WriteMessage {
+    WriteMessage::new(
+        Uuid::new_v4(),
+        thread_stream_name(thread_id),
+        THREAD_FORKED,
+    )
+    .with_data(serde_json::json!({ "forked_from": forked_from }))
+    .with_expected_version(-1)
+}
+
+/// Start thread `thread_id` as a fork, returning the positions written
+///
+/// Writes the `ThreadForked` lineage event followed by `messages`, the
+/// history the fork keeps (e.g. an [`Agent::fork_at`](crate::llm::Agent::fork_at)
+/// fork's `messages()`), in one transaction. Conversation readers skip the
+/// lineage event, so the fork renders and resumes like any other thread.
+///
+/// # Errors
+///
+/// Returns `Error::ConcurrencyError` if the thread already has events, or
+/// the client's error if the write fails.
+pub async fn append_fork(
+    client: &MessageDbClient,
+    thread_id: Uuid,
+    forked_from: &ForkedFrom,
+    messages: &[LlmMessage],
+) -> Result<Vec<i64>, Error> {
+    let events = std::iter::once(to_fork_message(thread_id, forked_from))
+        .chain(messages.iter().map(|m| to_write_message(thread_id, m)))
+        .collect();
+    client
+        .write_messages(&thread_stream_name(thread_id), events)
+        .await
+}
+
+/// Copy of `message` with its serialized size reduced by at least `excess` bytes
+///
+/// Shortens the longest text or tool result first; tool inputs and images
+/// are kept. Falls short if the message has too little text.
+fn truncate_message(message: &LlmMessage, excess: usize) -> LlmMessage {
+    let mut message = message.clone();
+    let mut remaining = excess;
+
+    while remaining > 0 {
+        let longest = message
+            .content
+            .iter_mut()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text),
+                ContentBlock::ToolResult { content, .. } => Some(content),
+                _ => None,
+            })
+            .max_by_key(|text| text.len());
+        let Some(text) = longest else {
+            break;
+        };
+
+        // Escaping makes each removed byte worth at least one serialized byte
+        let before = text.len();
+        truncate_by(text, remaining);
+        if text.len() >= before {
+            break;
+        }
+        remaining = remaining.saturating_sub(before - text.len());
+    }
+
+    message
+}
+
+/// Schema version an event was written with
+///
+/// Events written before versions were stamped are treated as v1.
+pub fn payload_schema_version(event: &StoredMessage) -> Option<u64> {
+    match event
+        .metadata
+        .as_ref()
+        .and_then(|m| m.get(PAYLOAD_SCHEMA_VERSION_KEY))
+    {
+        None => Some(1),
+        Some(version) => version.as_u64(),
+    }
+}
+
+/// Why a stored event couldn't be decoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    /// Version the event claims, if it is readable
+    pub schema_version: Option<u64>,
+    pub reason: String,
+}
+
+/// Decode a `MessageAppended` event, upgrading older payloads to the current shape
+pub fn decode(event: &StoredMessage) -> Result<LlmMessage, DecodeError> {
+    let version = payload_schema_version(event);
+    let error = |reason: String| DecodeError {
+        schema_version: version,
+        reason,
+    };
+
+    let Some(version) = version.filter(|v| (1..=PAYLOAD_SCHEMA_VERSION).contains(v)) else {
+        return Err(error("unsupported payload schema version".to_string()));
+    };
+
+    let mut data = event.data.clone();
+    for adapter in &ADAPTERS[(version - 1) as usize..] {
+        data = adapter(data).map_err(error)?;
+    }
+    if version < PAYLOAD_SCHEMA_VERSION {
+        tracing::warn!(
+            event_id = %event.id,
+            from = version,
+            to = PAYLOAD_SCHEMA_VERSION,
+            "migrated stored conversation message"
+        );
+    }
+
+    serde_json::from_value(data).map_err(|e| error(e.to_string()))
+}
+
+/// Render a thread stream's conversation events for the API
+///
+/// Events that aren't conversation messages are skipped. Events that can't
+/// be decoded become a single "unsupported message version" placeholder
+/// instead of failing the whole thread.
+pub fn render_thread(events: &[StoredMessage]) -> Vec<Message> {
+    let mut messages = Vec::new();
+
+    for event in events.iter().filter(|e| e.message_type == MESSAGE_APPENDED) {
+        match decode(event) {
+            Ok(message) => messages.extend(render_message(event, &message)),
+            Err(e) => {
+                tracing::warn!(
+                    event_id = %event.id,
+                    schema_version = ?e.schema_version,
+                    reason = %e.reason,
+                    "could not decode stored conversation message"
+                );
+                messages.push(Message {
+                    id: event.id.to_string(),
+                    message_type: MessageType::Unsupported,
+                    timestamp: event.time,
+                    content: MessageContent::Unsupported {
+                        text: "unsupported message version".to_string(),
+                        schema_version: e.schema_version,
+                    },
+                });
+            }
+        }
+    }
+
+    messages
+}
+
+/// One API message per content block; IDs are suffixed when there are several
+fn render_message(event: &StoredMessage, message: &LlmMessage) -> Vec<Message> {
+    let id = |i: usize| match message.content.len() {
+        1 => event.id.to_string(),
+        _ => format!("{}-{}", event.id, i),
+    };
+
+    message
+        .content
+        .iter()
+        .enumerate()
+        .filter_map(|(i, block)| {
+            let (message_type, content) = match (block, message.role) {
+                (ContentBlock::Text { text }, MessageRole::User) => (
+                    MessageType::User,
+                    MessageContent::User { text: text.clone() },
+                ),
+                (ContentBlock::Text { text }, _) => (
+                    MessageType::Agent,
+                    MessageContent::Agent { text: text.clone() },
+                ),
+                // The API has no image messages yet; show a placeholder
+                (ContentBlock::Image { media_type, .. }, MessageRole::User) => (
+                    MessageType::User,
+                    MessageContent::User {
+                        text: format!("[image: {}]", media_type),
+                    },
+                ),
+                (ContentBlock::Image { media_type, .. }, _) => (
+                    MessageType::Agent,
+                    MessageContent::Agent {
+                        text: format!("[image: {}]", media_type),
+                    },
+                ),
+                (ContentBlock::ToolUse { name, input, .. }, _) => (
+                    MessageType::ToolCall,
+                    MessageContent::ToolCall {
+                        tool_name: name.clone(),
+                        arguments: input.clone(),
+                    },
+                ),
+                (
+                    ContentBlock::ToolResult {
+                        tool_use_id,
+                        content,
+                        ..
+                    },
+                    _,
+                ) => (
+                    MessageType::ToolResponse,
+                    MessageContent::ToolResponse {
+                        tool_call_id: tool_use_id.clone(),
+                        result: serde_json::from_str(content)
+                            .unwrap_or_else(|_| Value::String(content.clone())),
+                    },
+                ),
+                // Encrypted model reasoning isn't shown
+                (ContentBlock::RedactedThinking { .. }, _) => return None,
+            };
+
+            Some(Message {
+                id: id(i),
+                message_type,
+                timestamp: event.time,
+                content,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+
+    fn stored(data: Value, metadata: Option<Value>) -> StoredMessage {
+        StoredMessage {
+            id: Uuid::new_v4(),
+            stream_name: "thread-1".to_string(),
+            message_type: MESSAGE_APPENDED.to_string(),
+            data,
+            metadata,
+            position: 0,
+            global_position: 0,
+            time: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_written_events_are_stamped() {
+        let thread_id = Uuid::new_v4();
+        let msg = to_write_message(thread_id, &LlmMessage::user("Hi"));
+
+        assert_eq!(msg.stream_name, format!("thread-{}", thread_id));
+        assert_eq!(msg.message_type, "MessageAppended");
+        assert_eq!(msg.metadata.unwrap()["payload_schema_version"], 1);
+        assert_eq!(msg.data["role"], "user");
+    }
+
+    #[test]
+    fn test_fork_point_counts_only_conversation_messages() {
+        let source = Uuid::new_v4();
+        let mut events: Vec<StoredMessage> = (0..4)
+            .map(|_| stored(json!({"role": "user", "content": []}), None))
+            .collect();
+        events[1].message_type = "WebhookDeliveryAttempted".to_string();
+        for (position, event) in events.iter_mut().enumerate() {
+            event.position = position as i64;
+        }
+
+        let fork = fork_point(source, &events, 2).unwrap();
+        assert_eq!(fork.stream, format!("thread-{}", source));
+        assert_eq!(fork.position, 2);
+        assert_eq!(fork_point(source, &events, 0).unwrap().position, -1);
+        assert_eq!(fork_point(source, &events, 3).unwrap().position, 3);
+        assert!(fork_point(source, &events, 4).is_none());
+    }
+
+    #[test]
+    fn test_fork_event_records_lineage() {
+        let thread_id = Uuid::new_v4();
+        let forked_from = ForkedFrom {
+            stream: "thread-source".to_string(),
+            position: 5,
+        };
+
+        let msg = to_fork_message(thread_id, &forked_from);
+
+        assert_eq!(msg.stream_name, format!("thread-{}", thread_id));
+        assert_eq!(msg.message_type, "ThreadForked");
+        assert_eq!(msg.expected_version, Some(-1));
+        assert_eq!(
+            msg.data,
+            json!({"forked_from": {"stream": "thread-source", "position": 5}})
+        );
+
+        // Conversation readers skip it
+        let mut event = stored(msg.data, None);
+        event.message_type = THREAD_FORKED.to_string();
+        assert!(render_thread(&[event]).is_empty());
+    }
+
+    #[test]
+    fn test_truncated_message_fits_the_limit() {
+        let message = LlmMessage {
+            role: MessageRole::Tool,
+            content: vec![
+                ContentBlock::ToolResult {
+                    tool_use_id: "t1".to_string(),
+                    content: "\"row\",".repeat(2000),
+                    is_error: false,
+                },
+                ContentBlock::Text {
+                    text: "short note".to_string(),
+                },
+            ],
+        };
+        let size = serde_json::to_string(&message).unwrap().len();
+
+        let truncated = truncate_message(&message, 5000);
+
+        assert!(serde_json::to_string(&truncated).unwrap().len() <= size - 5000);
+        match &truncated.content[..] {
+            [ContentBlock::ToolResult { content, .. }, ContentBlock::Text { text }] => {
+                assert!(content.ends_with("[truncated]"));
+                assert_eq!(text, "short note");
+            }
+            other => panic!("unexpected content: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_truncating_spreads_over_blocks() {
+        let message = LlmMessage::assistant("a".repeat(100));
+        let truncated = truncate_message(&message, 1000);
+
+        // Nothing left to cut beyond the marker
+        assert_eq!(truncated.content.len(), 1);
+        assert!(serde_json::to_string(&truncated).unwrap().len() < 100);
+    }
+
+    #[test]
+    fn test_v1_and_unstamped_events_decode() {
+        let data = json!({
+            "role": "assistant",
+            "content": [
+                {"type": "text", "text": "Checking"},
+                {"type": "tool_use", "id": "t1", "name": "weather", "input": {"city": "Paris"}}
+            ]
+        });
+
+        for metadata in [Some(json!({"payload_schema_version": 1})), None] {
+            let message = decode(&stored(data.clone(), metadata)).unwrap();
+            assert_eq!(message.role, MessageRole::Assistant);
+            assert_eq!(message.content.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_undecodable_events_render_as_placeholders() {
+        // Pre-release shape: a bare string body and a "sender" field
+        let old_shape = stored(
+            json!({"sender": "user", "body": "Hi"}),
+            Some(json!({"payload_schema_version": 1})),
+        );
+        let future = stored(
+            json!({"role": "user", "content": []}),
+            Some(json!({"payload_schema_version": 7})),
+        );
+        let ok = stored(
+            json!({"role": "tool", "content": [
+                {"type": "tool_result", "tool_use_id": "t1", "content": "{\"temp\": 18}"}
+            ]}),
+            None,
+        );
+        let mut unrelated = stored(json!({}), None);
+        unrelated.message_type = "WebhookDeliveryAttempted".to_string();
+
+        let err = decode(&old_shape).unwrap_err();
+        assert_eq!(err.schema_version, Some(1));
+        assert!(err.reason.contains("role"));
+        assert_eq!(decode(&future).unwrap_err().schema_version, Some(7));
+
+        let rendered = render_thread(&[old_shape.clone(), future, ok.clone(), unrelated]);
+        assert_eq!(rendered.len(), 3);
+        assert_eq!(rendered[0].id, old_shape.id.to_string());
+        assert_eq!(rendered[0].message_type, MessageType::Unsupported);
+        assert_eq!(
+            rendered[1].content,
+            MessageContent::Unsupported {
+                text: "unsupported message version".to_string(),
+                schema_version: Some(7),
+            }
+        );
+        assert_eq!(
+            rendered[2].content,
+            MessageContent::ToolResponse {
+                tool_call_id: "t1".to_string(),
+                result: json!({"temp": 18}),
+            }
+        );
+        assert_eq!(rendered[2].id, ok.id.to_string());
+    }
+
+    #[test]
+    fn test_images_render_as_placeholders() {
+        let event = stored(
+            json!({"role": "user", "content": [
+                {"type": "text", "text": "What is this?"},
+                {"type": "image", "media_type": "image/png", "data": {"url": "https://example.com/a.png"}}
+            ]}),
+            None,
+        );
+
+        let rendered = render_thread(&[event]);
+        assert_eq!(rendered.len(), 2);
+        assert_eq!(
+            rendered[1].content,
+            MessageContent::User {
+                text: "[image: image/png]".to_string(),
+            }
+        );
+    }
+}
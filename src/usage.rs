@@ -0,0 +1,374 @@
+// Per-principal token usage and monthly quotas
+
+use crate::llm::UsageMetadata;
+use crate::message_db::{Message, MessageDbClient, Result, StreamReadOptions, WriteMessage};
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Header identifying the principal making a request
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Message type of usage events
+pub const USAGE_RECORDED: &str = "UsageRecorded";
+
+/// Messages read per page when folding a usage stream
+const READ_BATCH: i64 = 500;
+
+/// Stable identifier for an API key, safe to use in stream names
+///
+/// The first 16 hex characters of the key's SHA-256 digest, so raw keys are
+/// never written to the message store.
+pub fn key_id(api_key: &str) -> String {
+    hex::encode(Sha256::digest(api_key.as_bytes()))[..16].to_string()
+}
+
+/// Stream holding a principal's usage events: `usageAccount-{key_id}`
+pub fn usage_stream_name(key_id: &str) -> String {
+    format!("usageAccount-{}", key_id)
+}
+
+/// Data of a `UsageRecorded` event, written once per finished run
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UsageRecorded {
+    pub run_id: Uuid,
+    pub model: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub total_tokens: u32,
+    /// Cost of the run in US dollars
+    pub cost_usd: f64,
+}
+
+impl UsageRecorded {
+    /// Usage for a run, with no cost attached
+    pub fn new(run_id: Uuid, model: impl Into<String>, usage: &UsageMetadata) -> Self {
+        Self {
+            run_id,
+            model: model.into(),
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            total_tokens: usage.total_tokens,
+            cost_usd: 0.0,
+        }
+    }
+
+    /// Set the cost of the run in US dollars
+    pub fn with_cost(mut self, cost_usd: f64) -> Self {
+        self.cost_usd = cost_usd;
+        self
+    }
+
+    /// Event for the principal's usage stream
+    pub fn to_write_message(&self, key_id: &str) -> WriteMessage {
+        WriteMessage::new(Uuid::new_v4(), usage_stream_name(key_id), USAGE_RECORDED)
+            .with_data(serde_json::to_value(self).expect("usage is always serializable"))
+    }
+}
+
+/// A principal's consumption in the current calendar month (UTC)
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UsageSummary {
+    pub key_id: String,
+    /// Month being reported, as `YYYY-MM`
+    pub period: String,
+    pub runs: u64,
+    pub tokens_used: u64,
+    pub cost_usd: f64,
+    /// Monthly token cap, if one is configured
+    pub token_limit: Option<u64>,
+    /// Tokens left before the cap is reached
+    pub tokens_remaining: Option<u64>,
+    /// When the budget resets (start of next month)
+    pub resets_at: DateTime<Utc>,
+}
+
+impl UsageSummary {
+    /// Fold the `UsageRecorded` events written in the month containing `now`
+    ///
+    /// Other message types and events from earlier months are ignored.
+    pub fn fold(
+        key_id: &str,
+        messages: &[Message],
+        now: DateTime<Utc>,
+        token_limit: Option<u64>,
+    ) -> Self {
+        let mut summary = Self::empty(key_id, now, token_limit);
+        summary.add_messages(messages);
+        summary
+    }
+
+    /// A summary of the month containing `now` with nothing used yet
+    fn empty(key_id: &str, now: DateTime<Utc>, token_limit: Option<u64>) -> Self {
+        let (period_start, resets_at) = month_bounds(now);
+        Self {
+            key_id: key_id.to_string(),
+            period: period_start.format("%Y-%m").to_string(),
+            runs: 0,
+            tokens_used: 0,
+            cost_usd: 0.0,
+            token_limit,
+            tokens_remaining: token_limit,
+            resets_at,
+        }
+    }
+
+    /// Fold more events into the summary, skipping those before its month
+    fn add_messages(&mut self, messages: &[Message]) {
+        // The month being reported is the one just before the reset
+        let (period_start, _) = month_bounds(self.resets_at - Duration::days(1));
+
+        for message in messages {
+            if message.message_type != USAGE_RECORDED || message.time < period_start {
+                continue;
+            }
+            match serde_json::from_value::<UsageRecorded>(message.data.clone()) {
+                Ok(usage) => {
+                    self.runs += 1;
+                    self.tokens_used += u64::from(usage.total_tokens);
+                    self.cost_usd += usage.cost_usd;
+                }
+                Err(e) => tracing::warn!(
+                    event_id = %message.id,
+                    error = %e,
+                    "skipping malformed usage event"
+                ),
+            }
+        }
+
+        self.tokens_remaining = self
+            .token_limit
+            .map(|limit| limit.saturating_sub(self.tokens_used));
+    }
+
+    /// Whether the monthly cap has been reached
+    pub fn is_over_budget(&self) -> bool {
+        self.tokens_remaining == Some(0)
+    }
+
+    /// Seconds from `now` until the budget resets
+    pub fn seconds_until_reset(&self, now: DateTime<Utc>) -> u64 {
+        (self.resets_at - now).num_seconds().max(0) as u64
+    }
+}
+
+/// Start of the UTC month containing `now`, and start of the next one
+fn month_bounds(now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    let (next_year, next_month) = if now.month() == 12 {
+        (now.year() + 1, 1)
+    } else {
+        (now.year(), now.month() + 1)
+    };
+    let start = Utc
+        .with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .unwrap();
+    let next = Utc.with_ymd_and_hms(next_year, next_month, 1, 0, 0, 0).unwrap();
+    (start, next)
+}
+
+/// Records usage events and enforces a monthly token cap per principal
+///
+/// Usage is kept in `usageAccount-{key_id}` streams. Summaries are folded on
+/// demand from the stream, so every server instance sees the same totals
+/// without running a consumer. Each ledger (and its clones) remembers the
+/// folded totals and stream position per principal, so a summary only reads
+/// the events written since the last one.
+#[derive(Clone)]
+pub struct UsageLedger {
+    client: MessageDbClient,
+    monthly_token_limit: Option<u64>,
+    folded: Arc<Mutex<HashMap<String, FoldedUsage>>>,
+}
+
+/// A principal's summary as of a stream position
+#[derive(Clone)]
+struct FoldedUsage {
+    summary: UsageSummary,
+    /// Position of the next event to fold
+    next_position: i64,
+}
+
+impl UsageLedger {
+    /// Create a ledger with no cap; usage is recorded but never enforced
+    pub fn new(client: MessageDbClient) -> Self {
+        Self {
+            client,
+            monthly_token_limit: None,
+            folded: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Set the number of tokens each principal may use per calendar month
+    pub fn with_monthly_token_limit(mut self, limit: u64) -> Self {
+        self.monthly_token_limit = Some(limit);
+        self
+    }
+
+    /// Append a `UsageRecorded` event to the principal's stream
+    ///
+    /// Returns the position of the written event.
+    pub async fn record(&self, key_id: &str, usage: &UsageRecorded) -> Result<i64> {
+        self.client.write_message(usage.to_write_message(key_id)).await
+    }
+
+    /// The principal's usage so far this month
+    ///
+    /// Folds only the events written since the previous summary for this
+    /// principal; the totals start over when a new month begins.
+    pub async fn summary(&self, key_id: &str) -> Result<UsageSummary> {
+        let now = Utc::now();
+        let empty = UsageSummary::empty(key_id, now, self.monthly_token_limit);
+        let cached = self.folded.lock().unwrap().get(key_id).cloned();
+        let (mut summary, mut position) = match cached {
+            Some(folded) if folded.summary.period == empty.period => {
+                (folded.summary, folded.next_position)
+            }
+            Some(folded) => (empty, folded.next_position),
+            None => (empty, 0),
+        };
+
+        let stream_name = usage_stream_name(key_id);
+        loop {
+            let options = StreamReadOptions::new(&stream_name)
+                .with_position(position)
+                .with_batch_size(READ_BATCH);
+            let page = self.client.get_stream_messages(options).await?;
+
+            if let Some(last) = page.last() {
+                position = last.position + 1;
+            }
+            summary.add_messages(&page);
+
+            if (page.len() as i64) < READ_BATCH {
+                break;
+            }
+        }
+
+        let mut folded = self.folded.lock().unwrap();
+        let newer_than_cached = folded
+            .get(key_id)
+            .is_none_or(|cached| cached.next_position <= position);
+        if newer_than_cached {
+            folded.insert(
+                key_id.to_string(),
+                FoldedUsage {
+                    summary: summary.clone(),
+                    next_position: position,
+                },
+            );
+        }
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn usage_message(position: i64, time: DateTime<Utc>, total_tokens: u32) -> Message {
+        Message {
+            id: Uuid::new_v4(),
+            stream_name: "usageAccount-abc".to_string(),
+            message_type: USAGE_RECORDED.to_string(),
+            data: json!({
+                "run_id": Uuid::new_v4(),
+                "model": "gemini-2.5-flash",
+                "input_tokens": total_tokens,
+                "output_tokens": 0,
+                "total_tokens": total_tokens,
+                "cost_usd": 0.25,
+            }),
+            metadata: None,
+            position,
+            global_position: position,
+            time,
+        }
+    }
+
+    #[test]
+    fn test_key_id_is_stable_and_hides_the_key() {
+        let id = key_id("sk-live-123");
+        assert_eq!(id.len(), 16);
+        assert_eq!(id, key_id("sk-live-123"));
+        assert_ne!(id, key_id("sk-live-456"));
+        assert!(!id.contains("sk-live"));
+        assert_eq!(usage_stream_name(&id), format!("usageAccount-{}", id));
+    }
+
+    #[test]
+    fn test_usage_event() {
+        let usage = UsageRecorded::new(Uuid::new_v4(), "claude-sonnet-4-5", &UsageMetadata::new(12, 8))
+            .with_cost(0.01);
+
+        let msg = usage.to_write_message("abc");
+        assert_eq!(msg.stream_name, "usageAccount-abc");
+        assert_eq!(msg.message_type, "UsageRecorded");
+        assert_eq!(msg.data["total_tokens"], 20);
+        assert_eq!(msg.data["cost_usd"], 0.01);
+    }
+
+    #[test]
+    fn test_fold_counts_only_the_current_month() {
+        let now = Utc.with_ymd_and_hms(2025, 12, 15, 10, 0, 0).unwrap();
+        let last_month = Utc.with_ymd_and_hms(2025, 11, 30, 23, 59, 59).unwrap();
+        let mut other = usage_message(3, now, 1000);
+        other.message_type = "SomethingElse".to_string();
+
+        let messages = vec![
+            usage_message(0, last_month, 500),
+            usage_message(1, now, 30),
+            usage_message(2, now, 40),
+            other,
+        ];
+
+        let summary = UsageSummary::fold("abc", &messages, now, Some(100));
+        assert_eq!(summary.period, "2025-12");
+        assert_eq!(summary.runs, 2);
+        assert_eq!(summary.tokens_used, 70);
+        assert_eq!(summary.cost_usd, 0.5);
+        assert_eq!(summary.tokens_remaining, Some(30));
+        assert!(!summary.is_over_budget());
+        assert_eq!(
+            summary.resets_at,
+            Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(summary.seconds_until_reset(now), 16 * 86400 + 14 * 3600);
+    }
+
+    #[test]
+    fn test_fold_over_budget() {
+        let now = Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap();
+        let messages = vec![usage_message(0, now, 150)];
+
+        let summary = UsageSummary::fold("abc", &messages, now, Some(100));
+        assert_eq!(summary.tokens_remaining, Some(0));
+        assert!(summary.is_over_budget());
+
+        let uncapped = UsageSummary::fold("abc", &messages, now, None);
+        assert_eq!(uncapped.tokens_remaining, None);
+        assert!(!uncapped.is_over_budget());
+    }
+
+    #[test]
+    fn test_adding_messages_matches_a_full_fold() {
+        let now = Utc.with_ymd_and_hms(2025, 12, 15, 10, 0, 0).unwrap();
+        let last_month = Utc.with_ymd_and_hms(2025, 11, 30, 23, 59, 59).unwrap();
+        let messages = vec![
+            usage_message(0, last_month, 500),
+            usage_message(1, now, 30),
+            usage_message(2, now, 40),
+        ];
+
+        let mut summary = UsageSummary::fold("abc", &messages[..2], now, Some(100));
+        assert_eq!(summary.tokens_remaining, Some(70));
+        summary.add_messages(&messages[2..]);
+
+        assert_eq!(summary, UsageSummary::fold("abc", &messages, now, Some(100)));
+        assert_eq!(summary.tokens_remaining, Some(30));
+    }
+}
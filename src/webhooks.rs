@@ -0,0 +1,382 @@
+// Webhook callbacks for finished agent runs
+
+use crate::llm::UsageMetadata;
+use crate::message_db::limits::truncate_by;
+use crate::message_db::types::WriteMessage;
+use crate::message_db::{Error, MessageDbClient};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Header carrying the payload signature
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// Webhook registration supplied with a send-message request
+#[derive(Debug, Clone)]
+pub struct WebhookRegistration {
+    pub callback_url: String,
+    pub secret: Option<String>,
+}
+
+/// Terminal state of a run
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Completed,
+    Failed,
+}
+
+/// JSON body POSTed to the callback URL
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub run_id: Uuid,
+    pub thread_id: Uuid,
+    pub status: RunStatus,
+    pub final_text: Option<String>,
+    pub usage: Option<UsageMetadata>,
+}
+
+/// Outcome of a single delivery attempt
+#[derive(Debug, Clone, Serialize)]
+pub struct DeliveryAttempt {
+    pub attempt: u32,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl DeliveryAttempt {
+    /// Whether the receiver accepted the payload
+    pub fn succeeded(&self) -> bool {
+        self.status_code.is_some_and(|code| (200..300).contains(&code))
+    }
+
+    /// Copy with the error text shortened by at least `excess` bytes
+    fn with_error_truncated(&self, excess: usize) -> Self {
+        let mut attempt = self.clone();
+        if let Some(error) = &mut attempt.error {
+            truncate_by(error, excess);
+        }
+        attempt
+    }
+
+    /// Audit record for the thread stream (`thread-{thread_id}`)
+    pub fn to_write_message(&self, payload: &WebhookPayload, callback_url: &str) -> WriteMessage {
+        WriteMessage::new(
+            Uuid::new_v4(),
+            format!("thread-{}", payload.thread_id),
+            "WebhookDeliveryAttempted",
+        )
+        .with_data(serde_json::json!({
+            "run_id": payload.run_id,
+            "callback_url": callback_url,
+            "attempt": self.attempt,
+            "status_code": self.status_code,
+            "error": self.error,
+            "succeeded": self.succeeded(),
+            "timestamp": self.timestamp,
+        }))
+    }
+}
+
+/// Compute the signature header value: `sha256=<hex HMAC-SHA256(body, secret)>`
+pub fn sign_payload(body: &[u8], secret: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Delivers webhook payloads with retries and exponential backoff
+///
+/// Non-2xx responses and transport errors are retried up to `max_attempts`
+/// times in total, doubling the delay after each failure.
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    client: reqwest::Client,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    timeout: Duration,
+    audit_log: Option<MessageDbClient>,
+}
+
+impl WebhookDispatcher {
+    /// Create a dispatcher with 4 attempts, 500ms initial backoff and a 10s timeout
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(500),
+            timeout: Duration::from_secs(10),
+            audit_log: None,
+        }
+    }
+
+    /// Set the total number of attempts, including the first
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry
+    pub fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the per-attempt request timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Record every attempt as a `WebhookDeliveryAttempted` event on the thread stream
+    pub fn with_audit_log(mut self, client: MessageDbClient) -> Self {
+        self.audit_log = Some(client);
+        self
+    }
+
+    /// POST `payload` to the registered URL until it succeeds or attempts run out
+    ///
+    /// Returns every attempt made, in order.
+    pub async fn deliver(
+        &self,
+        registration: &WebhookRegistration,
+        payload: &WebhookPayload,
+    ) -> Vec<DeliveryAttempt> {
+        let body = serde_json::to_vec(payload).expect("webhook payload is always serializable");
+        let signature = registration
+            .secret
+            .as_deref()
+            .map(|secret| sign_payload(&body, secret));
+
+        let mut attempts = Vec::new();
+        let mut backoff = self.initial_backoff;
+
+        for attempt in 1..=self.max_attempts {
+            let mut request = self
+                .client
+                .post(&registration.callback_url)
+                .timeout(self.timeout)
+                .header("Content-Type", "application/json")
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+
+            let result = match request.send().await {
+                Ok(response) => DeliveryAttempt {
+                    attempt,
+                    status_code: Some(response.status().as_u16()),
+                    error: None,
+                    timestamp: Utc::now(),
+                },
+                Err(e) => DeliveryAttempt {
+                    attempt,
+                    status_code: None,
+                    error: Some(e.to_string()),
+                    timestamp: Utc::now(),
+                },
+            };
+
+            self.record(&result, registration, payload).await;
+            let succeeded = result.succeeded();
+            attempts.push(result);
+
+            if succeeded {
+                break;
+            }
+            if attempt < self.max_attempts {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+
+        attempts
+    }
+
+    async fn record(
+        &self,
+        attempt: &DeliveryAttempt,
+        registration: &WebhookRegistration,
+        payload: &WebhookPayload,
+    ) {
+        if let Some(client) = &self.audit_log {
+            let msg = attempt.to_write_message(payload, &registration.callback_url);
+            let result = match client.write_message(msg).await {
+                // A receiver's error body can be huge; keep the record, not all of it
+                Err(Error::PayloadTooLarge {
+                    field: "data",
+                    size,
+                    limit,
+                }) => {
+                    let msg = attempt
+                        .with_error_truncated(size - limit)
+                        .to_write_message(payload, &registration.callback_url);
+                    client.write_message(msg).await
+                }
+                result => result,
+            };
+            if let Err(e) = result {
+                tracing::warn!(run_id = %payload.run_id, error = %e, "failed to record webhook delivery");
+            }
+        }
+    }
+}
+
+impl Default for WebhookDispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    type Received = Arc<Mutex<Vec<(Option<String>, bytes::Bytes)>>>;
+
+    /// Spawn a receiver that fails the first `failures` requests with a 500
+    async fn spawn_receiver(failures: usize) -> (SocketAddr, Received) {
+        let received: Received = Arc::new(Mutex::new(Vec::new()));
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let received_clone = received.clone();
+        let route = warp::post()
+            .and(warp::path("hook"))
+            .and(warp::header::optional::<String>(SIGNATURE_HEADER))
+            .and(warp::body::bytes())
+            .map(move |signature: Option<String>, body: bytes::Bytes| {
+                received_clone.lock().unwrap().push((signature, body));
+                if calls.fetch_add(1, Ordering::SeqCst) < failures {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                } else {
+                    StatusCode::NO_CONTENT
+                }
+            });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(warp::serve(route).incoming(listener).run());
+        (addr, received)
+    }
+
+    fn payload() -> WebhookPayload {
+        WebhookPayload {
+            run_id: Uuid::new_v4(),
+            thread_id: Uuid::new_v4(),
+            status: RunStatus::Completed,
+            final_text: Some("The weather is sunny!".to_string()),
+            usage: Some(UsageMetadata::new(12, 8)),
+        }
+    }
+
+    #[test]
+    fn test_sign_payload() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign_payload(b"what do ya want for nothing?", "Jefe"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delivery_retries_after_server_error() {
+        let (addr, received) = spawn_receiver(1).await;
+        let registration = WebhookRegistration {
+            callback_url: format!("http://{}/hook", addr),
+            secret: Some("s3cret".to_string()),
+        };
+        let payload = payload();
+
+        let dispatcher = WebhookDispatcher::new().with_initial_backoff(Duration::from_millis(10));
+        let attempts = dispatcher.deliver(&registration, &payload).await;
+
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].status_code, Some(500));
+        assert!(!attempts[0].succeeded());
+        assert_eq!(attempts[1].status_code, Some(204));
+        assert!(attempts[1].succeeded());
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        for (signature, body) in received.iter() {
+            assert_eq!(signature.as_deref(), Some(sign_payload(body, "s3cret").as_str()));
+        }
+
+        let body: serde_json::Value = serde_json::from_slice(&received[1].1).unwrap();
+        assert_eq!(body["run_id"], payload.run_id.to_string());
+        assert_eq!(body["status"], "completed");
+        assert_eq!(body["final_text"], "The weather is sunny!");
+        assert_eq!(body["usage"]["total_tokens"], 20);
+    }
+
+    #[tokio::test]
+    async fn test_delivery_gives_up_after_max_attempts() {
+        let (addr, received) = spawn_receiver(usize::MAX).await;
+        let registration = WebhookRegistration {
+            callback_url: format!("http://{}/hook", addr),
+            secret: None,
+        };
+
+        let dispatcher = WebhookDispatcher::new()
+            .with_max_attempts(3)
+            .with_initial_backoff(Duration::from_millis(1));
+        let attempts = dispatcher.deliver(&registration, &payload()).await;
+
+        assert_eq!(attempts.len(), 3);
+        assert!(attempts.iter().all(|a| !a.succeeded()));
+        assert_eq!(
+            attempts.iter().map(|a| a.attempt).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        // Unsigned when no secret is registered
+        assert!(received.lock().unwrap().iter().all(|(sig, _)| sig.is_none()));
+    }
+
+    #[test]
+    fn test_attempt_audit_message() {
+        let payload = payload();
+        let attempt = DeliveryAttempt {
+            attempt: 2,
+            status_code: Some(500),
+            error: None,
+            timestamp: Utc::now(),
+        };
+
+        let msg = attempt.to_write_message(&payload, "https://example.com/hook");
+        assert_eq!(msg.stream_name, format!("thread-{}", payload.thread_id));
+        assert_eq!(msg.message_type, "WebhookDeliveryAttempted");
+        assert_eq!(msg.data["attempt"], 2);
+        assert_eq!(msg.data["status_code"], 500);
+        assert_eq!(msg.data["succeeded"], false);
+    }
+
+    #[test]
+    fn test_oversized_error_is_truncated_for_the_audit_log() {
+        let attempt = DeliveryAttempt {
+            attempt: 1,
+            status_code: Some(502),
+            error: Some("<html>".repeat(1000)),
+            timestamp: Utc::now(),
+        };
+        let size = |attempt: &DeliveryAttempt| {
+            let msg = attempt.to_write_message(&payload(), "https://example.com/hook");
+            serde_json::to_string(&msg.data).unwrap().len()
+        };
+
+        let truncated = attempt.with_error_truncated(4000);
+
+        assert!(size(&truncated) <= size(&attempt) - 4000);
+        assert!(truncated.error.unwrap().starts_with("<html>"));
+    }
+}
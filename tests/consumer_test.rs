@@ -1,8 +1,11 @@
 mod common;
 
-use rust2::message_db::consumer::{Consumer, ConsumerConfig, PositionTracker};
+use rust2::message_db::consumer::{
+    Consumer, ConsumerConfig, ErrorPolicy, FilePositionStore, MemoryPositionStore, PositionStore,
+    PositionTracker,
+};
 use rust2::message_db::types::{Message, WriteMessage};
-use rust2::message_db::{MessageDbClient, MessageDbConfig};
+use rust2::message_db::{Error, MessageDbClient, MessageDbConfig};
 use serde_json::json;
 use std::sync::{Arc, Mutex};
 use testcontainers::clients::Cli;
@@ -38,12 +41,7 @@ async fn test_position_tracker_write_and_read() {
     let client = MessageDbClient::new(config).await.unwrap();
 
     // Create position tracker
-    let mut tracker = PositionTracker::new(
-        client.clone(),
-        "test-category",
-        "test-consumer",
-        10,
-    );
+    let mut tracker = PositionTracker::new(client.clone(), "test-category", "test-consumer", 10);
 
     // Update position
     tracker.update_position(100).await.unwrap();
@@ -69,12 +67,7 @@ async fn test_position_tracker_update_interval() {
     let client = MessageDbClient::new(config).await.unwrap();
 
     // Create position tracker with update interval of 3
-    let mut tracker = PositionTracker::new(
-        client.clone(),
-        "test-category",
-        "test-consumer",
-        3,
-    );
+    let mut tracker = PositionTracker::new(client.clone(), "test-category", "test-consumer", 3);
 
     // Update position twice (should not write yet)
     tracker.update_position(10).await.unwrap();
@@ -127,7 +120,10 @@ async fn test_consumer_poll_once() {
     consumer.on("TestEvent", move |msg: Message| {
         let processed = Arc::clone(&processed_clone);
         Box::pin(async move {
-            processed.lock().unwrap().push(msg.data["index"].as_i64().unwrap());
+            processed
+                .lock()
+                .unwrap()
+                .push(msg.data["index"].as_i64().unwrap());
             Ok(())
         })
     });
@@ -170,7 +166,9 @@ async fn test_consumer_resume_from_position() {
         .with_batch_size(3)
         .with_position_update_interval(1); // Write position after each message
 
-    let mut consumer1 = Consumer::new(client.clone(), consumer_config.clone()).await.unwrap();
+    let mut consumer1 = Consumer::new(client.clone(), consumer_config.clone())
+        .await
+        .unwrap();
 
     let processed1 = Arc::new(Mutex::new(Vec::new()));
     let processed1_clone = Arc::clone(&processed1);
@@ -178,7 +176,10 @@ async fn test_consumer_resume_from_position() {
     consumer1.on("TestEvent", move |msg: Message| {
         let processed = Arc::clone(&processed1_clone);
         Box::pin(async move {
-            processed.lock().unwrap().push(msg.data["index"].as_i64().unwrap());
+            processed
+                .lock()
+                .unwrap()
+                .push(msg.data["index"].as_i64().unwrap());
             Ok(())
         })
     });
@@ -186,11 +187,12 @@ async fn test_consumer_resume_from_position() {
     consumer1.poll_once().await.unwrap();
     consumer1.flush_position().await.unwrap();
 
-    let processed1 = processed1.lock().unwrap();
-    assert_eq!(processed1.len(), 3);
+    assert_eq!(processed1.lock().unwrap().len(), 3);
 
     // Second consumer should resume and process remaining messages
-    let mut consumer2 = Consumer::new(client.clone(), consumer_config).await.unwrap();
+    let mut consumer2 = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
 
     let processed2 = Arc::new(Mutex::new(Vec::new()));
     let processed2_clone = Arc::clone(&processed2);
@@ -198,7 +200,10 @@ async fn test_consumer_resume_from_position() {
     consumer2.on("TestEvent", move |msg: Message| {
         let processed = Arc::clone(&processed2_clone);
         Box::pin(async move {
-            processed.lock().unwrap().push(msg.data["index"].as_i64().unwrap());
+            processed
+                .lock()
+                .unwrap()
+                .push(msg.data["index"].as_i64().unwrap());
             Ok(())
         })
     });
@@ -318,8 +323,12 @@ async fn test_consumer_with_consumer_group() {
         .with_consumer_group(1, 2)
         .with_batch_size(20);
 
-    let mut consumer0 = Consumer::new(client.clone(), consumer_config_0).await.unwrap();
-    let mut consumer1 = Consumer::new(client.clone(), consumer_config_1).await.unwrap();
+    let mut consumer0 = Consumer::new(client.clone(), consumer_config_0)
+        .await
+        .unwrap();
+    let mut consumer1 = Consumer::new(client.clone(), consumer_config_1)
+        .await
+        .unwrap();
 
     let processed0 = Arc::new(Mutex::new(Vec::new()));
     let processed1 = Arc::new(Mutex::new(Vec::new()));
@@ -350,8 +359,8 @@ async fn test_consumer_with_consumer_group() {
     let streams1 = processed1.lock().unwrap();
 
     // Both should have processed some messages
-    assert!(streams0.len() > 0);
-    assert!(streams1.len() > 0);
+    assert!(!streams0.is_empty());
+    assert!(!streams1.is_empty());
 
     // Together they should have processed all 10
     assert_eq!(streams0.len() + streams1.len(), 10);
@@ -362,11 +371,9 @@ async fn test_consumer_with_consumer_group() {
     }
 }
 
-// TODO: Correlation filtering needs more investigation to understand exact semantics
-// The feature is implemented via CategoryReadOptions.with_correlation() but needs
-// a comprehensive test that matches Message DB's correlation behavior exactly.
+// Message DB's correlation filter matches the category of the
+// `correlationStreamName` metadata key
 #[tokio::test]
-#[ignore]
 async fn test_consumer_with_correlation() {
     let docker = Cli::default();
     let container = docker.run(common::create_message_db_container());
@@ -379,13 +386,13 @@ async fn test_consumer_with_correlation() {
     let test_id = Uuid::new_v4().to_string().replace("-", "");
 
     // Use separate category names for command and account
-    let cmd_category = format!("{}cmd", test_id);  // No hyphen - this is a category
+    let cmd_category = format!("{}cmd", test_id); // No hyphen - this is a category
     let account_category = format!("{}account", test_id);
 
-    // Write a command - the correlation will match based on stream ID "abc"
+    // Write a command in the cmd category
     let cmd_msg = WriteMessage::new(
         Uuid::new_v4(),
-        format!("{}-abc", cmd_category),  // Stream in cmd category with ID "abc"
+        format!("{}-abc", cmd_category), // Stream in cmd category with ID "abc"
         "WithdrawCommand",
     )
     .with_data(json!({ "amount": 50 }));
@@ -393,23 +400,23 @@ async fn test_consumer_with_correlation() {
     client.write_message(cmd_msg).await.unwrap();
 
     // Write events with and without matching correlation
-    // Event1's correlation_id "abc" matches the command stream ID "{cmd_category}-abc"
+    // Event1 is correlated with the command stream "{cmd_category}-abc"
     let event1 = WriteMessage::new(
         Uuid::new_v4(),
         format!("{}-1", account_category),
         "Withdrawn",
     )
     .with_data(json!({ "amount": 50 }))
-    .with_metadata(json!({ "correlation_id": "abc" }));  // Matches command stream ID
+    .with_correlation_stream_name(format!("{}-abc", cmd_category));
 
-    // Event2's correlation_id doesn't match any command stream
+    // Event2 is correlated with a stream in another category
     let event2 = WriteMessage::new(
         Uuid::new_v4(),
         format!("{}-2", account_category),
         "Withdrawn",
     )
     .with_data(json!({ "amount": 30 }))
-    .with_metadata(json!({ "correlation_id": "xyz" }));  // Doesn't match
+    .with_correlation_stream_name(format!("{}other-xyz", test_id));
 
     client.write_message(event1).await.unwrap();
     client.write_message(event2).await.unwrap();
@@ -426,7 +433,10 @@ async fn test_consumer_with_correlation() {
     consumer.on("Withdrawn", move |msg: Message| {
         let processed = Arc::clone(&processed_clone);
         Box::pin(async move {
-            processed.lock().unwrap().push(msg.data["amount"].as_i64().unwrap());
+            processed
+                .lock()
+                .unwrap()
+                .push(msg.data["amount"].as_i64().unwrap());
             Ok(())
         })
     });
@@ -453,7 +463,11 @@ async fn test_consumer_unhandled_message_type() {
 
     // Write messages of different types
     let msg1 = WriteMessage::new(Uuid::new_v4(), format!("{}-account-1", test_id), "Handled");
-    let msg2 = WriteMessage::new(Uuid::new_v4(), format!("{}-account-2", test_id), "NotHandled");
+    let msg2 = WriteMessage::new(
+        Uuid::new_v4(),
+        format!("{}-account-2", test_id),
+        "NotHandled",
+    );
 
     client.write_message(msg1).await.unwrap();
     client.write_message(msg2).await.unwrap();
@@ -482,3 +496,1200 @@ async fn test_consumer_unhandled_message_type() {
     // Position should still advance past both messages
     assert!(consumer.current_position() > 0);
 }
+
+#[tokio::test]
+async fn test_read_only_client_rejects_writes() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new_read_only(config).await.unwrap();
+
+    let stream_name = format!("account-{}", Uuid::new_v4());
+    let msg = WriteMessage::new(Uuid::new_v4(), &stream_name, "Withdrawn");
+    let result = client.write_message(msg).await;
+    assert!(matches!(result, Err(Error::ReadOnly(_))));
+
+    // Nothing reached the database
+    assert_eq!(client.stream_version(&stream_name).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_read_only_consumer_resumes_from_memory_store() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let writer = MessageDbClient::new(config.clone()).await.unwrap();
+    let reader = MessageDbClient::new_read_only(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    for amount in [10, 20] {
+        let msg = WriteMessage::new(
+            Uuid::new_v4(),
+            format!("{}-account-1", test_id),
+            "Withdrawn",
+        )
+        .with_data(json!({ "amount": amount }));
+        writer.write_message(msg).await.unwrap();
+    }
+
+    let store = MemoryPositionStore::new();
+    let processed = Arc::new(Mutex::new(Vec::new()));
+
+    let register = |consumer: &mut Consumer| {
+        let processed = Arc::clone(&processed);
+        consumer.on("Withdrawn", move |msg: Message| {
+            let processed = Arc::clone(&processed);
+            Box::pin(async move {
+                processed
+                    .lock()
+                    .unwrap()
+                    .push(msg.data["amount"].as_i64().unwrap());
+                Ok(())
+            })
+        });
+    };
+
+    let consumer_config =
+        ConsumerConfig::new(&test_id, "projection").with_position_store(Box::new(store.clone()));
+    let mut consumer = Consumer::new(reader.clone(), consumer_config.clone())
+        .await
+        .unwrap();
+    register(&mut consumer);
+    consumer.poll_once().await.unwrap();
+    consumer.flush_position().await.unwrap();
+    drop(consumer);
+
+    let msg = WriteMessage::new(
+        Uuid::new_v4(),
+        format!("{}-account-1", test_id),
+        "Withdrawn",
+    )
+    .with_data(json!({ "amount": 30 }));
+    writer.write_message(msg).await.unwrap();
+
+    // A new consumer sharing the store picks up only the new message
+    let mut resumed = Consumer::new(reader, consumer_config).await.unwrap();
+    register(&mut resumed);
+    resumed.poll_once().await.unwrap();
+
+    assert_eq!(*processed.lock().unwrap(), vec![10, 20, 30]);
+}
+
+#[tokio::test]
+async fn test_spawned_consumer_flushes_position_on_shutdown() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    for amount in [10, 20, 30] {
+        let msg = WriteMessage::new(
+            Uuid::new_v4(),
+            format!("{}-account-1", test_id),
+            "Withdrawn",
+        )
+        .with_data(json!({ "amount": amount }));
+        client.write_message(msg).await.unwrap();
+    }
+
+    let consumer_config = ConsumerConfig::new(&test_id, "background")
+        .with_polling_interval_ms(50)
+        .with_position_update_interval(100);
+    let mut consumer = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
+
+    let processed = Arc::new(Mutex::new(Vec::new()));
+    let processed_clone = Arc::clone(&processed);
+    consumer.on("Withdrawn", move |msg: Message| {
+        let processed = Arc::clone(&processed_clone);
+        Box::pin(async move {
+            processed.lock().unwrap().push(msg.global_position);
+            Ok(())
+        })
+    });
+
+    let (handle, task) = consumer.spawn();
+
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while processed.lock().unwrap().len() < 3 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("consumer did not process messages");
+
+    handle.pause();
+    handle.resume();
+    handle.shutdown();
+    task.await.unwrap().unwrap();
+
+    // The flushed position points just past the last processed message
+    let last_position = *processed.lock().unwrap().last().unwrap();
+    let mut tracker = PositionTracker::new(client, &test_id, "background", 100);
+    assert_eq!(tracker.read_position().await.unwrap(), last_position + 1);
+}
+
+#[tokio::test]
+async fn test_shutdown_mid_batch_keeps_processed_position() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    for amount in [10, 20, 30, 40, 50] {
+        let msg = WriteMessage::new(
+            Uuid::new_v4(),
+            format!("{}-account-1", test_id),
+            "Withdrawn",
+        )
+        .with_data(json!({ "amount": amount }));
+        client.write_message(msg).await.unwrap();
+    }
+
+    // One batch holds every message, and positions are only written on flush
+    let consumer_config = ConsumerConfig::new(&test_id, "graceful")
+        .with_batch_size(10)
+        .with_polling_interval_ms(50)
+        .with_position_update_interval(100);
+    let mut consumer = Consumer::new(client.clone(), consumer_config.clone())
+        .await
+        .unwrap();
+
+    // The signal arrives while the second message is being handled
+    let shutdown = tokio_util::sync::CancellationToken::new();
+    let processed = Arc::new(Mutex::new(Vec::new()));
+    let processed_clone = Arc::clone(&processed);
+    let signal = shutdown.clone();
+    consumer.on("Withdrawn", move |msg: Message| {
+        let processed = Arc::clone(&processed_clone);
+        let signal = signal.clone();
+        Box::pin(async move {
+            if processed.lock().unwrap().len() == 1 {
+                signal.cancel();
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+            processed
+                .lock()
+                .unwrap()
+                .push(msg.data["amount"].as_i64().unwrap());
+            Ok(())
+        })
+    });
+
+    tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        consumer.start_with_shutdown(shutdown),
+    )
+    .await
+    .expect("consumer did not stop")
+    .unwrap();
+    assert_eq!(*processed.lock().unwrap(), vec![10, 20]);
+
+    // A restarted consumer picks up exactly where the first one stopped
+    let mut restarted = Consumer::new(client, consumer_config).await.unwrap();
+    assert_eq!(restarted.current_position(), consumer.current_position());
+
+    let stop = restarted.stop_signal();
+    let processed_clone = Arc::clone(&processed);
+    restarted.on("Withdrawn", move |msg: Message| {
+        let processed = Arc::clone(&processed_clone);
+        let stop = stop.clone();
+        Box::pin(async move {
+            let amount = msg.data["amount"].as_i64().unwrap();
+            processed.lock().unwrap().push(amount);
+            // Self-cancel on the last message, like a poison pill
+            if amount == 50 {
+                stop.cancel();
+            }
+            Ok(())
+        })
+    });
+
+    tokio::time::timeout(std::time::Duration::from_secs(5), restarted.start())
+        .await
+        .expect("consumer did not stop")
+        .unwrap();
+    assert_eq!(*processed.lock().unwrap(), vec![10, 20, 30, 40, 50]);
+}
+
+#[tokio::test]
+async fn test_skipped_messages_are_passed_to_error_handler() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    for amount in [10, -20, 30] {
+        let msg = WriteMessage::new(
+            Uuid::new_v4(),
+            format!("{}-account-1", test_id),
+            "Withdrawn",
+        )
+        .with_data(json!({ "amount": amount }));
+        client.write_message(msg).await.unwrap();
+    }
+
+    let consumer_config =
+        ConsumerConfig::new(&test_id, "dead-letter").with_error_policy(ErrorPolicy::Skip);
+    let mut consumer = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
+
+    let processed = Arc::new(Mutex::new(Vec::new()));
+    let processed_clone = Arc::clone(&processed);
+    consumer.on("Withdrawn", move |msg: Message| {
+        let processed = Arc::clone(&processed_clone);
+        Box::pin(async move {
+            let amount = msg.data["amount"].as_i64().unwrap();
+            if amount < 0 {
+                return Err(Error::ValidationError("negative amount".to_string()));
+            }
+            processed.lock().unwrap().push(amount);
+            Ok(())
+        })
+    });
+
+    let dead_letter_stream = format!("{}:deadletter", test_id);
+    let dead_letter_client = client.clone();
+    let stream = dead_letter_stream.clone();
+    consumer.on_error(move |msg, error| {
+        let client = dead_letter_client.clone();
+        let stream = stream.clone();
+        Box::pin(async move {
+            let dead_letter = WriteMessage::new(Uuid::new_v4(), stream, msg.message_type)
+                .with_data(msg.data)
+                .with_metadata(json!({ "error": error.to_string() }));
+            client.write_message(dead_letter).await?;
+            Ok(())
+        })
+    });
+
+    assert!(consumer.poll_once().await.unwrap());
+
+    // The bad message didn't stop the batch, and the position moved past it
+    assert_eq!(*processed.lock().unwrap(), vec![10, 30]);
+    let last = client
+        .get_last_stream_message(&format!("{}-account-1", test_id), None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(consumer.current_position(), last.global_position + 1);
+
+    let dead_letters = client
+        .get_stream_messages(rust2::message_db::StreamReadOptions::new(
+            &dead_letter_stream,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(dead_letters.len(), 1);
+    assert_eq!(dead_letters[0].data["amount"], -20);
+    assert_eq!(
+        dead_letters[0].metadata.as_ref().unwrap()["error"],
+        "Validation error: negative amount"
+    );
+}
+
+#[tokio::test]
+async fn test_dead_letter_policy_writes_failed_messages() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    let stream_name = format!("{}-account-1", test_id);
+    for amount in [10, -20, 30, -40] {
+        let msg = WriteMessage::new(Uuid::new_v4(), &stream_name, "Withdrawn")
+            .with_data(json!({ "amount": amount }));
+        client.write_message(msg).await.unwrap();
+    }
+
+    let consumer_config =
+        ConsumerConfig::new(&test_id, "worker-1").with_error_policy(ErrorPolicy::dead_letter());
+    let mut consumer = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
+
+    let processed = Arc::new(Mutex::new(Vec::new()));
+    let processed_clone = Arc::clone(&processed);
+    consumer.on("Withdrawn", move |msg: Message| {
+        let processed = Arc::clone(&processed_clone);
+        Box::pin(async move {
+            let amount = msg.data["amount"].as_i64().unwrap();
+            if amount < 0 {
+                return Err(Error::ValidationError("negative amount".to_string()));
+            }
+            processed.lock().unwrap().push(amount);
+            Ok(())
+        })
+    });
+
+    assert!(consumer.poll_once().await.unwrap());
+
+    assert_eq!(*processed.lock().unwrap(), vec![10, 30]);
+    let originals = client
+        .get_stream_messages(rust2::message_db::StreamReadOptions::new(&stream_name))
+        .await
+        .unwrap();
+    assert_eq!(
+        consumer.current_position(),
+        originals[3].global_position + 1
+    );
+
+    let dead_letters = client
+        .get_stream_messages(rust2::message_db::StreamReadOptions::new(format!(
+            "{}:dead_letter-worker-1",
+            test_id
+        )))
+        .await
+        .unwrap();
+    assert_eq!(dead_letters.len(), 2);
+    for (dead_letter, original) in dead_letters.iter().zip([&originals[1], &originals[3]]) {
+        assert_eq!(dead_letter.message_type, "Withdrawn");
+        assert_eq!(dead_letter.data, original.data);
+        let metadata = dead_letter.metadata.as_ref().unwrap();
+        assert_eq!(metadata["error"], "Validation error: negative amount");
+        assert_eq!(metadata["originalStreamName"], stream_name.as_str());
+        assert_eq!(
+            metadata["originalGlobalPosition"],
+            original.global_position
+        );
+        assert_eq!(metadata["originalId"], original.id.to_string());
+    }
+}
+
+#[tokio::test]
+async fn test_retry_policy_retries_then_stops() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    let stream_name = format!("{}-account-1", test_id);
+    for amount in [10, 20] {
+        let msg = WriteMessage::new(Uuid::new_v4(), &stream_name, "Deposited")
+            .with_data(json!({ "amount": amount }));
+        client.write_message(msg).await.unwrap();
+    }
+
+    let policy = ErrorPolicy::Retry {
+        attempts: 2,
+        backoff: std::time::Duration::from_millis(10),
+    };
+    let consumer_config = ConsumerConfig::new(&test_id, "retrying").with_error_policy(policy);
+    let mut consumer = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
+
+    // The first message fails twice before succeeding; the second always fails
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let calls_clone = Arc::clone(&calls);
+    consumer.on("Deposited", move |msg: Message| {
+        let calls = Arc::clone(&calls_clone);
+        Box::pin(async move {
+            let amount = msg.data["amount"].as_i64().unwrap();
+            let mut calls = calls.lock().unwrap();
+            calls.push(amount);
+            let attempts = calls.iter().filter(|a| **a == amount).count();
+            if amount == 20 || attempts < 3 {
+                return Err(Error::ValidationError("flaky".to_string()));
+            }
+            Ok(())
+        })
+    });
+
+    let result = consumer.poll_once().await;
+
+    assert!(matches!(result, Err(Error::ValidationError(_))));
+    assert_eq!(*calls.lock().unwrap(), vec![10, 10, 10, 20, 20, 20]);
+    let first = client
+        .get_stream_messages(rust2::message_db::StreamReadOptions::new(&stream_name))
+        .await
+        .unwrap()
+        .remove(0);
+    assert_eq!(consumer.current_position(), first.global_position + 1);
+}
+
+#[tokio::test]
+async fn test_on_any_handles_only_unregistered_types() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    for message_type in ["Deposited", "Withdrawn", "Closed"] {
+        let msg = WriteMessage::new(
+            Uuid::new_v4(),
+            format!("{}-account-1", test_id),
+            message_type,
+        )
+        .with_data(json!({}));
+        client.write_message(msg).await.unwrap();
+    }
+
+    let consumer_config = ConsumerConfig::new(&test_id, "catch-all");
+    let mut consumer = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
+
+    let specific = Arc::new(Mutex::new(Vec::new()));
+    let specific_clone = Arc::clone(&specific);
+    consumer.on("Deposited", move |msg: Message| {
+        let specific = Arc::clone(&specific_clone);
+        Box::pin(async move {
+            specific.lock().unwrap().push(msg.message_type);
+            Ok(())
+        })
+    });
+
+    let fallback = Arc::new(Mutex::new(Vec::new()));
+    let fallback_clone = Arc::clone(&fallback);
+    consumer.on_any(move |msg: Message| {
+        let fallback = Arc::clone(&fallback_clone);
+        Box::pin(async move {
+            fallback.lock().unwrap().push(msg.message_type);
+            Ok(())
+        })
+    });
+
+    assert!(consumer.poll_once().await.unwrap());
+
+    assert_eq!(*specific.lock().unwrap(), vec!["Deposited"]);
+    assert_eq!(*fallback.lock().unwrap(), vec!["Withdrawn", "Closed"]);
+}
+
+#[tokio::test]
+async fn test_handlers_added_and_removed_while_running() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    let stream_name = format!("{}-account-1", test_id);
+
+    let consumer_config = ConsumerConfig::new(&test_id, "toggled").with_polling_interval_ms(50);
+    let mut consumer = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
+
+    // Markers tell us when the consumer has got past a given point in the stream
+    let markers = Arc::new(Mutex::new(0));
+    let markers_clone = Arc::clone(&markers);
+    consumer.on("Marker", move |_msg: Message| {
+        let markers = Arc::clone(&markers_clone);
+        Box::pin(async move {
+            *markers.lock().unwrap() += 1;
+            Ok(())
+        })
+    });
+
+    let (handle, task) = consumer.spawn();
+
+    let wait_for_markers = |count: usize| {
+        let markers = Arc::clone(&markers);
+        async move {
+            tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                while *markers.lock().unwrap() < count {
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("consumer did not reach marker");
+        }
+    };
+    let write = |message_type: &'static str| {
+        let client = client.clone();
+        let stream_name = stream_name.clone();
+        async move {
+            let msg = WriteMessage::new(Uuid::new_v4(), stream_name, message_type);
+            client.write_message(msg).await.unwrap();
+        }
+    };
+
+    // Registered after the consumer started, so it sees the next Withdrawn
+    let processed = Arc::new(Mutex::new(0));
+    let processed_clone = Arc::clone(&processed);
+    handle.on("Withdrawn", move |_msg: Message| {
+        let processed = Arc::clone(&processed_clone);
+        Box::pin(async move {
+            *processed.lock().unwrap() += 1;
+            Ok(())
+        })
+    });
+
+    write("Withdrawn").await;
+    write("Marker").await;
+    wait_for_markers(1).await;
+    assert_eq!(*processed.lock().unwrap(), 1);
+
+    // Once removed, later Withdrawn messages are skipped
+    assert!(handle.remove_handler("Withdrawn"));
+    write("Withdrawn").await;
+    write("Marker").await;
+    wait_for_markers(2).await;
+    assert_eq!(*processed.lock().unwrap(), 1);
+    assert_eq!(handle.message_types(), vec!["Marker"]);
+
+    handle.shutdown();
+    task.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_consumer_resumes_from_file_position_store() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    let position_dir = std::env::temp_dir().join(format!("rust2-positions-{}", test_id));
+
+    for amount in [10, 20] {
+        let msg = WriteMessage::new(
+            Uuid::new_v4(),
+            format!("{}-account-1", test_id),
+            "Withdrawn",
+        )
+        .with_data(json!({ "amount": amount }));
+        client.write_message(msg).await.unwrap();
+    }
+
+    let processed = Arc::new(Mutex::new(Vec::new()));
+    let register = |consumer: &mut Consumer| {
+        let processed = Arc::clone(&processed);
+        consumer.on("Withdrawn", move |msg: Message| {
+            let processed = Arc::clone(&processed);
+            Box::pin(async move {
+                processed
+                    .lock()
+                    .unwrap()
+                    .push(msg.data["amount"].as_i64().unwrap());
+                Ok(())
+            })
+        });
+    };
+
+    let consumer_config = || {
+        ConsumerConfig::new(&test_id, "edge")
+            .with_position_store(Box::new(FilePositionStore::new(&position_dir)))
+    };
+
+    let mut consumer = Consumer::new(client.clone(), consumer_config())
+        .await
+        .unwrap();
+    register(&mut consumer);
+    consumer.poll_once().await.unwrap();
+    consumer.flush_position().await.unwrap();
+    drop(consumer);
+
+    // Nothing was written to the Message DB position stream
+    let position_stream = format!("{}:position-edge", test_id);
+    assert_eq!(client.stream_version(&position_stream).await.unwrap(), None);
+
+    let msg = WriteMessage::new(
+        Uuid::new_v4(),
+        format!("{}-account-1", test_id),
+        "Withdrawn",
+    )
+    .with_data(json!({ "amount": 30 }));
+    client.write_message(msg).await.unwrap();
+
+    let mut resumed = Consumer::new(client, consumer_config()).await.unwrap();
+    register(&mut resumed);
+    resumed.poll_once().await.unwrap();
+
+    assert_eq!(*processed.lock().unwrap(), vec![10, 20, 30]);
+    assert!(FilePositionStore::new(&position_dir)
+        .load(&position_stream)
+        .await
+        .unwrap()
+        .is_some());
+
+    std::fs::remove_dir_all(&position_dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_projected_handler_retries_conflict_atomically() {
+    use rust2::message_db::operations::StreamReadOptions;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    let balance_stream = format!("{}balance-1", test_id);
+
+    let deposit = WriteMessage::new(
+        Uuid::new_v4(),
+        format!("{}-account-1", test_id),
+        "Deposited",
+    )
+    .with_data(json!({ "amount": 50 }));
+    client.write_message(deposit).await.unwrap();
+
+    let consumer_config = ConsumerConfig::new(&test_id, "balance-projector");
+    let mut consumer = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls_clone = Arc::clone(&calls);
+    let competitor = client.clone();
+    let target = balance_stream.clone();
+
+    consumer.on_projected(
+        "Deposited",
+        move |_msg| target.clone(),
+        move |msg, balance_events| {
+            // On the first attempt, another writer sneaks in a balance change
+            // after our read, which must cause a conflict and a fresh retry
+            if calls_clone.fetch_add(1, Ordering::SeqCst) == 0 {
+                let stream = msg.stream_name.replace("-account-", "balance-");
+                let competing = WriteMessage::new(Uuid::new_v4(), stream, "BalanceChanged")
+                    .with_data(json!({ "balance": 100 }));
+                tokio::task::block_in_place(|| {
+                    tokio::runtime::Handle::current()
+                        .block_on(competitor.write_message(competing))
+                        .unwrap()
+                });
+            }
+
+            let current = balance_events
+                .last()
+                .and_then(|m| m.data["balance"].as_i64())
+                .unwrap_or(0);
+            let amount = msg.data["amount"].as_i64().unwrap();
+            let stream = msg.stream_name.replace("-account-", "balance-");
+
+            Ok(vec![WriteMessage::new(
+                Uuid::new_v4(),
+                stream,
+                "BalanceChanged",
+            )
+            .with_data(json!({ "balance": current + amount }))])
+        },
+    );
+
+    consumer.poll_once().await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // Only the retried write landed, computed from the competitor's balance
+    let balances: Vec<i64> = client
+        .get_stream_messages(StreamReadOptions::new(&balance_stream))
+        .await
+        .unwrap()
+        .iter()
+        .map(|m| m.data["balance"].as_i64().unwrap())
+        .collect();
+    assert_eq!(balances, vec![100, 150]);
+
+    // The position was committed with the projection, exactly once
+    let position_messages = client
+        .get_stream_messages(StreamReadOptions::new(consumer.position_stream_name()))
+        .await
+        .unwrap();
+    assert_eq!(position_messages.len(), 1);
+    assert_eq!(
+        position_messages[0].data["position"].as_i64().unwrap(),
+        consumer.current_position()
+    );
+}
+
+#[tokio::test]
+async fn test_projected_handler_error_rolls_back() {
+    use rust2::message_db::operations::StreamReadOptions;
+
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    let balance_stream = format!("{}balance-1", test_id);
+
+    let deposit = WriteMessage::new(
+        Uuid::new_v4(),
+        format!("{}-account-1", test_id),
+        "Deposited",
+    )
+    .with_data(json!({ "amount": 50 }));
+    client.write_message(deposit).await.unwrap();
+
+    let consumer_config = ConsumerConfig::new(&test_id, "balance-projector");
+    let mut consumer = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
+    let start_position = consumer.current_position();
+
+    let audit_stream = format!("{}audit-1", test_id);
+    let target = balance_stream.clone();
+    let audit_target = audit_stream.clone();
+    let balance_target = balance_stream.clone();
+    consumer.on_projected(
+        "Deposited",
+        move |_msg| target.clone(),
+        move |_msg, _balance_events| {
+            // Second write carries a stale expected version, failing the transaction
+            Ok(vec![
+                WriteMessage::new(Uuid::new_v4(), audit_target.clone(), "Audited"),
+                WriteMessage::new(Uuid::new_v4(), balance_target.clone(), "BalanceChanged")
+                    .with_expected_version(5),
+            ])
+        },
+    );
+
+    let result = consumer.poll_once().await;
+    assert!(matches!(result, Err(Error::ConcurrencyError { .. })));
+    assert_eq!(consumer.current_position(), start_position);
+
+    // Nothing from the failed attempts was committed
+    let audit = client
+        .get_stream_messages(StreamReadOptions::new(&audit_stream))
+        .await
+        .unwrap();
+    assert!(audit.is_empty());
+    assert_eq!(client.stream_version(&balance_stream).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_batch_hooks_run_around_each_batch() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+
+    // Three messages with a batch size of two: one full batch, then one partial
+    let mut global_positions = Vec::new();
+    for i in 0..3 {
+        let msg = WriteMessage::new(
+            Uuid::new_v4(),
+            format!("{}-account-{}", test_id, i),
+            "TestEvent",
+        )
+        .with_data(json!({ "index": i }));
+        client.write_message(msg).await.unwrap();
+        let written = client
+            .get_last_stream_message(&format!("{}-account-{}", test_id, i), None)
+            .await
+            .unwrap()
+            .unwrap();
+        global_positions.push(written.global_position);
+    }
+
+    let consumer_config = ConsumerConfig::new(&test_id, "batch-consumer")
+        .with_batch_size(2)
+        .with_position_update_interval(1);
+    let mut consumer = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+
+    let start_log = Arc::clone(&log);
+    consumer.on_batch_start(move |batch| {
+        let log = Arc::clone(&start_log);
+        Box::pin(async move {
+            log.lock().unwrap().push(format!(
+                "start {} {}..{}",
+                batch.size, batch.first_global_position, batch.last_global_position
+            ));
+            Ok(())
+        })
+    });
+
+    let handler_log = Arc::clone(&log);
+    consumer.on("TestEvent", move |msg: Message| {
+        let log = Arc::clone(&handler_log);
+        Box::pin(async move {
+            log.lock()
+                .unwrap()
+                .push(format!("message {}", msg.data["index"]));
+            Ok(())
+        })
+    });
+
+    let end_log = Arc::clone(&log);
+    consumer.on_batch_end(move |result| {
+        let log = Arc::clone(&end_log);
+        Box::pin(async move {
+            log.lock()
+                .unwrap()
+                .push(format!("end {} {}", result.processed, result.failed));
+            Ok(())
+        })
+    });
+
+    assert!(consumer.poll_once().await.unwrap());
+    assert!(consumer.poll_once().await.unwrap());
+    // Empty polls run no hooks
+    assert!(!consumer.poll_once().await.unwrap());
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            format!("start 2 {}..{}", global_positions[0], global_positions[1]),
+            "message 0".to_string(),
+            "message 1".to_string(),
+            "end 2 0".to_string(),
+            format!("start 1 {}..{}", global_positions[2], global_positions[2]),
+            "message 2".to_string(),
+            "end 1 0".to_string(),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_failed_batch_end_hook_keeps_position() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    let msg = WriteMessage::new(
+        Uuid::new_v4(),
+        format!("{}-account-1", test_id),
+        "TestEvent",
+    );
+    client.write_message(msg).await.unwrap();
+
+    let store = MemoryPositionStore::new();
+    let consumer_config = ConsumerConfig::new(&test_id, "batch-consumer")
+        .with_position_update_interval(1)
+        .with_position_store(Box::new(store.clone()));
+    let mut consumer = Consumer::new(client, consumer_config).await.unwrap();
+
+    consumer.on("TestEvent", |_msg| Box::pin(async move { Ok(()) }));
+    consumer.on_batch_end(|_result| {
+        Box::pin(async move { Err(Error::ValidationError("flush failed".to_string())) })
+    });
+
+    let result = consumer.poll_once().await;
+    assert!(matches!(result, Err(Error::ValidationError(ref msg)) if msg == "flush failed"));
+
+    let stored = store
+        .load(&format!("{}:position-batch-consumer", test_id))
+        .await
+        .unwrap();
+    assert_eq!(stored, None);
+}
+
+#[tokio::test]
+async fn test_batch_result_counts_skipped_failures() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    for amount in [10, -20, 30, -40] {
+        let msg = WriteMessage::new(
+            Uuid::new_v4(),
+            format!("{}-account-1", test_id),
+            "Withdrawn",
+        )
+        .with_data(json!({ "amount": amount }));
+        client.write_message(msg).await.unwrap();
+    }
+
+    let consumer_config =
+        ConsumerConfig::new(&test_id, "batch-consumer").with_error_policy(ErrorPolicy::Skip);
+    let mut consumer = Consumer::new(client, consumer_config).await.unwrap();
+
+    consumer.on("Withdrawn", |msg: Message| {
+        Box::pin(async move {
+            if msg.data["amount"].as_i64().unwrap() < 0 {
+                return Err(Error::ValidationError("negative amount".to_string()));
+            }
+            Ok(())
+        })
+    });
+
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let results_clone = Arc::clone(&results);
+    consumer.on_batch_end(move |result| {
+        let results = Arc::clone(&results_clone);
+        Box::pin(async move {
+            results
+                .lock()
+                .unwrap()
+                .push((result.processed, result.failed));
+            Ok(())
+        })
+    });
+
+    assert!(consumer.poll_once().await.unwrap());
+    assert_eq!(*results.lock().unwrap(), vec![(2, 2)]);
+}
+
+#[tokio::test]
+async fn test_failed_batch_end_hook_follows_error_policy() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    let msg = WriteMessage::new(
+        Uuid::new_v4(),
+        format!("{}-account-1", test_id),
+        "TestEvent",
+    );
+    client.write_message(msg).await.unwrap();
+    let position_stream = format!("{}:position-batch-consumer", test_id);
+
+    // Retry: the flush fails once, then succeeds and the position is written
+    let store = MemoryPositionStore::new();
+    let policy = ErrorPolicy::Retry {
+        attempts: 1,
+        backoff: std::time::Duration::from_millis(10),
+    };
+    let consumer_config = ConsumerConfig::new(&test_id, "batch-consumer")
+        .with_position_update_interval(1)
+        .with_error_policy(policy)
+        .with_position_store(Box::new(store.clone()));
+    let mut consumer = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
+    consumer.on("TestEvent", |_msg| Box::pin(async move { Ok(()) }));
+
+    let calls = Arc::new(Mutex::new(0));
+    let calls_clone = Arc::clone(&calls);
+    consumer.on_batch_end(move |_result| {
+        let calls = Arc::clone(&calls_clone);
+        Box::pin(async move {
+            let mut calls = calls.lock().unwrap();
+            *calls += 1;
+            if *calls == 1 {
+                return Err(Error::ValidationError("flush failed".to_string()));
+            }
+            Ok(())
+        })
+    });
+
+    assert!(consumer.poll_once().await.unwrap());
+    assert_eq!(*calls.lock().unwrap(), 2);
+    assert_eq!(
+        store.load(&position_stream).await.unwrap(),
+        Some(consumer.current_position())
+    );
+
+    // Skip: a flush that always fails is logged and the position still moves on
+    let store = MemoryPositionStore::new();
+    let consumer_config = ConsumerConfig::new(&test_id, "batch-consumer")
+        .with_position_update_interval(1)
+        .with_error_policy(ErrorPolicy::Skip)
+        .with_position_store(Box::new(store.clone()));
+    let mut consumer = Consumer::new(client, consumer_config).await.unwrap();
+    consumer.on("TestEvent", |_msg| Box::pin(async move { Ok(()) }));
+    consumer.on_batch_end(|_result| {
+        Box::pin(async move { Err(Error::ValidationError("flush failed".to_string())) })
+    });
+
+    assert!(consumer.poll_once().await.unwrap());
+    assert_eq!(
+        store.load(&position_stream).await.unwrap(),
+        Some(consumer.current_position())
+    );
+}
+
+#[tokio::test]
+async fn test_measure_lag_after_partial_consumption() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+
+    let consumer_config = ConsumerConfig::new(&test_id, "lag-consumer").with_batch_size(3);
+    let mut consumer = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
+    consumer.on("TestEvent", |_msg: Message| Box::pin(async { Ok(()) }));
+
+    // Empty category has no head to measure against
+    assert_eq!(consumer.measure_lag().await.unwrap(), None);
+    assert_eq!(
+        client.get_category_head_position(&test_id).await.unwrap(),
+        None
+    );
+
+    // Write 10 messages, then consume one batch of 3
+    for i in 0..10 {
+        let msg = WriteMessage::new(
+            Uuid::new_v4(),
+            format!("{}-account-{}", test_id, i),
+            "TestEvent",
+        )
+        .with_data(json!({ "index": i }));
+        client.write_message(msg).await.unwrap();
+    }
+    assert_eq!(consumer.measure_lag().await.unwrap(), Some(10));
+
+    consumer.poll_once().await.unwrap();
+    assert_eq!(consumer.measure_lag().await.unwrap(), Some(7));
+
+    // Drain the rest
+    while consumer.poll_once().await.unwrap() {}
+    assert_eq!(consumer.measure_lag().await.unwrap(), Some(0));
+}
+
+#[tokio::test]
+async fn test_poll_batch_returns_fetched_messages() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+
+    // Only Deposited has a handler; Withdrawn is still returned
+    for (i, message_type) in ["Deposited", "Withdrawn", "Deposited"].iter().enumerate() {
+        let msg = WriteMessage::new(
+            Uuid::new_v4(),
+            format!("{}-account-{}", test_id, i),
+            *message_type,
+        )
+        .with_data(json!({ "index": i }));
+        client.write_message(msg).await.unwrap();
+    }
+
+    let consumer_config = ConsumerConfig::new(&test_id, "batch-consumer").with_batch_size(10);
+    let mut consumer = Consumer::new(client, consumer_config).await.unwrap();
+
+    let handled = Arc::new(Mutex::new(0));
+    let handled_clone = Arc::clone(&handled);
+    consumer.on("Deposited", move |_msg: Message| {
+        let handled = Arc::clone(&handled_clone);
+        Box::pin(async move {
+            *handled.lock().unwrap() += 1;
+            Ok(())
+        })
+    });
+
+    let batch = consumer.poll_batch().await.unwrap();
+
+    let types: Vec<&str> = batch.iter().map(|m| m.message_type.as_str()).collect();
+    assert_eq!(types, vec!["Deposited", "Withdrawn", "Deposited"]);
+    assert_eq!(batch[1].data["index"], 1);
+    assert_eq!(*handled.lock().unwrap(), 2);
+
+    // Caught up
+    assert!(consumer.poll_batch().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_preflight_rejects_condition_when_sql_condition_is_off() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+    assert!(!client.sql_condition_enabled().await.unwrap());
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    let consumer_config = ConsumerConfig::new(&test_id, "condition-consumer")
+        .with_condition("type = 'Withdrawn'")
+        .with_preflight(true);
+
+    match Consumer::new(client, consumer_config).await {
+        Err(Error::ValidationError(message)) => {
+            assert!(message.contains("message_store.sql_condition"))
+        }
+        Err(e) => panic!("expected ValidationError, got {:?}", e),
+        Ok(_) => panic!("expected preflight to fail"),
+    }
+}
+
+#[tokio::test]
+async fn test_preflight_warns_about_empty_category() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let conn_str = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&conn_str).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let test_id = Uuid::new_v4().to_string().replace("-", "");
+    let consumer_config = ConsumerConfig::new(&test_id, "preflight-consumer").with_preflight(true);
+    let consumer = Consumer::new(client.clone(), consumer_config)
+        .await
+        .unwrap();
+
+    let warnings = consumer.preflight().await.unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains(&test_id));
+
+    let msg = WriteMessage::new(Uuid::new_v4(), format!("{}-1", test_id), "TestEvent");
+    client.write_message(msg).await.unwrap();
+    assert!(consumer.preflight().await.unwrap().is_empty());
+}
@@ -24,13 +24,9 @@ async fn test_create_provider_claude() {
     let project_id = std::env::var("GCP_PROJECT_ID").expect("GCP_PROJECT_ID required");
     let location = std::env::var("GCP_LOCATION").unwrap_or_else(|_| "us-central1".to_string());
 
-    let provider = create_provider(
-        Model::Claude(ClaudeModel::Haiku45),
-        project_id,
-        location,
-    )
-    .await
-    .expect("Failed to create Claude provider");
+    let provider = create_provider(Model::Claude(ClaudeModel::Haiku45), project_id, location)
+        .await
+        .expect("Failed to create Claude provider");
 
     // Just verify we got a provider back (it implements LlmProvider)
     // Actual functionality is tested in integration tests
@@ -60,7 +56,7 @@ async fn test_create_provider_gemini() {
 #[test]
 fn test_all_claude_models() {
     // Verify all Claude model variants can be wrapped in Model enum
-    let models = vec![
+    let models = [
         Model::Claude(ClaudeModel::Sonnet45),
         Model::Claude(ClaudeModel::Haiku45),
     ];
@@ -72,7 +68,7 @@ fn test_all_claude_models() {
 #[test]
 fn test_all_gemini_models() {
     // Verify all Gemini model variants can be wrapped in Model enum
-    let models = vec![
+    let models = [
         Model::Gemini(GeminiModel::Gemini25Pro),
         Model::Gemini(GeminiModel::Gemini25Flash),
         Model::Gemini(GeminiModel::Gemini25FlashLite),
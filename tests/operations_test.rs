@@ -1,10 +1,14 @@
 mod common;
 
 use rust2::message_db::{
-    CategoryReadOptions, MessageDbClient, MessageDbConfig, StreamReadOptions,
-    WriteMessage,
+    CategoryReadOptions, CategoryStream, Error, IdConflictPolicy, MessageDbClient, MessageDbConfig,
+    StreamReadOptions, WriteMessage,
 };
+use deadpool_postgres::Hook;
+use futures::StreamExt;
 use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use testcontainers::clients::Cli;
 use uuid::Uuid;
 
@@ -94,6 +98,92 @@ async fn test_write_message_idempotent() {
     assert_eq!(pos1, pos2);
 }
 
+#[tokio::test]
+async fn test_write_message_id_conflict_policies_same_data() {
+    setup_test!(_docker, _container, client);
+
+    for (i, policy) in [
+        IdConflictPolicy::Idempotent,
+        IdConflictPolicy::Error,
+        IdConflictPolicy::Regenerate,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let msg_id = Uuid::new_v4();
+        let stream_name = format!("test-conflict-same-{}", i);
+        let msg = WriteMessage::new(msg_id, &stream_name, "Deposited")
+            .with_data(json!({ "amount": 100 }))
+            .with_id_conflict_policy(policy);
+
+        let pos1 = client.write_message(msg.clone()).await.unwrap();
+        let pos2 = client.write_message(msg).await.unwrap();
+
+        // A true retry is idempotent under every policy
+        assert_eq!(pos1, pos2);
+        let messages = client
+            .get_stream_messages(StreamReadOptions::new(&stream_name))
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+    }
+}
+
+#[tokio::test]
+async fn test_write_message_id_conflict_error_policy() {
+    setup_test!(_docker, _container, client);
+
+    let msg_id = Uuid::new_v4();
+    let stream_name = "test-conflict-error";
+
+    let msg1 = WriteMessage::new(msg_id, stream_name, "Deposited")
+        .with_data(json!({ "amount": 100 }));
+    client.write_message(msg1).await.unwrap();
+
+    let msg2 = WriteMessage::new(msg_id, stream_name, "Deposited")
+        .with_data(json!({ "amount": 200 }))
+        .with_id_conflict_policy(IdConflictPolicy::Error);
+    let err = client.write_message(msg2).await.unwrap_err();
+
+    match err {
+        Error::IdReuse { message_id, stream_name: existing } => {
+            assert_eq!(message_id, msg_id);
+            assert_eq!(existing, stream_name);
+        }
+        other => panic!("Expected IdReuse, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_write_message_id_conflict_regenerate_policy() {
+    setup_test!(_docker, _container, client);
+
+    let msg_id = Uuid::new_v4();
+    let stream_name = "test-conflict-regenerate";
+
+    let msg1 = WriteMessage::new(msg_id, stream_name, "Deposited")
+        .with_data(json!({ "amount": 100 }));
+    let pos1 = client.write_message(msg1).await.unwrap();
+
+    let msg2 = WriteMessage::new(msg_id, stream_name, "Deposited")
+        .with_data(json!({ "amount": 200 }))
+        .with_id_conflict_policy(IdConflictPolicy::Regenerate);
+    let pos2 = client.write_message(msg2).await.unwrap();
+    assert_eq!(pos2, pos1 + 1);
+
+    let messages = client
+        .get_stream_messages(StreamReadOptions::new(stream_name))
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_ne!(messages[1].id, msg_id);
+    assert_eq!(messages[1].data["amount"], 200);
+    assert_eq!(
+        messages[1].metadata.as_ref().unwrap()["original_message_id"],
+        msg_id.to_string()
+    );
+}
+
 #[tokio::test]
 async fn test_write_message_expected_version_success() {
     setup_test!(_docker, _container, client);
@@ -329,6 +419,154 @@ async fn test_get_category_messages_ordering() {
     }
 }
 
+#[tokio::test]
+async fn test_get_category_messages_correlation_uses_canonical_keys() {
+    setup_test!(_docker, _container, client);
+
+    let command_stream = "transfercmd-abc";
+    client
+        .write_message(WriteMessage::new(Uuid::new_v4(), command_stream, "Transfer"))
+        .await
+        .unwrap();
+    let command = client
+        .get_last_stream_message(command_stream, None)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let explicit = WriteMessage::new(Uuid::new_v4(), "ledger-1", "Debited")
+        .with_correlation_stream_name(command_stream);
+    let followed = WriteMessage::new(Uuid::new_v4(), "ledger-2", "Credited")
+        .with_correlation_stream_name(command_stream)
+        .follow(&command);
+    // Written by hand in snake_case; the server-side filter doesn't see it
+    let snake_case = WriteMessage::new(Uuid::new_v4(), "ledger-3", "Credited")
+        .with_metadata(json!({ "correlation_stream_name": command_stream }));
+
+    client.write_message(explicit).await.unwrap();
+    client.write_message(followed).await.unwrap();
+    client.write_message(snake_case).await.unwrap();
+
+    let options = CategoryReadOptions::new("ledger").with_correlation("transfercmd");
+    let messages = client.get_category_messages(options).await.unwrap();
+
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].stream_name, "ledger-1");
+    assert_eq!(messages[1].stream_name, "ledger-2");
+    assert_eq!(
+        messages[1].causation_message_stream_name(),
+        Some(command_stream)
+    );
+}
+
+// ============================================================================
+// stream_messages / stream_category_messages tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_stream_messages_pages_through_whole_stream() {
+    setup_test!(_docker, _container, client);
+
+    let stream_name = "bulk-1";
+    let messages = (0..2500)
+        .map(|i| {
+            WriteMessage::new(Uuid::new_v4(), stream_name, "TestEvent")
+                .with_data(json!({ "sequence": i }))
+        })
+        .collect();
+    client.write_messages(stream_name, messages).await.unwrap();
+
+    let options = StreamReadOptions::new(stream_name).with_batch_size(1000);
+    let positions: Vec<i64> = client
+        .stream_messages(options)
+        .map(|message| message.unwrap().position)
+        .collect()
+        .await;
+
+    assert_eq!(positions.len(), 2500);
+    assert!(positions.iter().copied().eq(0..2500));
+}
+
+#[tokio::test]
+async fn test_stream_category_pages_through_whole_category() {
+    setup_test!(_docker, _container, client);
+
+    for stream_name in ["bulkcat-1", "bulkcat-2"] {
+        let messages = (0..1250)
+            .map(|i| {
+                WriteMessage::new(Uuid::new_v4(), stream_name, "TestEvent")
+                    .with_data(json!({ "sequence": i }))
+            })
+            .collect();
+        client.write_messages(stream_name, messages).await.unwrap();
+    }
+
+    let options = CategoryReadOptions::new("bulkcat").with_batch_size(1000);
+    let count = client
+        .stream_category_messages(options)
+        .map(Result::unwrap)
+        .count()
+        .await;
+
+    assert_eq!(count, 2500);
+}
+
+#[tokio::test]
+async fn test_stream_messages_small_pages_in_global_order() {
+    setup_test!(_docker, _container, client);
+
+    let stream_name = "pages-1";
+    let messages = (0..500)
+        .map(|i| {
+            WriteMessage::new(Uuid::new_v4(), stream_name, "TestEvent")
+                .with_data(json!({ "sequence": i }))
+        })
+        .collect();
+    client.write_messages(stream_name, messages).await.unwrap();
+
+    let options = StreamReadOptions::new(stream_name).with_batch_size(10);
+    let global_positions: Vec<i64> = client
+        .stream_messages(options)
+        .map(|message| message.unwrap().global_position)
+        .collect()
+        .await;
+
+    assert_eq!(global_positions.len(), 500);
+    assert!(global_positions.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[tokio::test]
+async fn test_stream_category_messages_small_pages_in_global_order() {
+    setup_test!(_docker, _container, client);
+
+    // Interleave writes so each page spans several streams
+    for i in 0..500 {
+        let stream_name = format!("pagescat-{}", i % 7);
+        let msg = WriteMessage::new(Uuid::new_v4(), &stream_name, "TestEvent")
+            .with_data(json!({ "sequence": i }));
+        client.write_message(msg).await.unwrap();
+    }
+
+    let options = CategoryReadOptions::new("pagescat").with_batch_size(10);
+    let sequences: Vec<i64> = client
+        .stream_category_messages(options)
+        .map(|message| message.unwrap().data["sequence"].as_i64().unwrap())
+        .collect()
+        .await;
+
+    assert_eq!(sequences, (0..500).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn test_stream_messages_empty_stream() {
+    setup_test!(_docker, _container, client);
+
+    let options = StreamReadOptions::new("nonexistent-stream");
+    let count = client.stream_messages(options).map(Result::unwrap).count().await;
+
+    assert_eq!(count, 0);
+}
+
 // ============================================================================
 // get_last_stream_message tests
 // ============================================================================
@@ -508,3 +746,328 @@ async fn test_stream_version_after_writes() {
     let v3 = client.stream_version(stream_name).await.unwrap().unwrap();
     assert_eq!(v3, 2);
 }
+
+#[tokio::test]
+async fn test_stream_version_cache_reduces_queries() {
+    setup_test!(_docker, _container, client);
+
+    let client = client.with_version_cache(16, std::time::Duration::from_secs(60));
+    let stream_name = "test-version-cache-hot";
+
+    // Command handler loop: check the version, then write against it
+    for expected in 0..5 {
+        let version = client.stream_version(stream_name).await.unwrap();
+        assert_eq!(version, if expected == 0 { None } else { Some(expected - 1) });
+
+        let msg = WriteMessage::new(Uuid::new_v4(), stream_name, "TestEvent")
+            .with_expected_version(version.unwrap_or(-1));
+        assert_eq!(client.write_message(msg).await.unwrap(), expected);
+    }
+
+    // Only the very first lookup reached the database
+    let stats = client.version_cache_stats().unwrap();
+    assert_eq!(stats.misses, 1);
+    assert_eq!(stats.hits, 4);
+}
+
+#[tokio::test]
+async fn test_stream_version_cache_saves_round_trips() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", host_port);
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+
+    // Every operation checks out one pooled connection for its query
+    let checkouts = Arc::new(AtomicUsize::new(0));
+    let count = |checkouts: &Arc<AtomicUsize>| {
+        let checkouts = checkouts.clone();
+        Hook::sync_fn(move |_, _| {
+            checkouts.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+    };
+    let pool = config
+        .pool_builder()
+        .post_create(count(&checkouts))
+        .pre_recycle(count(&checkouts))
+        .build()
+        .unwrap();
+    let client = MessageDbClient::from_pool(pool, config.schema_name.clone())
+        .await
+        .unwrap();
+
+    // Command handler loop: check the version, then write against it
+    let handle_commands = |client: MessageDbClient, stream_name: &'static str| async move {
+        for expected in 0..5 {
+            let version = client.stream_version(stream_name).await.unwrap();
+            let msg = WriteMessage::new(Uuid::new_v4(), stream_name, "TestEvent")
+                .with_expected_version(version.unwrap_or(-1));
+            assert_eq!(client.write_message(msg).await.unwrap(), expected);
+        }
+    };
+
+    let before = checkouts.load(Ordering::SeqCst);
+    handle_commands(client.clone(), "test-round-trips-uncached").await;
+    assert_eq!(checkouts.load(Ordering::SeqCst) - before, 10);
+
+    let cached = client.with_version_cache(16, std::time::Duration::from_secs(60));
+    let before = checkouts.load(Ordering::SeqCst);
+    handle_commands(cached, "test-round-trips-cached").await;
+
+    // One version lookup plus five writes
+    assert_eq!(checkouts.load(Ordering::SeqCst) - before, 6);
+}
+
+#[tokio::test]
+async fn test_stream_version_cache_invalidated_after_conflict() {
+    setup_test!(_docker, _container, client);
+
+    let cached = client.clone().with_version_cache(16, std::time::Duration::from_secs(60));
+    let stream_name = "test-version-cache-conflict";
+
+    let msg = WriteMessage::new(Uuid::new_v4(), stream_name, "Event1");
+    cached.write_message(msg).await.unwrap();
+    assert_eq!(cached.stream_version(stream_name).await.unwrap(), Some(0));
+
+    // Another writer moves the stream on without the cache noticing
+    let msg = WriteMessage::new(Uuid::new_v4(), stream_name, "Event2");
+    client.write_message(msg).await.unwrap();
+
+    // The stale version can't produce a wrong write, only a conflict
+    let stale = cached.stream_version(stream_name).await.unwrap().unwrap();
+    assert_eq!(stale, 0);
+    let msg = WriteMessage::new(Uuid::new_v4(), stream_name, "Event3")
+        .with_expected_version(stale);
+    let result = cached.write_message(msg).await;
+    assert!(matches!(result, Err(Error::ConcurrencyError { .. })));
+
+    // The conflict evicted the entry, so the retry sees the real version
+    let fresh = cached.stream_version(stream_name).await.unwrap().unwrap();
+    assert_eq!(fresh, 1);
+    let msg = WriteMessage::new(Uuid::new_v4(), stream_name, "Event3")
+        .with_expected_version(fresh);
+    assert_eq!(cached.write_message(msg).await.unwrap(), 2);
+
+    let stats = cached.version_cache_stats().unwrap();
+    assert_eq!(stats.hits, 2);
+    assert_eq!(stats.misses, 1);
+}
+
+#[tokio::test]
+async fn test_await_correlated_event_returns_delayed_reply() {
+    setup_test!(_docker, _container, client);
+
+    let command = WriteMessage::new(Uuid::new_v4(), "account:command-123", "Withdraw")
+        .with_data(json!({ "amount": 50 }))
+        .with_correlation_stream_name("transfer-9");
+    let written = client.write_message_ex(command).await.unwrap();
+
+    // A handler replies after a delay; a reply for another transfer and an
+    // uncorrelated event arrive first and must be ignored
+    let replier = client.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        let other = WriteMessage::new(Uuid::new_v4(), "account-123", "Withdrawn")
+            .with_correlation_stream_name("transfer-8");
+        replier.write_message(other).await.unwrap();
+        let unrelated = WriteMessage::new(Uuid::new_v4(), "account-123", "Deposited");
+        replier.write_message(unrelated).await.unwrap();
+        let reply = WriteMessage::new(Uuid::new_v4(), "account-123", "Withdrawn")
+            .with_data(json!({ "amount": 50 }))
+            .with_correlation_stream_name("transfer-9");
+        replier.write_message(reply).await.unwrap();
+    });
+
+    let reply = client
+        .await_correlated_event(
+            "account",
+            "transfer-9",
+            &["Withdrawn", "WithdrawalRejected"],
+            written.global_position,
+            std::time::Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(reply.message_type, "Withdrawn");
+    assert_eq!(reply.correlation_stream_name(), Some("transfer-9"));
+    assert_eq!(reply.data["amount"], 50);
+}
+
+#[tokio::test]
+async fn test_await_correlated_event_ignores_replies_to_earlier_commands() {
+    setup_test!(_docker, _container, client);
+
+    // The first command on this correlation stream was already answered
+    let first = WriteMessage::new(Uuid::new_v4(), "account:command-123", "Withdraw")
+        .with_data(json!({ "amount": 10 }))
+        .with_correlation_stream_name("transfer-9");
+    client.write_message(first).await.unwrap();
+    let first_reply = WriteMessage::new(Uuid::new_v4(), "account-123", "Withdrawn")
+        .with_data(json!({ "amount": 10 }))
+        .with_correlation_stream_name("transfer-9");
+    client.write_message(first_reply).await.unwrap();
+
+    let second = WriteMessage::new(Uuid::new_v4(), "account:command-123", "Withdraw")
+        .with_data(json!({ "amount": 50 }))
+        .with_correlation_stream_name("transfer-9");
+    let written = client.write_message_ex(second).await.unwrap();
+
+    let replier = client.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        let reply = WriteMessage::new(Uuid::new_v4(), "account-123", "Withdrawn")
+            .with_data(json!({ "amount": 50 }))
+            .with_correlation_stream_name("transfer-9");
+        replier.write_message(reply).await.unwrap();
+    });
+
+    let reply = client
+        .await_correlated_event(
+            "account",
+            "transfer-9",
+            &["Withdrawn"],
+            written.global_position,
+            std::time::Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(reply.data["amount"], 50);
+    assert!(reply.global_position > written.global_position);
+}
+
+#[tokio::test]
+async fn test_await_correlated_event_times_out() {
+    setup_test!(_docker, _container, client);
+
+    // Right correlation, but not an expected type
+    let event = WriteMessage::new(Uuid::new_v4(), "account-123", "Deposited")
+        .with_correlation_stream_name("transfer-9");
+    client.write_message(event).await.unwrap();
+
+    let started = std::time::Instant::now();
+    let result = client
+        .await_correlated_event(
+            "account",
+            "transfer-9",
+            &["Withdrawn"],
+            0,
+            std::time::Duration::from_millis(300),
+        )
+        .await;
+
+    assert!(matches!(result, Err(Error::Timeout(_))));
+    assert!(started.elapsed() >= std::time::Duration::from_millis(300));
+}
+
+#[tokio::test]
+async fn test_write_message_ex_matches_stored_message() {
+    setup_test!(_docker, _container, client);
+
+    let stream_name = "test-write-ex-123";
+    client
+        .write_message(WriteMessage::new(Uuid::new_v4(), "test-write-ex-other", "Opened"))
+        .await
+        .unwrap();
+
+    let mut results = Vec::new();
+    for message_type in ["Opened", "Deposited"] {
+        let msg = WriteMessage::new(Uuid::new_v4(), stream_name, message_type);
+        results.push(client.write_message_ex(msg).await.unwrap());
+    }
+
+    let messages = client
+        .get_stream_messages(StreamReadOptions::new(stream_name))
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 2);
+    for (written, stored) in results.iter().zip(&messages) {
+        assert_eq!(written.position, stored.position);
+        assert_eq!(written.global_position, stored.global_position);
+        assert_eq!(written.time, stored.time);
+    }
+    assert_eq!(results[1].global_position, results[0].global_position + 1);
+}
+
+#[tokio::test]
+async fn test_write_message_ex_idempotent_rewrite_returns_original() {
+    setup_test!(_docker, _container, client);
+
+    let stream_name = "test-write-ex-456";
+    let msg = WriteMessage::new(Uuid::new_v4(), stream_name, "Deposited")
+        .with_data(json!({ "amount": 100 }));
+    let first = client.write_message_ex(msg.clone()).await.unwrap();
+
+    // Writing the same message again reports where it was first written
+    let second = client.write_message_ex(msg).await.unwrap();
+    assert_eq!(first, second);
+}
+
+#[tokio::test]
+async fn test_get_streams_in_category() {
+    setup_test!(_docker, _container, client);
+
+    // Interleave writes so each stream has several messages
+    let stream_names: Vec<String> = (1..=5).map(|n| format!("ledger-{}", n)).collect();
+    for message_type in ["Opened", "Credited"] {
+        for stream_name in &stream_names {
+            let msg = WriteMessage::new(Uuid::new_v4(), stream_name, message_type);
+            client.write_message(msg).await.unwrap();
+        }
+    }
+    client
+        .write_message(WriteMessage::new(Uuid::new_v4(), "other-1", "Opened"))
+        .await
+        .unwrap();
+
+    let streams = client.get_streams_in_category("ledger", 1, -1).await.unwrap();
+    assert_eq!(streams, stream_names);
+
+    // Each stream starts at its first message
+    let streams = client.get_category_streams("ledger", 1, -1).await.unwrap();
+    let opened = client
+        .get_stream_messages(StreamReadOptions::new("ledger-2"))
+        .await
+        .unwrap();
+    assert_eq!(streams[1].first_global_position, opened[0].global_position);
+
+    // Starting at the second stream's first message leaves the first stream
+    // out, even though it has later messages
+    let streams = client
+        .get_streams_in_category("ledger", opened[0].global_position, -1)
+        .await
+        .unwrap();
+    assert_eq!(streams, stream_names[1..]);
+}
+
+#[tokio::test]
+async fn test_get_category_streams_pages_list_each_stream_once() {
+    setup_test!(_docker, _container, client);
+
+    // Every stream has messages on both sides of every page boundary
+    let stream_names: Vec<String> = (1..=5).map(|n| format!("ledger-{}", n)).collect();
+    for message_type in ["Opened", "Credited", "Debited"] {
+        for stream_name in &stream_names {
+            let msg = WriteMessage::new(Uuid::new_v4(), stream_name, message_type);
+            client.write_message(msg).await.unwrap();
+        }
+    }
+
+    let mut listed: Vec<CategoryStream> = Vec::new();
+    let mut position = 1;
+    loop {
+        let page = client
+            .get_category_streams("ledger", position, 2)
+            .await
+            .unwrap();
+        let Some(last) = page.last() else { break };
+        position = last.first_global_position + 1;
+        listed.extend(page);
+    }
+
+    let names: Vec<String> = listed.into_iter().map(|stream| stream.stream_name).collect();
+    assert_eq!(names, stream_names);
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use rust2::message_db::{
+    CategoryReadOptions, ExportOptions, IdPolicy, MessageDbClient, MessageDbConfig, WriteMessage,
+};
+use serde_json::{json, Value};
+use testcontainers::clients::Cli;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_category_export_import_round_trip() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string)
+        .expect("Failed to create config");
+    let client = MessageDbClient::new(config)
+        .await
+        .expect("Failed to create client");
+
+    // 100 messages across 10 streams
+    let category = format!("exportSource{}", Uuid::new_v4().simple());
+    for i in 0..100 {
+        let msg = WriteMessage::new(
+            Uuid::new_v4(),
+            format!("{}-{}", category, i % 10),
+            if i % 2 == 0 { "Opened" } else { "Updated" },
+        )
+        .with_data(json!({"n": i, "email": format!("user{}@example.com", i)}))
+        .with_metadata(json!({"correlation_id": format!("c{}", i)}));
+        client.write_message(msg).await.unwrap();
+    }
+
+    let mut export = Vec::new();
+    let options = ExportOptions::new()
+        .with_batch_size(30)
+        .with_redaction(|_, data, _| {
+            data["email"] = json!("redacted");
+        });
+    let exported = client
+        .export_category(&category, &mut export, options)
+        .await
+        .unwrap();
+    assert_eq!(exported, 100);
+    assert_eq!(export.iter().filter(|b| **b == b'\n').count(), 100);
+
+    let imported = client
+        .import_jsonl(&export[..], IdPolicy::Regenerate, Some("copy".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(imported, 100);
+
+    let source = client
+        .get_category_messages(CategoryReadOptions::new(&category))
+        .await
+        .unwrap();
+    let copy = client
+        .get_category_messages(CategoryReadOptions::new(format!("copy{}", category)))
+        .await
+        .unwrap();
+    assert_eq!(copy.len(), 100);
+
+    for (original, copied) in source.iter().zip(&copy) {
+        assert_ne!(copied.id, original.id);
+        assert_eq!(copied.stream_name, format!("copy{}", original.stream_name));
+        assert_eq!(copied.message_type, original.message_type);
+        assert_eq!(copied.position, original.position);
+
+        let mut expected_data = original.data.clone();
+        expected_data["email"] = json!("redacted");
+        assert_eq!(copied.data, expected_data);
+
+        let metadata = copied.metadata.as_ref().unwrap();
+        assert_eq!(
+            metadata["correlation_id"],
+            original.metadata.as_ref().unwrap()["correlation_id"]
+        );
+        assert_eq!(
+            metadata["original_message_id"],
+            Value::String(original.id.to_string())
+        );
+    }
+
+    // Preserved ids make a second import of the same file a no-op
+    let reimported = client
+        .import_jsonl(&export[..], IdPolicy::Preserve, None)
+        .await
+        .unwrap();
+    assert_eq!(reimported, 100);
+    let source_after = client
+        .get_category_messages(CategoryReadOptions::new(&category))
+        .await
+        .unwrap();
+    assert_eq!(source_after.len(), 100);
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use rust2::message_db::types::WriteMessage;
+use rust2::message_db::{Error, MessageDbClient, MessageDbConfig, Projection};
+use serde_json::json;
+use testcontainers::clients::Cli;
+use uuid::Uuid;
+
+fn balance_projection() -> Projection<i64> {
+    Projection::new(0)
+        .when("Deposited", |balance, msg| {
+            Ok(balance + msg.data["amount"].as_i64().unwrap())
+        })
+        .when("Withdrawn", |balance, msg| {
+            Ok(balance - msg.data["amount"].as_i64().unwrap())
+        })
+}
+
+#[tokio::test]
+async fn test_fetch_entity_folds_stream_and_returns_version() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let stream_name = format!("account-{}", Uuid::new_v4().simple());
+    let projection = balance_projection();
+
+    // A stream that doesn't exist yet has the initial state and no version
+    let (balance, version) = client
+        .fetch_entity(&stream_name, &projection)
+        .await
+        .unwrap();
+    assert_eq!(balance, 0);
+    assert_eq!(version, None);
+
+    for (message_type, amount) in [
+        ("Deposited", 100),
+        ("Withdrawn", 30),
+        ("Renamed", 0),
+        ("Deposited", 5),
+    ] {
+        let msg = WriteMessage::new(Uuid::new_v4(), &stream_name, message_type)
+            .with_data(json!({ "amount": amount }));
+        client.write_message(msg).await.unwrap();
+    }
+
+    let (balance, version) = client
+        .fetch_entity(&stream_name, &projection)
+        .await
+        .unwrap();
+    assert_eq!(balance, 75);
+    assert_eq!(version, Some(3));
+
+    let withdrawal = WriteMessage::new(Uuid::new_v4(), &stream_name, "Withdrawn")
+        .with_data(json!({ "amount": 75 }))
+        .with_expected_version(version.unwrap());
+    assert_eq!(client.write_message(withdrawal).await.unwrap(), 4);
+
+    // The version is now stale
+    let stale = WriteMessage::new(Uuid::new_v4(), &stream_name, "Withdrawn")
+        .with_data(json!({ "amount": 1 }))
+        .with_expected_version(version.unwrap());
+    assert!(matches!(
+        client.write_message(stale).await,
+        Err(Error::ConcurrencyError { .. })
+    ));
+
+    let (balance, version) = client
+        .fetch_entity(&stream_name, &projection)
+        .await
+        .unwrap();
+    assert_eq!(balance, 0);
+    assert_eq!(version, Some(4));
+}
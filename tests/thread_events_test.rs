@@ -0,0 +1,151 @@
+mod common;
+
+use rust2::llm::Message;
+use rust2::message_db::{MessageDbClient, MessageDbConfig, StreamReadOptions, WriteMessage};
+use rust2::models::{MessageContent, MessageType};
+use rust2::message_db::Error;
+use rust2::thread_events::{
+    append_fork, append_message, decode, fork_point, render_thread, thread_stream_name,
+    to_write_message, ForkedFrom, MESSAGE_APPENDED, THREAD_FORKED,
+};
+use serde_json::json;
+use testcontainers::clients::Cli;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_thread_renders_old_shape_events_as_placeholders() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string)
+        .expect("Failed to create config");
+    let client = MessageDbClient::new(config)
+        .await
+        .expect("Failed to create client");
+
+    let thread_id = Uuid::new_v4();
+    let stream_name = thread_stream_name(thread_id);
+
+    // Current shape, stamped by the encoder
+    client
+        .write_message(to_write_message(
+            thread_id,
+            &Message::user("What's the weather?"),
+        ))
+        .await
+        .unwrap();
+
+    // Fixture v1 event with an intentionally old shape
+    let old_shape = WriteMessage::new(Uuid::new_v4(), stream_name.clone(), MESSAGE_APPENDED)
+        .with_data(json!({"sender": "assistant", "body": "Sunny"}))
+        .with_metadata(json!({"payload_schema_version": 1}));
+    let old_shape_id = old_shape.id;
+    client.write_message(old_shape).await.unwrap();
+
+    // Written before stamping existed; decoded through the v1 adapter
+    client
+        .write_message(
+            WriteMessage::new(Uuid::new_v4(), stream_name.clone(), MESSAGE_APPENDED).with_data(
+                json!({"role": "assistant", "content": [{"type": "text", "text": "It's 18°C"}]}),
+            ),
+        )
+        .await
+        .unwrap();
+
+    let events = client
+        .get_stream_messages(StreamReadOptions::new(stream_name))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 3);
+    assert_eq!(
+        events[0].metadata.as_ref().unwrap()["payload_schema_version"],
+        1
+    );
+    assert!(decode(&events[1]).is_err());
+    assert_eq!(decode(&events[2]).unwrap(), Message::assistant("It's 18°C"));
+
+    let rendered = render_thread(&events);
+    assert_eq!(rendered.len(), 3);
+    assert_eq!(
+        rendered[0].content,
+        MessageContent::User {
+            text: "What's the weather?".to_string()
+        }
+    );
+    assert_eq!(rendered[1].id, old_shape_id.to_string());
+    assert_eq!(rendered[1].message_type, MessageType::Unsupported);
+    assert_eq!(
+        rendered[2].content,
+        MessageContent::Agent {
+            text: "It's 18°C".to_string()
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_fork_starts_with_its_lineage() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string)
+        .expect("Failed to create config");
+    let client = MessageDbClient::new(config)
+        .await
+        .expect("Failed to create client");
+
+    let source_id = Uuid::new_v4();
+    let history = [
+        Message::user("Weather in Paris?"),
+        Message::assistant("Sunny"),
+        Message::user("And Lyon?"),
+        Message::assistant("Cloudy"),
+    ];
+    for message in &history {
+        append_message(&client, source_id, message).await.unwrap();
+    }
+    let source_events = client
+        .get_stream_messages(StreamReadOptions::new(thread_stream_name(source_id)))
+        .await
+        .unwrap();
+
+    // Branch before the second question
+    let fork_id = Uuid::new_v4();
+    let forked_from = fork_point(source_id, &source_events, 2).unwrap();
+    assert_eq!(
+        forked_from,
+        ForkedFrom {
+            stream: thread_stream_name(source_id),
+            position: 1,
+        }
+    );
+    let positions = append_fork(&client, fork_id, &forked_from, &history[..2])
+        .await
+        .unwrap();
+    assert_eq!(positions, vec![0, 1, 2]);
+
+    let events = client
+        .get_stream_messages(StreamReadOptions::new(thread_stream_name(fork_id)))
+        .await
+        .unwrap();
+    assert_eq!(events[0].message_type, THREAD_FORKED);
+    assert_eq!(
+        events[0].data,
+        json!({"forked_from": {"stream": thread_stream_name(source_id), "position": 1}})
+    );
+    let rendered = render_thread(&events);
+    assert_eq!(rendered.len(), 2);
+    assert_eq!(
+        rendered[1].content,
+        MessageContent::Agent {
+            text: "Sunny".to_string()
+        }
+    );
+
+    // A thread can only be forked into once
+    let result = append_fork(&client, fork_id, &forked_from, &history[..2]).await;
+    assert!(matches!(result, Err(Error::ConcurrencyError { .. })));
+}
@@ -0,0 +1,91 @@
+// Tests for #[derive(ToolInput)]
+
+use rust2::llm::create_tool_declaration;
+use rust2_tool_macros::ToolInput;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Search the order history
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize, ToolInput)]
+struct SearchOrdersArgs {
+    #[tool_param(description = "Customer id, e.g. c-123")]
+    customer_id: String,
+    /// Most orders to return
+    limit: u32,
+    #[tool_param(description = "Only orders with this status")]
+    #[serde(rename = "orderStatus")]
+    status: Option<String>,
+    tags: Vec<String>,
+}
+
+#[test]
+fn derived_schema_matches_hand_written() {
+    let declaration = create_tool_declaration::<SearchOrdersArgs>("search_orders", "Search orders");
+
+    assert_eq!(
+        declaration.input_schema,
+        json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "title": "SearchOrdersArgs",
+            "description": "Search the order history",
+            "type": "object",
+            "required": ["customer_id", "limit", "tags"],
+            "properties": {
+                "customer_id": {
+                    "description": "Customer id, e.g. c-123",
+                    "type": "string"
+                },
+                "limit": {
+                    "description": "Most orders to return",
+                    "type": "integer",
+                    "format": "uint32",
+                    "minimum": 0.0
+                },
+                "orderStatus": {
+                    "description": "Only orders with this status",
+                    "type": ["string", "null"]
+                },
+                "tags": {
+                    "type": "array",
+                    "items": {"type": "string"}
+                }
+            }
+        })
+    );
+}
+
+/// Page through the audit log
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize, ToolInput)]
+struct AuditLogArgs {
+    account_id: String,
+    #[serde(default)]
+    page_size: u32,
+    #[serde(skip)]
+    cursor: Vec<u8>,
+    #[serde(skip_deserializing)]
+    fetched: bool,
+}
+
+#[test]
+fn serde_skip_and_default_fields() {
+    let declaration = create_tool_declaration::<AuditLogArgs>("audit_log", "Read the audit log");
+
+    assert_eq!(declaration.input_schema["required"], json!(["account_id"]));
+    assert_eq!(
+        declaration.input_schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>(),
+        vec!["account_id", "page_size"]
+    );
+}
+
+#[test]
+fn tool_input_compile_errors() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/tool_input_serde_skip.rs");
+    t.compile_fail("tests/ui/tool_input_not_serde.rs");
+}
@@ -0,0 +1,108 @@
+// Tests for the #[tool] macro: description handling and stateful tools
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use rust2::llm::{FunctionRegistry, ToolExecutor};
+use rust2_tool_macros::tool;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[test]
+fn tool_macro_descriptions() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/tool_doc_description.rs");
+    t.pass("tests/ui/tool_attribute_description.rs");
+    t.pass("tests/ui/tool_both_descriptions.rs");
+    t.compile_fail("tests/ui/tool_without_description.rs");
+}
+
+#[test]
+fn tool_macro_input_schema() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/tool_documented_args.rs");
+    t.compile_fail("tests/ui/tool_args_not_json_schema.rs");
+}
+
+struct AppState {
+    visits: AtomicUsize,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct VisitArgs {
+    page: String,
+}
+
+/// Record a visit and return how many visits there have been
+#[tool]
+async fn visit(ctx: Arc<AppState>, args: VisitArgs) -> Result<String, String> {
+    let count = ctx.visits.fetch_add(1, Ordering::SeqCst) + 1;
+    Ok(format!("{} #{}", args.page, count))
+}
+
+#[tokio::test]
+async fn stateful_tool_shares_context_across_calls() {
+    let state = Arc::new(AppState {
+        visits: AtomicUsize::new(0),
+    });
+    let mut registry = FunctionRegistry::new();
+    registry
+        .register(visit_tool::registration_with(state.clone()))
+        .unwrap();
+
+    for expected in ["\"home #1\"", "\"home #2\""] {
+        let result = registry
+            .execute(
+                "call".to_string(),
+                visit_tool::NAME.to_string(),
+                serde_json::json!({"page": "home"}),
+            )
+            .await;
+        assert_eq!(result.unwrap(), expected);
+    }
+    assert_eq!(state.visits.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn empty_object_reports_missing_field() {
+    let mut registry = FunctionRegistry::new();
+    registry
+        .register(visit_tool::registration_with(Arc::new(AppState {
+            visits: AtomicUsize::new(0),
+        })))
+        .unwrap();
+
+    let result = registry
+        .execute(
+            "call".to_string(),
+            visit_tool::NAME.to_string(),
+            serde_json::json!({}),
+        )
+        .await;
+    let error = result.unwrap_err();
+    assert!(error.contains("missing field `page`"), "{}", error);
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct NoArgs;
+
+/// Check the service is up
+#[tool]
+fn ping(_args: NoArgs) -> Result<String, String> {
+    Ok("pong".to_string())
+}
+
+#[tokio::test]
+async fn unit_args_tool_accepts_empty_object() {
+    let mut registry = FunctionRegistry::new();
+    registry.register(ping_tool::registration()).unwrap();
+
+    let result = registry
+        .execute(
+            "call".to_string(),
+            ping_tool::NAME.to_string(),
+            serde_json::json!({}),
+        )
+        .await;
+    assert_eq!(result.unwrap(), "\"pong\"");
+}
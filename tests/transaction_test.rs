@@ -1,9 +1,11 @@
 mod common;
 
-use rust2::message_db::{MessageDbClient, MessageDbConfig};
+use rust2::message_db::transaction::with_retry;
+use rust2::message_db::{Error, MessageDbClient, MessageDbConfig};
 use rust2::message_db::types::WriteMessage;
 use rust2::message_db::operations::StreamReadOptions;
 use serde_json::json;
+use std::sync::atomic::{AtomicU32, Ordering};
 use testcontainers::clients::Cli;
 use uuid::Uuid;
 
@@ -323,3 +325,452 @@ async fn test_transaction_get_last_message() {
 
     txn.commit().await.unwrap();
 }
+
+#[tokio::test]
+async fn test_write_messages_gets_contiguous_positions() {
+    // Start Message DB container
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host = "127.0.0.1";
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string(host, port);
+
+    // Create client
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let stream_name = format!("test-account-{}", Uuid::new_v4());
+    let opened = WriteMessage::new(Uuid::new_v4(), &stream_name, "Opened");
+    client.write_message(opened).await.unwrap();
+
+    let messages: Vec<_> = (0..100)
+        .map(|n| {
+            let msg = WriteMessage::new(Uuid::new_v4(), &stream_name, "Deposited")
+                .with_data(json!({ "amount": n }));
+            if n == 0 {
+                msg.with_expected_version(0)
+            } else {
+                msg
+            }
+        })
+        .collect();
+
+    let positions = client.write_messages(&stream_name, messages).await.unwrap();
+
+    assert_eq!(positions, (1..=100).collect::<Vec<i64>>());
+    let stored = client
+        .get_stream_messages(StreamReadOptions::new(&stream_name).with_batch_size(1000))
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 101);
+    assert_eq!(stored[100].data["amount"], 99);
+}
+
+#[tokio::test]
+async fn test_write_messages_empty_batch() {
+    // Start Message DB container
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host = "127.0.0.1";
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string(host, port);
+
+    // Create client
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let positions = client.write_messages("test-account-empty", vec![]).await.unwrap();
+
+    assert!(positions.is_empty());
+}
+
+#[tokio::test]
+async fn test_write_messages_concurrency_error_writes_nothing() {
+    // Start Message DB container
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host = "127.0.0.1";
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string(host, port);
+
+    // Create client
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let stream_name = format!("test-account-{}", Uuid::new_v4());
+    let messages = vec![
+        WriteMessage::new(Uuid::new_v4(), &stream_name, "Opened").with_expected_version(-1),
+        WriteMessage::new(Uuid::new_v4(), &stream_name, "Deposited"),
+    ];
+    client.write_messages(&stream_name, messages).await.unwrap();
+
+    // Stale expected version: the stream is at version 1
+    let messages = vec![
+        WriteMessage::new(Uuid::new_v4(), &stream_name, "Withdrawn").with_expected_version(0),
+        WriteMessage::new(Uuid::new_v4(), &stream_name, "FeeCharged"),
+    ];
+    let result = client.write_messages(&stream_name, messages).await;
+
+    assert!(matches!(
+        result,
+        Err(rust2::message_db::Error::ConcurrencyError { .. })
+    ));
+    let stored = client
+        .get_stream_messages(StreamReadOptions::new(&stream_name))
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 2);
+}
+
+#[tokio::test]
+async fn test_write_messages_failure_rolls_back_earlier_writes() {
+    // Start Message DB container
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host = "127.0.0.1";
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string(host, port);
+
+    // Create client
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let stream_name = format!("test-account-{}", Uuid::new_v4());
+    let existing_id = Uuid::new_v4();
+    client
+        .write_message(WriteMessage::new(existing_id, &stream_name, "Opened"))
+        .await
+        .unwrap();
+
+    // The third message reuses an existing id, failing after two writes
+    let messages = vec![
+        WriteMessage::new(Uuid::new_v4(), &stream_name, "Deposited"),
+        WriteMessage::new(Uuid::new_v4(), &stream_name, "Deposited"),
+        WriteMessage::new(existing_id, &stream_name, "Deposited"),
+    ];
+    assert!(client.write_messages(&stream_name, messages).await.is_err());
+
+    let stored = client
+        .get_stream_messages(StreamReadOptions::new(&stream_name))
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 1);
+
+    // The connection is usable again after the rollback
+    let retry = vec![WriteMessage::new(Uuid::new_v4(), &stream_name, "Deposited")];
+    assert_eq!(client.write_messages(&stream_name, retry).await.unwrap(), vec![1]);
+}
+
+#[tokio::test]
+async fn test_transaction_write_messages() {
+    // Start Message DB container
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host = "127.0.0.1";
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string(host, port);
+
+    // Create client
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let stream1 = format!("test-account-{}", Uuid::new_v4());
+    let stream2 = format!("test-account-{}", Uuid::new_v4());
+
+    let mut txn = client.begin_transaction().await.unwrap();
+    let first = txn
+        .write_messages(
+            &stream1,
+            vec![
+                WriteMessage::new(Uuid::new_v4(), &stream1, "Withdrawn"),
+                WriteMessage::new(Uuid::new_v4(), &stream1, "FeeCharged"),
+            ],
+        )
+        .await
+        .unwrap();
+    let second = txn
+        .write_messages(
+            &stream2,
+            vec![WriteMessage::new(Uuid::new_v4(), &stream2, "Deposited")],
+        )
+        .await
+        .unwrap();
+    txn.commit().await.unwrap();
+
+    assert_eq!(first, vec![0, 1]);
+    assert_eq!(second, vec![0]);
+}
+
+#[tokio::test]
+async fn test_with_retry_succeeds_after_concurrency_conflict() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let stream_name = format!("test-account-{}", Uuid::new_v4());
+    client
+        .write_message(WriteMessage::new(Uuid::new_v4(), &stream_name, "Opened"))
+        .await
+        .unwrap();
+
+    let attempts = AtomicU32::new(0);
+    let position = with_retry(&client, 3, async |txn| {
+        let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+        let version = txn.stream_version(&stream_name).await?.unwrap();
+
+        // Another writer gets in between the read and the write, once
+        if attempt == 1 {
+            client
+                .write_message(WriteMessage::new(Uuid::new_v4(), &stream_name, "Deposited"))
+                .await?;
+        }
+
+        let msg = WriteMessage::new(Uuid::new_v4(), &stream_name, "Withdrawn")
+            .with_expected_version(version);
+        txn.write_message(msg).await
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    assert_eq!(position, 2);
+
+    let messages = client
+        .get_stream_messages(StreamReadOptions::new(&stream_name))
+        .await
+        .unwrap();
+    let types: Vec<&str> = messages.iter().map(|m| m.message_type.as_str()).collect();
+    assert_eq!(types, vec!["Opened", "Deposited", "Withdrawn"]);
+}
+
+#[tokio::test]
+async fn test_with_retry_returns_conflict_after_max_attempts() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let stream_name = format!("test-account-{}", Uuid::new_v4());
+    let attempts = AtomicU32::new(0);
+    let result = with_retry(&client, 3, async |txn| {
+        attempts.fetch_add(1, Ordering::SeqCst);
+        // The stream is empty, so version 5 never matches
+        let msg = WriteMessage::new(Uuid::new_v4(), &stream_name, "Withdrawn")
+            .with_expected_version(5);
+        txn.write_message(msg).await
+    })
+    .await;
+
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    match result {
+        Err(Error::ConcurrencyError { expected_version, .. }) => assert_eq!(expected_version, 5),
+        other => panic!("expected ConcurrencyError, got {:?}", other),
+    }
+
+    let version = client.stream_version(&stream_name).await.unwrap();
+    assert_eq!(version, None);
+}
+
+#[tokio::test]
+async fn test_savepoint_rollback_keeps_earlier_writes() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let stream_name = format!("test-account-{}", Uuid::new_v4());
+    let mut txn = client.begin_transaction().await.unwrap();
+
+    txn.write_message(WriteMessage::new(Uuid::new_v4(), &stream_name, "Opened"))
+        .await
+        .unwrap();
+
+    let mut savepoint = txn.savepoint("before_fees").await.unwrap();
+    savepoint
+        .write_message(WriteMessage::new(Uuid::new_v4(), &stream_name, "FeeCharged"))
+        .await
+        .unwrap();
+    assert_eq!(savepoint.stream_version(&stream_name).await.unwrap(), Some(1));
+    savepoint.rollback_to().await.unwrap();
+
+    // A dropped savepoint keeps its writes
+    {
+        let mut savepoint = txn.savepoint("deposit").await.unwrap();
+        savepoint
+            .write_message(WriteMessage::new(Uuid::new_v4(), &stream_name, "Deposited"))
+            .await
+            .unwrap();
+    }
+
+    // A failed write only aborts the work since the savepoint
+    let mut savepoint = txn.savepoint("withdrawal").await.unwrap();
+    let conflicting = WriteMessage::new(Uuid::new_v4(), &stream_name, "Withdrawn")
+        .with_expected_version(10);
+    assert!(matches!(
+        savepoint.write_message(conflicting).await,
+        Err(Error::ConcurrencyError { .. })
+    ));
+    savepoint.rollback_to().await.unwrap();
+
+    txn.write_message(WriteMessage::new(Uuid::new_v4(), &stream_name, "Closed"))
+        .await
+        .unwrap();
+    txn.commit().await.unwrap();
+
+    let types: Vec<String> = client
+        .get_stream_messages(StreamReadOptions::new(&stream_name))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|msg| msg.message_type)
+        .collect();
+    assert_eq!(types, vec!["Opened", "Deposited", "Closed"]);
+}
+
+#[tokio::test]
+async fn test_savepoint_rollback_after_failed_write_with_dropped_nested_savepoint() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let stream_name = format!("test-account-{}", Uuid::new_v4());
+    let mut txn = client.begin_transaction().await.unwrap();
+
+    txn.write_message(WriteMessage::new(Uuid::new_v4(), &stream_name, "Opened"))
+        .await
+        .unwrap();
+
+    let mut outer = txn.savepoint("transfer").await.unwrap();
+    {
+        let mut inner = outer.savepoint("debit").await.unwrap();
+        inner
+            .write_message(WriteMessage::new(Uuid::new_v4(), &stream_name, "Debited"))
+            .await
+            .unwrap();
+    }
+
+    // The failed write aborts the transaction while the inner release is pending
+    let conflicting = WriteMessage::new(Uuid::new_v4(), &stream_name, "Credited")
+        .with_expected_version(10);
+    assert!(matches!(
+        outer.write_message(conflicting).await,
+        Err(Error::ConcurrencyError { .. })
+    ));
+    outer.rollback_to().await.unwrap();
+
+    txn.write_message(WriteMessage::new(Uuid::new_v4(), &stream_name, "Closed"))
+        .await
+        .unwrap();
+    txn.commit().await.unwrap();
+
+    let types: Vec<String> = client
+        .get_stream_messages(StreamReadOptions::new(&stream_name))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|msg| msg.message_type)
+        .collect();
+    assert_eq!(types, vec!["Opened", "Closed"]);
+}
+
+#[tokio::test]
+async fn test_savepoint_rejects_invalid_name() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let mut txn = client.begin_transaction().await.unwrap();
+    assert!(matches!(
+        txn.savepoint("sp; COMMIT").await,
+        Err(Error::ValidationError(_))
+    ));
+    txn.rollback().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_write_message_ex_in_transaction_matches_stored_message() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let stream_name = format!("test-account-{}", Uuid::new_v4());
+    let mut txn = client.begin_transaction().await.unwrap();
+    let opened = txn
+        .write_message_ex(WriteMessage::new(Uuid::new_v4(), &stream_name, "Opened"))
+        .await
+        .unwrap();
+    let deposited = txn
+        .write_message_ex(WriteMessage::new(Uuid::new_v4(), &stream_name, "Deposited"))
+        .await
+        .unwrap();
+    txn.commit().await.unwrap();
+
+    // Messages written in one transaction share its timestamp
+    assert_eq!(opened.time, deposited.time);
+
+    let messages = client
+        .get_stream_messages(StreamReadOptions::new(&stream_name))
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 2);
+    for (written, stored) in [opened, deposited].iter().zip(&messages) {
+        assert_eq!(written.position, stored.position);
+        assert_eq!(written.global_position, stored.global_position);
+        assert_eq!(written.time, stored.time);
+    }
+}
+
+#[tokio::test]
+async fn test_get_streams_in_category_sees_uncommitted_writes() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string).unwrap();
+    let client = MessageDbClient::new(config).await.unwrap();
+
+    let stream_names: Vec<String> = (1..=5).map(|n| format!("ledger-{}", n)).collect();
+    let mut txn = client.begin_transaction().await.unwrap();
+    for stream_name in &stream_names {
+        txn.write_message(WriteMessage::new(Uuid::new_v4(), stream_name, "Opened"))
+            .await
+            .unwrap();
+    }
+
+    let streams = txn.get_streams_in_category("ledger", 1, -1).await.unwrap();
+    assert_eq!(streams, stream_names);
+    let streams = txn.get_category_streams("ledger", 1, -1).await.unwrap();
+    assert_eq!(streams.len(), stream_names.len());
+    assert!(client
+        .get_streams_in_category("ledger", 1, -1)
+        .await
+        .unwrap()
+        .is_empty());
+
+    txn.commit().await.unwrap();
+    let streams = client.get_streams_in_category("ledger", 1, -1).await.unwrap();
+    assert_eq!(streams, stream_names);
+}
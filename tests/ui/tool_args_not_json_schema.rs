@@ -0,0 +1,15 @@
+use rust2_tool_macros::tool;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct EchoArgs {
+    text: String,
+}
+
+/// Echo the text back.
+#[tool]
+fn echo(args: EchoArgs) -> Result<String, String> {
+    Ok(args.text)
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+use rust2_tool_macros::tool;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Deserialize, JsonSchema)]
+struct EchoArgs {
+    text: String,
+}
+
+/// Internal notes that the model shouldn't see
+#[tool(description = "Echo the text back")]
+async fn echo(args: EchoArgs) -> Result<String, String> {
+    Ok(args.text)
+}
+
+fn main() {
+    assert_eq!(echo_tool::declaration().description, "Echo the text back");
+}
@@ -0,0 +1,23 @@
+use rust2_tool_macros::tool;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Deserialize, JsonSchema)]
+struct EchoArgs {
+    text: String,
+}
+
+/// Echo the text back.
+///
+///     Indented lines are trimmed.
+#[tool]
+fn echo(args: EchoArgs) -> Result<String, String> {
+    Ok(args.text)
+}
+
+fn main() {
+    assert_eq!(
+        echo_tool::declaration().description,
+        "Echo the text back.\n\nIndented lines are trimmed."
+    );
+}
@@ -0,0 +1,46 @@
+use rust2_tool_macros::tool;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// Search the product catalogue
+#[derive(Deserialize, JsonSchema)]
+struct SearchArgs {
+    /// Words to look for in product names
+    query: String,
+    /// Most results to return
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct NoArgs;
+
+/// Search products.
+#[tool]
+fn search(args: SearchArgs) -> Result<String, String> {
+    Ok(args.query)
+}
+
+/// Check the service is up.
+#[tool]
+fn ping(_args: NoArgs) -> Result<String, String> {
+    Ok("pong".to_string())
+}
+
+fn main() {
+    let schema = search_tool::declaration().input_schema;
+    assert_eq!(schema["type"], "object");
+    assert_eq!(schema["required"], serde_json::json!(["query"]));
+    assert_eq!(schema["properties"]["query"]["type"], "string");
+    assert_eq!(
+        schema["properties"]["query"]["description"],
+        "Words to look for in product names"
+    );
+    assert_eq!(
+        schema["properties"]["limit"]["description"],
+        "Most results to return"
+    );
+
+    let schema = ping_tool::declaration().input_schema;
+    assert_eq!(schema["type"], "object");
+    assert_eq!(schema["properties"], serde_json::json!({}));
+}
@@ -0,0 +1,13 @@
+use rust2_tool_macros::ToolInput;
+use schemars::JsonSchema;
+
+#[derive(JsonSchema)]
+struct Handle(u32);
+
+#[derive(ToolInput)]
+struct OpenArgs {
+    path: String,
+    handle: Handle,
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use rust2_tool_macros::ToolInput;
+use serde::{Deserialize, Serialize};
+
+// Neither serde nor JsonSchema, which is fine for a skipped field
+#[derive(Default)]
+struct Handle(u32);
+
+#[derive(Serialize, Deserialize, ToolInput)]
+struct OpenArgs {
+    path: String,
+    #[serde(skip)]
+    handle: Handle,
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use rust2_tool_macros::tool;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Deserialize, JsonSchema)]
+struct EchoArgs {
+    text: String,
+}
+
+#[tool(name = "echo")]
+fn echo(args: EchoArgs) -> Result<String, String> {
+    Ok(args.text)
+}
+
+fn main() {}
@@ -0,0 +1,105 @@
+mod common;
+
+use rust2::message_db::{MessageDbClient, MessageDbConfig, StreamReadOptions};
+use rust2::deps::AppDeps;
+use rust2::routes::configure_routes;
+use rust2::usage::{key_id, usage_stream_name, UsageLedger, API_KEY_HEADER};
+use std::net::SocketAddr;
+use testcontainers::clients::Cli;
+
+async fn spawn_server(ledger: UsageLedger) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let routes = configure_routes(AppDeps::new().with_usage(ledger));
+    tokio::spawn(warp::serve(routes).incoming(listener).run());
+    addr
+}
+
+async fn post_message(addr: SocketAddr, api_key: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!(
+            "http://{}/api/v1/threads/550e8400-e29b-41d4-a716-446655440000",
+            addr
+        ))
+        .json(&serde_json::json!({ "text": "What's the weather?" }));
+    if let Some(api_key) = api_key {
+        request = request.header(API_KEY_HEADER, api_key);
+    }
+    request.send().await.unwrap()
+}
+
+async fn get_usage(addr: SocketAddr, api_key: &str) -> serde_json::Value {
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/api/v1/usage", addr))
+        .header(API_KEY_HEADER, api_key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn test_monthly_budget_is_enforced() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string)
+        .expect("Failed to create config");
+    let client = MessageDbClient::new(config)
+        .await
+        .expect("Failed to create client");
+
+    // A single mock run uses more than 10 tokens
+    let ledger = UsageLedger::new(client.clone()).with_monthly_token_limit(10);
+    let addr = spawn_server(ledger).await;
+
+    // Requests without a key are rejected
+    let response = post_message(addr, None).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // First run is within budget; the stream ends after usage is recorded
+    let response = post_message(addr, Some("tiny-budget-key")).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("event:done"));
+
+    let usage = get_usage(addr, "tiny-budget-key").await;
+    assert_eq!(usage["key_id"], key_id("tiny-budget-key"));
+    assert_eq!(usage["runs"], 1);
+    assert!(usage["tokens_used"].as_u64().unwrap() > 10);
+    assert_eq!(usage["token_limit"], 10);
+    assert_eq!(usage["tokens_remaining"], 0);
+
+    // Second run is over budget
+    let response = post_message(addr, Some("tiny-budget-key")).await;
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after <= 31 * 86400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "Monthly token budget exceeded");
+    assert_eq!(body["usage"]["tokens_remaining"], 0);
+    assert_eq!(body["usage"]["resets_at"], usage["resets_at"]);
+
+    // Other principals are unaffected
+    let usage = get_usage(addr, "another-key").await;
+    assert_eq!(usage["runs"], 0);
+    assert_eq!(usage["tokens_remaining"], 10);
+
+    // Exactly one event was written, to the hashed key's stream
+    let messages = client
+        .get_stream_messages(StreamReadOptions::new(usage_stream_name(&key_id(
+            "tiny-budget-key",
+        ))))
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].message_type, "UsageRecorded");
+    assert_eq!(messages[0].data["model"], "mock");
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use rust2::llm::UsageMetadata;
+use rust2::message_db::{MessageDbClient, MessageDbConfig, StreamReadOptions};
+use rust2::webhooks::{RunStatus, WebhookDispatcher, WebhookPayload, WebhookRegistration};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use testcontainers::clients::Cli;
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::Filter;
+
+#[tokio::test]
+async fn test_delivery_attempts_are_recorded_on_the_thread_stream() {
+    let docker = Cli::default();
+    let container = docker.run(common::create_message_db_container());
+    let host_port = container.get_host_port_ipv4(common::POSTGRES_PORT);
+    let connection_string = common::build_connection_string("127.0.0.1", host_port);
+
+    let config = MessageDbConfig::from_connection_string(&connection_string)
+        .expect("Failed to create config");
+    let client = MessageDbClient::new(config)
+        .await
+        .expect("Failed to create client");
+
+    // Receiver that fails the first request
+    let calls = Arc::new(AtomicUsize::new(0));
+    let route = warp::post().and(warp::path("hook")).map(move || {
+        if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::NO_CONTENT
+        }
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(warp::serve(route).incoming(listener).run());
+
+    let registration = WebhookRegistration {
+        callback_url: format!("http://{}/hook", addr),
+        secret: Some("s3cret".to_string()),
+    };
+    let payload = WebhookPayload {
+        run_id: Uuid::new_v4(),
+        thread_id: Uuid::new_v4(),
+        status: RunStatus::Completed,
+        final_text: Some("The weather is sunny!".to_string()),
+        usage: Some(UsageMetadata::new(12, 8)),
+    };
+
+    let attempts = WebhookDispatcher::new()
+        .with_initial_backoff(Duration::from_millis(10))
+        .with_audit_log(client.clone())
+        .deliver(&registration, &payload)
+        .await;
+    assert_eq!(attempts.len(), 2);
+
+    let events = client
+        .get_stream_messages(StreamReadOptions::new(format!("thread-{}", payload.thread_id)))
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 2);
+    for (event, attempt) in events.iter().zip(&attempts) {
+        assert_eq!(event.message_type, "WebhookDeliveryAttempted");
+        assert_eq!(event.data["run_id"], payload.run_id.to_string());
+        assert_eq!(event.data["callback_url"], registration.callback_url);
+        assert_eq!(event.data["attempt"], attempt.attempt);
+    }
+    assert_eq!(events[0].data["status_code"], 500);
+    assert_eq!(events[0].data["succeeded"], false);
+    assert_eq!(events[1].data["status_code"], 204);
+    assert_eq!(events[1].data["succeeded"], true);
+}